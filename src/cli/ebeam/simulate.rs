@@ -113,7 +113,8 @@ pub fn create_simulate_subcommand(_parent_command_name: &'static str) -> Command
                        \n    *.fl: Creates a binary file readable by the backstaff Python package\
                        \n    *.pickle: Creates a Python pickle file (requires the pickle feature)\
                        \n    *.json: Creates a JSON file (requires the json feature)\
-                       \n    *.h5part: Creates a H5Part file (requires the hdf5 feature)",
+                       \n    *.h5part: Creates a H5Part file (requires the hdf5 feature)\
+                       \n    *.vtp: Creates a VTK XML poly data file",
                 )
                 .required(true)
                 .takes_value(true),
@@ -257,6 +258,7 @@ enum OutputType {
     Json,
     #[cfg(feature = "hdf5")]
     H5Part,
+    Vtk,
 }
 
 impl OutputType {
@@ -310,6 +312,7 @@ impl OutputType {
                 exit_with_error!("Error: Compile with hdf5 feature in order to write H5Part files\n\
                                   Tip: Use cargo flag --features=hdf5 and make sure the HDF5 library is available");
             }
+            "vtp" => Self::Vtk,
             invalid => exit_with_error!(
                 "Error: Invalid extension {} for output file\n\
                  Valid extensions are: {}",
@@ -321,7 +324,7 @@ impl OutputType {
 
     fn valid_extensions_string() -> String {
         format!(
-            "fl, pickle, json{}",
+            "fl, pickle, json{}, vtp",
             if cfg!(feature = "hdf5") {
                 ", h5part"
             } else {
@@ -344,6 +347,7 @@ impl fmt::Display for OutputType {
                 Self::Json => "json",
                 #[cfg(feature = "hdf5")]
                 Self::H5Part => "h5part",
+                Self::Vtk => "vtp",
             }
         )
     }
@@ -810,6 +814,7 @@ fn perform_post_simulation_actions<A>(
                 extra_atomic_output_file.as_ref().unwrap().temporary_path(),
                 root_arguments.is_present("drop-h5part-id"),
             ),
+            OutputType::Vtk => beams.save_into_vtk(atomic_output_file.temporary_path()),
         },
         "Error: Could not save output data: {}"
     );
@@ -1,6 +1,8 @@
 //! Command line interface for seed point generators used for field line tracing.
 
 pub mod criterion;
+#[cfg(feature = "tracing")]
+pub mod evenly_spaced;
 pub mod manual;
 pub mod slice;
 pub mod volume;
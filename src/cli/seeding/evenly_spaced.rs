@@ -0,0 +1,153 @@
+//! Command line interface for options shared by the evenly spaced seeders.
+
+use crate::{
+    cli::utils,
+    exit_on_error,
+    field::{CachingScalarFieldProvider3, VectorField3},
+    geometry::Dim3::{X, Y, Z},
+    grid::fgr,
+    io::snapshot::fdt,
+    seeding::evenly_spaced::{EvenlySpacedSeedingConfig, SeparationModulation},
+};
+use clap::{Arg, ArgMatches, Command};
+use std::sync::Arc;
+
+/// Adds the arguments shared by the evenly spaced seeders to the given command.
+pub fn add_evenly_spaced_seeding_arguments(command: Command<'static>) -> Command<'static> {
+    command
+        .arg(
+            Arg::new("quantity")
+                .short('q')
+                .long("quantity")
+                .require_equals(true)
+                .value_name("NAME")
+                .help("Vector field to trace while seeding")
+                .takes_value(true)
+                .default_value("b"),
+        )
+        .arg(
+            Arg::new("separation")
+                .short('d')
+                .long("separation")
+                .require_equals(true)
+                .value_name("DISTANCE")
+                .help("Distance to keep between new seed points and existing field lines")
+                .required(true)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("test-fraction")
+                .long("test-fraction")
+                .require_equals(true)
+                .value_name("VALUE")
+                .help(
+                    "Fraction of the separation distance at which a field line being traced\n\
+                     is stopped when approaching another field line",
+                )
+                .takes_value(true)
+                .default_value("0.5"),
+        )
+        .arg(
+            Arg::new("strength-exponent")
+                .long("strength-exponent")
+                .require_equals(true)
+                .value_name("VALUE")
+                .help(
+                    "Scale the separation distance by (reference strength/local strength)^VALUE\n\
+                     [default: no scaling]",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("reference-strength")
+                .long("reference-strength")
+                .require_equals(true)
+                .value_name("VALUE")
+                .help(
+                    "Field strength where the separation distance is not scaled\n\
+                     [default: mean field strength in the grid]",
+                )
+                .takes_value(true)
+                .requires("strength-exponent"),
+        )
+        .arg(
+            Arg::new("max-separation-scale")
+                .long("max-separation-scale")
+                .require_equals(true)
+                .value_name("VALUE")
+                .help("Largest factor by which the separation distance can be scaled up or down")
+                .takes_value(true)
+                .default_value("10.0"),
+        )
+}
+
+/// Provides the vector field to trace while seeding, as specified in the given arguments.
+pub fn provide_evenly_spaced_seeding_field(
+    arguments: &ArgMatches,
+    snapshot: &mut dyn CachingScalarFieldProvider3<fdt>,
+) -> Arc<VectorField3<fdt>> {
+    let quantity = arguments
+        .value_of("quantity")
+        .expect("No value for argument with default")
+        .to_lowercase();
+    exit_on_error!(
+        snapshot.provide_vector_field(&quantity),
+        "Error: Could not read quantity {0} in snapshot: {1}",
+        &quantity
+    )
+}
+
+/// Determines evenly spaced seeding parameters based on provided options.
+pub fn construct_evenly_spaced_seeding_config_from_options(
+    arguments: &ArgMatches,
+    field: &VectorField3<fdt>,
+) -> EvenlySpacedSeedingConfig {
+    let separation = utils::get_finite_float_value_from_required_parseable_argument::<fgr>(
+        arguments,
+        "separation",
+    );
+    let test_separation_fraction = utils::get_finite_float_value_from_required_parseable_argument::<
+        fgr,
+    >(arguments, "test-fraction");
+
+    let strength_modulation = arguments.value_of("strength-exponent").map(|_| {
+        let exponent = utils::get_finite_float_value_from_required_parseable_argument::<fgr>(
+            arguments,
+            "strength-exponent",
+        );
+        let reference_strength = if arguments.is_present("reference-strength") {
+            utils::get_finite_float_value_from_required_parseable_argument::<fgr>(
+                arguments,
+                "reference-strength",
+            )
+        } else {
+            compute_mean_field_strength(field)
+        };
+        let max_scale = utils::get_finite_float_value_from_required_parseable_argument::<fgr>(
+            arguments,
+            "max-separation-scale",
+        );
+        SeparationModulation {
+            reference_strength,
+            exponent,
+            max_scale,
+        }
+    });
+
+    EvenlySpacedSeedingConfig {
+        separation,
+        test_separation_fraction,
+        strength_modulation,
+    }
+}
+
+fn compute_mean_field_strength(field: &VectorField3<fdt>) -> fgr {
+    let (x_values, y_values, z_values) = (field.values(X), field.values(Y), field.values(Z));
+    let total_strength: fgr = x_values
+        .iter()
+        .zip(y_values.iter())
+        .zip(z_values.iter())
+        .map(|((&x, &y), &z)| fgr::from(x * x + y * y + z * z).sqrt())
+        .sum();
+    total_strength / (x_values.len() as fgr)
+}
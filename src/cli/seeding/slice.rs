@@ -1,10 +1,16 @@
 //! Command line interface for generating of seed points in a slice through a field.
 
+#[cfg(feature = "tracing")]
+pub mod evenly_spaced;
 pub mod pdf;
 pub mod random;
 pub mod regular;
 pub mod stratified;
 
+#[cfg(feature = "tracing")]
+use self::evenly_spaced::{
+    create_evenly_spaced_slice_seeder_from_arguments, create_evenly_spaced_subcommand,
+};
 use self::{
     pdf::{create_slice_pdf_seeder_from_arguments, create_value_pdf_subcommand},
     random::{create_random_slice_seeder_from_arguments, create_random_subcommand},
@@ -14,7 +20,7 @@ use self::{
 use crate::{
     cli::utils,
     exit_with_error,
-    field::CachingScalarFieldProvider3,
+    field::{CachingScalarFieldProvider3, FieldGrid3},
     geometry::{Dim2, Dim3, Point2},
     grid::fgr,
     interpolation::Interpolator3,
@@ -36,7 +42,7 @@ pub fn create_slice_seeder_subcommand(_parent_command_name: &'static str) -> Com
 
     update_command_graph!(_parent_command_name, command_name);
 
-    Command::new(command_name)
+    let command = Command::new(command_name)
         .about("Use a slice seeder")
         .subcommand_required(true)
         .arg(
@@ -96,7 +102,12 @@ pub fn create_slice_seeder_subcommand(_parent_command_name: &'static str) -> Com
         .subcommand(create_regular_subcommand(command_name))
         .subcommand(create_random_subcommand(command_name))
         .subcommand(create_stratified_subcommand(command_name))
-        .subcommand(create_value_pdf_subcommand(command_name))
+        .subcommand(create_value_pdf_subcommand(command_name));
+
+    #[cfg(feature = "tracing")]
+    let command = command.subcommand(create_evenly_spaced_subcommand(command_name));
+
+    command
 }
 
 /// Creates a slice seeder based on the provided arguments.
@@ -138,6 +149,17 @@ pub fn create_slice_seeder_from_arguments(
             && point[Dim2::Y] < vertical_bounds.1
     };
 
+    #[cfg(feature = "tracing")]
+    if let Some(seeder_arguments) = arguments.subcommand_matches("evenly_spaced") {
+        return create_evenly_spaced_slice_seeder_from_arguments(
+            seeder_arguments,
+            &parameters,
+            snapshot,
+            interpolator,
+            &satisifes_constraints,
+        );
+    }
+
     if let Some(seeder_arguments) = arguments.subcommand_matches("regular") {
         create_regular_slice_seeder_from_arguments(
             seeder_arguments,
@@ -171,3 +193,44 @@ pub fn create_slice_seeder_from_arguments(
         exit_with_error!("Error: No seeder specified")
     }
 }
+
+/// Creates a slice seeder for the given slice through the grid, based on the
+/// provided arguments for a regular, random or stratified slice seeder.
+///
+/// Returns `None` if none of these seeders is specified.
+pub fn create_slice_seeder_for_slice_from_arguments(
+    arguments: &ArgMatches,
+    grid: &FieldGrid3,
+    axis: Dim3,
+    coord: fgr,
+) -> Option<SliceSeeder3> {
+    let parameters = CommonSliceSeederParameters { axis, coord };
+    let satisifes_constraints = |_: &Point2<fgr>| true;
+
+    if let Some(seeder_arguments) = arguments.subcommand_matches("regular") {
+        Some(create_regular_slice_seeder_from_arguments(
+            seeder_arguments,
+            &parameters,
+            grid,
+            &satisifes_constraints,
+        ))
+    } else if let Some(seeder_arguments) = arguments.subcommand_matches("random") {
+        Some(create_random_slice_seeder_from_arguments(
+            seeder_arguments,
+            &parameters,
+            grid,
+            &satisifes_constraints,
+        ))
+    } else {
+        arguments
+            .subcommand_matches("stratified")
+            .map(|seeder_arguments| {
+                create_stratified_slice_seeder_from_arguments(
+                    seeder_arguments,
+                    &parameters,
+                    grid,
+                    &satisifes_constraints,
+                )
+            })
+    }
+}
@@ -0,0 +1,59 @@
+//! Command line interface for producing evenly spaced seed points in a 2D slice of a 3D grid.
+
+use super::CommonSliceSeederParameters;
+use crate::{
+    cli::seeding::evenly_spaced::{
+        add_evenly_spaced_seeding_arguments, construct_evenly_spaced_seeding_config_from_options,
+        provide_evenly_spaced_seeding_field,
+    },
+    field::CachingScalarFieldProvider3,
+    geometry::Point2,
+    grid::fgr,
+    interpolation::Interpolator3,
+    io::snapshot::fdt,
+    seeding::slice::SliceSeeder3,
+    update_command_graph,
+};
+use clap::{ArgMatches, Command};
+
+/// Creates a subcommand for using the evenly spaced slice seeder.
+pub fn create_evenly_spaced_subcommand(_parent_command_name: &'static str) -> Command<'static> {
+    let command_name = "evenly_spaced";
+
+    update_command_graph!(_parent_command_name, command_name);
+
+    add_evenly_spaced_seeding_arguments(
+        Command::new(command_name)
+            .about("Use the evenly spaced slice seeder")
+            .long_about(
+                "Use the evenly spaced slice seeder.\n\
+                 Field lines are traced from the seed points while seeding, and new seed points\n\
+                 are placed around existing ones in the slice so that no seed point lies closer\n\
+                 than the separation distance to any traced field line.",
+            ),
+    )
+}
+
+/// Creates an evenly spaced slice seeder based on the provided arguments.
+pub fn create_evenly_spaced_slice_seeder_from_arguments<S>(
+    arguments: &ArgMatches,
+    parameters: &CommonSliceSeederParameters,
+    snapshot: &mut dyn CachingScalarFieldProvider3<fdt>,
+    interpolator: &dyn Interpolator3<fdt>,
+    satisfies_constraints: &S,
+) -> SliceSeeder3
+where
+    S: Fn(&Point2<fgr>) -> bool + Sync,
+{
+    let field = provide_evenly_spaced_seeding_field(arguments, snapshot);
+    let config = construct_evenly_spaced_seeding_config_from_options(arguments, &field);
+
+    SliceSeeder3::evenly_spaced(
+        field.as_ref(),
+        interpolator,
+        parameters.axis,
+        parameters.coord,
+        &config,
+        satisfies_constraints,
+    )
+}
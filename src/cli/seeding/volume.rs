@@ -1,10 +1,16 @@
 //! Command line interface for generating of seed points in a volume of a field.
 
+#[cfg(feature = "tracing")]
+pub mod evenly_spaced;
 pub mod pdf;
 pub mod random;
 pub mod regular;
 pub mod stratified;
 
+#[cfg(feature = "tracing")]
+use self::evenly_spaced::{
+    create_evenly_spaced_subcommand, create_evenly_spaced_volume_seeder_from_arguments,
+};
 use self::{
     pdf::{create_value_pdf_subcommand, create_volume_pdf_seeder_from_arguments},
     random::{create_random_subcommand, create_random_volume_seeder_from_arguments},
@@ -33,7 +39,7 @@ pub fn create_volume_seeder_subcommand(_parent_command_name: &'static str) -> Co
 
     update_command_graph!(_parent_command_name, command_name);
 
-    Command::new(command_name)
+    let command = Command::new(command_name)
         .about("Use a volume seeder")
         .subcommand_required(true)
         .arg(
@@ -87,7 +93,12 @@ pub fn create_volume_seeder_subcommand(_parent_command_name: &'static str) -> Co
         .subcommand(create_regular_subcommand(command_name))
         .subcommand(create_random_subcommand(command_name))
         .subcommand(create_stratified_subcommand(command_name))
-        .subcommand(create_value_pdf_subcommand(command_name))
+        .subcommand(create_value_pdf_subcommand(command_name));
+
+    #[cfg(feature = "tracing")]
+    let command = command.subcommand(create_evenly_spaced_subcommand(command_name));
+
+    command
 }
 
 /// Creates a volume seeder based on the provided arguments.
@@ -131,6 +142,18 @@ pub fn create_volume_seeder_from_arguments(
 
     let satisifes_constraints = |_: &Point3<fgr>| true;
 
+    #[cfg(feature = "tracing")]
+    if let Some(seeder_arguments) = arguments.subcommand_matches("evenly_spaced") {
+        return create_evenly_spaced_volume_seeder_from_arguments(
+            seeder_arguments,
+            lower_bounds,
+            upper_bounds,
+            snapshot,
+            interpolator,
+            &satisifes_constraints,
+        );
+    }
+
     if let Some(seeder_arguments) = arguments.subcommand_matches("regular") {
         create_regular_volume_seeder_from_arguments(
             seeder_arguments,
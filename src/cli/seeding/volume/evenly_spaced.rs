@@ -0,0 +1,59 @@
+//! Command line interface for producing evenly spaced seed points in a volume of a 3D grid.
+
+use crate::{
+    cli::seeding::evenly_spaced::{
+        add_evenly_spaced_seeding_arguments, construct_evenly_spaced_seeding_config_from_options,
+        provide_evenly_spaced_seeding_field,
+    },
+    field::CachingScalarFieldProvider3,
+    geometry::{Point3, Vec3},
+    grid::fgr,
+    interpolation::Interpolator3,
+    io::snapshot::fdt,
+    seeding::volume::VolumeSeeder3,
+    update_command_graph,
+};
+use clap::{ArgMatches, Command};
+
+/// Creates a subcommand for using the evenly spaced volume seeder.
+pub fn create_evenly_spaced_subcommand(_parent_command_name: &'static str) -> Command<'static> {
+    let command_name = "evenly_spaced";
+
+    update_command_graph!(_parent_command_name, command_name);
+
+    add_evenly_spaced_seeding_arguments(
+        Command::new(command_name)
+            .about("Use the evenly spaced volume seeder")
+            .long_about(
+                "Use the evenly spaced volume seeder.\n\
+                 Field lines are traced within the volume from the seed points while seeding,\n\
+                 and new seed points are placed next to the traced field lines so that no seed\n\
+                 point lies closer than the separation distance to any traced field line.",
+            ),
+    )
+}
+
+/// Creates an evenly spaced volume seeder based on the provided arguments.
+pub fn create_evenly_spaced_volume_seeder_from_arguments<S>(
+    arguments: &ArgMatches,
+    lower_bounds: Vec3<fgr>,
+    upper_bounds: Vec3<fgr>,
+    snapshot: &mut dyn CachingScalarFieldProvider3<fdt>,
+    interpolator: &dyn Interpolator3<fdt>,
+    satisfies_constraints: &S,
+) -> VolumeSeeder3
+where
+    S: Fn(&Point3<fgr>) -> bool + Sync,
+{
+    let field = provide_evenly_spaced_seeding_field(arguments, snapshot);
+    let config = construct_evenly_spaced_seeding_config_from_options(arguments, &field);
+
+    VolumeSeeder3::evenly_spaced(
+        field.as_ref(),
+        interpolator,
+        &lower_bounds,
+        &upper_bounds,
+        &config,
+        satisfies_constraints,
+    )
+}
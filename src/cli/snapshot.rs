@@ -2,8 +2,12 @@
 
 mod extract;
 mod inspect;
+mod profile;
 mod resample;
 mod slice;
+mod surface;
+mod temporal;
+mod verify;
 mod write;
 
 #[cfg(feature = "derivation")]
@@ -12,22 +16,37 @@ mod derive;
 #[cfg(feature = "corks")]
 mod corks;
 
+#[cfg(feature = "tracing")]
+mod deposit;
+
+#[cfg(feature = "tracing")]
+mod qsl;
+
 #[cfg(feature = "synthesis")]
 mod synthesize;
 
 use self::{
-    extract::create_extract_subcommand, inspect::create_inspect_subcommand,
-    resample::create_resample_subcommand, slice::create_slice_subcommand,
+    extract::create_extract_subcommand,
+    inspect::create_inspect_subcommand,
+    profile::create_profile_subcommand,
+    resample::create_resample_subcommand,
+    slice::create_slice_subcommand,
+    surface::create_surface_subcommand,
+    temporal::{create_temporal_subcommand, SnapshotSeries},
+    verify::create_verify_subcommand,
     write::create_write_subcommand,
 };
 use crate::{
     add_subcommand_combinations,
     cli::utils as cli_utils,
     exit_on_error, exit_on_false, exit_with_error,
-    field::{DynCachingScalarFieldProvider3, DynScalarFieldProvider3, ScalarFieldProvider3},
+    field::{
+        temporal::SnapshotOpener, DynCachingScalarFieldProvider3, DynScalarFieldProvider3,
+        ScalarFieldProvider3,
+    },
     io::{
         snapshot::{
-            self, fdt,
+            self, fdt, muram,
             utils::{self as snapshot_utils, SnapNumInRange, SnapshotInputType},
             SnapshotMetadata,
         },
@@ -37,17 +56,20 @@ use crate::{
     update_command_graph,
 };
 use clap::{Arg, ArgMatches, Command, ValueHint};
-use std::{collections::HashSet, path::PathBuf, str::FromStr};
+use std::{collections::HashSet, path::PathBuf, str::FromStr, sync::Arc};
 
 #[cfg(feature = "derivation")]
 use self::derive::create_derive_subcommand;
 
 #[cfg(feature = "tracing")]
-use super::tracing::create_trace_subcommand;
+use super::tracing::{create_trace_subcommand, null::create_nulls_subcommand, TraceState};
 
 #[cfg(feature = "corks")]
 use self::corks::{create_corks_subcommand, CorksState};
 
+#[cfg(feature = "tracing")]
+use self::{deposit::create_deposit_subcommand, qsl::create_qsl_subcommand};
+
 #[cfg(feature = "ebeam")]
 use super::ebeam::create_ebeam_subcommand;
 
@@ -69,7 +91,9 @@ pub fn create_snapshot_subcommand(_parent_command_name: &'static str) -> Command
                     "Path to the file representing the snapshot.\n\
                      Assumes the following format based on the file extension:\
                      \n    *.idl: Parameter file with associated .snap [and .aux] file\
-                     \n    *.nc: NetCDF file using the CF convention (requires the netcdf feature)",
+                     \n    *.nc: NetCDF file using the CF convention (requires the netcdf feature)\
+                     \n    *.h5: HDF5 file (requires the hdf5 feature)\
+                     \n    Header.<iteration>: MURaM header file with associated result_prim_* [and eos*] files",
                 )
                 .required(true)
                 .takes_value(true)
@@ -85,7 +109,9 @@ pub fn create_snapshot_subcommand(_parent_command_name: &'static str) -> Command
                 .value_names(&["FIRST", "LAST"])
                 .help(
                     "Inclusive range of snapshot numbers associated with the input\n\
-                     snapshot to process [default: only process INPUT_FILE]",
+                     snapshot to process [default: only process INPUT_FILE]\n\
+                     For MURaM input, the numbers are iterations, and only the\n\
+                     iterations with a header file in the range are processed",
                 )
                 .takes_value(true)
                 .number_of_values(2),
@@ -106,13 +132,17 @@ pub fn create_snapshot_subcommand(_parent_command_name: &'static str) -> Command
                 .short('v')
                 .long("verbose")
                 .help("Print status messages related to reading"),
-        );
+        )
+        .subcommand(create_verify_subcommand(command_name));
 
     add_subcommand_combinations!(
         command, command_name, true;
         derive if "derivation",
+        temporal,
+        qsl if "tracing",
+        deposit if "tracing",
         synthesize if "synthesis",
-        (inspect, slice, extract, resample, write, corks if "corks", trace if "tracing", ebeam if "ebeam")
+        (inspect, slice, surface, profile, extract, resample, write, corks if "corks", trace if "tracing", nulls if "tracing", ebeam if "ebeam")
     )
 }
 
@@ -148,9 +178,18 @@ pub fn run_snapshot_subcommand(arguments: &ArgMatches, io_context: &mut IOContex
                 "Error: Last snapshot number must be larger than first snapshot number"
             );
 
-            (snap_num_range[0]..=snap_num_range[1])
-                .map(|snap_num| {
-                    (
+            let input_snap_paths: Vec<_> = if input_type.is_muram() {
+                exit_on_error!(
+                    muram::find_header_files_in_range(
+                        &input_file_path,
+                        u64::from(snap_num_range[0]),
+                        u64::from(snap_num_range[1]),
+                    ),
+                    "Error: Could not search for MURaM header files: {}"
+                )
+            } else {
+                (snap_num_range[0]..=snap_num_range[1])
+                    .map(|snap_num| {
                         input_file_path.with_file_name(
                             snapshot::create_new_snapshot_file_name_from_path(
                                 &input_file_path,
@@ -158,12 +197,23 @@ pub fn run_snapshot_subcommand(arguments: &ArgMatches, io_context: &mut IOContex
                                 &input_type.to_string(),
                                 false,
                             ),
-                        ),
-                        Some(SnapNumInRange::new(
-                            snap_num_range[0],
-                            snap_num_range[1],
-                            snap_num,
-                        )),
+                        )
+                    })
+                    .collect()
+            };
+            exit_on_false!(
+                !input_snap_paths.is_empty(),
+                "Error: No snapshots found in snap-range"
+            );
+
+            let last_offset = input_snap_paths.len() as u32 - 1;
+            input_snap_paths
+                .into_iter()
+                .zip(0..)
+                .map(|(input_snap_path, offset)| {
+                    (
+                        input_snap_path,
+                        Some(SnapNumInRange::new(0, last_offset, offset)),
                     )
                 })
                 .collect()
@@ -181,8 +231,45 @@ pub fn run_snapshot_subcommand(arguments: &ArgMatches, io_context: &mut IOContex
         invalid => exit_with_error!("Error: Invalid endianness {}", invalid),
     };
 
+    if let Some(verify_arguments) = arguments.subcommand_matches("verify") {
+        let input_snap_paths: Vec<_> = input_snap_paths_and_num_offsets
+            .into_iter()
+            .map(|(file_path, _)| file_path)
+            .collect();
+        verify::run_verify_subcommand(verify_arguments, &input_type, &input_snap_paths, endianness);
+        return;
+    }
+
     let verbosity: Verbosity = cli_utils::parse_verbosity(arguments, false);
 
+    let derive_arguments = if cfg!(feature = "derivation") {
+        arguments.subcommand_matches("derive")
+    } else {
+        None
+    };
+    let uses_temporal = arguments.subcommand_matches("temporal").is_some()
+        || derive_arguments.is_some_and(|derive_arguments| {
+            derive_arguments.subcommand_matches("temporal").is_some()
+        });
+
+    let series = if uses_temporal {
+        let snapshot_paths: Vec<_> = input_snap_paths_and_num_offsets
+            .iter()
+            .map(|(file_path, _)| file_path.clone())
+            .collect();
+        let open_snapshot =
+            create_snapshot_opener(derive_arguments, snapshot_paths.clone(), endianness);
+        Some(SnapshotSeries::new(
+            &snapshot_paths,
+            endianness,
+            open_snapshot,
+        ))
+    } else {
+        None
+    };
+
+    let mut state = SubcommandState::default();
+
     for (file_path, snap_num_in_range) in input_snap_paths_and_num_offsets {
         io_context.set_snap_num_in_range(snap_num_in_range);
 
@@ -190,24 +277,199 @@ pub fn run_snapshot_subcommand(arguments: &ArgMatches, io_context: &mut IOContex
             snapshot_utils::new_snapshot_reader(file_path, endianness, verbosity.clone()),
             "Error: {}"
         );
-        run_snapshot_subcommand_with_derive(arguments, &*metadata, reader, io_context);
+        run_snapshot_subcommand_with_derive(
+            arguments,
+            &*metadata,
+            reader,
+            io_context,
+            &mut state,
+            series.as_ref(),
+        );
     }
 }
 
+/// State of the final subcommand that persists between the snapshots being
+/// processed.
+#[derive(Default)]
+struct SubcommandState {
+    #[cfg(feature = "corks")]
+    corks: Option<CorksState>,
+    #[cfg(feature = "tracing")]
+    trace: TraceState,
+}
+
+fn create_snapshot_opener(
+    _derive_arguments: Option<&ArgMatches>,
+    snapshot_paths: Vec<PathBuf>,
+    endianness: Endianness,
+) -> SnapshotOpener {
+    #[cfg(feature = "derivation")]
+    let derive_arguments = _derive_arguments.cloned();
+
+    Arc::new(move |snapshot_idx| {
+        let (reader, _metadata) = snapshot_utils::new_snapshot_reader(
+            snapshot_paths[snapshot_idx].clone(),
+            endianness,
+            Verbosity::Quiet,
+        )?;
+
+        #[cfg(feature = "derivation")]
+        if let Some(derive_arguments) = &derive_arguments {
+            return Ok(Box::new(derive::create_derive_provider_quietly(
+                derive_arguments,
+                reader,
+                &*_metadata,
+            )));
+        }
+
+        Ok(reader)
+    })
+}
+
 fn run_snapshot_subcommand_with_derive(
     arguments: &ArgMatches,
     metadata: &dyn SnapshotMetadata,
     provider: DynScalarFieldProvider3<fdt>,
     io_context: &mut IOContext,
+    state: &mut SubcommandState,
+    series: Option<&SnapshotSeries>,
 ) {
     #[cfg(feature = "derivation")]
     if let Some(derive_arguments) = arguments.subcommand_matches("derive") {
-        let provider = Box::new(derive::create_derive_provider(derive_arguments, provider));
-        run_snapshot_subcommand_with_synthesis(derive_arguments, metadata, provider, io_context);
+        let provider = Box::new(derive::create_derive_provider(
+            derive_arguments,
+            provider,
+            metadata,
+        ));
+        run_snapshot_subcommand_with_temporal(
+            derive_arguments,
+            metadata,
+            provider,
+            io_context,
+            state,
+            series,
+        );
+        return;
+    }
+
+    if let Some(temporal_arguments) = arguments.subcommand_matches("temporal") {
+        run_snapshot_subcommand_for_temporal_provider(
+            temporal_arguments,
+            metadata,
+            provider,
+            io_context,
+            state,
+            series,
+        );
+        return;
+    }
+
+    run_snapshot_subcommand_with_qsl(arguments, metadata, provider, io_context, state);
+}
+
+#[cfg(feature = "derivation")]
+fn run_snapshot_subcommand_with_temporal(
+    arguments: &ArgMatches,
+    metadata: &dyn SnapshotMetadata,
+    provider: DynCachingScalarFieldProvider3<fdt>,
+    io_context: &mut IOContext,
+    state: &mut SubcommandState,
+    series: Option<&SnapshotSeries>,
+) {
+    if let Some(temporal_arguments) = arguments.subcommand_matches("temporal") {
+        run_snapshot_subcommand_for_temporal_provider(
+            temporal_arguments,
+            metadata,
+            provider.as_scalar_field_provider(),
+            io_context,
+            state,
+            series,
+        );
+        return;
+    }
+
+    if cfg!(feature = "tracing")
+        && (arguments.subcommand_matches("qsl").is_some()
+            || arguments.subcommand_matches("deposit").is_some())
+    {
+        run_snapshot_subcommand_with_qsl(
+            arguments,
+            metadata,
+            provider.as_scalar_field_provider(),
+            io_context,
+            state,
+        );
+        return;
+    }
+
+    run_snapshot_subcommand_with_synthesis(arguments, metadata, provider, io_context, state);
+}
+
+fn run_snapshot_subcommand_for_temporal_provider(
+    arguments: &ArgMatches,
+    metadata: &dyn SnapshotMetadata,
+    provider: DynScalarFieldProvider3<fdt>,
+    io_context: &mut IOContext,
+    state: &mut SubcommandState,
+    series: Option<&SnapshotSeries>,
+) {
+    let series = series.expect("No snapshot series for temporal subcommand");
+    let snapshot_idx = io_context
+        .get_snap_num_in_range()
+        .map_or(0, |snap_num_in_range| snap_num_in_range.offset() as usize);
+
+    let provider = Box::new(temporal::create_temporal_provider(
+        arguments,
+        provider,
+        series,
+        snapshot_idx,
+    ));
+    run_snapshot_subcommand_with_qsl(arguments, metadata, provider, io_context, state);
+}
+
+fn run_snapshot_subcommand_with_qsl(
+    arguments: &ArgMatches,
+    metadata: &dyn SnapshotMetadata,
+    provider: DynScalarFieldProvider3<fdt>,
+    io_context: &mut IOContext,
+    state: &mut SubcommandState,
+) {
+    #[cfg(feature = "tracing")]
+    if let Some(qsl_arguments) = arguments.subcommand_matches("qsl") {
+        let provider = Box::new(qsl::create_qsl_provider(qsl_arguments, provider));
+        run_snapshot_subcommand_with_deposit(qsl_arguments, metadata, provider, io_context, state);
         return;
     }
 
-    run_snapshot_subcommand_with_synthesis_added_caching(arguments, metadata, provider, io_context);
+    run_snapshot_subcommand_with_deposit(arguments, metadata, provider, io_context, state);
+}
+
+fn run_snapshot_subcommand_with_deposit(
+    arguments: &ArgMatches,
+    metadata: &dyn SnapshotMetadata,
+    provider: DynScalarFieldProvider3<fdt>,
+    io_context: &mut IOContext,
+    state: &mut SubcommandState,
+) {
+    #[cfg(feature = "tracing")]
+    if let Some(deposit_arguments) = arguments.subcommand_matches("deposit") {
+        let provider = Box::new(deposit::create_deposit_provider(
+            deposit_arguments,
+            provider,
+        ));
+        run_snapshot_subcommand_with_synthesis_added_caching(
+            deposit_arguments,
+            metadata,
+            provider,
+            io_context,
+            state,
+        );
+        return;
+    }
+
+    run_snapshot_subcommand_with_synthesis_added_caching(
+        arguments, metadata, provider, io_context, state,
+    );
 }
 
 fn run_snapshot_subcommand_with_synthesis(
@@ -215,6 +477,7 @@ fn run_snapshot_subcommand_with_synthesis(
     metadata: &dyn SnapshotMetadata,
     provider: DynCachingScalarFieldProvider3<fdt>,
     io_context: &mut IOContext,
+    state: &mut SubcommandState,
 ) {
     #[cfg(feature = "synthesis")]
     if let Some(synthesize_arguments) = arguments.subcommand_matches("synthesize") {
@@ -222,7 +485,13 @@ fn run_snapshot_subcommand_with_synthesis(
             synthesize_arguments,
             provider,
         ));
-        run_snapshot_subcommand_for_provider(synthesize_arguments, metadata, provider, io_context);
+        run_snapshot_subcommand_for_provider(
+            synthesize_arguments,
+            metadata,
+            provider,
+            io_context,
+            state,
+        );
         return;
     }
 
@@ -231,6 +500,7 @@ fn run_snapshot_subcommand_with_synthesis(
         metadata,
         provider.as_scalar_field_provider(),
         io_context,
+        state,
     );
 }
 
@@ -239,6 +509,7 @@ fn run_snapshot_subcommand_with_synthesis_added_caching(
     metadata: &dyn SnapshotMetadata,
     provider: DynScalarFieldProvider3<fdt>,
     io_context: &mut IOContext,
+    state: &mut SubcommandState,
 ) {
     #[cfg(feature = "synthesis")]
     if let Some(synthesize_arguments) = arguments.subcommand_matches("synthesize") {
@@ -246,11 +517,17 @@ fn run_snapshot_subcommand_with_synthesis_added_caching(
             synthesize_arguments,
             provider,
         ));
-        run_snapshot_subcommand_for_provider(synthesize_arguments, metadata, provider, io_context);
+        run_snapshot_subcommand_for_provider(
+            synthesize_arguments,
+            metadata,
+            provider,
+            io_context,
+            state,
+        );
         return;
     }
 
-    run_snapshot_subcommand_for_provider(arguments, metadata, provider, io_context);
+    run_snapshot_subcommand_for_provider(arguments, metadata, provider, io_context, state);
 }
 
 fn run_snapshot_subcommand_for_provider(
@@ -258,11 +535,16 @@ fn run_snapshot_subcommand_for_provider(
     metadata: &dyn SnapshotMetadata,
     provider: DynScalarFieldProvider3<fdt>,
     io_context: &mut IOContext,
+    _state: &mut SubcommandState,
 ) {
     if let Some(inspect_arguments) = arguments.subcommand_matches("inspect") {
         inspect::run_inspect_subcommand(inspect_arguments, metadata, provider, io_context);
     } else if let Some(slice_arguments) = arguments.subcommand_matches("slice") {
-        slice::run_slice_subcommand(slice_arguments, provider, io_context);
+        slice::run_slice_subcommand(slice_arguments, metadata, provider, io_context);
+    } else if let Some(surface_arguments) = arguments.subcommand_matches("surface") {
+        surface::run_surface_subcommand(surface_arguments, provider, io_context);
+    } else if let Some(profile_arguments) = arguments.subcommand_matches("profile") {
+        profile::run_profile_subcommand(profile_arguments, provider, io_context);
     } else if let Some(extract_arguments) = arguments.subcommand_matches("extract") {
         extract::run_extract_subcommand(extract_arguments, metadata, provider, io_context);
     } else if let Some(resample_arguments) = arguments.subcommand_matches("resample") {
@@ -280,6 +562,11 @@ fn run_snapshot_subcommand_for_provider(
         } else {
             None
         };
+        let nulls_arguments = if cfg!(feature = "tracing") {
+            arguments.subcommand_matches("nulls")
+        } else {
+            None
+        };
         let ebeam_arguments = if cfg!(feature = "ebeam") {
             arguments.subcommand_matches("ebeam")
         } else {
@@ -288,18 +575,26 @@ fn run_snapshot_subcommand_for_provider(
         if let Some(_corks_arguments) = corks_arguments {
             #[cfg(feature = "corks")]
             {
-                let mut corks_state: Option<CorksState> = None;
                 corks::run_corks_subcommand(
                     _corks_arguments,
                     metadata,
                     provider,
                     io_context,
-                    &mut corks_state,
+                    &mut _state.corks,
                 );
             }
         } else if let Some(_trace_arguments) = trace_arguments {
             #[cfg(feature = "tracing")]
-            crate::cli::tracing::run_trace_subcommand(_trace_arguments, provider, io_context);
+            crate::cli::tracing::run_trace_subcommand(
+                _trace_arguments,
+                metadata,
+                provider,
+                io_context,
+                &mut _state.trace,
+            );
+        } else if let Some(_nulls_arguments) = nulls_arguments {
+            #[cfg(feature = "tracing")]
+            crate::cli::tracing::null::run_nulls_subcommand(_nulls_arguments, provider, io_context);
         } else if let Some(_ebeam_arguments) = ebeam_arguments {
             #[cfg(feature = "ebeam")]
             crate::cli::ebeam::run_ebeam_subcommand(
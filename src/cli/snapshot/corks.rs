@@ -59,7 +59,8 @@ pub fn create_corks_subcommand(_parent_command_name: &'static str) -> Command<'s
                     "Path of the file where the cork data should be saved\n\
                      Writes in the following format based on the file extension:\
                      \n    *.pickle: Creates a Python pickle file (requires the pickle feature)\
-                     \n    *.json: Creates a JSON file (requires the json feature)",
+                     \n    *.json: Creates a JSON file (requires the json feature)\
+                     \n    *.vtp: Creates a VTK XML poly data file",
                 )
                 .required(true)
                 .takes_value(true),
@@ -118,22 +119,7 @@ pub fn create_corks_subcommand(_parent_command_name: &'static str) -> Command<'s
     add_subcommand_combinations!(command, command_name, true; poly_fit_interpolator, (slice_seeder, volume_seeder, manual_seeder))
 }
 
-#[cfg(not(any(feature = "json", feature = "pickle")))]
-pub fn run_corks_subcommand(
-    arguments: &ArgMatches,
-    metadata: &dyn SnapshotMetadata,
-    provider: DynScalarFieldProvider3<fdt>,
-    io_context: &mut IOContext,
-    corks_state: &mut Option<CorksState>,
-) {
-    exit_with_error!(
-        "Error: Compile with json and/or pickle feature in order to write cork output\n\
-         Tip: Use cargo flag --features=json,pickle"
-    );
-}
-
 /// Runs the actions for the `snapshot-corks` subcommand using the given arguments.
-#[cfg(any(feature = "json", feature = "pickle"))]
 pub fn run_corks_subcommand(
     arguments: &ArgMatches,
     metadata: &dyn SnapshotMetadata,
@@ -356,12 +342,6 @@ fn should_write_output(io_context: &IOContext) -> bool {
     }
 }
 
-#[cfg(not(any(feature = "json", feature = "pickle")))]
-fn write_output(_: &ArgMatches, _: &mut IOContext, _: &Option<CorksState>) {
-    unreachable!()
-}
-
-#[cfg(any(feature = "json", feature = "pickle"))]
 fn write_output(
     root_arguments: &ArgMatches,
     io_context: &mut IOContext,
@@ -419,6 +399,7 @@ fn write_output(
                 OutputType::Json => corks.save_as_json(atomic_output_file.temporary_path()),
                 #[cfg(feature = "hdf5")]
                 OutputType::H5Part => unimplemented!(),
+                OutputType::Vtk => corks.save_as_vtk(atomic_output_file.temporary_path()),
             },
             "Error: Could not save output data: {}"
         );
@@ -439,6 +420,7 @@ enum OutputType {
     Json,
     #[cfg(feature = "hdf5")]
     H5Part,
+    Vtk,
 }
 
 impl OutputType {
@@ -492,6 +474,7 @@ impl OutputType {
                 exit_with_error!("Error: Compile with hdf5 feature in order to write H5Part files\n\
                                   Tip: Use cargo flag --features=hdf5 and make sure the HDF5 library is available");
             }
+            "vtp" => Self::Vtk,
             invalid => exit_with_error!(
                 "Error: Invalid extension {} for output file\n\
                  Valid extensions are: {}",
@@ -503,7 +486,7 @@ impl OutputType {
 
     fn valid_extensions_string() -> String {
         format!(
-            "cork, pickle, json{}",
+            "cork, pickle, json{}, vtp",
             if cfg!(feature = "hdf5") {
                 ", h5part"
             } else {
@@ -526,6 +509,7 @@ impl fmt::Display for OutputType {
                 Self::Json => "json",
                 #[cfg(feature = "hdf5")]
                 Self::H5Part => "h5part",
+                Self::Vtk => "vtp",
             }
         )
     }
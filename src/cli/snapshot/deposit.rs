@@ -0,0 +1,128 @@
+//! Command line interface for depositing field lines onto the snapshot grid.
+
+use crate::{
+    cli::utils as cli_utils,
+    exit_on_error,
+    field::{DynScalarFieldProvider3, ScalarFieldProvider3},
+    io::snapshot::fdt,
+    tracing::field_line::{deposition::FieldLineDepositionProvider3, FieldLineSet3},
+    update_command_graph,
+};
+use clap::{Arg, ArgMatches, Command, ValueHint};
+use std::path::Path;
+
+/// Builds a representation of the `snapshot-deposit` command line subcommand.
+pub fn create_deposit_subcommand(_parent_command_name: &'static str) -> Command<'static> {
+    let command_name = "deposit";
+
+    update_command_graph!(_parent_command_name, command_name);
+
+    Command::new(command_name)
+        .about("Deposit traced field lines or electron beams onto the snapshot grid")
+        .long_about(
+            "Deposit traced field lines or electron beams onto the snapshot grid.\n\
+             The following quantities become available:\n    \
+             fl_count: Number of field lines passing through each grid cell\n    \
+             fl_length: Total length of field line paths inside each grid cell\n    \
+             fl_avg_<q>: Average of the quantity <q> extracted along the field lines\n                \
+             inside each grid cell, weighted by path length\n\
+             The quantities are defined at the grid cell centers. Averages are NaN in grid\n\
+             cells that no field line passes through.",
+        )
+        .arg(
+            Arg::new("field-line-file")
+                .value_name("FIELD_LINE_FILE")
+                .help(
+                    "Path to a binary field line file (*.fl) produced by `trace` or `ebeam`\n\
+                     with field line paths",
+                )
+                .required(true)
+                .takes_value(true)
+                .value_hint(ValueHint::FilePath),
+        )
+        .arg(
+            Arg::new("quantities")
+                .short('Q')
+                .long("quantities")
+                .require_equals(true)
+                .use_value_delimiter(true)
+                .require_value_delimiter(true)
+                .value_name("NAMES")
+                .help(
+                    "List of deposition quantities to explicitly compute\n\
+                     (comma-separated) [default: none]",
+                )
+                .takes_value(true)
+                .multiple_values(true),
+        )
+        .arg(
+            Arg::new("ignore-warnings")
+                .long("ignore-warnings")
+                .help("Automatically continue on warnings"),
+        )
+        .arg(
+            Arg::new("verbose")
+                .short('v')
+                .long("verbose")
+                .help("Print status messages related to deposition of field lines"),
+        )
+        .arg(
+            Arg::new("progress")
+                .short('p')
+                .long("progress")
+                .help("Show progress bar for deposition of field lines (also implies `verbose`)"),
+        )
+}
+
+/// Creates a `FieldLineDepositionProvider3` for the given arguments and provider.
+pub fn create_deposit_provider(
+    arguments: &ArgMatches,
+    provider: DynScalarFieldProvider3<fdt>,
+) -> FieldLineDepositionProvider3 {
+    let field_line_file_path = Path::new(
+        arguments
+            .value_of("field-line-file")
+            .expect("No value for required argument"),
+    );
+
+    let quantity_names: Vec<_> = arguments
+        .values_of("quantities")
+        .map(|values| values.collect::<Vec<_>>())
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|name| {
+            if name.is_empty() {
+                None
+            } else {
+                Some(name.to_lowercase())
+            }
+        })
+        .collect();
+
+    let continue_on_warnings = arguments.is_present("ignore-warnings");
+    let verbosity = cli_utils::parse_verbosity(arguments, true);
+
+    let field_lines = exit_on_error!(
+        FieldLineSet3::from_custom_binary(field_line_file_path, verbosity),
+        "Error: Could not read field line file: {}"
+    );
+    if !field_lines.has_paths() {
+        eprintln!("Warning: Field line file does not contain field line paths");
+        if !continue_on_warnings {
+            cli_utils::verify_user_will_continue_or_abort()
+        }
+    }
+
+    let provider = FieldLineDepositionProvider3::new(provider, field_lines, quantity_names.clone());
+
+    for name in &quantity_names {
+        if !provider.has_variable(name) {
+            eprintln!("Warning: Deposition quantity {} not available", name);
+            if !continue_on_warnings {
+                cli_utils::verify_user_will_continue_or_abort()
+            }
+        }
+    }
+
+    provider
+}
@@ -4,13 +4,18 @@ use crate::{
     cli::utils as cli_utils,
     exit_on_error, exit_with_error,
     field::{
-        quantities::{DerivedScalarFieldProvider3, AVAILABLE_QUANTITY_TABLE_STRING},
+        quantities::{
+            eos::TabulatedEquationOfState,
+            expression::{QuantityDefinition, QuantityDefinitions},
+            DerivedScalarFieldProvider3, MagneticDiffusivity, AVAILABLE_QUANTITY_TABLE_STRING,
+        },
         DynScalarFieldProvider3, ScalarFieldCacher3,
     },
-    io::snapshot::fdt,
+    io::snapshot::{fdt, SnapshotMetadata},
     update_command_graph,
 };
-use clap::{Arg, ArgMatches, Command};
+use clap::{Arg, ArgMatches, Command, ValueHint};
+use std::{path::PathBuf, str::FromStr};
 
 /// Builds a representation of the `snapshot-derive` command line subcommand.
 pub fn create_derive_subcommand(_parent_command_name: &'static str) -> Command<'static> {
@@ -35,6 +40,49 @@ pub fn create_derive_subcommand(_parent_command_name: &'static str) -> Command<'
                 .takes_value(true)
                 .multiple_values(true),
         )
+        .arg(
+            Arg::new("define")
+                .long("define")
+                .require_equals(true)
+                .value_name("NAME=EXPRESSION")
+                .help(
+                    "Define a new quantity as an algebraic expression of other quantities\n\
+                     (can be repeated). The expression can use numbers, quantity names,\n\
+                     pi, the operators + - * / ^, parentheses and the functions sqrt, cbrt,\n\
+                     exp, ln, log10, abs, sin, cos, tan, asin, acos, atan, sinh, cosh, tanh,\n\
+                     min, max, pow and atan2. The comparisons < <= > >= evaluate to 1 or 0.\n\
+                     Defined quantities are computed in addition to the ones given by\n\
+                     --quantities, and can be used in later definitions.\n\
+                     E.g. --define=ekin=0.5*r*(ux^2+uy^2+uz^2)",
+                )
+                .takes_value(true)
+                .multiple_occurrences(true),
+        )
+        .arg(
+            Arg::new("eos-table")
+                .long("eos-table")
+                .require_equals(true)
+                .value_name("TABPARAM_FILE")
+                .help(
+                    "Path to the tabparam.in file of a tabulated equation of state, enabling\n\
+                     lookup of p, tg, nel, kr and radiation bin quantities from r and e",
+                )
+                .takes_value(true)
+                .value_hint(ValueHint::FilePath),
+        )
+        .arg(
+            Arg::new("magnetic-diffusivity")
+                .long("magnetic-diffusivity")
+                .require_equals(true)
+                .allow_hyphen_values(false)
+                .value_name("VALUE")
+                .help(
+                    "Uniform magnetic diffusivity used for the resistive term when deriving\n\
+                     the electric field from Ohm's law [Bifrost units]\n\
+                     [default: eta1 * Δs * (c_f + |u|) from the eta1 parameter, if present]",
+                )
+                .takes_value(true),
+        )
         .arg(
             Arg::new("max-memory-usage")
                 .short('m')
@@ -70,8 +118,51 @@ pub fn create_derive_subcommand(_parent_command_name: &'static str) -> Command<'
 pub fn create_derive_provider(
     arguments: &ArgMatches,
     provider: DynScalarFieldProvider3<fdt>,
+    metadata: &dyn SnapshotMetadata,
 ) -> DerivedScalarFieldProvider3 {
-    let derived_quantity_names = arguments
+    let continue_on_warnings = arguments.is_present("ignore-warnings");
+    create_derive_provider_with_warning_handler(
+        arguments,
+        provider,
+        metadata,
+        &|quantity_name, missing_dependencies| {
+            if let Some(missing_dependencies) = missing_dependencies {
+                eprintln!(
+                    "Warning: Missing following dependencies for derived quantity {}: {}",
+                    quantity_name,
+                    missing_dependencies.join(", ")
+                );
+                if !continue_on_warnings {
+                    cli_utils::verify_user_will_continue_or_abort()
+                }
+            } else {
+                eprintln!("Warning: Derived quantity {} not supported", quantity_name);
+                if !continue_on_warnings {
+                    cli_utils::verify_user_will_continue_or_abort()
+                }
+            }
+        },
+    )
+}
+
+/// Creates a provider of derived quantities without warning about unavailable
+/// quantities, for use with additional snapshots for which the warnings have
+/// already been given.
+pub fn create_derive_provider_quietly(
+    arguments: &ArgMatches,
+    provider: DynScalarFieldProvider3<fdt>,
+    metadata: &dyn SnapshotMetadata,
+) -> DerivedScalarFieldProvider3 {
+    create_derive_provider_with_warning_handler(arguments, provider, metadata, &|_, _| {})
+}
+
+fn create_derive_provider_with_warning_handler(
+    arguments: &ArgMatches,
+    provider: DynScalarFieldProvider3<fdt>,
+    metadata: &dyn SnapshotMetadata,
+    handle_unavailable: &dyn Fn(&str, Option<Vec<&str>>),
+) -> DerivedScalarFieldProvider3 {
+    let mut derived_quantity_names: Vec<_> = arguments
         .values_of("quantities")
         .map(|values| values.collect::<Vec<_>>())
         .unwrap_or_default()
@@ -97,9 +188,66 @@ pub fn create_derive_provider(
         exit_with_error!("Error: max-memory-usage can not be negative");
     }
 
-    let continue_on_warnings = arguments.is_present("ignore-warnings");
     let verbosity = cli_utils::parse_verbosity(arguments, true);
 
+    let equation_of_state = arguments.value_of("eos-table").map(|tabparam_file_path| {
+        let tabparam_file_path = exit_on_error!(
+            PathBuf::from_str(tabparam_file_path),
+            "Error: Could not interpret path to tabparam file: {}"
+        );
+        exit_on_error!(
+            TabulatedEquationOfState::from_tabparam_file(
+                tabparam_file_path,
+                metadata.endianness(),
+                &verbosity
+            ),
+            "Error: Could not read tabulated equation of state: {}"
+        )
+    });
+
+    let quantity_definitions = arguments
+        .values_of("define")
+        .map(|values| {
+            values
+                .map(|definition| {
+                    exit_on_error!(
+                        QuantityDefinition::parse(&definition.to_lowercase()),
+                        "Error: Could not parse quantity definition: {}"
+                    )
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    let quantity_definitions = exit_on_error!(
+        QuantityDefinitions::new(quantity_definitions),
+        "Error: Invalid quantity definitions: {}"
+    );
+    for name in quantity_definitions.names() {
+        if provider.has_variable(name) {
+            exit_with_error!(
+                "Error: Defined quantity {} is already present in the snapshot",
+                name
+            );
+        }
+        if !derived_quantity_names.contains(name) {
+            derived_quantity_names.push(name.clone());
+        }
+    }
+
+    let magnetic_diffusivity = match arguments.value_of("magnetic-diffusivity") {
+        Some(value) => {
+            let magnetic_diffusivity = exit_on_error!(
+                value.trim().parse::<fdt>(),
+                "Error: Could not parse value of magnetic-diffusivity: {}"
+            );
+            if magnetic_diffusivity < 0.0 {
+                exit_with_error!("Error: magnetic-diffusivity can not be negative");
+            }
+            Some(MagneticDiffusivity::Uniform(magnetic_diffusivity))
+        }
+        None => MagneticDiffusivity::from_parameters(metadata.parameters()),
+    };
+
     let cached_provider = Box::new(ScalarFieldCacher3::new_automatic_cacher(
         provider,
         max_memory_usage,
@@ -109,23 +257,10 @@ pub fn create_derive_provider(
     DerivedScalarFieldProvider3::new(
         cached_provider,
         derived_quantity_names,
-        &|quantity_name, missing_dependencies| {
-            if let Some(missing_dependencies) = missing_dependencies {
-                eprintln!(
-                    "Warning: Missing following dependencies for derived quantity {}: {}",
-                    quantity_name,
-                    missing_dependencies.join(", ")
-                );
-                if !continue_on_warnings {
-                    cli_utils::verify_user_will_continue_or_abort()
-                }
-            } else {
-                eprintln!("Warning: Derived quantity {} not supported", quantity_name);
-                if !continue_on_warnings {
-                    cli_utils::verify_user_will_continue_or_abort()
-                }
-            }
-        },
+        equation_of_state,
+        quantity_definitions,
+        magnetic_diffusivity,
+        handle_unavailable,
         verbosity,
     )
 }
@@ -280,6 +280,7 @@ fn run_extract_subcommand_with_derive(
         let provider = Box::new(super::derive::create_derive_provider(
             derive_arguments,
             provider,
+            metadata,
         ));
         run_extract_subcommand_with_synthesis(derive_arguments, metadata, provider, io_context);
         return;
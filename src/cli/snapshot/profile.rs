@@ -0,0 +1,528 @@
+//! Command line interface for computing vertical profiles of snapshot quantity fields.
+
+use crate::{
+    exit_with_error,
+    field::DynScalarFieldProvider3,
+    io::{snapshot::fdt, utils::IOContext},
+    update_command_graph,
+};
+use clap::{Arg, ArgMatches, Command};
+
+#[cfg(any(
+    feature = "pickle",
+    feature = "json",
+    feature = "netcdf",
+    feature = "npz"
+))]
+use crate::{
+    cli::utils as cli_utils,
+    exit_on_error,
+    field::{
+        profile::{self, ProfileSet, ProfileStatistic},
+        ResampledCoordLocation, ResamplingMethod, ScalarField3, ScalarFieldProvider3,
+    },
+    geometry::In3D,
+    interpolation::{
+        poly_fit::{PolyFitInterpolator3, PolyFitInterpolatorConfig},
+        InterpGridVerifier3,
+    },
+    io::{snapshot, Verbosity},
+};
+#[cfg(any(
+    feature = "pickle",
+    feature = "json",
+    feature = "netcdf",
+    feature = "npz"
+))]
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+};
+
+#[cfg(all(
+    feature = "derivation",
+    any(
+        feature = "pickle",
+        feature = "json",
+        feature = "netcdf",
+        feature = "npz"
+    )
+))]
+use crate::field::quantities::expression::{self, QuantityDefinition};
+
+/// Builds a representation of the `snapshot-profile` command line subcommand.
+pub fn create_profile_subcommand(_parent_command_name: &'static str) -> Command<'static> {
+    let command_name = "profile";
+
+    update_command_graph!(_parent_command_name, command_name);
+
+    Command::new(command_name)
+        .about("Compute horizontally reduced vertical profiles of quantity fields")
+        .long_about(
+            "Compute horizontally reduced vertical profiles of quantity fields.\n\
+             Each horizontal layer of the quantity field is reduced to a single value\n\
+             using the given statistics. NaN values are ignored, and layers without\n\
+             any included values get the value NaN. Each profile is named\n\
+             <quantity>_<statistic>.",
+        )
+        .arg(
+            Arg::new("output-file")
+                .value_name("OUTPUT_FILE")
+                .help(
+                    "Path where the profiles should be saved\n\
+                     Writes in the following format based on the file extension:\
+                     \n    *.pickle: Creates a Python pickle file (requires the pickle feature)\
+                     \n    *.json: Creates a JSON file (requires the json feature)\
+                     \n    *.nc: Creates a NetCDF file (requires the netcdf feature)\
+                     \n    *.npz: Creates a NumPy .npz file (requires the npz feature)",
+                )
+                .required(true)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("overwrite")
+                .long("overwrite")
+                .help("Automatically overwrite any existing files (unless listed as protected)")
+                .conflicts_with("no-overwrite"),
+        )
+        .arg(
+            Arg::new("no-overwrite")
+                .long("no-overwrite")
+                .help("Do not overwrite any existing files")
+                .conflicts_with("overwrite"),
+        )
+        .arg(
+            Arg::new("quantities")
+                .short('q')
+                .long("quantities")
+                .require_equals(true)
+                .use_value_delimiter(true)
+                .require_value_delimiter(true)
+                .value_name("NAMES")
+                .help("List of quantities to compute profiles for (comma-separated)")
+                .required(true)
+                .takes_value(true)
+                .multiple_values(true),
+        )
+        .arg(
+            Arg::new("statistics")
+                .short('s')
+                .long("statistics")
+                .require_equals(true)
+                .use_value_delimiter(true)
+                .require_value_delimiter(true)
+                .value_name("NAMES")
+                .help(
+                    "List of statistics to reduce the horizontal layers with (comma-separated)\n\
+                     Valid statistics are:\
+                     \n    mean: Arithmetic mean\
+                     \n    rms: Root mean square\
+                     \n    min, max: Minimum and maximum\
+                     \n    median, p<percentile>: Percentile, e.g. p90 for the 90th percentile\
+                     \n    wmean: Mean weighted by the --weight quantity\n",
+                )
+                .takes_value(true)
+                .multiple_values(true)
+                .default_value("mean"),
+        )
+        .arg(
+            Arg::new("weight")
+                .short('w')
+                .long("weight")
+                .require_equals(true)
+                .value_name("NAME")
+                .help("Quantity to use as weight for weighted statistics\n")
+                .takes_value(true)
+                .default_value("r"),
+        )
+        .arg(
+            Arg::new("mask")
+                .long("mask")
+                .require_equals(true)
+                .value_name("EXPRESSION")
+                .help(
+                    "Only include points where the given expression exceeds 0.5, e.g.\n\
+                     --mask='tg<1e5'. Uses the same syntax as quantity definitions in\n\
+                     the derive subcommand (requires the derivation feature). The mask\n\
+                     is interpolated to the locations of each quantity, so points\n\
+                     bordering the masked region may be excluded",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("verbose")
+                .short('v')
+                .long("verbose")
+                .help("Print status messages related to computing profiles"),
+        )
+}
+
+#[cfg(not(any(
+    feature = "pickle",
+    feature = "json",
+    feature = "netcdf",
+    feature = "npz"
+)))]
+pub fn run_profile_subcommand(_: &ArgMatches, _: DynScalarFieldProvider3<fdt>, _: &mut IOContext) {
+    exit_with_error!(
+        "Error: Compile with pickle, json, netcdf or npz feature in order to write profiles\n\
+         Tip: Use cargo flag --features=pickle"
+    );
+}
+
+/// Runs the actions for the `snapshot-profile` subcommand using the given arguments.
+#[cfg(any(
+    feature = "pickle",
+    feature = "json",
+    feature = "netcdf",
+    feature = "npz"
+))]
+pub fn run_profile_subcommand(
+    arguments: &ArgMatches,
+    mut provider: DynScalarFieldProvider3<fdt>,
+    io_context: &mut IOContext,
+) {
+    let parse_names = |argument_name: &str| -> Vec<String> {
+        arguments
+            .values_of(argument_name)
+            .map(|values| values.collect::<Vec<_>>())
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|name| {
+                let name = name.trim().to_lowercase();
+                if name.is_empty() {
+                    None
+                } else {
+                    Some(name)
+                }
+            })
+            .collect()
+    };
+
+    let quantities = parse_names("quantities");
+    if quantities.is_empty() {
+        exit_with_error!("Error: No quantities specified");
+    }
+
+    let statistics: Vec<_> = parse_names("statistics")
+        .iter()
+        .map(|name| exit_on_error!(ProfileStatistic::from_name(name), "Error: {}"))
+        .collect();
+
+    let weight_quantity = arguments
+        .value_of("weight")
+        .expect("No value for argument with default")
+        .to_lowercase();
+
+    let mut output_file_path = exit_on_error!(
+        PathBuf::from_str(
+            arguments
+                .value_of("output-file")
+                .expect("No value for required argument"),
+        ),
+        "Error: Could not interpret path to output file: {}"
+    );
+
+    let output_type = OutputType::from_path(&output_file_path);
+
+    if let Some(snap_num_in_range) = io_context.get_snap_num_in_range() {
+        output_file_path.set_file_name(snapshot::create_new_snapshot_file_name_from_path(
+            &output_file_path,
+            snap_num_in_range.offset(),
+            &output_type.to_string(),
+            true,
+        ));
+    }
+
+    let overwrite_mode = cli_utils::overwrite_mode_from_arguments(arguments);
+    let verbosity = cli_utils::parse_verbosity(arguments, false);
+
+    io_context.set_overwrite_mode(overwrite_mode);
+
+    let atomic_output_file = exit_on_error!(
+        io_context.create_atomic_output_file(output_file_path),
+        "Error: Could not create temporary output file: {}"
+    );
+
+    if !atomic_output_file.check_if_write_allowed(io_context, &verbosity) {
+        return;
+    }
+
+    let mask = arguments
+        .value_of("mask")
+        .map(|mask_expression| compute_mask(&mut *provider, mask_expression, &verbosity));
+
+    let weights = if statistics
+        .iter()
+        .any(|statistic| statistic.requires_weights())
+    {
+        Some(exit_on_error!(
+            provider.provide_scalar_field(&weight_quantity),
+            "Error: Could not read quantity {0} in snapshot: {1}",
+            &weight_quantity
+        ))
+    } else {
+        None
+    };
+
+    let interpolator = PolyFitInterpolator3::new(PolyFitInterpolatorConfig::default());
+
+    let mut profiles = ProfileSet::new();
+    for quantity in &quantities {
+        let field = exit_on_error!(
+            provider.provide_scalar_field(quantity),
+            "Error: Could not read quantity {0} in snapshot: {1}",
+            quantity
+        );
+        let weights = weights.as_ref().map(|weights| {
+            field_at_same_locations(&*provider, weights, &field, &interpolator, &verbosity)
+        });
+        let mask = mask.as_ref().map(|mask| {
+            field_at_same_locations(&*provider, mask, &field, &interpolator, &verbosity)
+        });
+
+        for &statistic in &statistics {
+            if verbosity.print_messages() {
+                println!("Computing {} profile of {}", statistic, quantity);
+            }
+            profiles.push(profile::compute_horizontal_profile(
+                &field,
+                statistic,
+                weights.as_deref(),
+                mask.as_deref(),
+            ));
+        }
+    }
+
+    exit_on_error!(
+        match output_type {
+            #[cfg(feature = "pickle")]
+            OutputType::Pickle => profiles.save_as_pickle(atomic_output_file.temporary_path()),
+            #[cfg(feature = "json")]
+            OutputType::Json => profiles.save_as_json(atomic_output_file.temporary_path()),
+            #[cfg(feature = "netcdf")]
+            OutputType::NetCDF => profiles.save_as_netcdf(atomic_output_file.temporary_path()),
+            #[cfg(feature = "npz")]
+            OutputType::Npz => profiles.save_as_npz(atomic_output_file.temporary_path()),
+        },
+        "Error: Could not save output data: {}"
+    );
+
+    exit_on_error!(
+        io_context.close_atomic_output_file(atomic_output_file),
+        "Error: Could not move temporary output file to target path: {}"
+    );
+}
+
+#[cfg(all(
+    feature = "derivation",
+    any(
+        feature = "pickle",
+        feature = "json",
+        feature = "netcdf",
+        feature = "npz"
+    )
+))]
+fn compute_mask(
+    provider: &mut dyn ScalarFieldProvider3<fdt>,
+    mask_expression: &str,
+    verbosity: &Verbosity,
+) -> Arc<ScalarField3<fdt>> {
+    let definition = exit_on_error!(
+        QuantityDefinition::new("mask".to_string(), &mask_expression.to_lowercase()),
+        "Error: Could not parse mask expression: {}"
+    );
+    Arc::new(exit_on_error!(
+        expression::compute_defined_quantity(provider, &definition, verbosity),
+        "Error: Could not compute mask: {}"
+    ))
+}
+
+#[cfg(all(
+    not(feature = "derivation"),
+    any(
+        feature = "pickle",
+        feature = "json",
+        feature = "netcdf",
+        feature = "npz"
+    )
+))]
+fn compute_mask(
+    _provider: &mut dyn ScalarFieldProvider3<fdt>,
+    _mask_expression: &str,
+    _verbosity: &Verbosity,
+) -> Arc<ScalarField3<fdt>> {
+    exit_with_error!(
+        "Error: Compile with derivation feature in order to use mask expressions\n\
+         Tip: Use cargo flag --features=derivation"
+    );
+}
+
+/// Returns the given field resampled to the locations of the reference field,
+/// if they are not already the same.
+#[cfg(any(
+    feature = "pickle",
+    feature = "json",
+    feature = "netcdf",
+    feature = "npz"
+))]
+fn field_at_same_locations(
+    provider: &dyn ScalarFieldProvider3<fdt>,
+    field: &Arc<ScalarField3<fdt>>,
+    reference_field: &ScalarField3<fdt>,
+    interpolator: &PolyFitInterpolator3,
+    verbosity: &Verbosity,
+) -> Arc<ScalarField3<fdt>> {
+    if field.locations() == reference_field.locations() {
+        Arc::clone(field)
+    } else {
+        exit_on_error!(
+            interpolator.verify_grid(provider.grid()),
+            "Invalid input grid for resampling: {}"
+        );
+        if verbosity.print_messages() {
+            println!(
+                "Resampling {} to locations of {}",
+                field.name(),
+                reference_field.name()
+            );
+        }
+        Arc::new(field.resampled_to_grid(
+            provider.arc_with_grid(),
+            In3D::with_each_component(|dim| {
+                ResampledCoordLocation::Specific(reference_field.locations()[dim])
+            }),
+            interpolator,
+            ResamplingMethod::DirectSampling,
+            verbosity,
+        ))
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+#[cfg(any(
+    feature = "pickle",
+    feature = "json",
+    feature = "netcdf",
+    feature = "npz"
+))]
+enum OutputType {
+    #[cfg(feature = "pickle")]
+    Pickle,
+    #[cfg(feature = "json")]
+    Json,
+    #[cfg(feature = "netcdf")]
+    NetCDF,
+    #[cfg(feature = "npz")]
+    Npz,
+}
+
+#[cfg(any(
+    feature = "pickle",
+    feature = "json",
+    feature = "netcdf",
+    feature = "npz"
+))]
+impl OutputType {
+    fn from_path(file_path: &Path) -> Self {
+        Self::from_extension(
+            file_path
+                .extension()
+                .unwrap_or_else(|| {
+                    exit_with_error!(
+                        "Error: Missing extension for output file\n\
+                         Valid extensions are: {}",
+                        Self::valid_extensions_string()
+                    )
+                })
+                .to_string_lossy()
+                .as_ref(),
+        )
+    }
+
+    fn from_extension(extension: &str) -> Self {
+        match extension {
+            "pickle" => {
+                #[cfg(feature = "pickle")]
+                {
+                    Self::Pickle
+                }
+                #[cfg(not(feature = "pickle"))]
+                exit_with_error!(
+                    "Error: Compile with pickle feature in order to write Pickle files\n\
+                     Tip: Use cargo flag --features=pickle"
+                );
+            }
+            "json" => {
+                #[cfg(feature = "json")]
+                {
+                    Self::Json
+                }
+                #[cfg(not(feature = "json"))]
+                exit_with_error!(
+                    "Error: Compile with json feature in order to write JSON files\n\
+                     Tip: Use cargo flag --features=json"
+                );
+            }
+            "nc" => {
+                #[cfg(feature = "netcdf")]
+                {
+                    Self::NetCDF
+                }
+                #[cfg(not(feature = "netcdf"))]
+                exit_with_error!(
+                    "Error: Compile with netcdf feature in order to write NetCDF files\n\
+                     Tip: Use cargo flag --features=netcdf"
+                );
+            }
+            "npz" => {
+                #[cfg(feature = "npz")]
+                {
+                    Self::Npz
+                }
+                #[cfg(not(feature = "npz"))]
+                exit_with_error!(
+                    "Error: Compile with npz feature in order to write NumPy .npz files\n\
+                     Tip: Use cargo flag --features=npz"
+                );
+            }
+            invalid => exit_with_error!(
+                "Error: Invalid extension {} for output file\n\
+                 Valid extensions are: {}",
+                invalid,
+                Self::valid_extensions_string()
+            ),
+        }
+    }
+
+    fn valid_extensions_string() -> String {
+        "pickle, json, nc, npz".to_string()
+    }
+}
+
+#[cfg(any(
+    feature = "pickle",
+    feature = "json",
+    feature = "netcdf",
+    feature = "npz"
+))]
+impl fmt::Display for OutputType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                #[cfg(feature = "pickle")]
+                Self::Pickle => "pickle",
+                #[cfg(feature = "json")]
+                Self::Json => "json",
+                #[cfg(feature = "netcdf")]
+                Self::NetCDF => "nc",
+                #[cfg(feature = "npz")]
+                Self::Npz => "npz",
+            }
+        )
+    }
+}
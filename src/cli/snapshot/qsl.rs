@@ -0,0 +1,203 @@
+//! Command line interface for computing the squashing factor of the field
+//! line mapping.
+
+use crate::{
+    cli::utils as cli_utils,
+    exit_on_error,
+    field::{DynScalarFieldProvider3, ScalarFieldCacher3, ScalarFieldProvider3},
+    interpolation::{
+        poly_fit::{PolyFitInterpolator3, PolyFitInterpolatorConfig},
+        InterpGridVerifier3,
+    },
+    io::snapshot::fdt,
+    tracing::{
+        qsl::{SquashingFactorConfig, SquashingFactorProvider3},
+        stepping::{
+            rkf::{rkf23::RKF23Stepper3, rkf45::RKF45Stepper3, RKFStepperConfig},
+            DynStepper3,
+        },
+    },
+    update_command_graph,
+};
+use clap::{Arg, ArgMatches, Command};
+
+/// Builds a representation of the `snapshot-qsl` command line subcommand.
+pub fn create_qsl_subcommand(_parent_command_name: &'static str) -> Command<'static> {
+    let command_name = "qsl";
+
+    update_command_graph!(_parent_command_name, command_name);
+
+    Command::new(command_name)
+        .about("Compute the squashing factor Q for locating quasi-separatrix layers")
+        .long_about(
+            "Compute the squashing factor Q for locating quasi-separatrix layers.\n\
+             The following quantities become available for any vector quantity <v>:\n    \
+             q_<v>: Squashing factor Q of the mapping between the footpoints of the\n           \
+             field lines of <v> on the non-periodic boundaries\n    \
+             slogq_<v>: log10(Q) with the sign of the z-component of <v>\n\
+             Q is computed at each grid cell center by tracing the field line through the\n\
+             center and four neighbouring field lines to their footpoints. Values are NaN\n\
+             where the field line does not reach the boundaries at both ends. Only the\n\
+             points required by the subsequent command are computed, so slicing or\n\
+             extracting a subdomain is much cheaper than computing Q for the whole grid.",
+        )
+        .arg(
+            Arg::new("quantities")
+                .short('Q')
+                .long("quantities")
+                .require_equals(true)
+                .use_value_delimiter(true)
+                .require_value_delimiter(true)
+                .value_name("NAMES")
+                .help(
+                    "List of squashing factor quantities to explicitly compute\n\
+                     (comma-separated) [default: none]",
+                )
+                .takes_value(true)
+                .multiple_values(true),
+        )
+        .arg(
+            Arg::new("offset-distance")
+                .long("offset-distance")
+                .require_equals(true)
+                .value_name("VALUE")
+                .help(
+                    "Distance to the neighbouring field lines used for computing Q, as a\n\
+                     fraction of the grid cell extent\n",
+                )
+                .takes_value(true)
+                .default_value("0.1"),
+        )
+        .arg(
+            Arg::new("max-length")
+                .long("max-length")
+                .require_equals(true)
+                .value_name("VALUE")
+                .help(
+                    "Field lines reaching lengths larger than this in either direction are\n\
+                     considered not to reach the boundaries\n",
+                )
+                .takes_value(true)
+                .default_value("inf"),
+        )
+        .arg(
+            Arg::new("stepping-scheme")
+                .long("stepping-scheme")
+                .require_equals(true)
+                .value_name("NAME")
+                .help("Which Runge-Kutta-Fehlberg stepping scheme to use for tracing\n")
+                .takes_value(true)
+                .possible_values(["rkf23", "rkf45"])
+                .default_value("rkf45"),
+        )
+        .arg(
+            Arg::new("interpolation-order")
+                .long("interpolation-order")
+                .require_equals(true)
+                .value_name("NUMBER")
+                .help("Order of the polynomials to fit when interpolating field values\n")
+                .takes_value(true)
+                .possible_values(["1", "2", "3", "4", "5"])
+                .default_value("3"),
+        )
+        .arg(
+            Arg::new("ignore-warnings")
+                .long("ignore-warnings")
+                .help("Automatically continue on warnings"),
+        )
+        .arg(
+            Arg::new("verbose")
+                .short('v')
+                .long("verbose")
+                .help("Print status messages related to computation of squashing factors"),
+        )
+        .arg(Arg::new("progress").short('p').long("progress").help(
+            "Show progress bar for computation of squashing factors (also implies `verbose`)",
+        ))
+}
+
+/// Creates a `SquashingFactorProvider3` for the given arguments and provider.
+pub fn create_qsl_provider(
+    arguments: &ArgMatches,
+    provider: DynScalarFieldProvider3<fdt>,
+) -> SquashingFactorProvider3 {
+    let quantity_names: Vec<_> = arguments
+        .values_of("quantities")
+        .map(|values| values.collect::<Vec<_>>())
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|name| {
+            if name.is_empty() {
+                None
+            } else {
+                Some(name.to_lowercase())
+            }
+        })
+        .collect();
+
+    let offset_distance = cli_utils::get_finite_float_value_from_required_parseable_argument(
+        arguments,
+        "offset-distance",
+    );
+
+    let max_length = match arguments
+        .value_of("max-length")
+        .expect("No value for argument with default")
+    {
+        "inf" => None,
+        length_str => Some(exit_on_error!(
+            length_str.trim().parse::<f64>(),
+            "Error: Could not parse value of max-length: {}"
+        )),
+    };
+
+    let stepper: DynStepper3<fdt> = match arguments
+        .value_of("stepping-scheme")
+        .expect("No value for argument with default")
+    {
+        "rkf23" => Box::new(RKF23Stepper3::new(RKFStepperConfig::default())),
+        _ => Box::new(RKF45Stepper3::new(RKFStepperConfig::default())),
+    };
+
+    let interpolator = Box::new(PolyFitInterpolator3::new(PolyFitInterpolatorConfig {
+        order: cli_utils::get_value_from_required_parseable_argument(
+            arguments,
+            "interpolation-order",
+        ),
+        ..PolyFitInterpolatorConfig::default()
+    }));
+
+    exit_on_error!(
+        interpolator.verify_grid(provider.grid()),
+        "Invalid input grid for computing squashing factors: {}"
+    );
+
+    let continue_on_warnings = arguments.is_present("ignore-warnings");
+    let verbosity = cli_utils::parse_verbosity(arguments, true);
+
+    let provider = SquashingFactorProvider3::new(
+        Box::new(ScalarFieldCacher3::new_manual_cacher(
+            provider,
+            verbosity.clone(),
+        )),
+        interpolator,
+        stepper,
+        SquashingFactorConfig {
+            offset_distance,
+            max_length,
+        },
+        quantity_names.clone(),
+        verbosity,
+    );
+
+    for name in &quantity_names {
+        if !provider.has_variable(name) {
+            eprintln!("Warning: Squashing factor quantity {} not available", name);
+            if !continue_on_warnings {
+                cli_utils::verify_user_will_continue_or_abort()
+            }
+        }
+    }
+
+    provider
+}
@@ -609,6 +609,7 @@ fn run_snapshot_resampling_with_derive(
         let provider = Box::new(super::derive::create_derive_provider(
             derive_arguments,
             provider,
+            metadata,
         ));
         run_snapshot_resampling_with_synthesis(derive_arguments, metadata, provider, io_context);
         return;
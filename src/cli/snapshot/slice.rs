@@ -1,6 +1,11 @@
 //! Command line interface for extracting slices of snapshot quantity fields.
 
+#[cfg(feature = "tracing")]
+use crate::cli::tracing::plane::{
+    create_plane_trace_subcommand as create_trace_subcommand, run_plane_trace_subcommand,
+};
 use crate::{
+    add_subcommand_combinations,
     cli::{
         interpolation::poly_fit::{
             construct_poly_fit_interpolator_config_from_options,
@@ -9,24 +14,30 @@ use crate::{
         utils as cli_utils,
     },
     exit_on_error, exit_with_error,
-    field::{DynScalarFieldProvider3, ResampledCoordLocation},
-    geometry::Dim3,
-    grid::{fgr, CoordLocation},
+    field::{
+        DynScalarFieldProvider3, ResampledCoordLocation, ScalarField2, ScalarField3,
+        ScalarFieldProvider3,
+    },
+    geometry::{Dim3, Idx3},
+    grid::{self, fgr, CoordLocation, Grid3},
     interpolation::{
         poly_fit::{PolyFitInterpolator3, PolyFitInterpolatorConfig},
         InterpGridVerifier3,
     },
     io::{
-        snapshot::{self, fdt},
+        fits,
+        snapshot::{self, fdt, SnapshotMetadata},
         utils::IOContext,
+        vtk,
     },
     update_command_graph,
 };
 use clap::{Arg, ArgMatches, Command};
 use std::{
-    fmt,
+    fmt, io,
     path::{Path, PathBuf},
     str::FromStr,
+    sync::Arc,
 };
 
 /// Builds a representation of the `snapshot-slice` command line subcommand.
@@ -35,12 +46,15 @@ pub fn create_slice_subcommand(_parent_command_name: &'static str) -> Command<'s
 
     update_command_graph!(_parent_command_name, command_name);
 
-    Command::new(command_name)
+    let command = Command::new(command_name)
         .about("Extract a 2D slice of a quantity field in the snapshot")
         .long_about(
             "Extract a 2D slice of a quantity field in the snapshot.\n\
              Any quantity field in the snapshot can be sliced across any axis at a given\n\
-             coordinate.",
+             coordinate. If the grid has a single cell along the axis, as for 2D-invariant\n\
+             (2.5D) snapshots, the values in that layer of cells are used directly.\n\
+             With the `trace` subcommand, field lines of the in-plane components of a vector\n\
+             field are also traced in the same slice and saved to a separate file.",
         )
         .after_help(
             "You can use a subcommand to configure the interpolator. If left unspecified,\n\
@@ -52,7 +66,10 @@ pub fn create_slice_subcommand(_parent_command_name: &'static str) -> Command<'s
                 .help(
                     "Path where the slice field should be saved\n\
                      Writes in the following format based on the file extension:\
-                     \n    *.pickle: Creates a Python pickle file (requires the pickle feature)",
+                     \n    *.pickle: Creates a Python pickle file (requires the pickle feature)\
+                     \n    *.npz: Creates a NumPy .npz file (requires the npz feature)\
+                     \n    *.fits: Creates a FITS image file\
+                     \n    *.vtr: Creates a VTK XML rectilinear grid file",
                 )
                 .required(true)
                 .takes_value(true),
@@ -97,8 +114,10 @@ pub fn create_slice_subcommand(_parent_command_name: &'static str) -> Command<'s
                 .require_equals(true)
                 .value_name("VALUE")
                 .allow_hyphen_values(true)
-                .help("Coordinate along the axis to slice at")
-                .required(true)
+                .help(
+                    "Coordinate along the axis to slice at\n\
+                     (not required if the grid has a single cell along the axis)",
+                )
                 .takes_value(true),
         )
         .arg(
@@ -125,22 +144,15 @@ pub fn create_slice_subcommand(_parent_command_name: &'static str) -> Command<'s
                 .short('v')
                 .long("verbose")
                 .help("Print status messages related to slicing"),
-        )
-        .subcommand(create_poly_fit_interpolator_subcommand(command_name))
-}
+        );
 
-#[cfg(not(feature = "pickle"))]
-pub fn run_slice_subcommand(_: &ArgMatches, _: DynScalarFieldProvider3<fdt>, _: &mut IOContext) {
-    exit_with_error!(
-        "Error: Compile with pickle feature in order to write Pickle files\n\
-         Tip: Use cargo flag --features=pickle"
-    );
+    add_subcommand_combinations!(command, command_name, false; poly_fit_interpolator, trace if "tracing")
 }
 
 /// Runs the actions for the `snapshot-slice` subcommand using the given arguments.
-#[cfg(feature = "pickle")]
 pub fn run_slice_subcommand(
     arguments: &ArgMatches,
+    metadata: &dyn SnapshotMetadata,
     mut provider: DynScalarFieldProvider3<fdt>,
     io_context: &mut IOContext,
 ) {
@@ -149,13 +161,27 @@ pub fn run_slice_subcommand(
         .expect("No value for required argument")
         .to_lowercase();
 
-    let axis = arguments
+    let axis = match arguments
         .value_of("axis")
-        .expect("No value for required argument");
+        .expect("No value for required argument")
+    {
+        "x" => Dim3::X,
+        "y" => Dim3::Y,
+        "z" => Dim3::Z,
+        invalid => exit_with_error!("Error: Invalid axis: {}", invalid),
+    };
 
-    let coord = cli_utils::get_finite_float_value_from_required_parseable_argument::<fgr>(
-        arguments, "coord",
-    );
+    let is_layer = provider.grid().shape()[axis] == 1;
+
+    let coord = if is_layer {
+        provider.grid().centers()[axis][0]
+    } else if arguments.is_present("coord") {
+        cli_utils::get_finite_float_value_from_required_parseable_argument::<fgr>(
+            arguments, "coord",
+        )
+    } else {
+        exit_with_error!("Error: No coordinate specified for the slice")
+    };
 
     let mut output_file_path = exit_on_error!(
         PathBuf::from_str(
@@ -202,89 +228,200 @@ pub fn run_slice_subcommand(
     } else {
         PolyFitInterpolatorConfig::default()
     };
+    let interpolator_order = interpolator_config.order;
     let interpolator = PolyFitInterpolator3::new(interpolator_config);
 
-    exit_on_error!(
-        interpolator.verify_grid(provider.grid()),
-        "Invalid input grid for slicing: {}"
-    );
+    let sampling = if is_layer {
+        if verbosity.print_messages() {
+            println!(
+                "Grid has a single cell along the {} axis, using the layer directly",
+                axis
+            );
+        }
+        SliceSampling::Layer
+    } else {
+        exit_on_error!(
+            interpolator.verify_grid(provider.grid()),
+            "Invalid input grid for slicing: {}"
+        );
+        if arguments.is_present("allow-non-uniform") {
+            SliceSampling::NonUniform(match sample_location {
+                "center" => ResampledCoordLocation::center(),
+                "lower" => ResampledCoordLocation::lower_edge(),
+                "original" => ResampledCoordLocation::Original,
+                invalid => exit_with_error!("Error: Invalid sample-location: {}", invalid),
+            })
+        } else {
+            SliceSampling::Regular(match sample_location {
+                "center" => CoordLocation::Center,
+                "lower" => CoordLocation::LowerEdge,
+                "original" => exit_with_error!(
+                    "Error: Invalid sample-location: original only available with --allow-non-uniform"
+                ),
+                invalid => exit_with_error!("Error: Invalid sample-location: {}", invalid),
+            })
+        }
+    };
 
     let field = exit_on_error!(
-        provider.provide_scalar_field(&quantity),
+        if is_layer {
+            provider.provide_scalar_field(&quantity)
+        } else {
+            provide_field_around_slice(&mut *provider, &quantity, axis, coord, interpolator_order)
+        },
         "Error: Could not read quantity {0} in snapshot: {1}",
         &quantity
     );
 
-    if arguments.is_present("allow-non-uniform") {
-        let resampled_coord_locations = match sample_location {
-            "center" => ResampledCoordLocation::center(),
-            "lower" => ResampledCoordLocation::lower_edge(),
-            "original" => ResampledCoordLocation::Original,
-            invalid => exit_with_error!("Error: Invalid sample-location: {}", invalid),
-        };
+    let slice_field = match sampling {
+        SliceSampling::Layer => field.layer_across_axis(axis, 0),
+        SliceSampling::NonUniform(resampled_coord_locations) => match axis {
+            Dim3::X => field.slice_across_x(&interpolator, coord, resampled_coord_locations),
+            Dim3::Y => field.slice_across_y(&interpolator, coord, resampled_coord_locations),
+            Dim3::Z => field.slice_across_z(&interpolator, coord, resampled_coord_locations),
+        },
+        SliceSampling::Regular(location) => {
+            field.regular_slice_across_axis(&interpolator, axis, coord, location)
+        }
+    };
+    drop(field);
 
-        exit_on_error!(
-            match axis {
-                "x" => {
-                    field
-                        .slice_across_x(&interpolator, coord, resampled_coord_locations)
-                        .save_as_pickle(atomic_output_file.temporary_path())
+    exit_on_error!(
+        save_slice_field(
+            &slice_field,
+            output_type,
+            atomic_output_file.temporary_path(),
+            axis,
+            coord,
+            metadata
+        ),
+        "Error: Could not save output data: {}"
+    );
+
+    exit_on_error!(
+        io_context.close_atomic_output_file(atomic_output_file),
+        "Error: Could not move temporary output file to target path: {}"
+    );
+
+    #[cfg(feature = "tracing")]
+    if let Some(trace_arguments) = arguments.subcommand_matches("trace").or_else(|| {
+        arguments
+            .subcommand_matches("poly_fit_interpolator")
+            .and_then(|interpolator_arguments| interpolator_arguments.subcommand_matches("trace"))
+    }) {
+        let vector_quantity = trace_arguments
+            .value_of("vector-quantity")
+            .expect("No value for argument with default")
+            .to_lowercase();
+
+        let vector_field = exit_on_error!(
+            provider.provide_vector_field(&vector_quantity),
+            "Error: Could not read quantity {0} in snapshot: {1}",
+            &vector_quantity
+        );
+
+        let plane_vector_field = match sampling {
+            SliceSampling::Layer => vector_field.layer_across_axis(axis, 0),
+            SliceSampling::NonUniform(resampled_coord_locations) => match axis {
+                Dim3::X => {
+                    vector_field.slice_across_x(&interpolator, coord, resampled_coord_locations)
                 }
-                "y" => {
-                    field
-                        .slice_across_y(&interpolator, coord, resampled_coord_locations)
-                        .save_as_pickle(atomic_output_file.temporary_path())
+                Dim3::Y => {
+                    vector_field.slice_across_y(&interpolator, coord, resampled_coord_locations)
                 }
-                "z" => {
-                    field
-                        .slice_across_z(&interpolator, coord, resampled_coord_locations)
-                        .save_as_pickle(atomic_output_file.temporary_path())
+                Dim3::Z => {
+                    vector_field.slice_across_z(&interpolator, coord, resampled_coord_locations)
                 }
-                invalid => exit_with_error!("Error: Invalid axis: {}", invalid),
             },
-            "Error: Could not save output data: {}"
-        );
-    } else {
-        let location = match sample_location {
-            "center" => CoordLocation::Center,
-            "lower" => CoordLocation::LowerEdge,
-            "original" => exit_with_error!(
-                "Error: Invalid sample-location: original only available with --allow-non-uniform"
-            ),
-            invalid => exit_with_error!("Error: Invalid sample-location: {}", invalid),
-        };
-
-        let axis = match axis {
-            "x" => Dim3::X,
-            "y" => Dim3::Y,
-            "z" => Dim3::Z,
-            invalid => exit_with_error!("Error: Invalid axis: {}", invalid),
+            SliceSampling::Regular(location) => {
+                vector_field.regular_slice_across_axis(&interpolator, axis, coord, location)
+            }
         };
+        drop(vector_field);
 
-        exit_on_error!(
-            match output_type {
-                OutputType::Pickle => field
-                    .regular_slice_across_axis(&interpolator, axis, coord, location)
-                    .save_as_pickle(atomic_output_file.temporary_path()),
-            },
-            "Error: Could not save output data: {}"
+        run_plane_trace_subcommand(
+            trace_arguments,
+            provider.grid(),
+            &plane_vector_field,
+            axis,
+            coord,
+            io_context,
         );
     }
+}
 
-    exit_on_error!(
-        io_context.close_atomic_output_file(atomic_output_file),
-        "Error: Could not move temporary output file to target path: {}"
-    );
+/// How the values of the slice are obtained from the 3D field.
+#[derive(Copy, Clone, Debug)]
+enum SliceSampling {
+    /// The grid has a single layer of cells along the slice axis, which is used directly.
+    Layer,
+    /// Values are interpolated onto the potentially non-uniform slice grid.
+    NonUniform(ResampledCoordLocation),
+    /// Values are interpolated onto a regular slice grid.
+    Regular(CoordLocation),
+}
+
+fn save_slice_field(
+    field: &ScalarField2<fdt>,
+    output_type: OutputType,
+    output_file_path: &Path,
+    axis: Dim3,
+    coord: fgr,
+    metadata: &dyn SnapshotMetadata,
+) -> io::Result<()> {
+    match output_type {
+        #[cfg(feature = "pickle")]
+        OutputType::Pickle => field.save_as_pickle(output_file_path),
+        #[cfg(feature = "npz")]
+        OutputType::Npz => field.save_as_npz(output_file_path),
+        OutputType::Fits => fits::write_slice(output_file_path, field, axis, coord, metadata),
+        OutputType::Vtk => vtk::write_slice(output_file_path, field, axis, coord),
+    }
+}
+
+/// Provides the part of the field required for slicing across the given axis at the
+/// given coordinate.
+///
+/// Only a slab of cells around the coordinate, wide enough to contain the points
+/// used by the interpolator, is produced. The full field is provided when the slab
+/// would reach the boundaries of the grid, so that boundary handling is unaffected.
+fn provide_field_around_slice(
+    provider: &mut dyn ScalarFieldProvider3<fdt>,
+    quantity: &str,
+    axis: Dim3,
+    coord: fgr,
+    interpolator_order: usize,
+) -> io::Result<Arc<ScalarField3<fdt>>> {
+    let grid = provider.grid();
+    let shape = grid.shape();
+    let margin = interpolator_order + 2;
+
+    match grid::search_idx_of_coord(&grid.lower_edges()[axis], coord) {
+        Some(idx) if idx >= margin && idx + margin < shape[axis] => {
+            let mut lower_indices = Idx3::origin();
+            let mut upper_indices = Idx3::with_each_component(|dim| shape[dim] - 1);
+            lower_indices[axis] = idx - margin;
+            upper_indices[axis] = idx + margin;
+
+            let subgrid = Arc::new(grid.subgrid(&lower_indices, &upper_indices));
+            provider
+                .produce_scalar_subfield(quantity, subgrid, &lower_indices)
+                .map(Arc::new)
+        }
+        _ => provider.provide_scalar_field(quantity),
+    }
 }
 
 #[derive(Copy, Clone, Debug)]
-#[cfg(feature = "pickle")]
 enum OutputType {
     #[cfg(feature = "pickle")]
     Pickle,
+    #[cfg(feature = "npz")]
+    Npz,
+    Fits,
+    Vtk,
 }
 
-#[cfg(feature = "pickle")]
 impl OutputType {
     fn from_path(file_path: &Path) -> Self {
         Self::from_extension(
@@ -315,6 +452,19 @@ impl OutputType {
                      Tip: Use cargo flag --features=pickle"
                 );
             }
+            "npz" => {
+                #[cfg(feature = "npz")]
+                {
+                    Self::Npz
+                }
+                #[cfg(not(feature = "npz"))]
+                exit_with_error!(
+                    "Error: Compile with npz feature in order to write NumPy .npz files\n\
+                     Tip: Use cargo flag --features=npz"
+                );
+            }
+            "fits" => Self::Fits,
+            "vtr" => Self::Vtk,
             invalid => exit_with_error!(
                 "Error: Invalid extension {} for output file\n\
                  Valid extensions are: {}",
@@ -325,11 +475,10 @@ impl OutputType {
     }
 
     fn valid_extensions_string() -> String {
-        "pickle".to_string()
+        "pickle, npz, fits, vtr".to_string()
     }
 }
 
-#[cfg(feature = "pickle")]
 impl fmt::Display for OutputType {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
@@ -338,6 +487,10 @@ impl fmt::Display for OutputType {
             match self {
                 #[cfg(feature = "pickle")]
                 Self::Pickle => "pickle",
+                #[cfg(feature = "npz")]
+                Self::Npz => "npz",
+                Self::Fits => "fits",
+                Self::Vtk => "vtr",
             }
         )
     }
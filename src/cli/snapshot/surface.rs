@@ -0,0 +1,370 @@
+//! Command line interface for extracting iso-value surfaces of snapshot quantity fields.
+
+use crate::{
+    exit_with_error,
+    field::DynScalarFieldProvider3,
+    io::{snapshot::fdt, utils::IOContext},
+    update_command_graph,
+};
+use clap::{Arg, ArgMatches, Command};
+
+#[cfg(feature = "pickle")]
+use crate::{
+    cli::utils as cli_utils,
+    exit_on_error,
+    field::{ResampledCoordLocation, ResamplingMethod},
+    geometry::{Dim3, In3D},
+    interpolation::{
+        poly_fit::{PolyFitInterpolator3, PolyFitInterpolatorConfig},
+        InterpGridVerifier3,
+    },
+    io::snapshot,
+};
+#[cfg(feature = "pickle")]
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+/// Builds a representation of the `snapshot-surface` command line subcommand.
+pub fn create_surface_subcommand(_parent_command_name: &'static str) -> Command<'static> {
+    let command_name = "surface";
+
+    update_command_graph!(_parent_command_name, command_name);
+
+    Command::new(command_name)
+        .about("Extract the 2D surface where a quantity field reaches a given value")
+        .long_about(
+            "Extract the 2D surface where a quantity field reaches a given value.\n\
+             Each column of the field along the given axis is searched from the lower end\n\
+             of the axis (the top of the domain for z), and the coordinate of the first\n\
+             crossing of the value is found by linear interpolation. This is intended for\n\
+             quantities that vary monotonically along the axis, like optical depth or\n\
+             column depth. Columns where the value is never reached are assigned NaN.\n\
+             Other quantities can be sampled at the surface, e.g. to get maps of\n\
+             quantities at optical depth unity.",
+        )
+        .arg(
+            Arg::new("output-file")
+                .value_name("OUTPUT_FILE")
+                .help(
+                    "Path where the field of surface coordinates should be saved\n\
+                     Writes in the following format based on the file extension:\
+                     \n    *.pickle: Creates a Python pickle file (requires the pickle feature)",
+                )
+                .required(true)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("overwrite")
+                .long("overwrite")
+                .help("Automatically overwrite any existing files (unless listed as protected)")
+                .conflicts_with("no-overwrite"),
+        )
+        .arg(
+            Arg::new("no-overwrite")
+                .long("no-overwrite")
+                .help("Do not overwrite any existing files")
+                .conflicts_with("overwrite"),
+        )
+        .arg(
+            Arg::new("quantity")
+                .short('q')
+                .long("quantity")
+                .require_equals(true)
+                .value_name("NAME")
+                .help("Quantity whose iso-value surface to extract (e.g. tau500)")
+                .required(true)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("value")
+                .long("value")
+                .require_equals(true)
+                .value_name("VALUE")
+                .allow_hyphen_values(true)
+                .help("Value of the quantity defining the surface")
+                .takes_value(true)
+                .default_value("1"),
+        )
+        .arg(
+            Arg::new("axis")
+                .short('a')
+                .long("axis")
+                .require_equals(true)
+                .value_name("AXIS")
+                .help("Axis along which to search for the surface")
+                .takes_value(true)
+                .possible_values(["x", "y", "z"])
+                .default_value("z"),
+        )
+        .arg(
+            Arg::new("sampled-quantities")
+                .short('s')
+                .long("sampled-quantities")
+                .require_equals(true)
+                .use_value_delimiter(true)
+                .require_value_delimiter(true)
+                .value_name("NAMES")
+                .help(
+                    "List of quantities to sample at the surface (comma-separated)\n\
+                     Each sampled field is saved next to OUTPUT_FILE, with _<name>\n\
+                     appended to the file name [default: none]",
+                )
+                .takes_value(true)
+                .multiple_values(true),
+        )
+        .arg(
+            Arg::new("verbose")
+                .short('v')
+                .long("verbose")
+                .help("Print status messages related to surface extraction"),
+        )
+}
+
+#[cfg(not(feature = "pickle"))]
+pub fn run_surface_subcommand(_: &ArgMatches, _: DynScalarFieldProvider3<fdt>, _: &mut IOContext) {
+    exit_with_error!(
+        "Error: Compile with pickle feature in order to write Pickle files\n\
+         Tip: Use cargo flag --features=pickle"
+    );
+}
+
+/// Runs the actions for the `snapshot-surface` subcommand using the given arguments.
+#[cfg(feature = "pickle")]
+pub fn run_surface_subcommand(
+    arguments: &ArgMatches,
+    mut provider: DynScalarFieldProvider3<fdt>,
+    io_context: &mut IOContext,
+) {
+    let quantity = arguments
+        .value_of("quantity")
+        .expect("No value for required argument")
+        .to_lowercase();
+
+    let iso_value = cli_utils::get_finite_float_value_from_required_parseable_argument::<fdt>(
+        arguments, "value",
+    );
+
+    let axis = match arguments
+        .value_of("axis")
+        .expect("No value for argument with default")
+    {
+        "x" => Dim3::X,
+        "y" => Dim3::Y,
+        "z" => Dim3::Z,
+        invalid => exit_with_error!("Error: Invalid axis: {}", invalid),
+    };
+
+    let sampled_quantities: Vec<_> = arguments
+        .values_of("sampled-quantities")
+        .map(|values| values.collect::<Vec<_>>())
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|name| {
+            let name = name.trim().to_lowercase();
+            if name.is_empty() {
+                None
+            } else {
+                Some(name)
+            }
+        })
+        .collect();
+
+    let mut output_file_path = exit_on_error!(
+        PathBuf::from_str(
+            arguments
+                .value_of("output-file")
+                .expect("No value for required argument"),
+        ),
+        "Error: Could not interpret path to output file: {}"
+    );
+
+    let output_type = OutputType::from_path(&output_file_path);
+
+    if let Some(snap_num_in_range) = io_context.get_snap_num_in_range() {
+        output_file_path.set_file_name(snapshot::create_new_snapshot_file_name_from_path(
+            &output_file_path,
+            snap_num_in_range.offset(),
+            &output_type.to_string(),
+            true,
+        ));
+    }
+
+    let overwrite_mode = cli_utils::overwrite_mode_from_arguments(arguments);
+    let verbosity = cli_utils::parse_verbosity(arguments, false);
+
+    io_context.set_overwrite_mode(overwrite_mode);
+
+    let field = exit_on_error!(
+        provider.provide_scalar_field(&quantity),
+        "Error: Could not read quantity {0} in snapshot: {1}",
+        &quantity
+    );
+
+    if verbosity.print_messages() {
+        println!(
+            "Finding surface where {} = {} along {}",
+            &quantity, iso_value, axis
+        );
+    }
+    let surface_coords = field.find_iso_surface_coords(axis, iso_value);
+
+    let atomic_output_file = exit_on_error!(
+        io_context.create_atomic_output_file(output_file_path.clone()),
+        "Error: Could not create temporary output file: {}"
+    );
+    if atomic_output_file.check_if_write_allowed(io_context, &verbosity) {
+        exit_on_error!(
+            match output_type {
+                OutputType::Pickle =>
+                    surface_coords.save_as_pickle(atomic_output_file.temporary_path()),
+            },
+            "Error: Could not save output data: {}"
+        );
+        exit_on_error!(
+            io_context.close_atomic_output_file(atomic_output_file),
+            "Error: Could not move temporary output file to target path: {}"
+        );
+    }
+
+    if sampled_quantities.is_empty() {
+        return;
+    }
+
+    let interpolator = PolyFitInterpolator3::new(PolyFitInterpolatorConfig::default());
+    exit_on_error!(
+        interpolator.verify_grid(provider.grid()),
+        "Invalid input grid for sampling: {}"
+    );
+
+    let surface_locations =
+        In3D::with_each_component(|dim| ResampledCoordLocation::Specific(field.locations()[dim]));
+
+    for sampled_quantity in sampled_quantities {
+        let sampled_output_file_path =
+            create_sampled_output_file_path(&output_file_path, &sampled_quantity);
+
+        let atomic_output_file = exit_on_error!(
+            io_context.create_atomic_output_file(sampled_output_file_path),
+            "Error: Could not create temporary output file: {}"
+        );
+        if !atomic_output_file.check_if_write_allowed(io_context, &verbosity) {
+            continue;
+        }
+
+        let mut sampled_field = exit_on_error!(
+            provider.provide_scalar_field(&sampled_quantity),
+            "Error: Could not read quantity {0} in snapshot: {1}",
+            &sampled_quantity
+        );
+
+        if Dim3::slice_except(axis)
+            .iter()
+            .any(|&dim| sampled_field.locations()[dim] != field.locations()[dim])
+        {
+            if verbosity.print_messages() {
+                println!(
+                    "Resampling {} to locations of {}",
+                    &sampled_quantity, &quantity
+                );
+            }
+            sampled_field = sampled_field
+                .resampled_to_grid(
+                    provider.arc_with_grid(),
+                    surface_locations.clone(),
+                    &interpolator,
+                    ResamplingMethod::DirectSampling,
+                    &verbosity,
+                )
+                .into();
+        }
+
+        if verbosity.print_messages() {
+            println!("Sampling {} at surface", &sampled_quantity);
+        }
+        exit_on_error!(
+            match output_type {
+                OutputType::Pickle => sampled_field
+                    .sample_at_surface(axis, &surface_coords)
+                    .save_as_pickle(atomic_output_file.temporary_path()),
+            },
+            "Error: Could not save output data: {}"
+        );
+        exit_on_error!(
+            io_context.close_atomic_output_file(atomic_output_file),
+            "Error: Could not move temporary output file to target path: {}"
+        );
+    }
+}
+
+#[cfg(feature = "pickle")]
+fn create_sampled_output_file_path(output_file_path: &Path, sampled_quantity: &str) -> PathBuf {
+    let mut file_name = output_file_path
+        .file_stem()
+        .expect("Output file has no file name")
+        .to_os_string();
+    file_name.push(format!("_{}", sampled_quantity));
+    if let Some(extension) = output_file_path.extension() {
+        file_name.push(".");
+        file_name.push(extension);
+    }
+    output_file_path.with_file_name(file_name)
+}
+
+#[derive(Copy, Clone, Debug)]
+#[cfg(feature = "pickle")]
+enum OutputType {
+    #[cfg(feature = "pickle")]
+    Pickle,
+}
+
+#[cfg(feature = "pickle")]
+impl OutputType {
+    fn from_path(file_path: &Path) -> Self {
+        Self::from_extension(
+            file_path
+                .extension()
+                .unwrap_or_else(|| {
+                    exit_with_error!(
+                        "Error: Missing extension for output file\n\
+                         Valid extensions are: {}",
+                        Self::valid_extensions_string()
+                    )
+                })
+                .to_string_lossy()
+                .as_ref(),
+        )
+    }
+
+    fn from_extension(extension: &str) -> Self {
+        match extension {
+            "pickle" => Self::Pickle,
+            invalid => exit_with_error!(
+                "Error: Invalid extension {} for output file\n\
+                 Valid extensions are: {}",
+                invalid,
+                Self::valid_extensions_string()
+            ),
+        }
+    }
+
+    fn valid_extensions_string() -> String {
+        "pickle".to_string()
+    }
+}
+
+#[cfg(feature = "pickle")]
+impl fmt::Display for OutputType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                #[cfg(feature = "pickle")]
+                Self::Pickle => "pickle",
+            }
+        )
+    }
+}
@@ -0,0 +1,159 @@
+//! Command line interface for computing time derivatives and time averages
+//! over a series of snapshots.
+
+use crate::{
+    cli::utils as cli_utils,
+    exit_on_error, exit_on_false,
+    field::{
+        temporal::{SharedTimeIntegralCache, SnapshotOpener, TemporalScalarFieldProvider3},
+        DynScalarFieldProvider3, ScalarFieldProvider3,
+    },
+    io::{
+        snapshot::{fdt, utils as snapshot_utils},
+        Endianness, Verbosity,
+    },
+    update_command_graph,
+};
+use clap::{Arg, ArgMatches, Command};
+use std::{path::PathBuf, sync::Arc};
+
+/// Builds a representation of the `snapshot-temporal` command line subcommand.
+pub fn create_temporal_subcommand(_parent_command_name: &'static str) -> Command<'static> {
+    let command_name = "temporal";
+
+    update_command_graph!(_parent_command_name, command_name);
+
+    Command::new(command_name)
+        .about("Compute time derivatives and time averages over the snapshot range")
+        .long_about(
+            "Compute time derivatives and time averages over the snapshot range.\n\
+             The following quantities become available for any quantity <q>:\n    \
+             ddt_<q>: Time derivative of <q>, using centered differences between\n             \
+             neighbouring snapshots [Bifrost units per Bifrost time unit]\n    \
+             tavg_<q>: Average of <q> over the whole snapshot range\n    \
+             ravg_<q>: Running average of <q> from the first to the current snapshot\n\
+             The snapshot times are taken from the t parameter, and averages are\n\
+             computed with the trapezoidal rule. Neighbouring snapshots are read only\n\
+             when required, so only a few fields are held in memory at any time.",
+        )
+        .arg(
+            Arg::new("quantities")
+                .short('Q')
+                .long("quantities")
+                .require_equals(true)
+                .use_value_delimiter(true)
+                .require_value_delimiter(true)
+                .value_name("NAMES")
+                .help(
+                    "List of temporal quantities to explicitly compute\n\
+                     (comma-separated) [default: none]",
+                )
+                .takes_value(true)
+                .multiple_values(true),
+        )
+        .arg(
+            Arg::new("ignore-warnings")
+                .long("ignore-warnings")
+                .help("Automatically continue on warnings"),
+        )
+        .arg(
+            Arg::new("verbose")
+                .short('v')
+                .long("verbose")
+                .help("Print status messages related to computation of temporal quantities"),
+        )
+}
+
+/// Snapshots in a time series, with a cache of time integrated fields
+/// shared between the snapshots.
+pub struct SnapshotSeries {
+    times: Arc<Vec<f64>>,
+    open_snapshot: SnapshotOpener,
+    cache: SharedTimeIntegralCache,
+}
+
+impl SnapshotSeries {
+    /// Creates a series from the snapshots at the given paths, reading the
+    /// time of each snapshot from its parameters. The given function is used
+    /// for opening the snapshots when data from them is required.
+    pub fn new(
+        snapshot_paths: &[PathBuf],
+        endianness: Endianness,
+        open_snapshot: SnapshotOpener,
+    ) -> Self {
+        let times: Vec<_> = snapshot_paths
+            .iter()
+            .map(|path| {
+                let (_, metadata) = exit_on_error!(
+                    snapshot_utils::new_snapshot_reader(path.clone(), endianness, Verbosity::Quiet),
+                    "Error: {}"
+                );
+                exit_on_error!(
+                    metadata.parameters().get_as_float("t"),
+                    "Error: Could not read time of snapshot {1}: {0}",
+                    path.display()
+                )
+            })
+            .collect();
+        exit_on_false!(
+            times.windows(2).all(|pair| pair[1] > pair[0]),
+            "Error: Snapshot times must be strictly increasing over the snapshot range"
+        );
+        Self {
+            times: Arc::new(times),
+            open_snapshot,
+            cache: SharedTimeIntegralCache::default(),
+        }
+    }
+}
+
+/// Creates a `TemporalScalarFieldProvider3` for the given arguments and
+/// provider of the snapshot with the given index in the series.
+pub fn create_temporal_provider(
+    arguments: &ArgMatches,
+    provider: DynScalarFieldProvider3<fdt>,
+    series: &SnapshotSeries,
+    snapshot_idx: usize,
+) -> TemporalScalarFieldProvider3 {
+    let quantity_names: Vec<_> = arguments
+        .values_of("quantities")
+        .map(|values| values.collect::<Vec<_>>())
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|name| {
+            if name.is_empty() {
+                None
+            } else {
+                Some(name.to_lowercase())
+            }
+        })
+        .collect();
+
+    let continue_on_warnings = arguments.is_present("ignore-warnings");
+    let verbosity = cli_utils::parse_verbosity(arguments, false);
+
+    let provider = TemporalScalarFieldProvider3::new(
+        provider,
+        snapshot_idx,
+        Arc::clone(&series.times),
+        Arc::clone(&series.open_snapshot),
+        Arc::clone(&series.cache),
+        quantity_names.clone(),
+        verbosity,
+    );
+
+    // Only warn for the first snapshot, since the same quantities are
+    // available for all snapshots in the series
+    if snapshot_idx == 0 {
+        for name in &quantity_names {
+            if !provider.has_variable(name) {
+                eprintln!("Warning: Temporal quantity {} not available", name);
+                if !continue_on_warnings {
+                    cli_utils::verify_user_will_continue_or_abort()
+                }
+            }
+        }
+    }
+
+    provider
+}
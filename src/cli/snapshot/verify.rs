@@ -0,0 +1,85 @@
+//! Command line interface for verifying the integrity of snapshot files.
+
+use crate::{
+    exit_with_error,
+    io::{
+        snapshot::{native, utils::SnapshotInputType},
+        Endianness,
+    },
+    update_command_graph,
+};
+use clap::{Arg, ArgMatches, Command};
+use std::path::PathBuf;
+
+/// Builds a representation of the `snapshot-verify` command line subcommand.
+pub fn create_verify_subcommand(_parent_command_name: &'static str) -> Command<'static> {
+    let command_name = "verify";
+
+    update_command_graph!(_parent_command_name, command_name);
+
+    Command::new(command_name)
+        .about("Verify the integrity of the snapshot files")
+        .long_about(
+            "Verify the integrity of the snapshot files.\n\
+             Checks that the .snap and .aux file sizes match the grid dimensions and\n\
+             variables in the parameter file, that the auxiliary variable list is\n\
+             consistent, that the mesh file matches the grid dimensions and has\n\
+             monotonic coordinates, and that no variable has NaN or infinite values.\n\
+             A report is printed for each snapshot, and the program exits with a\n\
+             non-zero status if any snapshot fails verification.\n\
+             Only snapshots in native format are supported.",
+        )
+        .arg(
+            Arg::new("verbose")
+                .short('v')
+                .long("verbose")
+                .help("Print the outcome of every check rather than only failed checks"),
+        )
+}
+
+/// Runs the actions for the `snapshot-verify` subcommand on each of the given
+/// snapshots, and exits with a non-zero status if any of them fail.
+pub fn run_verify_subcommand(
+    arguments: &ArgMatches,
+    input_type: &SnapshotInputType,
+    param_file_paths: &[PathBuf],
+    endianness: Endianness,
+) {
+    if !matches!(input_type, SnapshotInputType::Native(_)) {
+        exit_with_error!("Error: Verification is only supported for snapshots in native format");
+    }
+    let print_all_checks = arguments.is_present("verbose");
+
+    let mut number_of_failed_snapshots = 0;
+    for param_file_path in param_file_paths {
+        let report = native::verify_snapshot(param_file_path, endianness);
+        let passed = report.passed();
+        if !passed {
+            number_of_failed_snapshots += 1;
+        }
+
+        println!(
+            "{}: {}",
+            param_file_path.to_string_lossy(),
+            if passed { "OK" } else { "FAILED" }
+        );
+        for check in report.checks() {
+            if print_all_checks || !check.passed() {
+                println!(
+                    "    [{}] {}: {}",
+                    if check.passed() { "ok" } else { "FAILED" },
+                    check.name(),
+                    check.details()
+                );
+            }
+        }
+    }
+
+    if number_of_failed_snapshots > 0 {
+        exit_with_error!(
+            "Error: {} of {} snapshots failed verification",
+            number_of_failed_snapshots,
+            param_file_paths.len()
+        );
+    }
+}
@@ -5,8 +5,10 @@ use crate::{
     exit_on_error, exit_on_false, exit_with_error,
     field::DynScalarFieldProvider3,
     io::{
+        fits,
         snapshot::{self, fdt, native, SnapshotMetadata},
         utils::IOContext,
+        vtk,
     },
     update_command_graph,
 };
@@ -21,6 +23,12 @@ use std::{
 #[cfg(feature = "netcdf")]
 use crate::io::snapshot::netcdf;
 
+#[cfg(feature = "hdf5")]
+use crate::io::snapshot::hdf5;
+
+#[cfg(feature = "npz")]
+use crate::io::snapshot::npz;
+
 /// Builds a representation of the `snapshot-write` command line subcommand.
 pub fn create_write_subcommand(_parent_command_name: &'static str) -> Command<'static> {
     let command_name = "write";
@@ -37,7 +45,11 @@ pub fn create_write_subcommand(_parent_command_name: &'static str) -> Command<'s
                     "Path of the output file to produce.\n\
                      Writes in the following format based on the file extension:\
                      \n    *.idl: Creates a parameter file with an associated .snap [and .aux] file\
-                     \n    *.nc: Creates a NetCDF file using the CF convention (requires the netcdf feature)\n\
+                     \n    *.nc: Creates a NetCDF file using the CF convention (requires the netcdf feature)\
+                     \n    *.h5: Creates a HDF5 file (requires the hdf5 feature)\
+                     \n    *.npz: Creates a NumPy .npz file (requires the npz feature)\
+                     \n    *.fits: Creates a FITS file with an image extension for each quantity\
+                     \n    *.vtr: Creates a VTK XML rectilinear grid file with quantities at the cell centers\n\
                      If processing multiple snapshots, the output snapshot number will be\n\
                      incremented (or appended if necessary) with basis in this snapshot file name.",
                 )
@@ -87,6 +99,14 @@ pub fn create_write_subcommand(_parent_command_name: &'static str) -> Command<'s
                 .multiple_values(true)
                 .conflicts_with_all(&["included-quantities"]),
         )
+        .arg(
+            Arg::new("center-staggered")
+                .long("center-staggered")
+                .help(
+                    "Interpolate staggered quantities to the cell centers before writing\n\
+                     (only for VTK output)",
+                ),
+        )
         .arg(
             Arg::new("ignore-warnings")
                 .long("ignore-warnings")
@@ -99,7 +119,7 @@ pub fn create_write_subcommand(_parent_command_name: &'static str) -> Command<'s
                 .help("Print status messages related to writing"),
         );
 
-    #[cfg(feature = "netcdf")]
+    #[cfg(any(feature = "netcdf", feature = "hdf5", feature = "npz"))]
     let command = command.arg(
         Arg::new("strip")
             .short('s')
@@ -184,6 +204,48 @@ pub fn run_write_subcommand(
                     &verbosity,
                 )
             }
+            #[cfg(feature = "hdf5")]
+            OutputType::HDF5 => {
+                let strip_metadata = arguments.is_present("strip");
+                hdf5::write_modified_snapshot(
+                    metadata,
+                    &mut *provider,
+                    &quantity_names,
+                    &output_file_path,
+                    strip_metadata,
+                    io_context,
+                    &verbosity,
+                )
+            }
+            #[cfg(feature = "npz")]
+            OutputType::Npz => {
+                let strip_metadata = arguments.is_present("strip");
+                npz::write_modified_snapshot(
+                    metadata,
+                    &mut *provider,
+                    &quantity_names,
+                    &output_file_path,
+                    strip_metadata,
+                    io_context,
+                    &verbosity,
+                )
+            }
+            OutputType::Fits => fits::write_snapshot(
+                metadata,
+                &mut *provider,
+                &quantity_names,
+                &output_file_path,
+                io_context,
+                &verbosity,
+            ),
+            OutputType::Vtk => vtk::write_snapshot(
+                &mut *provider,
+                &quantity_names,
+                &output_file_path,
+                arguments.is_present("center-staggered"),
+                io_context,
+                &verbosity,
+            ),
         },
         "Error: Could not write snapshot: {}"
     );
@@ -194,6 +256,12 @@ enum OutputType {
     Native(NativeType),
     #[cfg(feature = "netcdf")]
     NetCDF,
+    #[cfg(feature = "hdf5")]
+    HDF5,
+    #[cfg(feature = "npz")]
+    Npz,
+    Fits,
+    Vtk,
 }
 
 #[derive(Copy, Clone, PartialEq, Debug)]
@@ -240,6 +308,28 @@ impl OutputType {
                 exit_with_error!("Error: Compile with netcdf feature in order to write NetCDF files\n\
                                   Tip: Use cargo flag --features=netcdf and make sure the NetCDF library is available");
             }
+            "h5" => {
+                #[cfg(feature = "hdf5")]
+                {
+                    Self::HDF5
+                }
+                #[cfg(not(feature = "hdf5"))]
+                exit_with_error!("Error: Compile with hdf5 feature in order to write HDF5 files\n\
+                                  Tip: Use cargo flag --features=hdf5 and make sure the HDF5 library is available");
+            }
+            "npz" => {
+                #[cfg(feature = "npz")]
+                {
+                    Self::Npz
+                }
+                #[cfg(not(feature = "npz"))]
+                exit_with_error!(
+                    "Error: Compile with npz feature in order to write NumPy .npz files\n\
+                                  Tip: Use cargo flag --features=npz"
+                );
+            }
+            "fits" => Self::Fits,
+            "vtr" => Self::Vtk,
             invalid => exit_with_error!(
                 "Error: Invalid extension {} for output file\n\
                  Valid extensions are: {}",
@@ -251,8 +341,10 @@ impl OutputType {
 
     fn valid_extensions_string() -> String {
         format!(
-            "idl[.scr]{}",
-            if cfg!(feature = "netcdf") { ", nc" } else { "" }
+            "idl[.scr]{}{}{}, fits, vtr",
+            if cfg!(feature = "netcdf") { ", nc" } else { "" },
+            if cfg!(feature = "hdf5") { ", h5" } else { "" },
+            if cfg!(feature = "npz") { ", npz" } else { "" }
         )
     }
 
@@ -271,6 +363,12 @@ impl fmt::Display for OutputType {
                 Self::Native(NativeType::Scratch) => "idl.scr",
                 #[cfg(feature = "netcdf")]
                 Self::NetCDF => "nc",
+                #[cfg(feature = "hdf5")]
+                Self::HDF5 => "h5",
+                #[cfg(feature = "npz")]
+                Self::Npz => "npz",
+                Self::Fits => "fits",
+                Self::Vtk => "vtr",
             }
         )
     }
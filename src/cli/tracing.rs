@@ -1,6 +1,8 @@
 //! Command line interface for field line tracing.
 
 pub mod field_line;
+pub mod null;
+pub mod plane;
 pub mod stepping;
 
 use self::{
@@ -24,20 +26,28 @@ use crate::{
         },
         utils as cli_utils,
     },
-    exit_on_error, exit_with_error,
-    field::{DynCachingScalarFieldProvider3, DynScalarFieldProvider3, ScalarFieldCacher3},
+    exit_on_error, exit_on_false, exit_with_error,
+    field::{
+        CachingScalarFieldProvider3, DynCachingScalarFieldProvider3, DynScalarFieldProvider3,
+        ScalarField2, ScalarFieldCacher3,
+    },
     interpolation::{
         poly_fit::{PolyFitInterpolator3, PolyFitInterpolatorConfig},
         InterpGridVerifier3, Interpolator3,
     },
     io::{
-        snapshot::{self, fdt},
+        snapshot::{self, fdt, SnapshotMetadata},
         utils::{AtomicOutputFile, IOContext},
+        vtk, Verbosity,
     },
-    seeding::DynSeeder3,
+    seeding::{slice::SeedSliceGrid, DynSeeder3},
     tracing::{
         field_line::{
-            basic::{BasicFieldLineTracer3, BasicFieldLineTracerConfig},
+            basic::{
+                BasicFieldLineConnectivityTracer3, BasicFieldLineTracer3,
+                BasicFieldLineTracerConfig,
+            },
+            loops::{LoopCatalog, LoopCatalogConfig},
             FieldLineSet3, FieldLineSetProperties3, FieldLineTracer3,
         },
         stepping::{
@@ -50,11 +60,17 @@ use crate::{
 use clap::{Arg, ArgMatches, Command};
 use rayon::prelude::*;
 use std::{
-    fmt,
+    fmt, io,
     path::{Path, PathBuf},
     str::FromStr,
 };
 
+#[cfg(feature = "corks")]
+use crate::{
+    corks::fco, io::snapshot::OUTPUT_TIME_STEP_NAME,
+    tracing::field_line::tracking::FieldLineFootpointTracker3,
+};
+
 /// Builds a representation of the `trace` command line subcommand.
 pub fn create_trace_subcommand(_parent_command_name: &'static str) -> Command<'static> {
     let command_name = "trace";
@@ -79,7 +95,8 @@ pub fn create_trace_subcommand(_parent_command_name: &'static str) -> Command<'s
                        \n    *.fl: Creates a binary file readable by the backstaff Python package\
                        \n    *.pickle: Creates a Python pickle file (requires the pickle feature)\
                        \n    *.json: Creates a JSON file (requires the json feature)\
-                       \n    *.h5part: Creates a H5Part file (requires the hdf5 feature)",
+                       \n    *.h5part: Creates a H5Part file (requires the hdf5 feature)\
+                       \n    *.vtp: Creates a VTK XML poly data file",
                 )
                 .required(true)
                 .takes_value(true),
@@ -113,7 +130,10 @@ pub fn create_trace_subcommand(_parent_command_name: &'static str) -> Command<'s
                 .use_value_delimiter(true)
                 .require_value_delimiter(true)
                 .value_name("NAMES")
-                .help("List of quantities to extract along field line paths (comma-separated)")
+                .help(
+                    "List of quantities to extract along field line paths, or at both ends\n\
+                     in connectivity mode (comma-separated)",
+                )
                 .takes_value(true)
                 .multiple_values(true),
         )
@@ -128,10 +148,101 @@ pub fn create_trace_subcommand(_parent_command_name: &'static str) -> Command<'s
                 .takes_value(true)
                 .multiple_values(true),
         )
+        .arg(
+            Arg::new("twist-diagnostics")
+                .long("twist-diagnostics")
+                .help(
+                    "Compute the twist number, integrated current and writhe of each field\n\
+                     line (requires the current density j, e.g. from `derive`)",
+                ),
+        )
         .arg(Arg::new("drop-h5part-id").long("drop-h5part-id").help(
             "Reduce H5Part file size by excluding particle IDs required by some tools\n\
                      (e.g. VisIt)",
         ))
+        .arg(
+            Arg::new("connectivity-map")
+                .long("connectivity-map")
+                .require_equals(true)
+                .value_name("FILE")
+                .help(
+                    "Also save a 2D map of the connectivity class of each field line on the\n\
+                     grid of the seed points (requires the connectivity mode of the basic field\n\
+                     line tracer and a regular slice seeder without coordinate bounds)\n\
+                     Writes in the following format based on the file extension:\
+                     \n    *.pickle: Creates a Python pickle file (requires the pickle feature)\
+                     \n    *.npz: Creates a NumPy .npz file (requires the npz feature)\
+                     \n    *.vtr: Creates a VTK XML rectilinear grid file",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("loop-catalog")
+                .long("loop-catalog")
+                .require_equals(true)
+                .value_name("FILE")
+                .help(
+                    "Also save a catalog of the closed loops among the traced field lines\n\
+                     Writes in the following format based on the file extension:\
+                     \n    *.csv: Creates a CSV file with a header line\
+                     \n    *.pickle: Creates a Python pickle file (requires the pickle feature)\
+                     \n    *.json: Creates a JSON file (requires the json feature)\n\
+                     Extract the temperature and density quantities along the paths to include\n\
+                     scale heights and equilibria",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("max-footpoint-height")
+                .long("max-footpoint-height")
+                .require_equals(true)
+                .value_name("VALUE")
+                .help("Both ends of a loop must lie below this height [Mm]\n")
+                .takes_value(true)
+                .default_value("2.0"),
+        )
+        .arg(
+            Arg::new("equilibrium-tolerance")
+                .long("equilibrium-tolerance")
+                .require_equals(true)
+                .value_name("VALUE")
+                .help(
+                    "Largest relative deviation from the hydrostatic scale height or RTV\n\
+                     temperature for a loop to be considered in equilibrium\n",
+                )
+                .takes_value(true)
+                .default_value("0.3"),
+        )
+        .arg(
+            Arg::new("loop-temperature-quantity")
+                .long("loop-temperature-quantity")
+                .require_equals(true)
+                .value_name("NAME")
+                .help("Name of the extracted temperature quantity [K] used for the loop catalog\n")
+                .takes_value(true)
+                .default_value("tg"),
+        )
+        .arg(
+            Arg::new("loop-density-quantity")
+                .long("loop-density-quantity")
+                .require_equals(true)
+                .value_name("NAME")
+                .help(
+                    "Name of the extracted mass density quantity [Bifrost units] used for the\n\
+                     loop catalog\n",
+                )
+                .takes_value(true)
+                .default_value("r"),
+        )
+        .arg(
+            Arg::new("mean-molecular-weight")
+                .long("mean-molecular-weight")
+                .require_equals(true)
+                .value_name("VALUE")
+                .help("Mean molecular weight used for computing loop gas pressures\n")
+                .takes_value(true)
+                .default_value("0.6"),
+        )
         .arg(
             Arg::new("verbose")
                 .short('v')
@@ -153,18 +264,36 @@ pub fn create_trace_subcommand(_parent_command_name: &'static str) -> Command<'s
         .subcommand_required(true)
         .subcommand(create_basic_field_line_tracer_subcommand(command_name));
 
+    #[cfg(feature = "corks")]
+    let command = command.arg(Arg::new("track-footpoints").long("track-footpoints").help(
+        "Use the seed points as footpoints that are advected with the horizontal\n\
+                 plasma velocity between the snapshots in --snap-range, and re-trace the field\n\
+                 lines from the advected footpoints in each snapshot. Each field line keeps its\n\
+                 ID (field_line_id) throughout the series. Requires quantities r and p",
+    ));
+
     add_subcommand_combinations!(command, command_name, true; rkf_stepper, poly_fit_interpolator, (slice_seeder, volume_seeder, manual_seeder))
 }
 
+/// State of the `trace` subcommand that persists between the snapshots being
+/// processed.
+#[derive(Default)]
+pub struct TraceState {
+    #[cfg(feature = "corks")]
+    footpoint_tracker: Option<FieldLineFootpointTracker3>,
+}
+
 /// Runs the actions for the `trace` subcommand using the given arguments.
 pub fn run_trace_subcommand(
     arguments: &ArgMatches,
+    metadata: &dyn SnapshotMetadata,
     provider: DynScalarFieldProvider3<fdt>,
     io_context: &mut IOContext,
+    state: &mut TraceState,
 ) {
     let verbosity = cli_utils::parse_verbosity(arguments, false);
     let snapshot = Box::new(ScalarFieldCacher3::new_manual_cacher(provider, verbosity));
-    run_with_selected_tracer(arguments, snapshot, io_context);
+    run_with_selected_tracer(arguments, metadata, snapshot, io_context, state);
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -176,6 +305,7 @@ enum OutputType {
     Json,
     #[cfg(feature = "hdf5")]
     H5Part,
+    Vtk,
 }
 
 impl OutputType {
@@ -229,6 +359,7 @@ impl OutputType {
                 exit_with_error!("Error: Compile with hdf5 feature in order to write H5Part files\n\
                                   Tip: Use cargo flag --features=hdf5 and make sure the HDF5 library is available");
             }
+            "vtp" => Self::Vtk,
             invalid => exit_with_error!(
                 "Error: Invalid extension {} for output file\n\
                  Valid extensions are: {}",
@@ -238,9 +369,19 @@ impl OutputType {
         }
     }
 
+    /// Whether the output format requires the paths of the field lines.
+    fn requires_paths(self) -> bool {
+        match self {
+            #[cfg(feature = "hdf5")]
+            Self::H5Part => true,
+            Self::Vtk => true,
+            _ => false,
+        }
+    }
+
     fn valid_extensions_string() -> String {
         format!(
-            "fl, pickle, json{}",
+            "fl, pickle, json{}, vtp",
             if cfg!(feature = "hdf5") {
                 ", h5part"
             } else {
@@ -263,6 +404,161 @@ impl fmt::Display for OutputType {
                 Self::Json => "json",
                 #[cfg(feature = "hdf5")]
                 Self::H5Part => "h5part",
+                Self::Vtk => "vtp",
+            }
+        )
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+enum ConnectivityMapOutputType {
+    #[cfg(feature = "pickle")]
+    Pickle,
+    #[cfg(feature = "npz")]
+    Npz,
+    Vtk,
+}
+
+/// Seed grid and output file for a connectivity map.
+struct ConnectivityMapOutput {
+    seed_grid: SeedSliceGrid,
+    output_type: ConnectivityMapOutputType,
+    atomic_output_file: AtomicOutputFile,
+}
+
+impl ConnectivityMapOutputType {
+    fn from_path(file_path: &Path) -> Self {
+        match file_path
+            .extension()
+            .unwrap_or_else(|| {
+                exit_with_error!(
+                    "Error: Missing extension for connectivity map file\n\
+                     Valid extensions are: pickle, npz, vtr"
+                )
+            })
+            .to_string_lossy()
+            .as_ref()
+        {
+            "pickle" => {
+                #[cfg(feature = "pickle")]
+                {
+                    Self::Pickle
+                }
+                #[cfg(not(feature = "pickle"))]
+                exit_with_error!(
+                    "Error: Compile with pickle feature in order to write Pickle files\n\
+                     Tip: Use cargo flag --features=pickle"
+                );
+            }
+            "npz" => {
+                #[cfg(feature = "npz")]
+                {
+                    Self::Npz
+                }
+                #[cfg(not(feature = "npz"))]
+                exit_with_error!(
+                    "Error: Compile with npz feature in order to write NumPy .npz files\n\
+                     Tip: Use cargo flag --features=npz"
+                );
+            }
+            "vtr" => Self::Vtk,
+            invalid => exit_with_error!(
+                "Error: Invalid extension {} for connectivity map file\n\
+                 Valid extensions are: pickle, npz, vtr",
+                invalid
+            ),
+        }
+    }
+}
+
+impl fmt::Display for ConnectivityMapOutputType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                #[cfg(feature = "pickle")]
+                Self::Pickle => "pickle",
+                #[cfg(feature = "npz")]
+                Self::Npz => "npz",
+                Self::Vtk => "vtr",
+            }
+        )
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+enum LoopCatalogOutputType {
+    Csv,
+    #[cfg(feature = "pickle")]
+    Pickle,
+    #[cfg(feature = "json")]
+    Json,
+}
+
+/// Configuration and output file for a loop catalog.
+struct LoopCatalogOutput {
+    config: LoopCatalogConfig,
+    output_type: LoopCatalogOutputType,
+    atomic_output_file: AtomicOutputFile,
+}
+
+impl LoopCatalogOutputType {
+    fn from_path(file_path: &Path) -> Self {
+        match file_path
+            .extension()
+            .unwrap_or_else(|| {
+                exit_with_error!(
+                    "Error: Missing extension for loop catalog file\n\
+                     Valid extensions are: csv, pickle, json"
+                )
+            })
+            .to_string_lossy()
+            .as_ref()
+        {
+            "csv" => Self::Csv,
+            "pickle" => {
+                #[cfg(feature = "pickle")]
+                {
+                    Self::Pickle
+                }
+                #[cfg(not(feature = "pickle"))]
+                exit_with_error!(
+                    "Error: Compile with pickle feature in order to write Pickle files\n\
+                     Tip: Use cargo flag --features=pickle"
+                );
+            }
+            "json" => {
+                #[cfg(feature = "json")]
+                {
+                    Self::Json
+                }
+                #[cfg(not(feature = "json"))]
+                exit_with_error!(
+                    "Error: Compile with json feature in order to write JSON files\n\
+                     Tip: Use cargo flag --features=json"
+                );
+            }
+            invalid => exit_with_error!(
+                "Error: Invalid extension {} for loop catalog file\n\
+                 Valid extensions are: csv, pickle, json",
+                invalid
+            ),
+        }
+    }
+}
+
+impl fmt::Display for LoopCatalogOutputType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::Csv => "csv",
+                #[cfg(feature = "pickle")]
+                Self::Pickle => "pickle",
+                #[cfg(feature = "json")]
+                Self::Json => "json",
             }
         )
     }
@@ -270,34 +566,91 @@ impl fmt::Display for OutputType {
 
 fn run_with_selected_tracer(
     arguments: &ArgMatches,
-    snapshot: DynCachingScalarFieldProvider3<fdt>,
+    metadata: &dyn SnapshotMetadata,
+    mut snapshot: DynCachingScalarFieldProvider3<fdt>,
     io_context: &mut IOContext,
+    state: &mut TraceState,
 ) {
-    let (tracer_config, tracer_arguments) =
+    let (tracer_config, connectivity, tracer_arguments) =
         if let Some(tracer_arguments) = arguments.subcommand_matches("basic_field_line_tracer") {
             (
                 construct_basic_field_line_tracer_config_from_options(tracer_arguments),
+                tracer_arguments.is_present("connectivity"),
                 tracer_arguments,
             )
         } else {
-            (BasicFieldLineTracerConfig::default(), arguments)
+            (BasicFieldLineTracerConfig::default(), false, arguments)
         };
 
     if arguments.is_present("print-parameter-values") {
-        println!("{:#?}", tracer_config);
+        println!("{:#?}\nconnectivity: {}", tracer_config, connectivity);
     }
 
-    let tracer = BasicFieldLineTracer3::new(tracer_config);
+    if arguments.is_present("connectivity-map") && !connectivity {
+        exit_with_error!(
+            "Error: Connectivity maps require the connectivity mode of the basic field line tracer"
+        );
+    }
+
+    for name in tracer_config
+        .stopping_criteria
+        .iter()
+        .filter_map(|criterion| criterion.required_quantity())
+    {
+        exit_on_error!(
+            snapshot.cache_scalar_field(name),
+            "Error: Could not read quantity {0} in snapshot: {1}",
+            name
+        );
+    }
 
-    run_with_selected_stepper(arguments, tracer_arguments, snapshot, tracer, io_context);
+    if connectivity {
+        let output_type = OutputType::from_path(Path::new(
+            arguments
+                .value_of("output-file")
+                .expect("No value for required argument"),
+        ));
+        if output_type.requires_paths() {
+            exit_with_error!(
+                "Error: Field lines traced in connectivity mode can not be saved as {} files",
+                output_type
+            );
+        }
+        if arguments.is_present("twist-diagnostics") {
+            exit_with_error!("Error: Twist diagnostics can not be computed in connectivity mode");
+        }
+        let tracer = BasicFieldLineConnectivityTracer3::new(tracer_config);
+        run_with_selected_stepper(
+            arguments,
+            tracer_arguments,
+            metadata,
+            snapshot,
+            tracer,
+            io_context,
+            state,
+        );
+    } else {
+        let tracer = BasicFieldLineTracer3::new(tracer_config);
+        run_with_selected_stepper(
+            arguments,
+            tracer_arguments,
+            metadata,
+            snapshot,
+            tracer,
+            io_context,
+            state,
+        );
+    }
 }
 
 fn run_with_selected_stepper<Tr>(
     root_arguments: &ArgMatches,
     arguments: &ArgMatches,
+    metadata: &dyn SnapshotMetadata,
     snapshot: DynCachingScalarFieldProvider3<fdt>,
     tracer: Tr,
     io_context: &mut IOContext,
+    state: &mut TraceState,
 ) where
     Tr: FieldLineTracer3 + Sync,
     <Tr as FieldLineTracer3>::Data: Send,
@@ -324,18 +677,22 @@ fn run_with_selected_stepper<Tr>(
         RKFStepperType::RKF23 => run_with_selected_interpolator(
             root_arguments,
             stepper_arguments,
+            metadata,
             snapshot,
             tracer,
             Box::new(RKF23Stepper3::new(stepper_config)),
             io_context,
+            state,
         ),
         RKFStepperType::RKF45 => run_with_selected_interpolator(
             root_arguments,
             stepper_arguments,
+            metadata,
             snapshot,
             tracer,
             Box::new(RKF45Stepper3::new(stepper_config)),
             io_context,
+            state,
         ),
     }
 }
@@ -343,10 +700,12 @@ fn run_with_selected_stepper<Tr>(
 fn run_with_selected_interpolator<Tr>(
     root_arguments: &ArgMatches,
     arguments: &ArgMatches,
+    metadata: &dyn SnapshotMetadata,
     snapshot: DynCachingScalarFieldProvider3<fdt>,
     tracer: Tr,
     stepper: DynStepper3<fdt>,
     io_context: &mut IOContext,
+    state: &mut TraceState,
 ) where
     Tr: FieldLineTracer3 + Sync,
     <Tr as FieldLineTracer3>::Data: Send,
@@ -377,69 +736,397 @@ fn run_with_selected_interpolator<Tr>(
     run_with_selected_seeder(
         root_arguments,
         interpolator_arguments,
+        metadata,
         snapshot,
         tracer,
         stepper,
         interpolator.as_ref(),
         io_context,
+        state,
     );
 }
 
 fn run_with_selected_seeder<Tr>(
     root_arguments: &ArgMatches,
     arguments: &ArgMatches,
+    _metadata: &dyn SnapshotMetadata,
     mut snapshot: DynCachingScalarFieldProvider3<fdt>,
     tracer: Tr,
     stepper: DynStepper3<fdt>,
     interpolator: &dyn Interpolator3<fdt>,
     io_context: &mut IOContext,
+    _state: &mut TraceState,
 ) where
     Tr: FieldLineTracer3 + Sync,
     <Tr as FieldLineTracer3>::Data: Send,
     FieldLineSetProperties3: FromParallelIterator<<Tr as FieldLineTracer3>::Data>,
 {
-    let seeder = if let Some(seeder_arguments) = arguments.subcommand_matches("slice_seeder") {
+    #[cfg(feature = "corks")]
+    if root_arguments.is_present("track-footpoints") {
+        let tracker = update_footpoint_tracker(
+            root_arguments,
+            arguments,
+            _metadata,
+            &mut *snapshot,
+            interpolator,
+            &mut _state.footpoint_tracker,
+        );
+        run_tracing(
+            root_arguments,
+            snapshot,
+            interpolator,
+            None,
+            io_context,
+            |quantity, snapshot| tracker.trace(quantity, snapshot, &tracer, interpolator, stepper),
+        );
+        return;
+    }
+
+    let seeder = create_seeder_from_arguments(arguments, &mut *snapshot, interpolator);
+    let verbosity = cli_utils::parse_verbosity(root_arguments, true);
+
+    run_tracing(
+        root_arguments,
+        snapshot,
+        interpolator,
+        seeder.seed_grid(),
+        io_context,
+        |quantity, snapshot| {
+            FieldLineSet3::trace(
+                quantity,
+                snapshot,
+                &*seeder,
+                &tracer,
+                interpolator,
+                stepper,
+                verbosity,
+            )
+        },
+    );
+}
+
+fn create_seeder_from_arguments(
+    arguments: &ArgMatches,
+    snapshot: &mut dyn CachingScalarFieldProvider3<fdt>,
+    interpolator: &dyn Interpolator3<fdt>,
+) -> DynSeeder3 {
+    if let Some(seeder_arguments) = arguments.subcommand_matches("slice_seeder") {
         Box::new(create_slice_seeder_from_arguments(
             seeder_arguments,
-            &mut *snapshot,
+            snapshot,
             interpolator,
-        )) as DynSeeder3
+        ))
     } else if let Some(seeder_arguments) = arguments.subcommand_matches("volume_seeder") {
         Box::new(create_volume_seeder_from_arguments(
             seeder_arguments,
-            &mut *snapshot,
+            snapshot,
             interpolator,
-        )) as DynSeeder3
+        ))
     } else if let Some(seeder_arguments) = arguments.subcommand_matches("manual_seeder") {
-        Box::new(create_manual_seeder_from_arguments(seeder_arguments)) as DynSeeder3
+        Box::new(create_manual_seeder_from_arguments(seeder_arguments))
     } else {
         exit_with_error!("Error: No seeder specified")
-    };
+    }
+}
 
-    run_tracing(
-        root_arguments,
-        snapshot,
-        tracer,
-        stepper,
-        interpolator,
-        seeder,
-        io_context,
-    );
+/// Creates the footpoint tracker from the seeder for the first snapshot, or
+/// advects the footpoints of the existing tracker to the current snapshot.
+#[cfg(feature = "corks")]
+fn update_footpoint_tracker<'a>(
+    root_arguments: &ArgMatches,
+    arguments: &ArgMatches,
+    metadata: &dyn SnapshotMetadata,
+    snapshot: &mut dyn CachingScalarFieldProvider3<fdt>,
+    interpolator: &dyn Interpolator3<fdt>,
+    footpoint_tracker: &'a mut Option<FieldLineFootpointTracker3>,
+) -> &'a FieldLineFootpointTracker3 {
+    if let Some(tracker) = footpoint_tracker {
+        let step_duration = exit_on_error!(
+            metadata
+                .parameters()
+                .get_value(OUTPUT_TIME_STEP_NAME)
+                .and_then(|value| value.try_as_float())
+                .map(|value| value as fco),
+            "Error: Could not determine step duration for footpoint tracking: {}"
+        );
+        exit_on_error!(
+            tracker.advect(snapshot, interpolator, step_duration),
+            "Error: Could not advect footpoints: {}"
+        );
+    } else {
+        let seeder = create_seeder_from_arguments(arguments, snapshot, interpolator);
+        *footpoint_tracker = Some(exit_on_error!(
+            FieldLineFootpointTracker3::new(
+                &*seeder,
+                snapshot,
+                interpolator,
+                cli_utils::parse_verbosity(root_arguments, true),
+            ),
+            "Error: Could not initialize footpoints: {}"
+        ));
+    }
+    footpoint_tracker.as_ref().unwrap()
 }
 
-fn run_tracing<Tr>(
+fn run_tracing<T>(
     root_arguments: &ArgMatches,
     mut snapshot: DynCachingScalarFieldProvider3<fdt>,
-    tracer: Tr,
-    stepper: DynStepper3<fdt>,
     interpolator: &dyn Interpolator3<fdt>,
-    seeder: DynSeeder3,
+    seed_grid: Option<&SeedSliceGrid>,
     io_context: &mut IOContext,
+    trace_field_lines: T,
 ) where
-    Tr: FieldLineTracer3 + Sync,
-    <Tr as FieldLineTracer3>::Data: Send,
-    FieldLineSetProperties3: FromParallelIterator<<Tr as FieldLineTracer3>::Data>,
+    T: FnOnce(&str, &dyn CachingScalarFieldProvider3<fdt>) -> FieldLineSet3,
 {
+    let verbosity = cli_utils::parse_verbosity(root_arguments, true);
+
+    let (output_type, atomic_output_file, extra_atomic_output_file) =
+        match create_output_files(root_arguments, io_context, &verbosity) {
+            Some(output_files) => output_files,
+            None => return,
+        };
+
+    let loop_catalog_output = root_arguments
+        .value_of("loop-catalog")
+        .and_then(|_| create_loop_catalog_output(root_arguments, io_context, &verbosity));
+
+    let connectivity_map_output = root_arguments.value_of("connectivity-map").and_then(|_| {
+        create_connectivity_map_output(root_arguments, seed_grid, io_context, &verbosity)
+    });
+
+    let quantity = root_arguments
+        .value_of("vector-quantity")
+        .expect("No value for argument with default");
+    exit_on_error!(
+        snapshot.cache_vector_field(quantity),
+        "Error: Could not read quantity {0} in snapshot: {1}",
+        quantity
+    );
+
+    let field_lines = trace_field_lines(quantity, &*snapshot);
+
+    let connectivity_map = connectivity_map_output.map(|connectivity_map_output| {
+        (
+            field_lines.connectivity_map(&connectivity_map_output.seed_grid),
+            connectivity_map_output,
+        )
+    });
+
+    perform_post_tracing_actions(
+        root_arguments,
+        output_type,
+        atomic_output_file,
+        extra_atomic_output_file,
+        io_context,
+        snapshot,
+        interpolator,
+        field_lines,
+        loop_catalog_output,
+    );
+
+    if let Some((map, connectivity_map_output)) = connectivity_map {
+        save_connectivity_map(map, connectivity_map_output, io_context, &verbosity);
+    }
+}
+
+/// Creates the atomic output file for the connectivity map from the `trace`
+/// arguments.
+///
+/// Returns `None` if the connectivity map file can not be written, in which
+/// case the field lines are still traced and saved without a map.
+fn create_connectivity_map_output(
+    root_arguments: &ArgMatches,
+    seed_grid: Option<&SeedSliceGrid>,
+    io_context: &IOContext,
+    verbosity: &Verbosity,
+) -> Option<ConnectivityMapOutput> {
+    let seed_grid = seed_grid.unwrap_or_else(|| {
+        exit_with_error!(
+            "Error: Connectivity maps require a regular slice seeder without coordinate bounds"
+        )
+    });
+
+    let mut output_file_path = exit_on_error!(
+        PathBuf::from_str(
+            root_arguments
+                .value_of("connectivity-map")
+                .expect("No value for connectivity-map argument"),
+        ),
+        "Error: Could not interpret path to connectivity map file: {}"
+    );
+
+    let output_type = ConnectivityMapOutputType::from_path(&output_file_path);
+
+    if let Some(snap_num_in_range) = io_context.get_snap_num_in_range() {
+        output_file_path.set_file_name(snapshot::create_new_snapshot_file_name_from_path(
+            &output_file_path,
+            snap_num_in_range.offset(),
+            &output_type.to_string(),
+            true,
+        ));
+    }
+
+    let atomic_output_file = match io_context.create_atomic_output_file(output_file_path) {
+        Ok(atomic_output_file) => atomic_output_file,
+        Err(err) => {
+            eprintln!(
+                "Warning: Could not create temporary connectivity map file, skipping connectivity map: {}",
+                err
+            );
+            return None;
+        }
+    };
+
+    if !atomic_output_file.check_if_write_allowed(io_context, verbosity) {
+        return None;
+    }
+
+    Some(ConnectivityMapOutput {
+        seed_grid: seed_grid.clone(),
+        output_type,
+        atomic_output_file,
+    })
+}
+
+/// Saves the connectivity map in the given output file and moves the file to
+/// its target path.
+fn save_connectivity_map(
+    map: io::Result<ScalarField2<fdt>>,
+    connectivity_map_output: ConnectivityMapOutput,
+    io_context: &IOContext,
+    verbosity: &Verbosity,
+) {
+    let ConnectivityMapOutput {
+        seed_grid,
+        output_type,
+        atomic_output_file,
+    } = connectivity_map_output;
+
+    let map = exit_on_error!(map, "Error: Could not create connectivity map: {}");
+    if verbosity.print_messages() {
+        println!(
+            "Saving connectivity map in {}",
+            atomic_output_file
+                .target_path()
+                .file_name()
+                .unwrap()
+                .to_string_lossy()
+        );
+    }
+    let output_file_path = atomic_output_file.temporary_path();
+    exit_on_error!(
+        match output_type {
+            #[cfg(feature = "pickle")]
+            ConnectivityMapOutputType::Pickle => map.save_as_pickle(output_file_path),
+            #[cfg(feature = "npz")]
+            ConnectivityMapOutputType::Npz => map.save_as_npz(output_file_path),
+            ConnectivityMapOutputType::Vtk =>
+                vtk::write_slice(output_file_path, &map, seed_grid.axis(), seed_grid.coord()),
+        },
+        "Error: Could not save connectivity map: {}"
+    );
+    exit_on_error!(
+        io_context.close_atomic_output_file(atomic_output_file),
+        "Error: Could not move temporary output file to target path: {}"
+    );
+}
+
+/// Creates the loop catalog configuration and atomic output file from the
+/// `trace` arguments.
+///
+/// Returns `None` if the loop catalog file can not be written, in which case
+/// the field lines are still traced and saved without a catalog.
+fn create_loop_catalog_output(
+    root_arguments: &ArgMatches,
+    io_context: &IOContext,
+    verbosity: &Verbosity,
+) -> Option<LoopCatalogOutput> {
+    let mut output_file_path = exit_on_error!(
+        PathBuf::from_str(
+            root_arguments
+                .value_of("loop-catalog")
+                .expect("No value for loop-catalog argument"),
+        ),
+        "Error: Could not interpret path to loop catalog file: {}"
+    );
+
+    let output_type = LoopCatalogOutputType::from_path(&output_file_path);
+
+    if let Some(snap_num_in_range) = io_context.get_snap_num_in_range() {
+        output_file_path.set_file_name(snapshot::create_new_snapshot_file_name_from_path(
+            &output_file_path,
+            snap_num_in_range.offset(),
+            &output_type.to_string(),
+            true,
+        ));
+    }
+
+    let config = LoopCatalogConfig {
+        max_footpoint_height: cli_utils::get_finite_float_value_from_required_parseable_argument(
+            root_arguments,
+            "max-footpoint-height",
+        ),
+        equilibrium_tolerance: cli_utils::get_finite_float_value_from_required_parseable_argument(
+            root_arguments,
+            "equilibrium-tolerance",
+        ),
+        temperature_quantity: root_arguments
+            .value_of("loop-temperature-quantity")
+            .expect("No value for argument with default")
+            .to_string(),
+        density_quantity: root_arguments
+            .value_of("loop-density-quantity")
+            .expect("No value for argument with default")
+            .to_string(),
+        mean_molecular_weight: cli_utils::get_finite_float_value_from_required_parseable_argument(
+            root_arguments,
+            "mean-molecular-weight",
+        ),
+    };
+    exit_on_false!(
+        config.mean_molecular_weight > 0.0,
+        "Error: Mean molecular weight must be larger than zero"
+    );
+    exit_on_false!(
+        config.equilibrium_tolerance >= 0.0,
+        "Error: Equilibrium tolerance must be non-negative"
+    );
+
+    if root_arguments.is_present("print-parameter-values") {
+        println!("{:#?}", config);
+    }
+
+    let atomic_output_file = match io_context.create_atomic_output_file(output_file_path) {
+        Ok(atomic_output_file) => atomic_output_file,
+        Err(err) => {
+            eprintln!(
+                "Warning: Could not create temporary loop catalog file, skipping loop catalog: {}",
+                err
+            );
+            return None;
+        }
+    };
+
+    if !atomic_output_file.check_if_write_allowed(io_context, verbosity) {
+        return None;
+    }
+
+    Some(LoopCatalogOutput {
+        config,
+        output_type,
+        atomic_output_file,
+    })
+}
+
+/// Creates the atomic output file for the field line data, along with the
+/// extra output file for seed data if required by the output type.
+///
+/// Returns `None` if writing to the output files is not allowed.
+fn create_output_files(
+    root_arguments: &ArgMatches,
+    io_context: &mut IOContext,
+    verbosity: &Verbosity,
+) -> Option<(OutputType, AtomicOutputFile, Option<AtomicOutputFile>)> {
     let mut output_file_path = exit_on_error!(
         PathBuf::from_str(
             root_arguments
@@ -468,10 +1155,8 @@ fn run_tracing<Tr>(
         "Error: Could not create temporary output file: {}"
     );
 
-    let verbosity = cli_utils::parse_verbosity(root_arguments, true);
-
-    if !atomic_output_file.check_if_write_allowed(io_context, &verbosity) {
-        return;
+    if !atomic_output_file.check_if_write_allowed(io_context, verbosity) {
+        return None;
     }
 
     let extra_atomic_output_file = match output_type {
@@ -485,42 +1170,15 @@ fn run_tracing<Tr>(
                 ),
                 "Error: Could not create temporary output file: {}"
             );
-            if !extra_atomic_output_file.check_if_write_allowed(io_context, &verbosity) {
-                return;
+            if !extra_atomic_output_file.check_if_write_allowed(io_context, verbosity) {
+                return None;
             }
             Some(extra_atomic_output_file)
         }
         _ => None,
     };
 
-    let quantity = root_arguments
-        .value_of("vector-quantity")
-        .expect("No value for argument with default");
-    exit_on_error!(
-        snapshot.cache_vector_field(quantity),
-        "Error: Could not read quantity {0} in snapshot: {1}",
-        quantity
-    );
-
-    let field_lines = FieldLineSet3::trace(
-        quantity,
-        &*snapshot,
-        &*seeder,
-        &tracer,
-        interpolator,
-        stepper,
-        verbosity,
-    );
-    perform_post_tracing_actions(
-        root_arguments,
-        output_type,
-        atomic_output_file,
-        extra_atomic_output_file,
-        io_context,
-        snapshot,
-        interpolator,
-        field_lines,
-    );
+    Some((output_type, atomic_output_file, extra_atomic_output_file))
 }
 
 fn perform_post_tracing_actions(
@@ -532,6 +1190,7 @@ fn perform_post_tracing_actions(
     mut snapshot: DynCachingScalarFieldProvider3<fdt>,
     interpolator: &dyn Interpolator3<fdt>,
     mut field_lines: FieldLineSet3,
+    loop_catalog_output: Option<LoopCatalogOutput>,
 ) {
     if let Some(extra_fixed_scalars) = root_arguments
         .values_of("extracted-seed-quantities")
@@ -555,17 +1214,117 @@ fn perform_post_tracing_actions(
     {
         for name in extra_varying_scalars {
             let name = name.to_lowercase();
-            field_lines.extract_varying_scalars(
-                exit_on_error!(
-                    snapshot.provide_scalar_field(&name).as_ref(),
-                    "Error: Could not read quantity {0} from snapshot: {1}",
-                    &name
-                ),
-                interpolator,
+            let field = exit_on_error!(
+                snapshot.provide_scalar_field(&name),
+                "Error: Could not read quantity {0} from snapshot: {1}",
+                &name
             );
+            if field_lines.has_paths() {
+                field_lines.extract_varying_scalars(field.as_ref(), interpolator);
+            } else {
+                field_lines.extract_end_scalars(field.as_ref(), interpolator);
+            }
+        }
+    }
+
+    if root_arguments.is_present("twist-diagnostics") {
+        if !field_lines.has_paths() {
+            exit_with_error!("Error: Twist diagnostics require field line paths");
         }
+        let magnetic_field = exit_on_error!(
+            snapshot.provide_vector_field("b"),
+            "Error: Could not read magnetic field from snapshot: {}"
+        );
+        let current_density = exit_on_error!(
+            snapshot.provide_vector_field("j"),
+            "Error: Could not read current density from snapshot: {}"
+        );
+        field_lines.compute_twist_diagnostics(
+            magnetic_field.as_ref(),
+            current_density.as_ref(),
+            interpolator,
+        );
+    }
+
+    let loop_catalog = loop_catalog_output.map(|loop_catalog_output| {
+        (
+            LoopCatalog::from_field_lines(&field_lines, &loop_catalog_output.config),
+            loop_catalog_output,
+        )
+    });
+    let verbosity = field_lines.verbosity().clone();
+
+    save_field_lines(
+        root_arguments,
+        output_type,
+        atomic_output_file,
+        extra_atomic_output_file,
+        io_context,
+        field_lines,
+    );
+
+    if let Some((catalog, loop_catalog_output)) = loop_catalog {
+        save_loop_catalog(catalog, loop_catalog_output, io_context, &verbosity);
+    }
+}
+
+/// Saves the loop catalog in the given output file and moves the file to its
+/// target path.
+///
+/// This is done after the field lines have been saved, so that a failure to
+/// create or save the catalog does not prevent the primary output.
+fn save_loop_catalog(
+    catalog: io::Result<LoopCatalog>,
+    loop_catalog_output: LoopCatalogOutput,
+    io_context: &IOContext,
+    verbosity: &Verbosity,
+) {
+    let LoopCatalogOutput {
+        output_type,
+        atomic_output_file,
+        ..
+    } = loop_catalog_output;
+
+    let catalog = exit_on_error!(catalog, "Error: Could not create loop catalog: {}");
+    if verbosity.print_messages() {
+        println!(
+            "Found {} loops\nSaving loop catalog in {}",
+            catalog.number_of_loops(),
+            atomic_output_file
+                .target_path()
+                .file_name()
+                .unwrap()
+                .to_string_lossy()
+        );
     }
+    exit_on_error!(
+        match output_type {
+            LoopCatalogOutputType::Csv => catalog.save_as_csv(atomic_output_file.temporary_path()),
+            #[cfg(feature = "pickle")]
+            LoopCatalogOutputType::Pickle =>
+                catalog.save_as_pickle(atomic_output_file.temporary_path()),
+            #[cfg(feature = "json")]
+            LoopCatalogOutputType::Json =>
+                catalog.save_as_json(atomic_output_file.temporary_path()),
+        },
+        "Error: Could not save loop catalog: {}"
+    );
+    exit_on_error!(
+        io_context.close_atomic_output_file(atomic_output_file),
+        "Error: Could not move temporary output file to target path: {}"
+    );
+}
 
+/// Saves the field lines in the given output files and moves the files to
+/// their target paths.
+fn save_field_lines(
+    _root_arguments: &ArgMatches,
+    output_type: OutputType,
+    atomic_output_file: AtomicOutputFile,
+    extra_atomic_output_file: Option<AtomicOutputFile>,
+    io_context: &IOContext,
+    field_lines: FieldLineSet3,
+) {
     if field_lines.verbosity().print_messages() {
         println!(
             "Saving field lines in {}",
@@ -590,8 +1349,9 @@ fn perform_post_tracing_actions(
             OutputType::H5Part => field_lines.save_as_h5part(
                 atomic_output_file.temporary_path(),
                 extra_atomic_output_file.as_ref().unwrap().temporary_path(),
-                root_arguments.is_present("drop-h5part-id"),
+                _root_arguments.is_present("drop-h5part-id"),
             ),
+            OutputType::Vtk => field_lines.save_into_vtk(atomic_output_file.temporary_path()),
         },
         "Error: Could not save output data: {}"
     );
@@ -11,9 +11,14 @@ use crate::{
         tracing::stepping::rkf::create_rkf_stepper_subcommand,
         utils,
     },
-    exit_on_error,
-    tracing::field_line::basic::{
-        BasicFieldLineTracerConfig, FieldLinePointSpacing, FieldLineTracingSense,
+    exit_on_error, exit_with_error,
+    geometry::{Dim3, Vec3},
+    tracing::{
+        field_line::basic::{
+            BasicFieldLineTracerConfig, FieldLinePointSpacing, FieldLineStoppingCriterion,
+            FieldLineTracingSense,
+        },
+        ftr,
     },
     update_command_graph,
 };
@@ -34,7 +39,21 @@ pub fn create_basic_field_line_tracer_subcommand(
              A field line can be traced in both or one direction relative to the vector field\n\
              direction, and output points can be produced at regular intervals or at the\n\
              natural positions provided by the stepper. The field line can have a maximum\n\
-             length.",
+             length, and can be stopped where a quantity crosses a threshold or where it\n\
+             leaves a region. The crossing point becomes the final point of the field line.\n\
+             In connectivity mode, the field line is traced in both directions but only its\n\
+             two ends are stored. For each end, the position, the length from the seed, the\n\
+             location (0: inside, 1: bottom, 2: top, 3: side boundary) and the stopping cause\n\
+             (0: not traced, 1: null, 2: sink, 3: out of bounds, 4: too many attempts,\n\
+             5: max length, 6 + i: stopping criterion i) are stored with suffix _backward\n\
+             or _forward. The connectivity class of each field line is stored as\n\
+             connectivity (0: undetermined, 1: closed, 2: open from positive polarity,\n\
+             3: open from negative polarity, 4: not connected to the bottom). Quantities\n\
+             extracted with --extracted-quantities are evaluated at both ends.\n\
+             Field lines are stored in the order of the seeds. With a regular slice seeder\n\
+             without coordinate bounds, the first slice coordinate varies fastest, and a\n\
+             2D map of the connectivity class on the grid of the seeder can be saved with\n\
+             the --connectivity-map option of the trace command.",
         )
         .arg(
             Arg::new("tracing-sense")
@@ -64,7 +83,79 @@ pub fn create_basic_field_line_tracer_subcommand(
                 .help("Field lines reaching lengths larger than this will be terminated\n")
                 .takes_value(true)
                 .default_value("inf"),
-        );
+        )
+        .arg(
+            Arg::new("stop-below")
+                .long("stop-below")
+                .require_equals(true)
+                .use_value_delimiter(true)
+                .require_value_delimiter(true)
+                .allow_hyphen_values(true)
+                .value_name("NAME:VALUE")
+                .help(
+                    "Stop field lines where the given quantity falls below the given value\n\
+                     (comma-separated, may be repeated)",
+                )
+                .takes_value(true)
+                .multiple_values(true)
+                .multiple_occurrences(true),
+        )
+        .arg(
+            Arg::new("stop-above")
+                .long("stop-above")
+                .require_equals(true)
+                .use_value_delimiter(true)
+                .require_value_delimiter(true)
+                .allow_hyphen_values(true)
+                .value_name("NAME:VALUE")
+                .help(
+                    "Stop field lines where the given quantity rises above the given value\n\
+                     (comma-separated, may be repeated)",
+                )
+                .takes_value(true)
+                .multiple_values(true)
+                .multiple_occurrences(true),
+        )
+        .arg(
+            Arg::new("stop-outside-x")
+                .long("stop-outside-x")
+                .require_equals(true)
+                .use_value_delimiter(true)
+                .require_value_delimiter(true)
+                .allow_hyphen_values(true)
+                .value_names(&["LOWER", "UPPER"])
+                .help("Stop field lines where they leave this range of x-coordinates")
+                .takes_value(true)
+                .number_of_values(2),
+        )
+        .arg(
+            Arg::new("stop-outside-y")
+                .long("stop-outside-y")
+                .require_equals(true)
+                .use_value_delimiter(true)
+                .require_value_delimiter(true)
+                .allow_hyphen_values(true)
+                .value_names(&["LOWER", "UPPER"])
+                .help("Stop field lines where they leave this range of y-coordinates")
+                .takes_value(true)
+                .number_of_values(2),
+        )
+        .arg(
+            Arg::new("stop-outside-z")
+                .long("stop-outside-z")
+                .require_equals(true)
+                .use_value_delimiter(true)
+                .require_value_delimiter(true)
+                .allow_hyphen_values(true)
+                .value_names(&["LOWER", "UPPER"])
+                .help("Stop field lines where they leave this range of z-coordinates")
+                .takes_value(true)
+                .number_of_values(2),
+        )
+        .arg(Arg::new("connectivity").long("connectivity").help(
+            "Store only the two ends of each field line instead of the full path\n\
+                     (the tracing sense and point spacing are ignored)",
+        ));
 
     add_subcommand_combinations!(command, command_name, true; rkf_stepper, poly_fit_interpolator, (slice_seeder, volume_seeder, manual_seeder))
 }
@@ -73,6 +164,17 @@ pub fn create_basic_field_line_tracer_subcommand(
 /// provided options.
 pub fn construct_basic_field_line_tracer_config_from_options(
     arguments: &ArgMatches,
+) -> BasicFieldLineTracerConfig {
+    BasicFieldLineTracerConfig {
+        stopping_criteria: construct_stopping_criteria_from_options(arguments),
+        ..construct_basic_2d_field_line_tracer_config_from_options(arguments)
+    }
+}
+
+/// Determines basic field line tracer parameters for tracing in 2D,
+/// which does not support stopping criteria, based on provided options.
+pub fn construct_basic_2d_field_line_tracer_config_from_options(
+    arguments: &ArgMatches,
 ) -> BasicFieldLineTracerConfig {
     let tracing_sense = utils::get_value_from_required_constrained_argument(
         arguments,
@@ -107,5 +209,131 @@ pub fn construct_basic_field_line_tracer_config_from_options(
         tracing_sense,
         point_spacing,
         max_length,
+        stopping_criteria: Vec::new(),
+    }
+}
+
+/// Determines field line stopping criteria based on provided options.
+///
+/// The criteria are ordered with the `stop-below` criteria first, followed
+/// by the `stop-above` criteria and finally any region criterion.
+fn construct_stopping_criteria_from_options(
+    arguments: &ArgMatches,
+) -> Vec<FieldLineStoppingCriterion> {
+    let parse_thresholds = |argument_name: &str| -> Vec<(String, ftr)> {
+        arguments
+            .values_of(argument_name)
+            .map(|values| values.collect::<Vec<_>>())
+            .unwrap_or_default()
+            .into_iter()
+            .map(|value_string| {
+                let (name, threshold) = value_string.split_once(':').unwrap_or_else(|| {
+                    exit_with_error!(
+                        "Error: Could not parse value of {}: Expected NAME:VALUE, got {}",
+                        argument_name,
+                        value_string
+                    )
+                });
+                (
+                    name.trim().to_lowercase(),
+                    utils::parse_value_string(argument_name, threshold),
+                )
+            })
+            .collect()
+    };
+
+    let mut stopping_criteria: Vec<_> = parse_thresholds("stop-below")
+        .into_iter()
+        .map(|(name, threshold)| FieldLineStoppingCriterion::Below(name, threshold))
+        .chain(
+            parse_thresholds("stop-above")
+                .into_iter()
+                .map(|(name, threshold)| FieldLineStoppingCriterion::Above(name, threshold)),
+        )
+        .collect();
+
+    let ranges: Vec<_> = ["stop-outside-x", "stop-outside-y", "stop-outside-z"]
+        .into_iter()
+        .map(|argument_name| {
+            utils::get_values_from_parseable_argument::<ftr>(arguments, argument_name)
+        })
+        .collect();
+    if ranges.iter().any(Option::is_some) {
+        let mut lower_bounds = Vec3::new(ftr::NEG_INFINITY, ftr::NEG_INFINITY, ftr::NEG_INFINITY);
+        let mut upper_bounds = Vec3::new(ftr::INFINITY, ftr::INFINITY, ftr::INFINITY);
+        for (dim, range) in Dim3::slice().into_iter().zip(ranges) {
+            if let Some(range) = range {
+                if range[0] > range[1] {
+                    exit_with_error!("Error: Minimum of stopping region exceeds maximum");
+                }
+                lower_bounds[dim] = range[0];
+                upper_bounds[dim] = range[1];
+            }
+        }
+        stopping_criteria.push(FieldLineStoppingCriterion::OutsideRegion(
+            lower_bounds,
+            upper_bounds,
+        ));
+    }
+
+    stopping_criteria
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn parse_config(args: &[&str]) -> BasicFieldLineTracerConfig {
+        let arguments = create_basic_field_line_tracer_subcommand("trace")
+            .try_get_matches_from(
+                ["basic_field_line_tracer"]
+                    .iter()
+                    .chain(args)
+                    .chain(&["manual_seeder", "--input-file=seeds.txt"]),
+            )
+            .unwrap();
+        construct_basic_field_line_tracer_config_from_options(&arguments)
+    }
+
+    #[test]
+    fn no_stopping_criteria_are_parsed_by_default() {
+        assert!(parse_config(&[]).stopping_criteria.is_empty());
+    }
+
+    #[test]
+    fn stopping_criteria_are_parsed_from_command_line() {
+        let config = parse_config(&[
+            "--stop-below=tg:1e4,r:-2",
+            "--stop-below=x:0.5",
+            "--stop-above=beta:1",
+            "--stop-outside-x=-1,1",
+            "--stop-outside-z=-10,-2",
+        ]);
+        assert_eq!(
+            config.stopping_criteria,
+            vec![
+                FieldLineStoppingCriterion::Below("tg".to_string(), 1e4),
+                FieldLineStoppingCriterion::Below("r".to_string(), -2.0),
+                FieldLineStoppingCriterion::Below("x".to_string(), 0.5),
+                FieldLineStoppingCriterion::Above("beta".to_string(), 1.0),
+                FieldLineStoppingCriterion::OutsideRegion(
+                    Vec3::new(-1.0, ftr::NEG_INFINITY, -10.0),
+                    Vec3::new(1.0, ftr::INFINITY, -2.0)
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn region_stopping_criterion_requires_two_bounds() {
+        assert!(create_basic_field_line_tracer_subcommand("trace")
+            .try_get_matches_from([
+                "basic_field_line_tracer",
+                "--stop-outside-y=0",
+                "manual_seeder",
+                "--input-file=seeds.txt",
+            ])
+            .is_err());
     }
 }
@@ -0,0 +1,394 @@
+//! Command line interface for finding magnetic nulls and tracing their skeleton.
+
+use super::{create_output_files, perform_post_tracing_actions};
+use crate::{
+    add_subcommand_combinations,
+    cli::{
+        interpolation::poly_fit::{
+            construct_poly_fit_interpolator_config_from_options,
+            create_poly_fit_interpolator_subcommand,
+        },
+        tracing::stepping::rkf::{
+            construct_rkf_stepper_config_from_options, create_rkf_stepper_subcommand,
+        },
+        utils as cli_utils,
+    },
+    exit_on_error,
+    field::{
+        CachingScalarFieldProvider3, DynScalarFieldProvider3, ScalarFieldCacher3,
+        ScalarFieldProvider3,
+    },
+    interpolation::{
+        poly_fit::{PolyFitInterpolator3, PolyFitInterpolatorConfig},
+        InterpGridVerifier3,
+    },
+    io::{snapshot::fdt, utils::IOContext},
+    tracing::{
+        field_line::basic::{
+            BasicFieldLineTracerConfig, FieldLinePointSpacing, FieldLineTracingSense,
+        },
+        null::{self, MagneticNullFinderConfig, NullSkeletonTracerConfig},
+        stepping::{
+            rkf::{rkf23::RKF23Stepper3, rkf45::RKF45Stepper3, RKFStepperConfig, RKFStepperType},
+            DynStepper3,
+        },
+    },
+    update_command_graph,
+};
+use clap::{Arg, ArgMatches, Command};
+
+/// Builds a representation of the `nulls` command line subcommand.
+pub fn create_nulls_subcommand(_parent_command_name: &'static str) -> Command<'static> {
+    let command_name = "nulls";
+
+    update_command_graph!(_parent_command_name, command_name);
+
+    let command = Command::new(command_name)
+        .about("Find magnetic nulls and trace their spine and fan field lines")
+        .long_about(
+            "Find magnetic nulls and trace their spine and fan field lines.\n\
+             Nulls are located cell by cell with Newton iteration and classified from the\n\
+             eigenvalues of the field Jacobian. The spine and fan field lines of each null\n\
+             are saved as a field line set, and a catalog of the nulls is saved as a text\n\
+             table with extension nulls.txt next to the field line file.",
+        )
+        .after_help(
+            "You can use subcommands to configure each action. The subcommands must be\n\
+             specified in the order stepper -> interpolator, with options for each action\n\
+             directly following the subcommand. Any action(s) can be left unspecified, in\n\
+             which case the default implementation and parameters are used for that action.",
+        )
+        .arg(
+            Arg::new("output-file")
+                .value_name("OUTPUT_FILE")
+                .help(
+                    "Path of the file where the field line data should be saved\n\
+                       Writes in the following format based on the file extension:\
+                       \n    *.fl: Creates a binary file readable by the backstaff Python package\
+                       \n    *.pickle: Creates a Python pickle file (requires the pickle feature)\
+                       \n    *.json: Creates a JSON file (requires the json feature)\
+                       \n    *.h5part: Creates a H5Part file (requires the hdf5 feature)\
+                       \n    *.vtp: Creates a VTK XML poly data file",
+                )
+                .required(true)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("overwrite")
+                .long("overwrite")
+                .help("Automatically overwrite any existing files (unless listed as protected)")
+                .conflicts_with("no-overwrite"),
+        )
+        .arg(
+            Arg::new("no-overwrite")
+                .long("no-overwrite")
+                .help("Do not overwrite any existing files")
+                .conflicts_with("overwrite"),
+        )
+        .arg(
+            Arg::new("vector-quantity")
+                .short('q')
+                .long("vector-quantity")
+                .require_equals(true)
+                .value_name("NAME")
+                .help("Vector field from the snapshot to find nulls in")
+                .takes_value(true)
+                .default_value("b"),
+        )
+        .arg(
+            Arg::new("max-newton-iterations")
+                .long("max-newton-iterations")
+                .require_equals(true)
+                .value_name("NUMBER")
+                .help("Maximum number of Newton iterations for locating a null\n")
+                .takes_value(true)
+                .default_value("20"),
+        )
+        .arg(
+            Arg::new("position-tolerance")
+                .long("position-tolerance")
+                .require_equals(true)
+                .value_name("VALUE")
+                .help(
+                    "Null positions are converged when the Newton update is smaller than\n\
+                     this fraction of the grid cell extent\n",
+                )
+                .takes_value(true)
+                .default_value("1e-6"),
+        )
+        .arg(
+            Arg::new("fan-field-lines")
+                .long("fan-field-lines")
+                .require_equals(true)
+                .value_name("NUMBER")
+                .help("Number of field lines to trace in the fan plane of each null\n")
+                .takes_value(true)
+                .default_value("16"),
+        )
+        .arg(
+            Arg::new("seed-distance")
+                .long("seed-distance")
+                .require_equals(true)
+                .value_name("VALUE")
+                .help(
+                    "Distance from the null where spine and fan field lines start, as a\n\
+                     fraction of the grid cell extent\n",
+                )
+                .takes_value(true)
+                .default_value("0.1"),
+        )
+        .arg(
+            Arg::new("point-spacing")
+                .long("point-spacing")
+                .require_equals(true)
+                .value_name("TYPE")
+                .help("Form of spacing between field line points\n")
+                .takes_value(true)
+                .possible_values(["regular", "natural"])
+                .default_value("regular"),
+        )
+        .arg(
+            Arg::new("max-length")
+                .long("max-length")
+                .require_equals(true)
+                .value_name("VALUE")
+                .help("Field lines reaching lengths larger than this will be terminated\n")
+                .takes_value(true)
+                .default_value("inf"),
+        )
+        .arg(
+            Arg::new("extracted-quantities")
+                .long("extracted-quantities")
+                .require_equals(true)
+                .use_value_delimiter(true)
+                .require_value_delimiter(true)
+                .value_name("NAMES")
+                .help("List of quantities to extract along field line paths (comma-separated)")
+                .takes_value(true)
+                .multiple_values(true),
+        )
+        .arg(
+            Arg::new("extracted-seed-quantities")
+                .long("extracted-seed-quantities")
+                .require_equals(true)
+                .use_value_delimiter(true)
+                .require_value_delimiter(true)
+                .value_name("NAMES")
+                .help("List of quantities to extract at seed positions (comma-separated)")
+                .takes_value(true)
+                .multiple_values(true),
+        )
+        .arg(
+            Arg::new("twist-diagnostics")
+                .long("twist-diagnostics")
+                .help(
+                    "Compute the twist number, integrated current and writhe of each field\n\
+                     line (requires the current density j, e.g. from `derive`)",
+                ),
+        )
+        .arg(Arg::new("drop-h5part-id").long("drop-h5part-id").help(
+            "Reduce H5Part file size by excluding particle IDs required by some tools\n\
+                     (e.g. VisIt)",
+        ))
+        .arg(
+            Arg::new("verbose")
+                .short('v')
+                .long("verbose")
+                .help("Print status messages while finding nulls and tracing field lines"),
+        )
+        .arg(
+            Arg::new("progress")
+                .short('p')
+                .long("progress")
+                .help("Show progress bars for finding nulls and tracing (also implies `verbose`)"),
+        )
+        .arg(
+            Arg::new("print-parameter-values")
+                .long("print-parameter-values")
+                .help("Prints the values of all the parameters that will be used")
+                .hide(true),
+        );
+
+    add_subcommand_combinations!(command, command_name, false; rkf_stepper, poly_fit_interpolator)
+}
+
+/// Runs the actions for the `nulls` subcommand using the given arguments.
+pub fn run_nulls_subcommand(
+    arguments: &ArgMatches,
+    provider: DynScalarFieldProvider3<fdt>,
+    io_context: &mut IOContext,
+) {
+    let finder_config = MagneticNullFinderConfig {
+        max_newton_iterations: cli_utils::get_value_from_required_parseable_argument(
+            arguments,
+            "max-newton-iterations",
+        ),
+        position_tolerance: cli_utils::get_finite_float_value_from_required_parseable_argument(
+            arguments,
+            "position-tolerance",
+        ),
+    };
+    let skeleton_config = NullSkeletonTracerConfig {
+        number_of_fan_field_lines: cli_utils::get_value_from_required_parseable_argument(
+            arguments,
+            "fan-field-lines",
+        ),
+        seed_distance: cli_utils::get_finite_float_value_from_required_parseable_argument(
+            arguments,
+            "seed-distance",
+        ),
+    };
+    let point_spacing = cli_utils::get_value_from_required_constrained_argument(
+        arguments,
+        "point-spacing",
+        &["regular", "natural"],
+        &[
+            FieldLinePointSpacing::Regular,
+            FieldLinePointSpacing::Natural,
+        ],
+    );
+    let max_length = match arguments
+        .value_of("max-length")
+        .expect("No value for argument with default")
+    {
+        "inf" => None,
+        length_str => Some(exit_on_error!(
+            length_str.trim().parse::<f64>(),
+            "Error: Could not parse value of max-length: {}"
+        )),
+    };
+    let tracer_config = BasicFieldLineTracerConfig {
+        tracing_sense: FieldLineTracingSense::Both,
+        point_spacing,
+        max_length,
+        stopping_criteria: Vec::new(),
+    };
+
+    if arguments.is_present("print-parameter-values") {
+        println!(
+            "{:#?}\n{:#?}\n{:#?}",
+            finder_config, skeleton_config, tracer_config
+        );
+    }
+
+    let ((stepper_type, stepper_config), stepper_arguments) =
+        if let Some(stepper_arguments) = arguments.subcommand_matches("rkf_stepper") {
+            (
+                construct_rkf_stepper_config_from_options(stepper_arguments),
+                stepper_arguments,
+            )
+        } else {
+            (
+                (RKFStepperType::RKF45, RKFStepperConfig::default()),
+                arguments,
+            )
+        };
+
+    if arguments.is_present("print-parameter-values") {
+        println!("{:#?}\nstepper_type: {:?}", stepper_config, stepper_type);
+    }
+
+    let stepper: DynStepper3<fdt> = match stepper_type {
+        RKFStepperType::RKF23 => Box::new(RKF23Stepper3::new(stepper_config)),
+        RKFStepperType::RKF45 => Box::new(RKF45Stepper3::new(stepper_config)),
+    };
+
+    let interpolator_config = if let Some(interpolator_arguments) =
+        stepper_arguments.subcommand_matches("poly_fit_interpolator")
+    {
+        construct_poly_fit_interpolator_config_from_options(interpolator_arguments)
+    } else {
+        PolyFitInterpolatorConfig::default()
+    };
+
+    if arguments.is_present("print-parameter-values") {
+        println!("{:#?}", interpolator_config);
+    }
+
+    let interpolator = PolyFitInterpolator3::new(interpolator_config);
+
+    let verbosity = cli_utils::parse_verbosity(arguments, true);
+    let mut snapshot = Box::new(ScalarFieldCacher3::new_manual_cacher(
+        provider,
+        verbosity.clone(),
+    ));
+
+    exit_on_error!(
+        interpolator.verify_grid(snapshot.grid()),
+        "Invalid input grid for finding nulls: {}"
+    );
+
+    let (output_type, atomic_output_file, extra_atomic_output_file) =
+        match create_output_files(arguments, io_context, &verbosity) {
+            Some(output_files) => output_files,
+            None => return,
+        };
+
+    let catalog_atomic_output_file = exit_on_error!(
+        io_context.create_atomic_output_file(
+            atomic_output_file.target_path().with_extension("nulls.txt")
+        ),
+        "Error: Could not create temporary output file: {}"
+    );
+    if !catalog_atomic_output_file.check_if_write_allowed(io_context, &verbosity) {
+        return;
+    }
+
+    let quantity = arguments
+        .value_of("vector-quantity")
+        .expect("No value for argument with default");
+    exit_on_error!(
+        snapshot.cache_vector_field(quantity),
+        "Error: Could not read quantity {0} in snapshot: {1}",
+        quantity
+    );
+
+    let nulls = null::find_magnetic_nulls(
+        snapshot.cached_vector_field(quantity),
+        &interpolator,
+        &finder_config,
+        &verbosity,
+    );
+
+    if verbosity.print_messages() {
+        println!(
+            "Saving null catalog in {}",
+            catalog_atomic_output_file
+                .target_path()
+                .file_name()
+                .unwrap()
+                .to_string_lossy()
+        );
+    }
+    exit_on_error!(
+        null::save_magnetic_null_catalog(catalog_atomic_output_file.temporary_path(), &nulls),
+        "Error: Could not save null catalog: {}"
+    );
+    exit_on_error!(
+        io_context.close_atomic_output_file(catalog_atomic_output_file),
+        "Error: Could not move temporary output file to target path: {}"
+    );
+
+    let field_lines = null::trace_null_skeleton(
+        quantity,
+        &*snapshot,
+        &nulls,
+        &skeleton_config,
+        &tracer_config,
+        &interpolator,
+        stepper,
+        verbosity,
+    );
+
+    perform_post_tracing_actions(
+        arguments,
+        output_type,
+        atomic_output_file,
+        extra_atomic_output_file,
+        io_context,
+        snapshot,
+        &interpolator,
+        field_lines,
+        None,
+    );
+}
@@ -0,0 +1,248 @@
+//! Command line interface for tracing field lines in a 2D plane.
+
+use super::stepping::rkf::{
+    construct_rkf_stepper_config_from_options, create_rkf_stepper_subcommand,
+};
+use crate::{
+    add_subcommand_combinations,
+    cli::{
+        interpolation::poly_fit::{
+            construct_poly_fit_interpolator_config_from_options,
+            create_poly_fit_interpolator_subcommand,
+        },
+        seeding::slice::{
+            create_slice_seeder_for_slice_from_arguments, random::create_random_subcommand,
+            regular::create_regular_subcommand, stratified::create_stratified_subcommand,
+        },
+        tracing::field_line::basic::construct_basic_2d_field_line_tracer_config_from_options,
+        utils as cli_utils,
+    },
+    exit_with_error,
+    field::{FieldGrid3, PlaneVectorField3},
+    geometry::{Dim2, Dim3, Point2},
+    grid::fgr,
+    interpolation::poly_fit::{PolyFitInterpolator2, PolyFitInterpolatorConfig},
+    io::{snapshot::fdt, utils::IOContext},
+    seeding::Seeder3,
+    tracing::{
+        field_line::{basic::BasicFieldLineTracer2, FieldLineSet3},
+        stepping::{
+            rkf::{rkf23::RKF23Stepper2, rkf45::RKF45Stepper2, RKFStepperConfig, RKFStepperType},
+            DynStepper2,
+        },
+    },
+    update_command_graph,
+};
+use clap::{Arg, ArgMatches, Command};
+
+/// Builds a representation of the `trace` command line subcommand for
+/// tracing field lines in a 2D plane.
+pub fn create_plane_trace_subcommand(_parent_command_name: &'static str) -> Command<'static> {
+    let command_name = "trace";
+
+    update_command_graph!(_parent_command_name, command_name);
+
+    let command = Command::new(command_name)
+        .about("Trace field lines of the in-plane components of a vector field in the slice")
+        .long_about(
+            "Trace field lines of the in-plane components of a vector field in the slice.\n\
+             The field lines are traced in 2D using only the two vector components along the\n\
+             slice, and are stored as 3D field lines lying in the slice plane.",
+        )
+        .after_help(
+            "You can use subcommands to configure each action. The subcommands must be\n\
+             specified in the order stepper -> interpolator -> seeder, with options for each\n\
+             action directly following the subcommand. The stepper and interpolator can be\n\
+             left unspecified, in which case the default implementation and parameters are\n\
+             used for that action.",
+        )
+        .arg(
+            Arg::new("output-file")
+                .value_name("OUTPUT_FILE")
+                .help(
+                    "Path of the file where the field line data should be saved\n\
+                       Writes in the following format based on the file extension:\
+                       \n    *.fl: Creates a binary file readable by the backstaff Python package\
+                       \n    *.pickle: Creates a Python pickle file (requires the pickle feature)\
+                       \n    *.json: Creates a JSON file (requires the json feature)\
+                       \n    *.h5part: Creates a H5Part file (requires the hdf5 feature)\
+                       \n    *.vtp: Creates a VTK XML poly data file",
+                )
+                .required(true)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("overwrite")
+                .long("overwrite")
+                .help("Automatically overwrite any existing files (unless listed as protected)")
+                .conflicts_with("no-overwrite"),
+        )
+        .arg(
+            Arg::new("no-overwrite")
+                .long("no-overwrite")
+                .help("Do not overwrite any existing files")
+                .conflicts_with("overwrite"),
+        )
+        .arg(
+            Arg::new("vector-quantity")
+                .short('q')
+                .long("vector-quantity")
+                .require_equals(true)
+                .value_name("NAME")
+                .help("Vector field from the snapshot to trace")
+                .takes_value(true)
+                .default_value("b"),
+        )
+        .arg(
+            Arg::new("tracing-sense")
+                .long("tracing-sense")
+                .require_equals(true)
+                .value_name("SENSE")
+                .help("Direction(s) to trace the field line relative to the field direction\n")
+                .takes_value(true)
+                .possible_values(["both", "same", "opposite"])
+                .default_value("both"),
+        )
+        .arg(
+            Arg::new("point-spacing")
+                .long("point-spacing")
+                .require_equals(true)
+                .value_name("SPACING")
+                .help("Form of spacing between field line points\n")
+                .takes_value(true)
+                .possible_values(["regular", "natural"])
+                .default_value("regular"),
+        )
+        .arg(
+            Arg::new("max-length")
+                .long("max-length")
+                .require_equals(true)
+                .value_name("VALUE")
+                .help("Field lines reaching lengths larger than this will be terminated\n")
+                .takes_value(true)
+                .default_value("inf"),
+        )
+        .arg(Arg::new("drop-h5part-id").long("drop-h5part-id").help(
+            "Reduce H5Part file size by excluding particle IDs required by some tools\n\
+                     (e.g. VisIt)",
+        ))
+        .arg(
+            Arg::new("verbose")
+                .short('v')
+                .long("verbose")
+                .help("Print status messages while tracing field lines"),
+        )
+        .arg(
+            Arg::new("progress")
+                .short('p')
+                .long("progress")
+                .help("Show progress bar for tracing (also implies `verbose`)"),
+        )
+        .arg(
+            Arg::new("print-parameter-values")
+                .long("print-parameter-values")
+                .help("Prints the values of all the parameters that will be used")
+                .hide(true),
+        );
+
+    add_subcommand_combinations!(command, command_name, true; rkf_stepper, poly_fit_interpolator, (regular, random, stratified))
+}
+
+/// Runs the actions for the `trace` subcommand of a slice, tracing field lines
+/// of the given vector field lying in the plane normal to the given axis at the
+/// given coordinate.
+pub fn run_plane_trace_subcommand(
+    arguments: &ArgMatches,
+    grid: &FieldGrid3,
+    field: &PlaneVectorField3<fdt>,
+    axis: Dim3,
+    coord: fgr,
+    io_context: &mut IOContext,
+) {
+    let verbosity = cli_utils::parse_verbosity(arguments, false);
+
+    let (output_type, atomic_output_file, extra_atomic_output_file) =
+        if let Some(output_files) = super::create_output_files(arguments, io_context, &verbosity) {
+            output_files
+        } else {
+            return;
+        };
+
+    let tracer_config = construct_basic_2d_field_line_tracer_config_from_options(arguments);
+    if arguments.is_present("print-parameter-values") {
+        println!("{:#?}", tracer_config);
+    }
+    let tracer = BasicFieldLineTracer2::new(tracer_config);
+
+    let ((stepper_type, stepper_config), stepper_arguments) =
+        if let Some(stepper_arguments) = arguments.subcommand_matches("rkf_stepper") {
+            (
+                construct_rkf_stepper_config_from_options(stepper_arguments),
+                stepper_arguments,
+            )
+        } else {
+            (
+                (RKFStepperType::RKF45, RKFStepperConfig::default()),
+                arguments,
+            )
+        };
+    if arguments.is_present("print-parameter-values") {
+        println!("{:#?}\nstepper_type: {:?}", stepper_config, stepper_type);
+    }
+    let stepper: DynStepper2<fdt> = match stepper_type {
+        RKFStepperType::RKF23 => Box::new(RKF23Stepper2::new(stepper_config)),
+        RKFStepperType::RKF45 => Box::new(RKF45Stepper2::new(stepper_config)),
+    };
+
+    let (interpolator_config, interpolator_arguments) = if let Some(interpolator_arguments) =
+        stepper_arguments.subcommand_matches("poly_fit_interpolator")
+    {
+        (
+            construct_poly_fit_interpolator_config_from_options(interpolator_arguments),
+            interpolator_arguments,
+        )
+    } else {
+        (PolyFitInterpolatorConfig::default(), stepper_arguments)
+    };
+    if arguments.is_present("print-parameter-values") {
+        println!("{:#?}", interpolator_config);
+    }
+    let interpolator = PolyFitInterpolator2::new(interpolator_config);
+
+    let seeder =
+        create_slice_seeder_for_slice_from_arguments(interpolator_arguments, grid, axis, coord)
+            .unwrap_or_else(|| exit_with_error!("Error: No seeder specified"));
+
+    let plane_axes = Dim3::slice_except(axis);
+    let start_positions: Vec<_> = seeder
+        .points()
+        .iter()
+        .map(|point| {
+            let mut position = Point2::origin();
+            for (dim, plane_axis) in Dim2::slice().into_iter().zip(plane_axes) {
+                position[dim] = point[plane_axis];
+            }
+            position
+        })
+        .collect();
+
+    let field_lines = FieldLineSet3::trace_in_plane(
+        &field.in_plane_vector_field(axis),
+        axis,
+        coord,
+        &start_positions,
+        &tracer,
+        &interpolator,
+        stepper,
+        verbosity,
+    );
+
+    super::save_field_lines(
+        arguments,
+        output_type,
+        atomic_output_file,
+        extra_atomic_output_file,
+        io_context,
+        field_lines,
+    );
+}
@@ -33,6 +33,10 @@ pub const KBOLTZMANN: fcn = 1.380_658e-16;
 pub const HPLANCK: fcn = 6.626_075_5e-27;
 /// Stefan-Boltzmann constant [erg/(cm^2 s K^4)].
 pub const STEFBOLTZ: fcn = 5.670_400e-5;
+/// Coefficient of the Spitzer thermal conductivity along the magnetic field [erg/(s cm K^(7/2))].
+pub const KAPPA_SPITZER: fcn = 1.1e-6;
+/// Adiabatic index of a monatomic ideal gas.
+pub const GAMMA_IDEAL: fcn = 5.0 / 3.0;
 /// Bohr radius [cm].
 pub const RBOHR: fcn = 5.291_773_49e-9;
 /// Ionization potential of hydrogen [erg].
@@ -49,6 +53,10 @@ pub const XI_H: fcn = 13.595;
 pub const XI_HEI: fcn = 24.587;
 /// Second ionization energy of a helium atom [eV].
 pub const XI_HEII: fcn = 54.418;
+/// Binding energy of the extra electron in a negative hydrogen ion [eV].
+pub const XI_HMINUS: fcn = 0.754;
+/// Gravitational acceleration at the solar surface [cm/s^2].
+pub const G_SUN: fcn = 2.74e4;
 
 // Unit conversion factors
 
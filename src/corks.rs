@@ -2,12 +2,14 @@
 
 use crate::{
     field::{CachingScalarFieldProvider3, ScalarField3, VectorField3},
-    geometry::{Idx3, Point3, Vec3},
+    geometry::{Dim3, Idx3, Point3, Vec3},
     grid::{Grid3, GridPointQuery3},
     interpolation::Interpolator3,
     io::{
         snapshot::{fdt, MASS_DENSITY_VARIABLE_NAME, MOMENTUM_VARIABLE_NAME},
-        utils, Verbosity,
+        utils,
+        vtk::{self, VtkDataArray},
+        Verbosity,
     },
     seeding::Seeder3,
 };
@@ -72,6 +74,11 @@ pub struct CorkSet {
 #[derive(Clone, Copy, Debug)]
 pub struct HeunCorkStepper;
 
+/// Uses the Heun method for advecting corks with the horizontal component of
+/// the velocity, keeping the corks at a fixed height.
+#[derive(Clone, Copy, Debug)]
+pub struct HorizontalHeunCorkStepper;
+
 /// Traces a constant number of initial corks.
 #[derive(Clone, Copy, Debug)]
 pub struct ConstantCorkAdvector {
@@ -343,6 +350,21 @@ impl CorkSet {
         &self.verbosity
     }
 
+    /// Returns an iterator over the index and current position of each cork
+    /// that has not been terminated.
+    pub fn active_cork_positions(&self) -> impl Iterator<Item = (usize, &Point3<fco>)> {
+        self.corks
+            .iter()
+            .enumerate()
+            .filter(|(_, cork)| !cork.is_terminated())
+            .map(|(idx, cork)| (idx, cork.last_position()))
+    }
+
+    /// Returns the point in time that the corks have been evolved to.
+    pub fn current_time(&self) -> fco {
+        *self.times.last().unwrap()
+    }
+
     fn number_of_corks(&self) -> usize {
         self.corks.len()
     }
@@ -353,10 +375,6 @@ impl CorkSet {
         self.times.len()
     }
 
-    fn current_time(&self) -> fco {
-        *self.times.last().unwrap()
-    }
-
     #[allow(dead_code)]
     fn current_time_idx(&self) -> usize {
         self.number_of_times() - 1
@@ -473,6 +491,74 @@ impl CorkSet {
     pub fn save_as_pickle(&self, output_file_path: &Path) -> io::Result<()> {
         utils::save_data_as_pickle(output_file_path, &self)
     }
+
+    /// Saves the cork trajectories as a VTK XML poly data file at the given path.
+    ///
+    /// Each cork trajectory becomes a poly-line cell, with the time, velocity and
+    /// sampled quantities as point data.
+    pub fn save_as_vtk(&self, output_file_path: &Path) -> io::Result<()> {
+        let line_lengths: Vec<_> = self.corks.iter().map(|cork| cork.positions.len()).collect();
+
+        let points = VtkDataArray::new(
+            "points".to_string(),
+            3,
+            self.corks
+                .iter()
+                .flat_map(|cork| cork.positions.iter())
+                .flat_map(|position| [position[Dim3::X], position[Dim3::Y], position[Dim3::Z]])
+                .collect::<Vec<_>>(),
+        );
+
+        let mut point_data = vec![
+            VtkDataArray::new(
+                "time".to_string(),
+                1,
+                self.corks
+                    .iter()
+                    .flat_map(|cork| {
+                        self.times[cork.first_time_idx..][..cork.positions.len()]
+                            .iter()
+                            .copied()
+                    })
+                    .collect::<Vec<_>>(),
+            ),
+            VtkDataArray::new(
+                "velocity".to_string(),
+                3,
+                self.corks
+                    .iter()
+                    .flat_map(|cork| cork.velocities.iter())
+                    .flat_map(|velocity| [velocity[Dim3::X], velocity[Dim3::Y], velocity[Dim3::Z]])
+                    .collect::<Vec<_>>(),
+            ),
+        ];
+        for (quantity_idx, name) in self.scalar_quantity_names.iter().enumerate() {
+            point_data.push(VtkDataArray::new(
+                name.clone(),
+                1,
+                self.corks
+                    .iter()
+                    .flat_map(|cork| cork.scalar_field_values[quantity_idx].iter().copied())
+                    .collect::<Vec<_>>(),
+            ));
+        }
+        for (quantity_idx, name) in self.vector_quantity_names.iter().enumerate() {
+            point_data.push(VtkDataArray::new(
+                name.clone(),
+                3,
+                self.corks
+                    .iter()
+                    .flat_map(|cork| {
+                        cork.vector_field_values[quantity_idx]
+                            .iter()
+                            .flat_map(|vector| [vector[Dim3::X], vector[Dim3::Y], vector[Dim3::Z]])
+                    })
+                    .collect::<Vec<_>>(),
+            ));
+        }
+
+        vtk::write_poly_lines(output_file_path, &line_lengths, points, &point_data, &[])
+    }
 }
 
 #[cfg(feature = "serialization")]
@@ -546,38 +632,34 @@ impl CorkStepper for HeunCorkStepper {
         momentum_field: &VectorField3<fdt>,
         interpolator: &dyn Interpolator3<fdt>,
     ) {
-        if cork.is_terminated() {
-            return;
-        }
-        let mut next_position = cork.last_position() + cork.last_velocity() * step_duration;
+        step_cork_with_heun(
+            cork,
+            step_duration,
+            mass_density_field,
+            momentum_field,
+            interpolator,
+            false,
+        );
+    }
+}
 
-        match evaluate_velocity(
-            &mut next_position,
+impl CorkStepper for HorizontalHeunCorkStepper {
+    fn step_one_cork(
+        &self,
+        cork: &mut Cork,
+        step_duration: fco,
+        mass_density_field: &ScalarField3<fdt>,
+        momentum_field: &VectorField3<fdt>,
+        interpolator: &dyn Interpolator3<fdt>,
+    ) {
+        step_cork_with_heun(
+            cork,
+            step_duration,
             mass_density_field,
             momentum_field,
             interpolator,
-        ) {
-            Some((_, next_velocity)) => {
-                next_position = cork.last_position()
-                    + (cork.last_velocity() + next_velocity) * (0.5 * step_duration);
-                match evaluate_velocity(
-                    &mut next_position,
-                    mass_density_field,
-                    momentum_field,
-                    interpolator,
-                ) {
-                    Some((next_position_indices, next_velocity)) => {
-                        cork.add_next_position_and_velocity(
-                            next_position,
-                            next_position_indices,
-                            next_velocity,
-                        );
-                    }
-                    None => cork.terminate(),
-                }
-            }
-            None => cork.terminate(),
-        }
+            true,
+        );
     }
 }
 
@@ -629,6 +711,54 @@ impl CorkAdvector for ConstantCorkAdvector {
     }
 }
 
+fn step_cork_with_heun(
+    cork: &mut Cork,
+    step_duration: fco,
+    mass_density_field: &ScalarField3<fdt>,
+    momentum_field: &VectorField3<fdt>,
+    interpolator: &dyn Interpolator3<fdt>,
+    horizontal_only: bool,
+) {
+    if cork.is_terminated() {
+        return;
+    }
+    let evaluate = |position: &mut Point3<fco>| {
+        evaluate_velocity(position, mass_density_field, momentum_field, interpolator).map(
+            |(indices, mut velocity)| {
+                if horizontal_only {
+                    velocity[Dim3::Z] = 0.0;
+                }
+                (indices, velocity)
+            },
+        )
+    };
+
+    let mut last_velocity = cork.last_velocity().clone();
+    if horizontal_only {
+        last_velocity[Dim3::Z] = 0.0;
+    }
+
+    let mut next_position = cork.last_position() + &last_velocity * step_duration;
+
+    match evaluate(&mut next_position) {
+        Some((_, next_velocity)) => {
+            next_position =
+                cork.last_position() + (last_velocity + next_velocity) * (0.5 * step_duration);
+            match evaluate(&mut next_position) {
+                Some((next_position_indices, next_velocity)) => {
+                    cork.add_next_position_and_velocity(
+                        next_position,
+                        next_position_indices,
+                        next_velocity,
+                    );
+                }
+                None => cork.terminate(),
+            }
+        }
+        None => cork.terminate(),
+    }
+}
+
 fn evaluate_velocity(
     position: &mut Point3<fco>,
     mass_density_field: &ScalarField3<fdt>,
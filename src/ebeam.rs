@@ -12,7 +12,7 @@ use self::{
     propagation::{DepletionStatus, PropagationResult, Propagator},
 };
 use crate::{
-    field::{CachingScalarFieldProvider3, ScalarField3, VectorField3},
+    field::{CachingScalarFieldProvider3, FieldGrid3, ScalarField3, VectorField3},
     geometry::{
         Dim3::{X, Y, Z},
         Point3, Vec3,
@@ -23,7 +23,7 @@ use crate::{
     num::BFloat,
     tracing::{
         self,
-        field_line::{self, FieldLineSetProperties3},
+        field_line::{self, deposition::FieldLineDepositionQuantity, FieldLineSetProperties3},
         ftr,
         stepping::{DynStepper3, StepperInstruction},
         TracerResult,
@@ -35,6 +35,7 @@ use std::{
     collections::HashMap,
     io::{self, Write},
     path::Path,
+    sync::Arc,
     time::Instant,
 };
 
@@ -642,6 +643,22 @@ impl<A: Accelerator> ElectronBeamSwarm<A> {
             .insert(field.name().to_string(), vectors);
     }
 
+    /// Deposits the beam trajectories onto the given grid, producing the given
+    /// deposition quantity as a cell-centered scalar field.
+    pub fn deposit_onto_grid(
+        &self,
+        grid: Arc<FieldGrid3>,
+        quantity: &FieldLineDepositionQuantity,
+    ) -> io::Result<ScalarField3<fdt>> {
+        field_line::deposition::deposit_field_lines_onto_grid(
+            grid,
+            &self.properties.varying_scalar_values,
+            &(&self.upper_bounds - &self.lower_bounds),
+            quantity,
+            &self.verbosity,
+        )
+    }
+
     /// Serializes the electron beam data into JSON format and saves at the given path.
     #[cfg(feature = "json")]
     pub fn save_as_json(&self, output_file_path: &Path) -> io::Result<()> {
@@ -738,6 +755,14 @@ impl<A: Accelerator> ElectronBeamSwarm<A> {
         )
     }
 
+    /// Serializes the electron beam data into VTK XML poly data format and saves at the given path.
+    pub fn save_as_vtk(&self, output_file_path: &Path) -> io::Result<()> {
+        field_line::save_field_line_data_as_vtk(
+            output_file_path,
+            self.properties.clone().into_field_line_set_properties(),
+        )
+    }
+
     /// Serializes the electron beam data into a custom binary format and saves at the given path,
     /// consuming the electron beam swarm in the process.
     pub fn save_into_custom_binary(self, output_file_path: &Path) -> io::Result<()> {
@@ -750,6 +775,15 @@ impl<A: Accelerator> ElectronBeamSwarm<A> {
         self.acceleration_data.write_into("fl", &mut file)
     }
 
+    /// Serializes the electron beam data into VTK XML poly data format and saves at the given path,
+    /// consuming the electron beam swarm in the process.
+    pub fn save_into_vtk(self, output_file_path: &Path) -> io::Result<()> {
+        field_line::save_field_line_data_as_vtk(
+            output_file_path,
+            self.properties.into_field_line_set_properties(),
+        )
+    }
+
     /// Serializes the electron beam data into a H5Part format and saves to the given path,
     /// consuming the electron beam swarm in the process.
     #[cfg(feature = "hdf5")]
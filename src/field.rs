@@ -1,5 +1,8 @@
 //! Scalar and vector fields.
 
+pub mod profile;
+pub mod temporal;
+
 #[cfg(feature = "derivation")]
 pub mod quantities;
 
@@ -41,6 +44,12 @@ use serde::Serialize;
 #[cfg(feature = "pickle")]
 use crate::io::utils::save_data_as_pickle;
 
+#[cfg(feature = "npz")]
+use crate::io::utils as io_utils;
+
+#[cfg(feature = "npz")]
+use ndarray_npy::WritableElement;
+
 #[cfg(feature = "for-testing")]
 use approx::{AbsDiffEq, RelativeEq};
 
@@ -79,6 +88,22 @@ pub trait ScalarFieldProvider3<F: BFloat>: AsScalarFieldProvider3<F> + Sync {
         Ok(Arc::new(self.produce_scalar_field(variable_name)?))
     }
 
+    /// Produces the part of the field of the specified 3D scalar variable lying in the
+    /// given subgrid, which starts at the given indices in the full grid.
+    ///
+    /// By default the full field is provided and the subfield extracted from it, but
+    /// providers that can fetch subdomains directly should override this.
+    fn produce_scalar_subfield(
+        &mut self,
+        variable_name: &str,
+        subgrid: Arc<FieldGrid3>,
+        start_indices: &Idx3<usize>,
+    ) -> io::Result<ScalarField3<F>> {
+        Ok(self
+            .provide_scalar_field(variable_name)?
+            .subfield(subgrid, start_indices))
+    }
+
     /// Produces the field of the specified 3D vector variable and returns it by value.
     fn produce_vector_field(&mut self, variable_name: &str) -> io::Result<VectorField3<F>> {
         Ok(VectorField3::new(
@@ -396,6 +421,26 @@ impl<F: BFloat> ScalarFieldProvider3<F> for ScalarFieldCacher3<F> {
         Ok(self.provide_scalar_field(variable_name)?.as_ref().clone())
     }
 
+    fn produce_scalar_subfield(
+        &mut self,
+        variable_name: &str,
+        subgrid: Arc<FieldGrid3>,
+        start_indices: &Idx3<usize>,
+    ) -> io::Result<ScalarField3<F>> {
+        // Subfields are not cached, but can be extracted from cached fields
+        match self.scalar_fields.get(variable_name) {
+            Some(cached_field) => {
+                if self.verbosity.print_messages() {
+                    println!("Using cached {}", variable_name);
+                }
+                Ok(cached_field.field().subfield(subgrid, start_indices))
+            }
+            None => self
+                .provider
+                .produce_scalar_subfield(variable_name, subgrid, start_indices),
+        }
+    }
+
     fn provide_scalar_field(&mut self, variable_name: &str) -> io::Result<Arc<ScalarField3<F>>> {
         let request_count = self.increment_request_count(variable_name);
 
@@ -940,11 +985,25 @@ impl ScalarFieldProvider3<fdt> for ExtractedScalarFieldProvider3 {
     }
 
     fn produce_scalar_field(&mut self, variable_name: &str) -> io::Result<ScalarField3<fdt>> {
-        let field = self.provider_mut().provide_scalar_field(variable_name)?;
         if self.verbosity.print_messages() {
             println!("Extracting {} in subgrid", variable_name);
         }
-        Ok(field.subfield(self.arc_with_grid(), &self.lower_indices))
+        let new_grid = self.arc_with_grid();
+        let lower_indices = self.lower_indices.clone();
+        self.provider_mut()
+            .produce_scalar_subfield(variable_name, new_grid, &lower_indices)
+    }
+
+    fn produce_scalar_subfield(
+        &mut self,
+        variable_name: &str,
+        subgrid: Arc<FieldGrid3>,
+        start_indices: &Idx3<usize>,
+    ) -> io::Result<ScalarField3<fdt>> {
+        let start_indices =
+            Idx3::with_each_component(|dim| self.lower_indices[dim] + start_indices[dim]);
+        self.provider_mut()
+            .produce_scalar_subfield(variable_name, subgrid, &start_indices)
     }
 }
 
@@ -1941,6 +2000,23 @@ where
         self.values.index_axis(Axis(axis as usize), idx)
     }
 
+    /// Returns a 2D scalar field holding the values in the layer of grid cells at the
+    /// given index along the given axis, at their original locations.
+    ///
+    /// No interpolation is performed, so this is suitable for fields that are invariant
+    /// along the axis.
+    pub fn layer_across_axis(&self, axis: Dim3, idx: usize) -> ScalarField2<F> {
+        let layer = self.slice_across_axis_at_idx(axis, idx);
+        let mut layer_values = Array2::zeros(layer.raw_dim().f());
+        layer_values.assign(&layer);
+        ScalarField2::new(
+            self.name.clone(),
+            self.create_slice_grid(axis),
+            self.select_slice_locations(Dim3::slice_except(axis), ResampledCoordLocation::Original),
+            layer_values,
+        )
+    }
+
     /// Returns a 2D scalar field corresponding to a slice through the x-axis at the given coordinate.
     pub fn slice_across_x(
         &self,
@@ -2007,6 +2083,157 @@ where
         )
     }
 
+    /// Returns a 2D scalar field with the coordinate along the given axis where the
+    /// field first reaches the given value, searching from the lower end of the axis.
+    ///
+    /// The coordinate is linearly interpolated between the two grid points bracketing
+    /// the crossing. Columns where the value is never reached are assigned NaN.
+    pub fn find_iso_surface_coords(&self, axis: Dim3, iso_value: F) -> ScalarField2<F> {
+        let axes = Dim3::slice_except(axis);
+        let axis_coords = self.coords()[axis];
+        let surface_grid = self.create_slice_grid(axis);
+        let surface_locations = self.select_slice_locations(axes, ResampledCoordLocation::Original);
+
+        let surface_shape = surface_grid.shape();
+        let mut surface_coords =
+            Array2::uninit((surface_shape[Dim2::X], surface_shape[Dim2::Y]).f());
+        let coords_buffer = surface_coords.as_slice_memory_order_mut().unwrap();
+
+        coords_buffer
+            .par_iter_mut()
+            .enumerate()
+            .for_each(|(idx, surface_coord)| {
+                let column_indices = self.compute_slice_indices_from_flat_idx(axes, idx);
+                let column_value = |k: usize| {
+                    let mut indices = Idx3::origin();
+                    indices[axes[0]] = column_indices[0];
+                    indices[axes[1]] = column_indices[1];
+                    indices[axis] = k;
+                    self.value(&indices) - iso_value
+                };
+
+                let mut coord = F::nan();
+                let mut prev_value = column_value(0);
+                if prev_value == F::zero() {
+                    coord = F::from(axis_coords[0]).unwrap();
+                } else {
+                    for k in 1..axis_coords.len() {
+                        let value = column_value(k);
+                        if value == F::zero() || value.signum() != prev_value.signum() {
+                            let fraction = prev_value / (prev_value - value);
+                            let lower_coord = F::from(axis_coords[k - 1]).unwrap();
+                            let upper_coord = F::from(axis_coords[k]).unwrap();
+                            coord = lower_coord + fraction * (upper_coord - lower_coord);
+                            break;
+                        }
+                        prev_value = value;
+                    }
+                }
+                surface_coord.write(coord);
+            });
+        let surface_coords = unsafe { surface_coords.assume_init() };
+
+        ScalarField2::new(
+            axis.to_string(),
+            surface_grid,
+            surface_locations,
+            surface_coords,
+        )
+    }
+
+    /// Returns a 2D scalar field with the values of the field linearly interpolated
+    /// along the given axis to the coordinates in the given surface field.
+    ///
+    /// The surface field must be defined at the same locations as the field across
+    /// the given axis. Values are taken from the closest grid point for surface
+    /// coordinates outside the grid, and are NaN where the surface coordinate is NaN.
+    pub fn sample_at_surface(
+        &self,
+        axis: Dim3,
+        surface_coords: &ScalarField2<F>,
+    ) -> ScalarField2<F> {
+        let axes = Dim3::slice_except(axis);
+        assert_eq!(
+            surface_coords.locations(),
+            &self.select_slice_locations(axes, ResampledCoordLocation::Original),
+            "Surface and field locations are not consistent."
+        );
+        let axis_coords = self.coords()[axis];
+        let n_coords = axis_coords.len();
+
+        let mut sampled_values = Array2::uninit(surface_coords.values().raw_dim().f());
+        let values_buffer = sampled_values.as_slice_memory_order_mut().unwrap();
+
+        values_buffer
+            .par_iter_mut()
+            .enumerate()
+            .for_each(|(idx, sampled_value)| {
+                let column_indices = self.compute_slice_indices_from_flat_idx(axes, idx);
+                let column_value = |k: usize| {
+                    let mut indices = Idx3::origin();
+                    indices[axes[0]] = column_indices[0];
+                    indices[axes[1]] = column_indices[1];
+                    indices[axis] = k;
+                    self.value(&indices)
+                };
+
+                let coord = surface_coords.value(&Idx2::new(column_indices[0], column_indices[1]));
+                let value = if coord.is_nan() {
+                    F::nan()
+                } else {
+                    let coord = coord.to_f64().unwrap() as fgr;
+                    let upper_idx = axis_coords.partition_point(|&c| c <= coord);
+                    if upper_idx == 0 {
+                        column_value(0)
+                    } else if upper_idx == n_coords {
+                        column_value(n_coords - 1)
+                    } else {
+                        let lower_coord = axis_coords[upper_idx - 1];
+                        let fraction =
+                            F::from((coord - lower_coord) / (axis_coords[upper_idx] - lower_coord))
+                                .unwrap();
+                        let lower_value = column_value(upper_idx - 1);
+                        lower_value + fraction * (column_value(upper_idx) - lower_value)
+                    }
+                };
+                sampled_value.write(value);
+            });
+        let sampled_values = unsafe { sampled_values.assume_init() };
+
+        ScalarField2::new(
+            self.name.clone(),
+            surface_coords.arc_with_grid(),
+            surface_coords.locations().clone(),
+            sampled_values,
+        )
+    }
+
+    /// Saves the field data as an uncompressed NumPy `.npz` file at the given path.
+    ///
+    /// The values are stored in Fortran order as `values`, and the coordinates
+    /// where the values are defined as `x`, `y` and `z`.
+    #[cfg(feature = "npz")]
+    pub fn save_as_npz(&self, output_file_path: &Path) -> io::Result<()>
+    where
+        F: WritableElement,
+    {
+        let coords = self.coords();
+        let mut writer = io_utils::create_npz_writer(output_file_path)?;
+        io_utils::add_array_to_npz(&mut writer, "values", self.values())?;
+        io_utils::add_array_to_npz(&mut writer, "x", &ArrayView::from(coords[X]))?;
+        io_utils::add_array_to_npz(&mut writer, "y", &ArrayView::from(coords[Y]))?;
+        io_utils::add_array_to_npz(&mut writer, "z", &ArrayView::from(coords[Z]))?;
+        io_utils::finish_npz(writer)
+    }
+
+    fn create_slice_grid(&self, axis: Dim3) -> Arc<FieldGrid2> {
+        Arc::new(match axis {
+            X => self.grid.slice_across_x(),
+            Y => self.grid.slice_across_y(),
+            Z => self.grid.slice_across_z().into(),
+        })
+    }
+
     fn compute_overlying_grid_cell_corners_for_resampling(
         overlying_grid: &FieldGrid3,
         overlying_grid_cell_idx: usize,
@@ -2658,6 +2885,15 @@ where
         In3D::with_each_component(|dim| self.components[dim].slice_across_axis_at_idx(axis, idx))
     }
 
+    /// Returns a field of 3D vectors in a 2D plane holding the vectors in the layer of
+    /// grid cells at the given index along the given axis, without interpolation.
+    pub fn layer_across_axis(&self, axis: Dim3, idx: usize) -> PlaneVectorField3<F> {
+        let layer_field_components =
+            In3D::with_each_component(|dim| self.components[dim].layer_across_axis(axis, idx));
+        let layer_grid = layer_field_components[X].arc_with_grid();
+        PlaneVectorField3::new(self.name.to_string(), layer_grid, layer_field_components)
+    }
+
     /// Returns a field of 3D vectors in a 2D plane corresponding to a slice through the x-axis at the given coordinate.
     pub fn slice_across_x(
         &self,
@@ -3047,6 +3283,23 @@ where
         save_data_as_pickle(output_file_path, &data)
     }
 
+    /// Saves the field data as an uncompressed NumPy `.npz` file at the given path.
+    ///
+    /// The values are stored in Fortran order as `values`, and the coordinates
+    /// where the values are defined as `x` and `y`.
+    #[cfg(feature = "npz")]
+    pub fn save_as_npz(&self, output_file_path: &Path) -> io::Result<()>
+    where
+        F: WritableElement,
+    {
+        let coords = self.coords();
+        let mut writer = io_utils::create_npz_writer(output_file_path)?;
+        io_utils::add_array_to_npz(&mut writer, "values", self.values())?;
+        io_utils::add_array_to_npz(&mut writer, "x", &ArrayView::from(coords[Dim2::X]))?;
+        io_utils::add_array_to_npz(&mut writer, "y", &ArrayView::from(coords[Dim2::Y]))?;
+        io_utils::finish_npz(writer)
+    }
+
     fn set_grid(&mut self, new_grid: Arc<FieldGrid2>) {
         let grid_shape = new_grid.shape();
         let values_shape = self.values.shape();
@@ -3231,6 +3484,19 @@ where
         In3D::with_each_component(|dim| self.values(dim))
     }
 
+    /// Returns a 2D vector field holding the two components lying in the plane,
+    /// given the axis normal to the plane.
+    ///
+    /// The components are ordered like the axes of slices across the normal axis.
+    pub fn in_plane_vector_field(&self, normal_axis: Dim3) -> VectorField2<F> {
+        let axes = Dim3::slice_except(normal_axis);
+        VectorField2::new(
+            self.name.to_string(),
+            self.arc_with_grid(),
+            In2D::with_each_component(|dim| self.components[axes[dim.num()]].clone()),
+        )
+    }
+
     /// Returns the field vector at the given 3D index.
     pub fn vector(&self, indices: &Idx2<usize>) -> Vec3<F> {
         Vec3::new(
@@ -0,0 +1,439 @@
+//! Reduction of 3D scalar fields to vertical profiles.
+
+use super::{FieldGrid1, ScalarField1, ScalarField3};
+use crate::{
+    geometry::{
+        Dim3::{X, Y, Z},
+        Idx3,
+    },
+    grid::{Grid1, Grid3},
+    num::BFloat,
+};
+use ndarray::prelude::*;
+use rayon::prelude::*;
+use std::{fmt, io, sync::Arc};
+
+#[cfg(feature = "serialization")]
+use super::ScalarFieldSerializeData1;
+
+#[cfg(feature = "serialization")]
+use serde::Serialize;
+
+#[cfg(feature = "serialization")]
+use std::collections::BTreeMap;
+
+#[cfg(any(
+    feature = "pickle",
+    feature = "json",
+    feature = "netcdf",
+    feature = "npz"
+))]
+use std::path::Path;
+
+#[cfg(feature = "pickle")]
+use crate::io::utils::save_data_as_pickle;
+
+#[cfg(feature = "json")]
+use crate::io::utils::save_data_as_json;
+
+#[cfg(feature = "netcdf")]
+use crate::io::snapshot::{fdt, netcdf};
+
+#[cfg(feature = "npz")]
+use crate::{grid::CoordLocation, io::utils as io_utils};
+
+#[cfg(feature = "npz")]
+use ndarray_npy::WritableElement;
+
+/// Statistic used for reducing each horizontal layer of a 3D field to a single value.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ProfileStatistic {
+    Mean,
+    RootMeanSquare,
+    Minimum,
+    Maximum,
+    /// Percentile between 0 and 100, using linear interpolation between
+    /// the closest ranks.
+    Percentile(f64),
+    /// Mean weighted by a separate weight field (e.g. the mass density).
+    WeightedMean,
+}
+
+impl ProfileStatistic {
+    /// Parses a statistic from its name.
+    ///
+    /// Valid names are `mean`, `rms`, `min`, `max`, `median`, `wmean` and
+    /// `p<percentile>` (e.g. `p90`).
+    pub fn from_name(name: &str) -> io::Result<Self> {
+        match name {
+            "mean" => Ok(Self::Mean),
+            "rms" => Ok(Self::RootMeanSquare),
+            "min" => Ok(Self::Minimum),
+            "max" => Ok(Self::Maximum),
+            "median" => Ok(Self::Percentile(50.0)),
+            "wmean" => Ok(Self::WeightedMean),
+            _ => match name.strip_prefix('p').map(str::parse::<f64>) {
+                Some(Ok(percentile)) if (0.0..=100.0).contains(&percentile) => {
+                    Ok(Self::Percentile(percentile))
+                }
+                _ => Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Invalid profile statistic: {}", name),
+                )),
+            },
+        }
+    }
+
+    /// Whether the statistic requires a weight field.
+    pub fn requires_weights(&self) -> bool {
+        *self == Self::WeightedMean
+    }
+
+    fn reduce<F: BFloat>(&self, values: &mut [F], weights: &[F]) -> F {
+        if values.is_empty() {
+            return F::nan();
+        }
+        let count = F::from(values.len()).unwrap();
+        match *self {
+            Self::Mean => values.iter().fold(F::zero(), |sum, &value| sum + value) / count,
+            Self::RootMeanSquare => (values
+                .iter()
+                .fold(F::zero(), |sum, &value| sum + value * value)
+                / count)
+                .sqrt(),
+            Self::Minimum => values.iter().copied().fold(F::infinity(), F::min),
+            Self::Maximum => values.iter().copied().fold(F::neg_infinity(), F::max),
+            Self::Percentile(percentile) => {
+                values.sort_unstable_by(|a, b| a.partial_cmp(b).unwrap());
+                let rank = 0.01 * percentile * ((values.len() - 1) as f64);
+                let lower_idx = rank.floor() as usize;
+                let upper_idx = usize::min(lower_idx + 1, values.len() - 1);
+                let fraction = F::from(rank - rank.floor()).unwrap();
+                values[lower_idx] + fraction * (values[upper_idx] - values[lower_idx])
+            }
+            Self::WeightedMean => {
+                let (weighted_sum, weight_sum) = values.iter().zip(weights).fold(
+                    (F::zero(), F::zero()),
+                    |(weighted_sum, weight_sum), (&value, &weight)| {
+                        (weighted_sum + weight * value, weight_sum + weight)
+                    },
+                );
+                weighted_sum / weight_sum
+            }
+        }
+    }
+}
+
+impl fmt::Display for ProfileStatistic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Mean => write!(f, "mean"),
+            Self::RootMeanSquare => write!(f, "rms"),
+            Self::Minimum => write!(f, "min"),
+            Self::Maximum => write!(f, "max"),
+            Self::Percentile(percentile) => write!(f, "p{}", percentile),
+            Self::WeightedMean => write!(f, "wmean"),
+        }
+    }
+}
+
+/// Computes the vertical profile of the given field by reducing each horizontal
+/// layer with the given statistic.
+///
+/// Only points where the optional mask field exceeds 0.5 are included, so that
+/// masks of zeros and ones remain valid after being interpolated to other grid
+/// locations. NaN values are ignored. The weight field must be given for weighted statistics.
+/// The mask and weight fields are assumed to have the same shape as the field,
+/// and the profile is defined at the vertical coordinates of the field. Layers
+/// without any included points are assigned NaN.
+pub fn compute_horizontal_profile<F: BFloat>(
+    field: &ScalarField3<F>,
+    statistic: ProfileStatistic,
+    weights: Option<&ScalarField3<F>>,
+    mask: Option<&ScalarField3<F>>,
+) -> ScalarField1<F> {
+    assert!(
+        weights.is_some() || !statistic.requires_weights(),
+        "No weights given for weighted statistic."
+    );
+    for other in weights.iter().chain(mask.iter()) {
+        assert_eq!(
+            other.shape(),
+            field.shape(),
+            "Field shapes for profile are not consistent."
+        );
+    }
+
+    let mask_threshold = F::from(0.5).unwrap();
+
+    let grid = field.grid();
+    let shape = field.shape();
+    let layer_size = shape[X] * shape[Y];
+
+    let profile_values: Vec<F> = (0..shape[Z])
+        .into_par_iter()
+        .map(|k| {
+            let mut values = Vec::with_capacity(layer_size);
+            let mut layer_weights = Vec::new();
+            for j in 0..shape[Y] {
+                for i in 0..shape[X] {
+                    let indices = Idx3::new(i, j, k);
+                    let value = field.value(&indices);
+                    if value.is_nan()
+                        || mask.is_some_and(|mask| mask.value(&indices) <= mask_threshold)
+                    {
+                        continue;
+                    }
+                    values.push(value);
+                    if let Some(weights) = weights {
+                        layer_weights.push(weights.value(&indices));
+                    }
+                }
+            }
+            statistic.reduce(&mut values, &layer_weights)
+        })
+        .collect();
+
+    let profile_grid = FieldGrid1::from_coords(
+        grid.centers()[Z].to_vec(),
+        grid.lower_edges()[Z].to_vec(),
+        false,
+    );
+
+    ScalarField1::new(
+        format!("{}_{}", field.name(), statistic),
+        Arc::new(profile_grid),
+        field.locations()[Z],
+        Array1::from(profile_values),
+    )
+}
+
+/// Collection of vertical profiles that can be saved together.
+#[derive(Clone, Debug, Default)]
+pub struct ProfileSet<F> {
+    profiles: Vec<ScalarField1<F>>,
+}
+
+impl<F: BFloat> ProfileSet<F> {
+    /// Creates a new empty set of profiles.
+    pub fn new() -> Self {
+        Self {
+            profiles: Vec::new(),
+        }
+    }
+
+    /// Adds the given profile to the set.
+    pub fn push(&mut self, profile: ScalarField1<F>) {
+        self.profiles.push(profile);
+    }
+
+    /// Returns a slice with the profiles in the set.
+    pub fn profiles(&self) -> &[ScalarField1<F>] {
+        &self.profiles
+    }
+
+    /// Serializes the profiles into pickle format and saves at the given path.
+    ///
+    /// The data is saved as a dictionary mapping each profile name to a
+    /// dictionary with its coordinates and values.
+    #[cfg(feature = "pickle")]
+    pub fn save_as_pickle(&self, output_file_path: &Path) -> io::Result<()>
+    where
+        F: Serialize,
+    {
+        save_data_as_pickle(output_file_path, &self.create_serialize_data())
+    }
+
+    /// Serializes the profiles into JSON format and saves at the given path.
+    ///
+    /// The data is saved as an object mapping each profile name to an
+    /// object with its coordinates and values.
+    #[cfg(feature = "json")]
+    pub fn save_as_json(&self, output_file_path: &Path) -> io::Result<()>
+    where
+        F: Serialize,
+    {
+        save_data_as_json(output_file_path, &self.create_serialize_data())
+    }
+
+    #[cfg(feature = "serialization")]
+    fn create_serialize_data(&self) -> BTreeMap<&str, ScalarFieldSerializeData1<F>> {
+        self.profiles
+            .iter()
+            .map(|profile| {
+                (
+                    profile.name(),
+                    ScalarFieldSerializeData1 {
+                        coords: profile.coords().to_vec(),
+                        values: profile.values().clone(),
+                    },
+                )
+            })
+            .collect()
+    }
+}
+
+impl<F: BFloat> ProfileSet<F> {
+    /// Saves the profiles as an uncompressed NumPy `.npz` file at the given path.
+    ///
+    /// The values of each profile are stored under the profile name, and the
+    /// vertical grid as `zm` and `zmdn`. The name of the coordinate array each
+    /// profile is defined on is stored as a JSON object under `coordinates`,
+    /// encoded as an array of UTF-8 bytes.
+    ///
+    /// All profiles must be computed from fields on the same grid.
+    #[cfg(feature = "npz")]
+    pub fn save_as_npz(&self, output_file_path: &Path) -> io::Result<()>
+    where
+        F: WritableElement,
+    {
+        let mut writer = io_utils::create_npz_writer(output_file_path)?;
+        if let Some(first_profile) = self.profiles.first() {
+            let grid = first_profile.grid();
+            io_utils::add_array_to_npz(&mut writer, "zm", &ArrayView::from(grid.centers()))?;
+            io_utils::add_array_to_npz(&mut writer, "zmdn", &ArrayView::from(grid.lower_edges()))?;
+        }
+        let mut coordinates = serde_json::Map::new();
+        for profile in &self.profiles {
+            io_utils::add_array_to_npz(&mut writer, profile.name(), profile.values())?;
+            coordinates.insert(
+                profile.name().to_string(),
+                serde_json::Value::from(match profile.location() {
+                    CoordLocation::Center => "zm",
+                    CoordLocation::LowerEdge => "zmdn",
+                }),
+            );
+        }
+        io_utils::add_json_to_npz(
+            &mut writer,
+            "coordinates",
+            &serde_json::Value::Object(coordinates),
+        )?;
+        io_utils::finish_npz(writer)
+    }
+}
+
+#[cfg(feature = "netcdf")]
+impl ProfileSet<fdt> {
+    /// Writes the profiles to a NetCDF file at the given path.
+    ///
+    /// All profiles must be computed from fields on the same grid.
+    pub fn save_as_netcdf(&self, output_file_path: &Path) -> io::Result<()> {
+        let mut file = netcdf::create_file(output_file_path)?;
+        let mut root_group = file.root_mut().unwrap();
+        if let Some(first_profile) = self.profiles.first() {
+            netcdf::write_vertical_grid(&mut root_group, first_profile.grid())?;
+        }
+        for profile in &self.profiles {
+            netcdf::write_1d_scalar_field(&mut root_group, profile)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{
+        field::{
+            CustomScalarFieldGenerator3, FieldGrid3, ResampledCoordLocation, ResamplingMethod,
+            ScalarFieldProvider3,
+        },
+        geometry::{In3D, Vec3},
+        grid::{fgr, regular::RegularGrid3, CoordLocation},
+        interpolation::poly_fit::{PolyFitInterpolator3, PolyFitInterpolatorConfig},
+        io::Verbosity,
+    };
+
+    #[test]
+    fn horizontal_profile_statistics_are_correct() {
+        let grid = Arc::new(FieldGrid3::from(RegularGrid3::from_bounds(
+            In3D::new(4, 1, 2),
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(4.0, 1.0, 2.0),
+            In3D::new(true, true, false),
+        )));
+        let mut provider = CustomScalarFieldGenerator3::<f32>::new(grid, Verbosity::Quiet)
+            .with_variable(
+                "f".to_string(),
+                Box::new(|x: fgr, _, z: fgr| ((x - 0.5) + 10.0 * (z - 0.5)) as f32),
+            )
+            .with_variable("w".to_string(), Box::new(|x: fgr, _, _| x.floor() as f32))
+            .with_variable(
+                "m".to_string(),
+                Box::new(|x: fgr, _, _| (x < 2.0) as u8 as f32),
+            );
+        let f = provider.produce_scalar_field("f").unwrap();
+        let w = provider.produce_scalar_field("w").unwrap();
+        let m = provider.produce_scalar_field("m").unwrap();
+
+        let profile = |statistic, mask| {
+            compute_horizontal_profile(&f, statistic, Some(&w), mask)
+                .values()
+                .to_vec()
+        };
+        assert_eq!(profile(ProfileStatistic::Mean, None), [1.5, 11.5]);
+        assert_eq!(profile(ProfileStatistic::Minimum, None), [0.0, 10.0]);
+        assert_eq!(profile(ProfileStatistic::Maximum, None), [3.0, 13.0]);
+        assert_eq!(
+            profile(ProfileStatistic::Percentile(50.0), None),
+            [1.5, 11.5]
+        );
+        assert_eq!(
+            profile(ProfileStatistic::WeightedMean, None),
+            [14.0 / 6.0, 74.0 / 6.0]
+        );
+        assert_eq!(profile(ProfileStatistic::Mean, Some(&m)), [0.5, 10.5]);
+        assert_eq!(
+            profile(ProfileStatistic::RootMeanSquare, Some(&m)),
+            [0.5_f32.sqrt(), 110.5_f32.sqrt()]
+        );
+        assert_eq!(
+            compute_horizontal_profile(&f, ProfileStatistic::Mean, None, None).name(),
+            "f_mean"
+        );
+    }
+
+    #[test]
+    fn resampled_mask_excludes_partially_masked_points() {
+        let grid = Arc::new(FieldGrid3::from(RegularGrid3::from_bounds(
+            In3D::new(8, 4, 4),
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(8.0, 4.0, 4.0),
+            In3D::new(true, true, true),
+        )));
+        let mut provider = CustomScalarFieldGenerator3::<f32>::new(grid, Verbosity::Quiet)
+            .with_variable_at_locations(
+                "f".to_string(),
+                Box::new(|x: fgr, _, _| (x * x) as f32),
+                In3D::new(
+                    CoordLocation::LowerEdge,
+                    CoordLocation::Center,
+                    CoordLocation::Center,
+                ),
+            )
+            .with_variable(
+                "m".to_string(),
+                Box::new(|x: fgr, _, _| (x < 2.0) as u8 as f32),
+            );
+        let f = provider.produce_scalar_field("f").unwrap();
+        let m = provider.produce_scalar_field("m").unwrap();
+
+        let resampled_mask = m.resampled_to_grid(
+            provider.arc_with_grid(),
+            In3D::with_each_component(|dim| ResampledCoordLocation::Specific(f.locations()[dim])),
+            &PolyFitInterpolator3::new(PolyFitInterpolatorConfig::default()),
+            ResamplingMethod::DirectSampling,
+            &Verbosity::Quiet,
+        );
+        assert!(resampled_mask
+            .values()
+            .iter()
+            .any(|&value| value > 0.0 && value < 1.0));
+
+        let profile =
+            compute_horizontal_profile(&f, ProfileStatistic::Mean, None, Some(&resampled_mask));
+        assert!(profile.values().iter().all(|&value| value == 1.0));
+    }
+}
@@ -1,25 +1,42 @@
 //! Computation of various derived physical quantities.
 
+pub mod eos;
+pub mod expression;
+
+use self::{
+    eos::{TabulatedEquationOfState, TabulatedQuantity},
+    expression::QuantityDefinitions,
+};
 use crate::{
+    constants::{
+        fcn, CLIGHT, EV_TO_K, GAMMA_IDEAL, HPLANCK, KAPPA_SPITZER, KBOLTZMANN, M_ELECTRON, M_H, PI,
+        XI_HMINUS,
+    },
     field::{
         self, CachingScalarFieldProvider3, DynCachingScalarFieldProvider3, FieldGrid3,
         ResampledCoordLocation, ResamplingMethod, ScalarField3, ScalarFieldProvider3, VectorField3,
     },
-    geometry::{Dim3::Z, Idx3, In3D},
-    grid::{CoordLocation, Grid3},
+    geometry::{
+        Dim3::{self, X, Y, Z},
+        Idx3, In3D,
+    },
+    grid::{self, CoordLocation, Grid3},
     interpolation::{
         poly_fit::{PolyFitInterpolator3, PolyFitInterpolatorConfig},
         InterpGridVerifier3,
     },
-    io::{snapshot::fdt, Verbosity},
+    io::{
+        snapshot::{fdt, SnapshotParameters},
+        Verbosity,
+    },
     io_result,
-    units::solar::{U_B, U_E, U_L, U_L3, U_P, U_R, U_T, U_U},
+    units::solar::{U_B, U_E, U_EL, U_L, U_L3, U_P, U_R, U_T, U_U},
 };
 use lazy_static::lazy_static;
-use ndarray::Axis;
+use ndarray::{Array3, Axis, ShapeBuilder};
 use rayon::prelude::*;
 use regex::Regex;
-use std::{collections::HashMap, io, sync::Arc};
+use std::{borrow::Cow, collections::HashMap, io, sync::Arc};
 
 lazy_static! {
     static ref DERIVABLE_QUANTITIES: HashMap<&'static str, (&'static str, Vec<&'static str>)> =
@@ -64,6 +81,267 @@ lazy_static! {
                     vec!["r"]
                 )
             ),
+            (
+                "jx",
+                (
+                    "Current density in x-direction, curl of B (lower edges in y and z)\n\
+                 [magnetic flux density / length in Bifrost units]",
+                    vec!["bx", "by", "bz"]
+                )
+            ),
+            (
+                "jy",
+                (
+                    "Current density in y-direction, curl of B (lower edges in x and z)\n\
+                 [magnetic flux density / length in Bifrost units]",
+                    vec!["bx", "by", "bz"]
+                )
+            ),
+            (
+                "jz",
+                (
+                    "Current density in z-direction, curl of B (lower edges in x and y)\n\
+                 [magnetic flux density / length in Bifrost units]",
+                    vec!["bx", "by", "bz"]
+                )
+            ),
+            (
+                "vortx",
+                (
+                    "Vorticity in x-direction, curl of velocity (cell centered)\n\
+                 [1/time in Bifrost units]",
+                    vec!["ux", "uy", "uz"]
+                )
+            ),
+            (
+                "vorty",
+                (
+                    "Vorticity in y-direction, curl of velocity (cell centered)\n\
+                 [1/time in Bifrost units]",
+                    vec!["ux", "uy", "uz"]
+                )
+            ),
+            (
+                "vortz",
+                (
+                    "Vorticity in z-direction, curl of velocity (cell centered)\n\
+                 [1/time in Bifrost units]",
+                    vec!["ux", "uy", "uz"]
+                )
+            ),
+            (
+                "beta",
+                (
+                    "Plasma beta, ratio of gas to magnetic pressure (cell centered)\n\
+                 [dimensionless]",
+                    vec!["p", "bx", "by", "bz"]
+                )
+            ),
+            (
+                "va",
+                (
+                    "Alfvén speed (cell centered)\n\
+                 [10 km/s]",
+                    vec!["r", "bx", "by", "bz"]
+                )
+            ),
+            (
+                "vs",
+                (
+                    "Adiabatic sound speed of an ideal monatomic gas (cell centered)\n\
+                 [10 km/s]",
+                    vec!["r", "p"]
+                )
+            ),
+            (
+                "emag",
+                (
+                    "Magnetic energy density (cell centered)\n\
+                 [energy/volume in Bifrost units]",
+                    vec!["bx", "by", "bz"]
+                )
+            ),
+            (
+                "ekin",
+                (
+                    "Kinetic energy density (cell centered)\n\
+                 [energy/volume in Bifrost units]",
+                    vec!["r", "px", "py", "pz"]
+                )
+            ),
+            (
+                "eth",
+                (
+                    "Thermal energy density of an ideal monatomic gas, 3p/2 (cell centered)\n\
+                 [energy/volume in Bifrost units]",
+                    vec!["p"]
+                )
+            ),
+            (
+                "poyntx",
+                (
+                    "Poynting flux in x-direction for ideal MHD (cell centered)\n\
+                 [energy/(time*area) in Bifrost units]",
+                    vec!["ux", "uy", "uz", "bx", "by", "bz"]
+                )
+            ),
+            (
+                "poynty",
+                (
+                    "Poynting flux in y-direction for ideal MHD (cell centered)\n\
+                 [energy/(time*area) in Bifrost units]",
+                    vec!["ux", "uy", "uz", "bx", "by", "bz"]
+                )
+            ),
+            (
+                "poyntz",
+                (
+                    "Poynting flux in z-direction for ideal MHD (cell centered)\n\
+                 [energy/(time*area) in Bifrost units]",
+                    vec!["ux", "uy", "uz", "bx", "by", "bz"]
+                )
+            ),
+            (
+                "fspitz",
+                (
+                    "Spitzer conductive heat flux along the magnetic field (cell centered)\n\
+                 [energy/(time*area) in Bifrost units]",
+                    vec!["tg", "bx", "by", "bz"]
+                )
+            ),
+            (
+                "eta",
+                (
+                    "Magnetic diffusivity η, eta1 * Δs * (c_f + |u|) from the eta1 parameter,\n\
+                 cell size Δs and fast mode speed c_f, unless a uniform value is given\n\
+                 (cell centered)\n\
+                 [length^2/time in Bifrost units]",
+                    vec!["r", "p", "ux", "uy", "uz", "bx", "by", "bz"]
+                )
+            ),
+            (
+                "ex",
+                (
+                    "Electric field in x-direction from Ohm's law, -u x B + ηJ (cell centered)\n\
+                 [c * electric field strength in Bifrost units]",
+                    vec!["ux", "uy", "uz", "bx", "by", "bz", "eta"]
+                )
+            ),
+            (
+                "ey",
+                (
+                    "Electric field in y-direction from Ohm's law, -u x B + ηJ (cell centered)\n\
+                 [c * electric field strength in Bifrost units]",
+                    vec!["ux", "uy", "uz", "bx", "by", "bz", "eta"]
+                )
+            ),
+            (
+                "ez",
+                (
+                    "Electric field in z-direction from Ohm's law, -u x B + ηJ (cell centered)\n\
+                 [c * electric field strength in Bifrost units]",
+                    vec!["ux", "uy", "uz", "bx", "by", "bz", "eta"]
+                )
+            ),
+            (
+                "enix",
+                (
+                    "Non-ideal electric field in x-direction, E + u x B (cell centered)\n\
+                 [c * electric field strength in Bifrost units]",
+                    vec!["ex", "ey", "ez", "ux", "uy", "uz", "bx", "by", "bz"]
+                )
+            ),
+            (
+                "eniy",
+                (
+                    "Non-ideal electric field in y-direction, E + u x B (cell centered)\n\
+                 [c * electric field strength in Bifrost units]",
+                    vec!["ex", "ey", "ez", "ux", "uy", "uz", "bx", "by", "bz"]
+                )
+            ),
+            (
+                "eniz",
+                (
+                    "Non-ideal electric field in z-direction, E + u x B (cell centered)\n\
+                 [c * electric field strength in Bifrost units]",
+                    vec!["ex", "ey", "ez", "ux", "uy", "uz", "bx", "by", "bz"]
+                )
+            ),
+            (
+                "epar",
+                (
+                    "Electric field along the magnetic field, E·B/|B| (cell centered)\n\
+                 [c * electric field strength in Bifrost units]",
+                    vec!["ex", "ey", "ez", "bx", "by", "bz"]
+                )
+            ),
+            (
+                "jpar",
+                (
+                    "Current density along the magnetic field, J·B/|B| (cell centered)\n\
+                 [magnetic flux density / length in Bifrost units]",
+                    vec!["bx", "by", "bz"]
+                )
+            ),
+            (
+                "alpha",
+                (
+                    "Force-free parameter, J·B/B² (cell centered)\n\
+                 [1/length in Bifrost units]",
+                    vec!["bx", "by", "bz"]
+                )
+            ),
+            (
+                "angjb",
+                (
+                    "Angle between the current density and the magnetic field (cell centered)\n\
+                 [degrees]",
+                    vec!["bx", "by", "bz"]
+                )
+            ),
+            (
+                "krec",
+                (
+                    "Reconnection factor, |B x (curl of E + u x B)| (cell centered)\n\
+                 [magnetic flux density * c * electric field strength / length in Bifrost units]",
+                    vec!["enix", "eniy", "eniz", "bx", "by", "bz"]
+                )
+            ),
+            (
+                "chi500",
+                (
+                    "H- bound-free extinction coefficient at 500 nm, assuming all hydrogen\n\
+                 is neutral (cell centered)\n\
+                 [1/length in Bifrost units]",
+                    vec!["r", "tg", "nel"]
+                )
+            ),
+            (
+                "chiross",
+                (
+                    "Rosseland mean extinction coefficient, kr * r (cell centered)\n\
+                 [1/length in Bifrost units]",
+                    vec!["r", "kr"]
+                )
+            ),
+            (
+                "tau500",
+                (
+                    "Optical depth at 500 nm from the H- extinction, integrated downward\n\
+                 from the top of the domain (cell centered)\n\
+                 [dimensionless]",
+                    vec!["chi500"]
+                )
+            ),
+            (
+                "tauross",
+                (
+                    "Rosseland optical depth, integrated downward from the top of the\n\
+                 domain (cell centered)\n\
+                 [dimensionless]",
+                    vec!["chiross"]
+                )
+            ),
         ]
         .into_iter()
         .collect();
@@ -97,7 +375,39 @@ lazy_static! {
         ("qbeam", ((U_E / U_T) as fdt)),
         ("ubeam", ((U_E * U_L3 / U_T) as fdt)),
         ("beam_en", ((U_E / U_T) as fdt)),
-        ("coldepth", ((U_R * U_L) as fdt))
+        ("coldepth", ((U_R * U_L) as fdt)),
+        ("jx", ((*U_B / U_L) * CLIGHT / (4.0 * PI)) as fdt),
+        ("jy", ((*U_B / U_L) * CLIGHT / (4.0 * PI)) as fdt),
+        ("jz", ((*U_B / U_L) * CLIGHT / (4.0 * PI)) as fdt),
+        ("vortx", (1.0 / U_T) as fdt),
+        ("vorty", (1.0 / U_T) as fdt),
+        ("vortz", (1.0 / U_T) as fdt),
+        ("beta", 1.0),
+        ("va", (U_U as fdt)),
+        ("vs", (U_U as fdt)),
+        ("emag", (U_E as fdt)),
+        ("ekin", (U_E as fdt)),
+        ("eth", (U_E as fdt)),
+        ("poyntx", ((U_E * U_U) as fdt)),
+        ("poynty", ((U_E * U_U) as fdt)),
+        ("poyntz", ((U_E * U_U) as fdt)),
+        ("fspitz", ((U_E * U_U) as fdt)),
+        ("eta", ((U_L * U_L / U_T) as fdt)),
+        ("ex", (*U_EL as fdt)),
+        ("ey", (*U_EL as fdt)),
+        ("ez", (*U_EL as fdt)),
+        ("enix", (*U_EL as fdt)),
+        ("eniy", (*U_EL as fdt)),
+        ("eniz", (*U_EL as fdt)),
+        ("epar", (*U_EL as fdt)),
+        ("jpar", ((*U_B / U_L) * CLIGHT / (4.0 * PI)) as fdt),
+        ("alpha", (1.0 / U_L) as fdt),
+        ("angjb", 1.0),
+        ("krec", (*U_B * *U_EL / U_L) as fdt),
+        ("chi500", (1.0 / U_L) as fdt),
+        ("chiross", (1.0 / U_L) as fdt),
+        ("tau500", 1.0),
+        ("tauross", 1.0)
     ]
     .into_iter()
     .collect();
@@ -113,6 +423,10 @@ lazy_static! {
     static ref CGS_REGEX: Regex = Regex::new(r"^(\w+)_cgs$").unwrap();
     static ref CENTER_REGEX: Regex = Regex::new(r"^(\w+[xyz])c$").unwrap();
     static ref MOD_REGEX: Regex = Regex::new(r"^mod(\w+)$").unwrap();
+    static ref GRAD_REGEX: Regex = Regex::new(r"^grad(\w+)([xyz])$").unwrap();
+    static ref DIV_REGEX: Regex = Regex::new(r"^div(\w+)$").unwrap();
+    static ref CURL_REGEX: Regex = Regex::new(r"^curl(\w+)([xyz])$").unwrap();
+    static ref COLUMN_REGEX: Regex = Regex::new(r"^col(\w+)([xyz])$").unwrap();
 }
 
 fn cgs_base_name(quantity_name: &str) -> Option<&str> {
@@ -137,6 +451,70 @@ fn mod_vec_component_names(quantity_name: &str) -> Option<(String, String, Strin
     })
 }
 
+fn column_integrand_name_and_axis(quantity_name: &str) -> Option<(&str, Dim3)> {
+    COLUMN_REGEX.captures(quantity_name).map(|groups| {
+        (
+            groups.get(1).map_or("", |m| m.as_str()),
+            Dim3::from_char(groups[2].chars().next().unwrap()).unwrap(),
+        )
+    })
+}
+
+fn vec_component_names(vector_name: &str) -> In3D<String> {
+    In3D::with_each_component(|dim| format!("{}{}", vector_name, dim))
+}
+
+/// Differential operator applied to a scalar or vector quantity.
+#[derive(Clone, Debug, PartialEq)]
+enum DifferentialQuantity {
+    /// Component of the gradient of a scalar quantity.
+    Gradient(String, Dim3),
+    /// Divergence of a vector quantity.
+    Divergence(String),
+    /// Component of the curl of a vector quantity.
+    Curl(String, Dim3),
+}
+
+impl DifferentialQuantity {
+    fn from_name(quantity_name: &str) -> Option<Self> {
+        let component =
+            |groups: &regex::Captures| Dim3::from_char(groups[2].chars().next().unwrap()).unwrap();
+        if let Some(groups) = GRAD_REGEX.captures(quantity_name) {
+            Some(Self::Gradient(groups[1].to_string(), component(&groups)))
+        } else if let Some(groups) = CURL_REGEX.captures(quantity_name) {
+            Some(Self::Curl(groups[1].to_string(), component(&groups)))
+        } else {
+            DIV_REGEX
+                .captures(quantity_name)
+                .map(|groups| Self::Divergence(groups[1].to_string()))
+        }
+    }
+
+    fn dependencies(&self) -> Vec<String> {
+        match self {
+            Self::Gradient(scalar_name, _) => vec![scalar_name.clone()],
+            Self::Divergence(vector_name) | Self::Curl(vector_name, _) => {
+                vec_component_names(vector_name)
+                    .into_iter()
+                    .cloned()
+                    .collect()
+            }
+        }
+    }
+
+    fn cgs_scale(&self) -> Option<fdt> {
+        let base_name = match self {
+            Self::Gradient(scalar_name, _) => Cow::from(scalar_name.as_str()),
+            Self::Divergence(vector_name) | Self::Curl(vector_name, _) => {
+                Cow::from(format!("{}x", vector_name))
+            }
+        };
+        QUANTITY_CGS_SCALES
+            .get(base_name.as_ref())
+            .map(|&scale| scale / (U_L as fdt))
+    }
+}
+
 /// Creates a string with an overview of available quantities and their dependencies.
 fn create_available_quantity_table_string() -> String {
     let mut lines: Vec<_> = DERIVABLE_QUANTITIES
@@ -162,6 +540,22 @@ fn create_available_quantity_table_string() -> String {
          <quantity><component>c - Cell centered version of a vector component quantity\n\
          E.g., write bzc to get bz interpolated to cell centers\n\
          --------------------------------------------------------------------------------\n\
+         grad<scalar quantity><component> - Component of the gradient of any scalar quantity\n\
+         E.g., write gradtgz to derive the vertical temperature gradient (lower edge in z)\n\
+         --------------------------------------------------------------------------------\n\
+         div<vector quantity> - Divergence of any vector quantity\n\
+         E.g., write divb to derive the divergence of the magnetic field (cell centered)\n\
+         --------------------------------------------------------------------------------\n\
+         curl<vector quantity><component> - Component of the curl of any vector quantity\n\
+         E.g., write curlpx to derive the x-component of the curl of the momentum\n\
+         --------------------------------------------------------------------------------\n\
+         p, tg, nel, kr, opa<bin>, eps<bin>, temt<bin> - Tabulated equation of state\n\
+         quantities looked up from r and e (cell centered, requires --eos-table)\n\
+         --------------------------------------------------------------------------------\n\
+         col<scalar quantity><axis> - Integral of any scalar quantity along an axis,\n\
+         accumulated from the lower end of the axis (the top of the domain for z)\n\
+         E.g., write colchi500z to derive the optical depth at 500 nm (equivalent to tau500)\n\
+         --------------------------------------------------------------------------------\n\
          <quantity>_cgs - Values of a quantity converted to CGS units\n\
          (not available for all quantities)\n\
          ================================================================================",
@@ -171,26 +565,74 @@ fn create_available_quantity_table_string() -> String {
     )
 }
 
+/// Name of the derived magnetic diffusivity quantity.
+const MAGNETIC_DIFFUSIVITY_NAME: &str = "eta";
+
+/// Magnetic diffusivity used for the resistive term of Ohm's law.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MagneticDiffusivity {
+    /// Diffusivity with the same value everywhere [Bifrost units].
+    Uniform(fdt),
+    /// The low-order part of the Bifrost magnetic diffusivity,
+    /// eta1 * Δs * (c_f + |u|), with the given value of eta1, the cell size
+    /// Δs and the fast mode speed c_f. The hyper-diffusive enhancement
+    /// controlled by eta3 is not included.
+    FastModeScaled(fdt),
+}
+
+impl MagneticDiffusivity {
+    /// Determines the magnetic diffusivity from the `eta1` parameter of the given
+    /// snapshot parameters, if present.
+    pub fn from_parameters(parameters: &dyn SnapshotParameters) -> Option<Self> {
+        parameters
+            .get_as_float("eta1")
+            .ok()
+            .map(|eta1| Self::FastModeScaled(eta1 as fdt))
+    }
+}
+
 /// Computer of derived quantities from Bifrost 3D simulation snapshots.
 pub struct DerivedScalarFieldProvider3 {
     provider: DynCachingScalarFieldProvider3<fdt>,
     derived_quantity_names: Vec<String>,
     all_variable_names: Vec<String>,
     cached_scalar_fields: HashMap<String, Arc<ScalarField3<fdt>>>,
+    equation_of_state: Option<Arc<TabulatedEquationOfState>>,
+    quantity_definitions: QuantityDefinitions,
+    magnetic_diffusivity: Option<MagneticDiffusivity>,
     verbosity: Verbosity,
 }
 
 impl DerivedScalarFieldProvider3 {
     /// Creates a computer of derived 3D quantities.
+    ///
+    /// If a tabulated equation of state is given, quantities available in the
+    /// tables can be derived from the mass and energy densities. Quantities
+    /// with user-provided definitions can be derived in addition to the
+    /// built-in ones. The given magnetic diffusivity is used for the resistive
+    /// term when the electric field is derived from Ohm's law, which is not
+    /// possible if no diffusivity is given and the snapshot has no `eta` variable.
     pub fn new(
         provider: DynCachingScalarFieldProvider3<fdt>,
         derived_quantity_names: Vec<String>,
+        equation_of_state: Option<TabulatedEquationOfState>,
+        quantity_definitions: QuantityDefinitions,
+        magnetic_diffusivity: Option<MagneticDiffusivity>,
         handle_unavailable: &dyn Fn(&str, Option<Vec<&str>>),
         verbosity: Verbosity,
     ) -> Self {
         let derived_quantity_names: Vec<_> = derived_quantity_names
             .into_iter()
-            .filter(|name| Self::verify_variable_availability(&*provider, name, handle_unavailable))
+            .filter(|name| {
+                Self::verify_variable_availability(
+                    &*provider,
+                    equation_of_state.as_ref(),
+                    &quantity_definitions,
+                    magnetic_diffusivity,
+                    name,
+                    handle_unavailable,
+                )
+            })
             .collect();
 
         let mut all_variable_names = provider.all_variable_names().to_vec();
@@ -201,6 +643,9 @@ impl DerivedScalarFieldProvider3 {
             derived_quantity_names,
             all_variable_names,
             cached_scalar_fields: HashMap::new(),
+            equation_of_state: equation_of_state.map(Arc::new),
+            quantity_definitions,
+            magnetic_diffusivity,
             verbosity,
         }
     }
@@ -220,6 +665,21 @@ impl DerivedScalarFieldProvider3 {
         &self.derived_quantity_names
     }
 
+    /// Returns a reference to the tabulated equation of state, if present.
+    pub fn equation_of_state(&self) -> Option<&TabulatedEquationOfState> {
+        self.equation_of_state.as_deref()
+    }
+
+    /// Returns the magnetic diffusivity used for deriving the electric field, if present.
+    pub fn magnetic_diffusivity(&self) -> Option<MagneticDiffusivity> {
+        self.magnetic_diffusivity
+    }
+
+    /// Returns a reference to the user-provided quantity definitions.
+    pub fn quantity_definitions(&self) -> &QuantityDefinitions {
+        &self.quantity_definitions
+    }
+
     fn produce_uncached_scalar_field(
         &mut self,
         variable_name: &str,
@@ -228,139 +688,240 @@ impl DerivedScalarFieldProvider3 {
             self.provider_mut().produce_scalar_field(variable_name)
         } else {
             let verbosity = self.verbosity.clone();
-            compute_quantity(self, variable_name, &verbosity)
+            if let Some(definition) = self.quantity_definitions.get(variable_name).cloned() {
+                return expression::compute_defined_quantity(self, &definition, &verbosity);
+            }
+            match self.equation_of_state.clone() {
+                Some(equation_of_state) if equation_of_state.provides(variable_name) => {
+                    eos::compute_tabulated_quantity(
+                        variable_name,
+                        self,
+                        &equation_of_state,
+                        &verbosity,
+                    )
+                }
+                _ => {
+                    let magnetic_diffusivity = self.magnetic_diffusivity;
+                    compute_quantity(self, variable_name, magnetic_diffusivity, &verbosity)
+                }
+            }
         }
     }
 
     fn basic_variable_is_available(
         provider: &dyn CachingScalarFieldProvider3<fdt>,
+        equation_of_state: Option<&TabulatedEquationOfState>,
+        quantity_definitions: &QuantityDefinitions,
+        magnetic_diffusivity: Option<MagneticDiffusivity>,
         variable_name: &str,
-    ) -> (bool, Option<Vec<&'static str>>) {
-        if provider.has_variable(variable_name) {
-            (true, None)
-        } else if let Some((_, dependencies)) = DERIVABLE_QUANTITIES.get(variable_name) {
-            let missing_dependencies: Vec<_> = dependencies
-                .iter()
+    ) -> (bool, Option<Vec<String>>) {
+        let is_tabulated = |name: &str| equation_of_state.is_some_and(|eos| eos.provides(name));
+        // Derivable dependencies are checked recursively, so that quantities
+        // requiring e.g. the magnetic diffusivity are reported as unavailable
+        let is_derivable = |name: &str| {
+            DERIVABLE_QUANTITIES.contains_key(name)
+                && Self::basic_variable_is_available(
+                    provider,
+                    equation_of_state,
+                    quantity_definitions,
+                    magnetic_diffusivity,
+                    name,
+                )
+                .0
+        };
+        let missing_dependencies = |dependencies: &mut dyn Iterator<Item = &str>| {
+            dependencies
                 .filter_map(|name| {
-                    if provider.has_variable(name) || DERIVABLE_QUANTITIES.contains_key(name) {
+                    if provider.has_variable(name) || is_derivable(name) || is_tabulated(name) {
                         None
                     } else {
-                        Some(*name)
+                        Some(name.to_string())
                     }
                 })
+                .collect::<Vec<_>>()
+        };
+        if provider.has_variable(variable_name) {
+            (true, None)
+        } else if let Some(definition) = quantity_definitions.get(variable_name) {
+            // Definitions can only refer to earlier definitions, so this recursion terminates
+            let missing_dependencies: Vec<_> = definition
+                .dependencies()
+                .iter()
+                .filter(|name| {
+                    !Self::verify_variable_availability(
+                        provider,
+                        equation_of_state,
+                        quantity_definitions,
+                        magnetic_diffusivity,
+                        name,
+                        &|_, _| {},
+                    )
+                })
+                .cloned()
                 .collect();
-
+            (missing_dependencies.is_empty(), Some(missing_dependencies))
+        } else if is_tabulated(variable_name) {
+            let missing_dependencies = missing_dependencies(&mut ["r", "e"].into_iter());
+            (missing_dependencies.is_empty(), Some(missing_dependencies))
+        } else if variable_name == MAGNETIC_DIFFUSIVITY_NAME {
+            match magnetic_diffusivity {
+                Some(MagneticDiffusivity::Uniform(_)) => (true, None),
+                Some(MagneticDiffusivity::FastModeScaled(_)) => {
+                    let missing_dependencies = missing_dependencies(
+                        &mut DERIVABLE_QUANTITIES[variable_name].1.iter().cloned(),
+                    );
+                    (missing_dependencies.is_empty(), Some(missing_dependencies))
+                }
+                None => (false, Some(vec!["eta1".to_string()])),
+            }
+        } else if let Some((_, dependencies)) = DERIVABLE_QUANTITIES.get(variable_name) {
+            let missing_dependencies = missing_dependencies(&mut dependencies.iter().cloned());
+            (missing_dependencies.is_empty(), Some(missing_dependencies))
+        } else if let Some(differential_quantity) = DifferentialQuantity::from_name(variable_name) {
+            let dependencies = differential_quantity.dependencies();
+            let missing_dependencies =
+                missing_dependencies(&mut dependencies.iter().map(String::as_str));
+            (missing_dependencies.is_empty(), Some(missing_dependencies))
+        } else if let Some((integrand_name, _)) = column_integrand_name_and_axis(variable_name) {
+            let missing_dependencies = missing_dependencies(&mut [integrand_name].into_iter());
             (missing_dependencies.is_empty(), Some(missing_dependencies))
         } else {
             (false, None)
         }
     }
 
+    fn vector_variable_is_available(
+        provider: &dyn CachingScalarFieldProvider3<fdt>,
+        equation_of_state: Option<&TabulatedEquationOfState>,
+        quantity_definitions: &QuantityDefinitions,
+        magnetic_diffusivity: Option<MagneticDiffusivity>,
+        component_names: (&str, &str, &str),
+    ) -> (bool, Option<Vec<String>>) {
+        let (x_comp_name, y_comp_name, z_comp_name) = component_names;
+        let (available_x, missing_dependencies_x) = Self::basic_variable_is_available(
+            provider,
+            equation_of_state,
+            quantity_definitions,
+            magnetic_diffusivity,
+            x_comp_name,
+        );
+        let (available_y, missing_dependencies_y) = Self::basic_variable_is_available(
+            provider,
+            equation_of_state,
+            quantity_definitions,
+            magnetic_diffusivity,
+            y_comp_name,
+        );
+        let (available_z, missing_dependencies_z) = Self::basic_variable_is_available(
+            provider,
+            equation_of_state,
+            quantity_definitions,
+            magnetic_diffusivity,
+            z_comp_name,
+        );
+        if available_x && available_y && available_z {
+            (true, None)
+        } else {
+            let mut missing_dependencies = Vec::new();
+            if let Some(m) = missing_dependencies_x {
+                missing_dependencies.extend(m);
+            }
+            if let Some(m) = missing_dependencies_y {
+                missing_dependencies.extend(m);
+            }
+            if let Some(m) = missing_dependencies_z {
+                missing_dependencies.extend(m);
+            }
+            (
+                false,
+                if missing_dependencies.is_empty() {
+                    None
+                } else {
+                    Some(missing_dependencies)
+                },
+            )
+        }
+    }
+
     fn verify_variable_availability(
         provider: &dyn CachingScalarFieldProvider3<fdt>,
+        equation_of_state: Option<&TabulatedEquationOfState>,
+        quantity_definitions: &QuantityDefinitions,
+        magnetic_diffusivity: Option<MagneticDiffusivity>,
         variable_name: &str,
         handle_unavailable: &dyn Fn(&str, Option<Vec<&str>>),
     ) -> bool {
-        let (available, missing_dependencies) =
-            Self::basic_variable_is_available(provider, variable_name);
+        let report_unavailable = |missing_dependencies: Option<Vec<String>>| {
+            handle_unavailable(
+                variable_name,
+                missing_dependencies
+                    .as_ref()
+                    .map(|names| names.iter().map(String::as_str).collect()),
+            );
+            false
+        };
+        let (available, missing_dependencies) = Self::basic_variable_is_available(
+            provider,
+            equation_of_state,
+            quantity_definitions,
+            magnetic_diffusivity,
+            variable_name,
+        );
         if available {
             true
         } else if let Some(cgs_base_name) = cgs_base_name(variable_name) {
             if let Some((x_comp_name, y_comp_name, z_comp_name)) =
                 mod_vec_component_names(cgs_base_name)
             {
-                let (available_x, missing_dependencies_x) =
-                    Self::basic_variable_is_available(provider, &x_comp_name);
-                let (available_y, missing_dependencies_y) =
-                    Self::basic_variable_is_available(provider, &y_comp_name);
-                let (available_z, missing_dependencies_z) =
-                    Self::basic_variable_is_available(provider, &z_comp_name);
-                if available_x && available_y && available_z {
-                    true
-                } else {
-                    let mut missing_dependencies = Vec::new();
-                    if let Some(m) = missing_dependencies_x {
-                        missing_dependencies.extend(m);
-                    }
-                    if let Some(m) = missing_dependencies_y {
-                        missing_dependencies.extend(m);
-                    }
-                    if let Some(m) = missing_dependencies_z {
-                        missing_dependencies.extend(m);
-                    }
-                    handle_unavailable(
-                        variable_name,
-                        if missing_dependencies.is_empty() {
-                            None
-                        } else {
-                            Some(missing_dependencies)
-                        },
-                    );
-                    false
-                }
+                let (available, missing_dependencies) = Self::vector_variable_is_available(
+                    provider,
+                    equation_of_state,
+                    quantity_definitions,
+                    magnetic_diffusivity,
+                    (&x_comp_name, &y_comp_name, &z_comp_name),
+                );
+                available || report_unavailable(missing_dependencies)
             } else if let Some(centered_base_name) = centered_base_name(cgs_base_name) {
-                let (available, missing_dependencies) =
-                    Self::basic_variable_is_available(provider, centered_base_name);
-                if available {
-                    true
-                } else {
-                    handle_unavailable(variable_name, missing_dependencies);
-                    false
-                }
+                let (available, missing_dependencies) = Self::basic_variable_is_available(
+                    provider,
+                    equation_of_state,
+                    quantity_definitions,
+                    magnetic_diffusivity,
+                    centered_base_name,
+                );
+                available || report_unavailable(missing_dependencies)
             } else {
-                let (available, missing_dependencies) =
-                    Self::basic_variable_is_available(provider, cgs_base_name);
-                if available {
-                    true
-                } else {
-                    handle_unavailable(variable_name, missing_dependencies);
-                    false
-                }
+                let (available, missing_dependencies) = Self::basic_variable_is_available(
+                    provider,
+                    equation_of_state,
+                    quantity_definitions,
+                    magnetic_diffusivity,
+                    cgs_base_name,
+                );
+                available || report_unavailable(missing_dependencies)
             }
         } else if let Some((x_comp_name, y_comp_name, z_comp_name)) =
             mod_vec_component_names(variable_name)
         {
-            let (available_x, missing_dependencies_x) =
-                Self::basic_variable_is_available(provider, &x_comp_name);
-            let (available_y, missing_dependencies_y) =
-                Self::basic_variable_is_available(provider, &y_comp_name);
-            let (available_z, missing_dependencies_z) =
-                Self::basic_variable_is_available(provider, &z_comp_name);
-            if available_x && available_y && available_z {
-                true
-            } else {
-                let mut missing_dependencies = Vec::new();
-                if let Some(m) = missing_dependencies_x {
-                    missing_dependencies.extend(m);
-                }
-                if let Some(m) = missing_dependencies_y {
-                    missing_dependencies.extend(m);
-                }
-                if let Some(m) = missing_dependencies_z {
-                    missing_dependencies.extend(m);
-                }
-                handle_unavailable(
-                    variable_name,
-                    if missing_dependencies.is_empty() {
-                        None
-                    } else {
-                        Some(missing_dependencies)
-                    },
-                );
-                false
-            }
+            let (available, missing_dependencies) = Self::vector_variable_is_available(
+                provider,
+                equation_of_state,
+                quantity_definitions,
+                magnetic_diffusivity,
+                (&x_comp_name, &y_comp_name, &z_comp_name),
+            );
+            available || report_unavailable(missing_dependencies)
         } else if let Some(centered_base_name) = centered_base_name(variable_name) {
-            let (available, missing_dependencies) =
-                Self::basic_variable_is_available(provider, centered_base_name);
-            if available {
-                true
-            } else {
-                handle_unavailable(variable_name, missing_dependencies);
-                false
-            }
+            let (available, missing_dependencies) = Self::basic_variable_is_available(
+                provider,
+                equation_of_state,
+                quantity_definitions,
+                magnetic_diffusivity,
+                centered_base_name,
+            );
+            available || report_unavailable(missing_dependencies)
         } else {
-            handle_unavailable(variable_name, missing_dependencies);
-            false
+            report_unavailable(missing_dependencies)
         }
     }
 }
@@ -379,7 +940,14 @@ impl ScalarFieldProvider3<fdt> for DerivedScalarFieldProvider3 {
     }
 
     fn has_variable(&self, variable_name: &str) -> bool {
-        Self::verify_variable_availability(self.provider(), variable_name, &|_, _| {})
+        Self::verify_variable_availability(
+            self.provider(),
+            self.equation_of_state(),
+            self.quantity_definitions(),
+            self.magnetic_diffusivity(),
+            variable_name,
+            &|_, _| {},
+        )
     }
 
     fn produce_scalar_field(&mut self, variable_name: &str) -> io::Result<ScalarField3<fdt>> {
@@ -397,6 +965,26 @@ impl ScalarFieldProvider3<fdt> for DerivedScalarFieldProvider3 {
             Ok(Arc::new(self.produce_uncached_scalar_field(variable_name)?))
         }
     }
+
+    fn produce_scalar_subfield(
+        &mut self,
+        variable_name: &str,
+        subgrid: Arc<FieldGrid3>,
+        start_indices: &Idx3<usize>,
+    ) -> io::Result<ScalarField3<fdt>> {
+        // Basic variables can be read directly in the subgrid, while derived
+        // quantities generally depend on values outside of it
+        if !self.cached_scalar_fields.contains_key(variable_name)
+            && self.provider().has_variable(variable_name)
+        {
+            self.provider_mut()
+                .produce_scalar_subfield(variable_name, subgrid, start_indices)
+        } else {
+            Ok(self
+                .provide_scalar_field(variable_name)?
+                .subfield(subgrid, start_indices))
+        }
+    }
 }
 
 impl CachingScalarFieldProvider3<fdt> for DerivedScalarFieldProvider3 {
@@ -512,6 +1100,7 @@ macro_rules! compute_derived_quantity {
 fn compute_quantity(
     provider: &mut dyn ScalarFieldProvider3<fdt>,
     quantity_name: &str,
+    magnetic_diffusivity: Option<MagneticDiffusivity>,
     verbosity: &Verbosity,
 ) -> io::Result<ScalarField3<fdt>> {
     let grid = provider.arc_with_grid();
@@ -525,8 +1114,127 @@ fn compute_quantity(
                 with indices |indices, qbeam| qbeam * grid.grid_cell_volume(indices) as fdt,
                 provider, verbosity
             ),
-            "coldepth" => compute_column_depth(provider, verbosity),
-            _ => unreachable!(),
+            "coldepth" => compute_column_integral("coldepth", provider, "r", Z, verbosity),
+            "jx" => compute_curl_component("jx", provider, "b", X, verbosity),
+            "jy" => compute_curl_component("jy", provider, "b", Y, verbosity),
+            "jz" => compute_curl_component("jz", provider, "b", Z, verbosity),
+            "vortx" => compute_curl_component("vortx", provider, "u", X, verbosity),
+            "vorty" => compute_curl_component("vorty", provider, "u", Y, verbosity),
+            "vortz" => compute_curl_component("vortz", provider, "u", Z, verbosity),
+            "beta" => compute_general_double_dep_quantity(
+                "beta",
+                provider,
+                "p",
+                "modb",
+                |p, b| 2.0 * p / (b * b),
+                verbosity,
+            ),
+            "va" => compute_general_double_dep_quantity(
+                "va",
+                provider,
+                "modb",
+                "r",
+                |b, r| b / r.sqrt(),
+                verbosity,
+            ),
+            "vs" => compute_derived_quantity!(
+                vs,
+                |p, r| ((GAMMA_IDEAL as fdt) * p / r).sqrt(),
+                provider,
+                verbosity
+            ),
+            "emag" => compute_general_single_dep_quantity(
+                "emag",
+                provider,
+                "modb",
+                |b| 0.5 * b * b,
+                verbosity,
+            ),
+            "ekin" => compute_general_double_dep_quantity(
+                "ekin",
+                provider,
+                "modp",
+                "r",
+                |p, r| 0.5 * p * p / r,
+                verbosity,
+            ),
+            "eth" => compute_scaled_quantity("eth", provider, "p", 1.5, verbosity),
+            "poyntx" => compute_poynting_flux_component("poyntx", provider, X, verbosity),
+            "poynty" => compute_poynting_flux_component("poynty", provider, Y, verbosity),
+            "poyntz" => compute_poynting_flux_component("poyntz", provider, Z, verbosity),
+            "fspitz" => compute_parallel_spitzer_flux(provider, verbosity),
+            "eta" => compute_magnetic_diffusivity(provider, magnetic_diffusivity, verbosity),
+            "ex" => compute_ohmic_electric_field_component(
+                "ex",
+                provider,
+                X,
+                magnetic_diffusivity,
+                verbosity,
+            ),
+            "ey" => compute_ohmic_electric_field_component(
+                "ey",
+                provider,
+                Y,
+                magnetic_diffusivity,
+                verbosity,
+            ),
+            "ez" => compute_ohmic_electric_field_component(
+                "ez",
+                provider,
+                Z,
+                magnetic_diffusivity,
+                verbosity,
+            ),
+            "enix" => compute_nonideal_electric_field_component("enix", provider, X, verbosity),
+            "eniy" => compute_nonideal_electric_field_component("eniy", provider, Y, verbosity),
+            "eniz" => compute_nonideal_electric_field_component("eniz", provider, Z, verbosity),
+            "epar" => compute_general_multi_dep_quantity(
+                "epar",
+                provider,
+                &["ex", "ey", "ez", "bx", "by", "bz"],
+                |values| parallel_component(&values[..3], &values[3..]),
+                verbosity,
+            ),
+            "jpar" => compute_general_multi_dep_quantity(
+                "jpar",
+                provider,
+                &["jx", "jy", "jz", "bx", "by", "bz"],
+                |values| parallel_component(&values[..3], &values[3..]),
+                verbosity,
+            ),
+            "alpha" => compute_general_multi_dep_quantity(
+                "alpha",
+                provider,
+                &["jx", "jy", "jz", "bx", "by", "bz"],
+                |values| {
+                    let (j, b) = (&values[..3], &values[3..]);
+                    dot_product(j, b) / dot_product(b, b)
+                },
+                verbosity,
+            ),
+            "angjb" => compute_general_multi_dep_quantity(
+                "angjb",
+                provider,
+                &["jx", "jy", "jz", "bx", "by", "bz"],
+                |values| {
+                    let (j, b) = (&values[..3], &values[3..]);
+                    let cos_angle =
+                        dot_product(j, b) / (dot_product(j, j) * dot_product(b, b)).sqrt();
+                    cos_angle.clamp(-1.0, 1.0).acos().to_degrees()
+                },
+                verbosity,
+            ),
+            "krec" => compute_reconnection_factor(provider, verbosity),
+            "chi500" => compute_derived_quantity!(
+                chi500,
+                |r, tg, nel| compute_hminus_extinction_at_500_nm(r, tg, nel),
+                provider,
+                verbosity
+            ),
+            "chiross" => compute_quantity_product("chiross", provider, "kr", "r", 1.0, verbosity),
+            "tau500" => compute_column_integral("tau500", provider, "chi500", Z, verbosity),
+            "tauross" => compute_column_integral("tauross", provider, "chiross", Z, verbosity),
+            _ => unreachable!(),
         }
     } else if let Some(cgs_base_name) = cgs_base_name(quantity_name) {
         if let Some((x_comp_name, y_comp_name, z_comp_name)) =
@@ -568,6 +1276,23 @@ fn compute_quantity(
             }
         } else if let Some(&scale) = QUANTITY_CGS_SCALES.get(cgs_base_name) {
             compute_scaled_quantity(quantity_name, provider, cgs_base_name, scale, verbosity)
+        } else if let Some(scale) =
+            DifferentialQuantity::from_name(cgs_base_name).and_then(|q| q.cgs_scale())
+        {
+            compute_scaled_quantity(quantity_name, provider, cgs_base_name, scale, verbosity)
+        } else if let Some(scale) = column_integrand_name_and_axis(cgs_base_name)
+            .and_then(|(integrand_name, _)| QUANTITY_CGS_SCALES.get(integrand_name))
+            .map(|&scale| scale * (U_L as fdt))
+        {
+            compute_scaled_quantity(quantity_name, provider, cgs_base_name, scale, verbosity)
+        } else if let Some(quantity) = TabulatedQuantity::from_name(cgs_base_name) {
+            compute_scaled_quantity(
+                quantity_name,
+                provider,
+                cgs_base_name,
+                quantity.cgs_scale() as fdt,
+                verbosity,
+            )
         } else {
             Err(io::Error::new(
                 io::ErrorKind::InvalidInput,
@@ -588,6 +1313,20 @@ fn compute_quantity(
         )
     } else if let Some(centered_base_name) = centered_base_name(quantity_name) {
         compute_centered_quantity(quantity_name, provider, centered_base_name, 1.0, verbosity)
+    } else if let Some(differential_quantity) = DifferentialQuantity::from_name(quantity_name) {
+        match differential_quantity {
+            DifferentialQuantity::Gradient(scalar_name, dim) => {
+                compute_gradient_component(quantity_name, provider, &scalar_name, dim, verbosity)
+            }
+            DifferentialQuantity::Divergence(vector_name) => {
+                compute_divergence(quantity_name, provider, &vector_name, verbosity)
+            }
+            DifferentialQuantity::Curl(vector_name, dim) => {
+                compute_curl_component(quantity_name, provider, &vector_name, dim, verbosity)
+            }
+        }
+    } else if let Some((integrand_name, axis)) = column_integrand_name_and_axis(quantity_name) {
+        compute_column_integral(quantity_name, provider, integrand_name, axis, verbosity)
     } else {
         Err(io::Error::new(
             io::ErrorKind::InvalidInput,
@@ -871,13 +1610,154 @@ pub fn compute_general_double_dep_quantity<C>(
 where
     C: Fn(fdt, fdt) -> fdt + Sync,
 {
-    let mut field_1 = provider.produce_scalar_field(dep_name_1)?;
+    let field_1 = provider.produce_scalar_field(dep_name_1)?;
+    let field_2 = provider.provide_scalar_field(dep_name_2)?;
+    combine_two_fields(
+        quantity_name,
+        provider,
+        field_1,
+        field_2,
+        compute,
+        verbosity,
+    )
+}
+
+pub fn compute_general_triple_dep_quantity<C>(
+    quantity_name: &str,
+    provider: &mut dyn ScalarFieldProvider3<fdt>,
+    dep_name_1: &str,
+    dep_name_2: &str,
+    dep_name_3: &str,
+    compute: C,
+    verbosity: &Verbosity,
+) -> io::Result<ScalarField3<fdt>>
+where
+    C: Fn(fdt, fdt, fdt) -> fdt + Sync,
+{
+    let field_1 = provider.produce_scalar_field(dep_name_1)?;
     let field_2 = provider.provide_scalar_field(dep_name_2)?;
+    let field_3 = provider.provide_scalar_field(dep_name_3)?;
+    combine_three_fields(
+        quantity_name,
+        provider,
+        field_1,
+        field_2,
+        field_3,
+        compute,
+        verbosity,
+    )
+}
+
+/// Computes a quantity from an arbitrary number of dependencies using the
+/// given compute closure, which receives the dependency values for a grid
+/// cell in the order of the given dependency names. If the dependencies are
+/// not defined at the same locations within the grid cell, they are first
+/// resampled to the cell centers.
+pub fn compute_general_multi_dep_quantity<C>(
+    quantity_name: &str,
+    provider: &mut dyn ScalarFieldProvider3<fdt>,
+    dep_names: &[&str],
+    compute: C,
+    verbosity: &Verbosity,
+) -> io::Result<ScalarField3<fdt>>
+where
+    C: Fn(&[fdt]) -> fdt + Sync,
+{
+    let mut fields = dep_names
+        .iter()
+        .map(|name| provider.provide_scalar_field(name))
+        .collect::<io::Result<Vec<_>>>()?;
+
+    let locations = align_field_locations(provider, &mut fields, verbosity)?;
+
+    if verbosity.print_messages() {
+        println!("Computing {}", quantity_name);
+    }
+
+    let values_buffers: Vec<_> = fields
+        .iter()
+        .map(|field| field.values().as_slice_memory_order().unwrap())
+        .collect();
+
+    let mut values = Array3::zeros(provider.grid().shape().to_tuple().f());
+    let values_buffer = values.as_slice_memory_order_mut().unwrap();
+
+    values_buffer.par_iter_mut().enumerate().for_each_init(
+        || vec![0.0; values_buffers.len()],
+        |dep_values, (idx, value)| {
+            for (dep_value, dep_values_buffer) in dep_values.iter_mut().zip(&values_buffers) {
+                *dep_value = dep_values_buffer[idx];
+            }
+            *value = compute(dep_values);
+        },
+    );
+
+    Ok(ScalarField3::new(
+        quantity_name.to_string(),
+        provider.arc_with_grid(),
+        locations,
+        values,
+    ))
+}
+
+/// Brings the given fields to common locations within the grid cell and
+/// returns the common locations. If the fields are not all defined at the
+/// same locations, the ones that are not cell centered are resampled to the
+/// cell centers.
+fn align_field_locations(
+    provider: &dyn ScalarFieldProvider3<fdt>,
+    fields: &mut [Arc<ScalarField3<fdt>>],
+    verbosity: &Verbosity,
+) -> io::Result<In3D<CoordLocation>> {
+    let center_locations = In3D::same(CoordLocation::Center);
+
+    let locations = match fields.first() {
+        Some(first_field) => first_field.locations().clone(),
+        None => return Ok(center_locations),
+    };
+    if fields.iter().all(|field| field.locations() == &locations) {
+        return Ok(locations);
+    }
 
+    let interpolator = PolyFitInterpolator3::new(PolyFitInterpolatorConfig::default());
+
+    io_result!(interpolator.verify_grid(provider.grid()))?;
+
+    for field in fields.iter_mut() {
+        if field.locations() != &center_locations {
+            if verbosity.print_messages() {
+                println!("Resampling {} to grid cell centers", field.name());
+            }
+            *field = Arc::new(field.resampled_to_grid(
+                provider.arc_with_grid(),
+                In3D::same(ResampledCoordLocation::center()),
+                &interpolator,
+                ResamplingMethod::DirectSampling,
+                verbosity,
+            ));
+        }
+    }
+    Ok(center_locations)
+}
+
+/// Combines the values of two fields into a new field using the given
+/// compute closure. If the fields are not defined at the same locations
+/// within the grid cell, both are first resampled to the cell centers.
+fn combine_two_fields<C>(
+    quantity_name: &str,
+    provider: &dyn ScalarFieldProvider3<fdt>,
+    mut field_1: ScalarField3<fdt>,
+    field_2: Arc<ScalarField3<fdt>>,
+    compute: C,
+    verbosity: &Verbosity,
+) -> io::Result<ScalarField3<fdt>>
+where
+    C: Fn(fdt, fdt) -> fdt + Sync,
+{
     let center_locations = In3D::same(CoordLocation::Center);
     let mut locations = field_1.locations().clone();
 
-    if field_1.locations() != field_2.locations() {
+    let field_2 = if field_1.locations() != field_2.locations() {
         let interpolator = PolyFitInterpolator3::new(PolyFitInterpolatorConfig::default());
 
         io_result!(interpolator.verify_grid(provider.grid()))?;
@@ -900,7 +1780,10 @@ where
         resample_to_center(&mut field_1);
         resample_to_center(&mut field_2);
         locations = center_locations;
-    }
+        Arc::new(field_2)
+    } else {
+        field_2
+    };
 
     if verbosity.print_messages() {
         println!("Computing {}", quantity_name);
@@ -925,26 +1808,27 @@ where
     ))
 }
 
-pub fn compute_general_triple_dep_quantity<C>(
+/// Combines the values of three fields into a new field using the given
+/// compute closure. If the fields are not defined at the same locations
+/// within the grid cell, all are first resampled to the cell centers.
+fn combine_three_fields<C>(
     quantity_name: &str,
-    provider: &mut dyn ScalarFieldProvider3<fdt>,
-    dep_name_1: &str,
-    dep_name_2: &str,
-    dep_name_3: &str,
+    provider: &dyn ScalarFieldProvider3<fdt>,
+    mut field_1: ScalarField3<fdt>,
+    field_2: Arc<ScalarField3<fdt>>,
+    field_3: Arc<ScalarField3<fdt>>,
     compute: C,
     verbosity: &Verbosity,
 ) -> io::Result<ScalarField3<fdt>>
 where
     C: Fn(fdt, fdt, fdt) -> fdt + Sync,
 {
-    let mut field_1 = provider.produce_scalar_field(dep_name_1)?;
-    let field_2 = provider.provide_scalar_field(dep_name_2)?;
-    let field_3 = provider.provide_scalar_field(dep_name_3)?;
-
     let center_locations = In3D::same(CoordLocation::Center);
     let mut locations = field_1.locations().clone();
 
-    if field_1.locations() != field_2.locations() || field_1.locations() != field_3.locations() {
+    let (field_2, field_3) = if field_1.locations() != field_2.locations()
+        || field_1.locations() != field_3.locations()
+    {
         let interpolator = PolyFitInterpolator3::new(PolyFitInterpolatorConfig::default());
 
         io_result!(interpolator.verify_grid(provider.grid()))?;
@@ -969,7 +1853,10 @@ where
         resample_to_center(&mut field_2);
         resample_to_center(&mut field_3);
         locations = center_locations;
-    }
+        (Arc::new(field_2), Arc::new(field_3))
+    } else {
+        (field_2, field_3)
+    };
 
     if verbosity.print_messages() {
         println!("Computing {}", quantity_name);
@@ -998,13 +1885,443 @@ where
     ))
 }
 
-pub fn compute_column_depth(
+/// Computes the given component of the gradient of a scalar quantity.
+///
+/// The derivative is evaluated with the staggered scheme used in Bifrost, so
+/// the resulting field is shifted half a grid cell along the differentiation
+/// axis relative to the original field.
+pub fn compute_gradient_component(
+    quantity_name: &str,
+    provider: &mut dyn ScalarFieldProvider3<fdt>,
+    scalar_name: &str,
+    dim: Dim3,
+    verbosity: &Verbosity,
+) -> io::Result<ScalarField3<fdt>> {
+    let field = provider.provide_scalar_field(scalar_name)?;
+
+    if verbosity.print_messages() {
+        println!("Computing {}", quantity_name);
+    }
+
+    Ok(compute_staggered_derivative(&field, dim).with_name(quantity_name.to_string()))
+}
+
+/// Computes the divergence of a vector quantity.
+///
+/// For vector components that are staggered along their own direction, as
+/// is the case for the primary Bifrost vector variables, the result is cell
+/// centered.
+pub fn compute_divergence(
+    quantity_name: &str,
+    provider: &mut dyn ScalarFieldProvider3<fdt>,
+    vector_name: &str,
+    verbosity: &Verbosity,
+) -> io::Result<ScalarField3<fdt>> {
+    let component_names = vec_component_names(vector_name);
+
+    let mut compute_term = |dim: Dim3| -> io::Result<ScalarField3<fdt>> {
+        let component = provider.provide_scalar_field(&component_names[dim])?;
+        if verbosity.print_messages() {
+            println!("Computing d{}/d{}", component.name(), dim);
+        }
+        Ok(compute_staggered_derivative(&component, dim))
+    };
+    let term_x = compute_term(X)?;
+    let term_y = compute_term(Y)?;
+    let term_z = compute_term(Z)?;
+
+    combine_three_fields(
+        quantity_name,
+        provider,
+        term_x,
+        Arc::new(term_y),
+        Arc::new(term_z),
+        |term_x, term_y, term_z| term_x + term_y + term_z,
+        verbosity,
+    )
+}
+
+/// Computes the given component of the curl of a vector quantity.
+///
+/// For the primary Bifrost vector variables, the result is located on
+/// the lower cell edges along the two dimensions perpendicular to the
+/// component direction.
+pub fn compute_curl_component(
+    quantity_name: &str,
+    provider: &mut dyn ScalarFieldProvider3<fdt>,
+    vector_name: &str,
+    dim: Dim3,
+    verbosity: &Verbosity,
+) -> io::Result<ScalarField3<fdt>> {
+    let component_names = vec_component_names(vector_name);
+
+    let (dim_1, dim_2) = match dim {
+        X => (Y, Z),
+        Y => (Z, X),
+        Z => (X, Y),
+    };
+
+    let mut compute_term = |component_dim: Dim3, derivative_dim: Dim3| {
+        let component = provider.provide_scalar_field(&component_names[component_dim])?;
+        if verbosity.print_messages() {
+            println!("Computing d{}/d{}", component.name(), derivative_dim);
+        }
+        io::Result::Ok(compute_staggered_derivative(&component, derivative_dim))
+    };
+    let positive_term = compute_term(dim_2, dim_1)?;
+    let negative_term = compute_term(dim_1, dim_2)?;
+
+    combine_two_fields(
+        quantity_name,
+        provider,
+        positive_term,
+        Arc::new(negative_term),
+        |positive_term, negative_term| positive_term - negative_term,
+        verbosity,
+    )
+}
+
+/// Computes the derivative of the given field along the given dimension
+/// using the sixth order staggered finite difference scheme of Bifrost.
+///
+/// Values defined at the lower cell edges along the dimension are
+/// differentiated upward, producing cell centered values, while cell centered
+/// values are differentiated downward, producing values at the lower edges.
+/// Non-periodic boundaries are handled by repeating the boundary values.
+pub fn compute_staggered_derivative(field: &ScalarField3<fdt>, dim: Dim3) -> ScalarField3<fdt> {
+    const C: fdt = 3.0 / 640.0;
+    const B: fdt = -25.0 / 384.0;
+    const A: fdt = 1.0 - 3.0 * B - 5.0 * C;
+
+    let grid = field.grid();
+    let shape = grid.shape();
+    let n = shape[dim] as isize;
+    let is_periodic = grid.is_periodic(dim);
+
+    let upward = field.locations()[dim] == CoordLocation::LowerEdge;
+
+    let mut locations = field.locations().clone();
+    locations[dim] = if upward {
+        CoordLocation::Center
+    } else {
+        CoordLocation::LowerEdge
+    };
+
+    let coord_derivatives = coordinate_derivatives(grid, dim, upward);
+
+    let values = field.values();
+    let mut derivatives = Array3::uninit(shape.to_tuple().f());
+    let derivatives_buffer = derivatives.as_slice_memory_order_mut().unwrap();
+
+    derivatives_buffer
+        .par_iter_mut()
+        .enumerate()
+        .for_each(|(idx, derivative)| {
+            let indices = field::compute_3d_array_indices_from_flat_idx(shape, idx);
+            if n == 1 {
+                derivative.write(0.0);
+                return;
+            }
+            let i = indices[dim] as isize;
+            let value_at_offset = |offset: isize| {
+                let mut offset_indices = indices.clone();
+                let j = i + offset;
+                offset_indices[dim] = if is_periodic {
+                    j.rem_euclid(n)
+                } else {
+                    j.clamp(0, n - 1)
+                } as usize;
+                values[[offset_indices[X], offset_indices[Y], offset_indices[Z]]]
+            };
+            // Shift the stencil so that it is centered on the lower edge
+            // for downward derivatives
+            let s = if upward { 0 } else { -1 };
+            let difference = A * (value_at_offset(s + 1) - value_at_offset(s))
+                + B * (value_at_offset(s + 2) - value_at_offset(s - 1))
+                + C * (value_at_offset(s + 3) - value_at_offset(s - 2));
+            derivative.write(difference * coord_derivatives[indices[dim]]);
+        });
+    let derivatives = unsafe { derivatives.assume_init() };
+
+    ScalarField3::new(
+        format!("d{}d{}", field.name(), dim),
+        field.arc_with_grid(),
+        locations,
+        derivatives,
+    )
+}
+
+/// Returns the inverse coordinate derivatives along the given dimension at the
+/// output locations of upward or downward staggered differentiation.
+///
+/// Downward differentiation produces values at the lower cell edges, where the
+/// downward derivatives of the cell centers are located. Upward differentiation
+/// produces cell centered values, while the upward derivatives of the cell
+/// centers are located at the upper cell edges. The derivatives for upward
+/// differentiation are therefore computed from the lower cell edges instead.
+/// If the grid does not store the downward derivatives, they are computed from
+/// the cell centers.
+fn coordinate_derivatives(grid: &FieldGrid3, dim: Dim3, upward: bool) -> Cow<'_, [fdt]> {
+    let stored_derivatives = if upward {
+        None
+    } else {
+        grid.down_derivatives()
+    };
+    if let Some(derivatives) = stored_derivatives {
+        Cow::from(
+            derivatives[dim]
+                .iter()
+                .map(|&derivative| derivative as fdt)
+                .collect::<Vec<_>>(),
+        )
+    } else {
+        let coords = if upward {
+            &grid.lower_edges()[dim]
+        } else {
+            &grid.centers()[dim]
+        };
+        let derivatives = if coords.len() >= 8 {
+            let (up_derivatives, down_derivatives) = grid::compute_up_and_down_derivatives(coords);
+            if upward {
+                up_derivatives
+            } else {
+                down_derivatives
+            }
+        } else {
+            grid::compute_regular_derivatives(coords.len(), grid.average_grid_cell_extents()[dim])
+        };
+        Cow::from(
+            derivatives
+                .into_iter()
+                .map(|derivative| derivative as fdt)
+                .collect::<Vec<_>>(),
+        )
+    }
+}
+
+/// Computes the given component of the Poynting flux in the ideal MHD
+/// approximation, where the electric field is given by -u x B. In Bifrost
+/// units the flux is B²u - (u·B)B.
+pub fn compute_poynting_flux_component(
+    quantity_name: &str,
+    provider: &mut dyn ScalarFieldProvider3<fdt>,
+    dim: Dim3,
+    verbosity: &Verbosity,
+) -> io::Result<ScalarField3<fdt>> {
+    compute_general_multi_dep_quantity(
+        quantity_name,
+        provider,
+        &["ux", "uy", "uz", "bx", "by", "bz"],
+        |values| {
+            let (u, b) = (&values[..3], &values[3..]);
+            dot_product(b, b) * u[dim as usize] - dot_product(u, b) * b[dim as usize]
+        },
+        verbosity,
+    )
+}
+
+/// Computes the Spitzer conductive heat flux along the magnetic field,
+/// -κ₀T^(5/2)(B·∇T)/|B|, in Bifrost units.
+pub fn compute_parallel_spitzer_flux(
+    provider: &mut dyn ScalarFieldProvider3<fdt>,
+    verbosity: &Verbosity,
+) -> io::Result<ScalarField3<fdt>> {
+    // Converts κ₀T^(5/2)dT/dl from T in K and l in Bifrost length units to
+    // energy flux in Bifrost units
+    let scale = (KAPPA_SPITZER / (U_L * U_E * U_U)) as fdt;
+    compute_general_multi_dep_quantity(
+        "fspitz",
+        provider,
+        &["tg", "gradtgx", "gradtgy", "gradtgz", "bx", "by", "bz"],
+        |values| {
+            let (tg, grad_tg, b) = (values[0], &values[1..4], &values[4..]);
+            -scale * tg * tg * tg.sqrt() * parallel_component(grad_tg, b)
+        },
+        verbosity,
+    )
+}
+
+/// Computes the magnetic diffusivity η given by the magnetic diffusivity
+/// specification. The Bifrost diffusivity is scaled with the cube root of the
+/// cell volume.
+pub fn compute_magnetic_diffusivity(
+    provider: &mut dyn ScalarFieldProvider3<fdt>,
+    magnetic_diffusivity: Option<MagneticDiffusivity>,
+    verbosity: &Verbosity,
+) -> io::Result<ScalarField3<fdt>> {
+    match magnetic_diffusivity {
+        Some(MagneticDiffusivity::Uniform(magnetic_diffusivity)) => Ok(ScalarField3::new(
+            MAGNETIC_DIFFUSIVITY_NAME.to_string(),
+            provider.arc_with_grid(),
+            In3D::same(CoordLocation::Center),
+            Array3::from_elem(provider.grid().shape().to_tuple().f(), magnetic_diffusivity),
+        )),
+        Some(MagneticDiffusivity::FastModeScaled(eta1)) => {
+            let gamma = GAMMA_IDEAL as fdt;
+            let field = compute_general_multi_dep_quantity(
+                MAGNETIC_DIFFUSIVITY_NAME,
+                provider,
+                &["r", "p", "ux", "uy", "uz", "bx", "by", "bz"],
+                |values| {
+                    let (r, p, u, b) = (values[0], values[1], &values[2..5], &values[5..]);
+                    let fast_mode_speed = ((gamma * p + dot_product(b, b)) / r).sqrt();
+                    eta1 * (fast_mode_speed + dot_product(u, u).sqrt())
+                },
+                verbosity,
+            )?;
+
+            let grid = provider.arc_with_grid();
+            let grid_shape = grid.shape();
+            let locations = field.locations().clone();
+            let mut values = field.into_values();
+            values
+                .as_slice_memory_order_mut()
+                .unwrap()
+                .par_iter_mut()
+                .enumerate()
+                .for_each(|(idx, value)| {
+                    let indices = field::compute_3d_array_indices_from_flat_idx(grid_shape, idx);
+                    *value *= grid.grid_cell_volume(&indices).cbrt() as fdt;
+                });
+            Ok(ScalarField3::new(
+                MAGNETIC_DIFFUSIVITY_NAME.to_string(),
+                provider.arc_with_grid(),
+                locations,
+                values,
+            ))
+        }
+        None => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Magnetic diffusivity not available: the snapshot has no eta1 parameter\n\
+             and no uniform diffusivity was given",
+        )),
+    }
+}
+
+/// Computes the given component of the electric field from the resistive MHD
+/// Ohm's law, -u x B + ηJ. The magnetic diffusivity η is the given uniform
+/// value, or otherwise the `eta` quantity. As in Bifrost, the electric field is
+/// premultiplied with c.
+pub fn compute_ohmic_electric_field_component(
+    quantity_name: &str,
+    provider: &mut dyn ScalarFieldProvider3<fdt>,
+    dim: Dim3,
+    magnetic_diffusivity: Option<MagneticDiffusivity>,
+    verbosity: &Verbosity,
+) -> io::Result<ScalarField3<fdt>> {
+    let ohms_law = |u: &[fdt], b: &[fdt], j: &[fdt], magnetic_diffusivity: fdt| {
+        magnetic_diffusivity * j[dim as usize] - cross_product(u, b)[dim as usize]
+    };
+    if let Some(MagneticDiffusivity::Uniform(magnetic_diffusivity)) = magnetic_diffusivity {
+        if magnetic_diffusivity == 0.0 && verbosity.print_messages() {
+            println!(
+                "Magnetic diffusivity is zero, so {} will only contain the ideal term",
+                quantity_name
+            );
+        }
+        compute_general_multi_dep_quantity(
+            quantity_name,
+            provider,
+            &["ux", "uy", "uz", "bx", "by", "bz", "jx", "jy", "jz"],
+            |values| {
+                ohms_law(
+                    &values[..3],
+                    &values[3..6],
+                    &values[6..],
+                    magnetic_diffusivity,
+                )
+            },
+            verbosity,
+        )
+    } else {
+        compute_general_multi_dep_quantity(
+            quantity_name,
+            provider,
+            &[
+                "ux",
+                "uy",
+                "uz",
+                "bx",
+                "by",
+                "bz",
+                "jx",
+                "jy",
+                "jz",
+                MAGNETIC_DIFFUSIVITY_NAME,
+            ],
+            |values| ohms_law(&values[..3], &values[3..6], &values[6..9], values[9]),
+            verbosity,
+        )
+    }
+}
+
+/// Computes the given component of the non-ideal part of the electric field,
+/// E + u x B, which vanishes for ideal MHD.
+pub fn compute_nonideal_electric_field_component(
+    quantity_name: &str,
     provider: &mut dyn ScalarFieldProvider3<fdt>,
+    dim: Dim3,
     verbosity: &Verbosity,
 ) -> io::Result<ScalarField3<fdt>> {
-    let quantity_name = "coldepth";
+    compute_general_multi_dep_quantity(
+        quantity_name,
+        provider,
+        &["ex", "ey", "ez", "ux", "uy", "uz", "bx", "by", "bz"],
+        |values| {
+            let (e, u, b) = (&values[..3], &values[3..6], &values[6..]);
+            e[dim as usize] + cross_product(u, b)[dim as usize]
+        },
+        verbosity,
+    )
+}
+
+/// Computes the reconnection factor |B x (∇ x R)|, where R = E + u x B is the
+/// non-ideal electric field. Following Biskamp (2005), magnetic field line
+/// topology is conserved wherever this factor vanishes.
+pub fn compute_reconnection_factor(
+    provider: &mut dyn ScalarFieldProvider3<fdt>,
+    verbosity: &Verbosity,
+) -> io::Result<ScalarField3<fdt>> {
+    compute_general_multi_dep_quantity(
+        "krec",
+        provider,
+        &["bx", "by", "bz", "curlenix", "curleniy", "curleniz"],
+        |values| {
+            let factor = cross_product(&values[..3], &values[3..]);
+            dot_product(&factor, &factor).sqrt()
+        },
+        verbosity,
+    )
+}
 
-    let field = provider.produce_scalar_field("r")?;
+/// Computes the projection of the first vector onto the direction of the second.
+fn parallel_component(vector: &[fdt], direction: &[fdt]) -> fdt {
+    dot_product(vector, direction) / dot_product(direction, direction).sqrt()
+}
+
+fn dot_product(a: &[fdt], b: &[fdt]) -> fdt {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross_product(a: &[fdt], b: &[fdt]) -> [fdt; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+/// Computes the integral of the given quantity along the given axis, accumulated
+/// from the lower end of the axis. Since z increases downward in Bifrost, integrals
+/// along z are accumulated downward from the top of the domain.
+pub fn compute_column_integral(
+    quantity_name: &str,
+    provider: &mut dyn ScalarFieldProvider3<fdt>,
+    integrand_name: &str,
+    axis: Dim3,
+    verbosity: &Verbosity,
+) -> io::Result<ScalarField3<fdt>> {
+    let field = provider.produce_scalar_field(integrand_name)?;
 
     if verbosity.print_messages() {
         println!("Computing {}", quantity_name);
@@ -1023,10 +2340,10 @@ pub fn compute_column_depth(
         .enumerate()
         .for_each(|(idx, value)| {
             let indices = field::compute_3d_array_indices_from_flat_idx(grid_shape, idx);
-            *value *= grid.grid_cell_extents(&indices)[Z] as fdt;
+            *value *= grid.grid_cell_extents(&indices)[axis] as fdt;
         });
 
-    values.accumulate_axis_inplace(Axis(2), |&prev, curr| *curr += prev);
+    values.accumulate_axis_inplace(Axis(axis as usize), |&prev, curr| *curr += prev);
 
     Ok(ScalarField3::new(
         quantity_name.to_string(),
@@ -1035,3 +2352,439 @@ pub fn compute_column_depth(
         values,
     ))
 }
+
+/// Computes the H- bound-free extinction coefficient at 500 nm [1/length in Bifrost units]
+/// from the mass density [Bifrost units], temperature [K] and electron density [1/cm^3].
+///
+/// All hydrogen is assumed to be neutral, with the H- number density given by
+/// the Saha equation. The cross section is evaluated from the fit of Gray (2005).
+fn compute_hminus_extinction_at_500_nm(
+    mass_density: fdt,
+    temperature: fdt,
+    electron_density: fdt,
+) -> fdt {
+    const HYDROGEN_MASS_FRACTION: fcn = 0.735;
+    const WAVELENGTH: fcn = 5e-5; // [cm]
+    const CROSS_SECTION: fcn = 2.93e-17; // [cm^2]
+
+    let temperature = temperature as fcn;
+    let hydrogen_density = HYDROGEN_MASS_FRACTION * (mass_density as fcn) * U_R / M_H;
+    let saha_factor = 0.25
+        * (HPLANCK * HPLANCK / (2.0 * PI * M_ELECTRON * KBOLTZMANN * temperature)).powf(1.5)
+        * fcn::exp(XI_HMINUS * EV_TO_K / temperature);
+    let hminus_density = saha_factor * hydrogen_density * (electron_density as fcn);
+    let stimulated_emission_correction =
+        1.0 - fcn::exp(-HPLANCK * CLIGHT / (WAVELENGTH * KBOLTZMANN * temperature));
+    (CROSS_SECTION * hminus_density * stimulated_emission_correction * U_L) as fdt
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{
+        field::{CustomScalarFieldGenerator3, ScalarFieldCacher3},
+        geometry::{Coords3, Vec3},
+        grid::{fgr, regular::RegularGrid3},
+        io::snapshot::{MapOfSnapshotParameters, ParameterValue},
+    };
+
+    #[test]
+    fn staggered_derivative_of_periodic_sine_is_accurate() {
+        let grid = Arc::new(FieldGrid3::from(RegularGrid3::from_bounds(
+            In3D::new(32, 1, 16),
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(2.0 * std::f64::consts::PI, 1.0, 1.0),
+            In3D::new(true, true, false),
+        )));
+        let mut provider = CustomScalarFieldGenerator3::<fdt>::new(grid, Verbosity::Quiet)
+            .with_variable("f".to_string(), Box::new(|x: fgr, _, _| x.sin() as fdt))
+            .with_variable_at_locations(
+                "g".to_string(),
+                Box::new(|_, _, z: fgr| (3.0 * z) as fdt),
+                In3D::new(
+                    CoordLocation::Center,
+                    CoordLocation::Center,
+                    CoordLocation::LowerEdge,
+                ),
+            );
+
+        let f = provider.produce_scalar_field("f").unwrap();
+        let dfdx = compute_staggered_derivative(&f, X);
+        assert_eq!(dfdx.locations()[X], CoordLocation::LowerEdge);
+        let lower_edges_x = &dfdx.grid().lower_edges()[X];
+        for (i, &x) in lower_edges_x.iter().enumerate() {
+            let derivative = dfdx.value(&Idx3::new(i, 0, 3));
+            assert!((derivative - x.cos() as fdt).abs() < 1e-5);
+        }
+
+        let dfdy = compute_staggered_derivative(&f, Y);
+        assert!(dfdy.values().iter().all(|&value| value == 0.0));
+
+        let g = provider.produce_scalar_field("g").unwrap();
+        let dgdz = compute_staggered_derivative(&g, Z);
+        assert_eq!(dgdz.locations()[Z], CoordLocation::Center);
+        for k in 3..13 {
+            assert!((dgdz.value(&Idx3::new(0, 0, k)) - 3.0).abs() < 1e-4);
+        }
+    }
+
+    /// Creates a grid that is regular and periodic horizontally, and where the
+    /// cell extents grow by 8% per cell from the top to the bottom.
+    fn create_stretched_grid() -> Arc<FieldGrid3> {
+        let regular_coords = |n: usize, offset: fgr| -> Vec<fgr> {
+            (0..n).map(|i| (i as fgr + offset) / n as fgr).collect()
+        };
+        let stretched_coords = |n: usize, offset: fgr| -> Vec<fgr> {
+            (0..n)
+                .map(|k| {
+                    let s = k as fgr + offset;
+                    0.1 * (s + 0.04 * s * s)
+                })
+                .collect()
+        };
+        Arc::new(
+            FieldGrid3::from_coords(
+                Coords3::new(
+                    regular_coords(8, 0.5),
+                    regular_coords(8, 0.5),
+                    stretched_coords(24, 0.5),
+                ),
+                Coords3::new(
+                    regular_coords(8, 0.0),
+                    regular_coords(8, 0.0),
+                    stretched_coords(24, 0.0),
+                ),
+                In3D::new(true, true, false),
+                None,
+                None,
+            )
+            .unwrap(),
+        )
+    }
+
+    #[test]
+    fn divergence_of_potential_field_vanishes_on_stretched_grid() {
+        // B = grad((x² + y²)/2 - z²) with the Bifrost staggering
+        let mut provider =
+            CustomScalarFieldGenerator3::<fdt>::new(create_stretched_grid(), Verbosity::Quiet)
+                .with_variable_at_locations(
+                    "bx".to_string(),
+                    Box::new(|x: fgr, _, _| x as fdt),
+                    In3D::new(
+                        CoordLocation::LowerEdge,
+                        CoordLocation::Center,
+                        CoordLocation::Center,
+                    ),
+                )
+                .with_variable_at_locations(
+                    "by".to_string(),
+                    Box::new(|_, y: fgr, _| y as fdt),
+                    In3D::new(
+                        CoordLocation::Center,
+                        CoordLocation::LowerEdge,
+                        CoordLocation::Center,
+                    ),
+                )
+                .with_variable_at_locations(
+                    "bz".to_string(),
+                    Box::new(|_, _, z: fgr| (-2.0 * z) as fdt),
+                    In3D::new(
+                        CoordLocation::Center,
+                        CoordLocation::Center,
+                        CoordLocation::LowerEdge,
+                    ),
+                );
+
+        let dbzdz = compute_staggered_derivative(&provider.produce_scalar_field("bz").unwrap(), Z);
+        let div_b = compute_divergence("divb", &mut provider, "b", &Verbosity::Quiet).unwrap();
+        assert_eq!(*div_b.locations(), In3D::same(CoordLocation::Center));
+
+        // Skip the boundaries, where values are repeated
+        for k in 5..19 {
+            let idx = Idx3::new(4, 4, k);
+            assert!(
+                (dbzdz.value(&idx) + 2.0).abs() < 1e-4,
+                "dbz/dz = {} at k = {}",
+                dbzdz.value(&idx),
+                k
+            );
+            assert!(div_b.value(&idx).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn curl_of_linear_field_is_uniform_on_stretched_grid() {
+        // B = (3z, 0, x), with curl B = (0, 2, 0)
+        let mut provider =
+            CustomScalarFieldGenerator3::<fdt>::new(create_stretched_grid(), Verbosity::Quiet)
+                .with_variable_at_locations(
+                    "bx".to_string(),
+                    Box::new(|_, _, z: fgr| (3.0 * z) as fdt),
+                    In3D::new(
+                        CoordLocation::LowerEdge,
+                        CoordLocation::Center,
+                        CoordLocation::Center,
+                    ),
+                )
+                .with_variable_at_locations(
+                    "by".to_string(),
+                    Box::new(|_, _, _| 0.0),
+                    In3D::new(
+                        CoordLocation::Center,
+                        CoordLocation::LowerEdge,
+                        CoordLocation::Center,
+                    ),
+                )
+                .with_variable_at_locations(
+                    "bz".to_string(),
+                    Box::new(|x: fgr, _, _| x as fdt),
+                    In3D::new(
+                        CoordLocation::Center,
+                        CoordLocation::Center,
+                        CoordLocation::LowerEdge,
+                    ),
+                );
+
+        let curl_b_y =
+            compute_curl_component("jy", &mut provider, "b", Y, &Verbosity::Quiet).unwrap();
+        assert_eq!(
+            *curl_b_y.locations(),
+            In3D::new(
+                CoordLocation::LowerEdge,
+                CoordLocation::Center,
+                CoordLocation::LowerEdge
+            )
+        );
+        // The linear bz wraps around at the periodic x-boundary
+        for i in 3..6 {
+            for k in 5..19 {
+                let idx = Idx3::new(i, 4, k);
+                assert!(
+                    (curl_b_y.value(&idx) - 2.0).abs() < 1e-4,
+                    "(curl B)_y = {} at k = {}",
+                    curl_b_y.value(&idx),
+                    k
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn ideal_poynting_flux_is_perpendicular_to_magnetic_field() {
+        let grid = Arc::new(FieldGrid3::from(RegularGrid3::from_bounds(
+            In3D::new(4, 4, 4),
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(1.0, 1.0, 1.0),
+            In3D::new(true, true, true),
+        )));
+        let constant =
+            |value: fdt| -> field::FieldValueComputer<fdt> { Box::new(move |_, _, _| value) };
+        let mut provider = CustomScalarFieldGenerator3::<fdt>::new(grid, Verbosity::Quiet)
+            .with_variable("ux".to_string(), constant(1.0))
+            .with_variable("uy".to_string(), constant(2.0))
+            .with_variable("uz".to_string(), constant(0.0))
+            .with_variable("bx".to_string(), constant(0.0))
+            .with_variable("by".to_string(), constant(0.0))
+            .with_variable("bz".to_string(), constant(3.0));
+
+        let expected = [9.0, 18.0, 0.0];
+        for (dim, expected) in [X, Y, Z].into_iter().zip(expected) {
+            let flux =
+                compute_poynting_flux_component("poynt", &mut provider, dim, &Verbosity::Quiet)
+                    .unwrap();
+            assert_eq!(flux.locations(), &In3D::same(CoordLocation::Center));
+            assert!(flux.values().iter().all(|&value| value == expected));
+        }
+    }
+
+    fn create_derived_provider_for_magnetized_flow(
+        magnetic_field: [field::FieldValueComputer<fdt>; 3],
+        vertical_velocity: fdt,
+        magnetic_diffusivity: Option<MagneticDiffusivity>,
+    ) -> DerivedScalarFieldProvider3 {
+        let grid = Arc::new(FieldGrid3::from(RegularGrid3::from_bounds(
+            In3D::new(4, 4, 64),
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(1.0, 1.0, 2.0 * std::f64::consts::PI),
+            In3D::new(true, true, true),
+        )));
+        let [bx, by, bz] = magnetic_field;
+        let provider = CustomScalarFieldGenerator3::<fdt>::new(grid, Verbosity::Quiet)
+            .with_variable("r".to_string(), Box::new(|_, _, _| 4.0))
+            .with_variable("p".to_string(), Box::new(|_, _, _| 3.0))
+            .with_variable("ux".to_string(), Box::new(|_, _, _| 0.0))
+            .with_variable("uy".to_string(), Box::new(|_, _, _| 0.0))
+            .with_variable("uz".to_string(), Box::new(move |_, _, _| vertical_velocity))
+            .with_variable("bx".to_string(), bx)
+            .with_variable("by".to_string(), by)
+            .with_variable("bz".to_string(), bz);
+        DerivedScalarFieldProvider3::new(
+            Box::new(ScalarFieldCacher3::new_manual_cacher(
+                Box::new(provider),
+                Verbosity::Quiet,
+            )),
+            Vec::new(),
+            None,
+            QuantityDefinitions::default(),
+            magnetic_diffusivity,
+            &|_, _| {},
+            Verbosity::Quiet,
+        )
+    }
+
+    fn assert_matches_vertical_profile(
+        provider: &mut DerivedScalarFieldProvider3,
+        quantity_name: &str,
+        expected: impl Fn(fgr) -> fgr,
+    ) {
+        let field = provider.produce_scalar_field(quantity_name).unwrap();
+        assert_eq!(field.locations(), &In3D::same(CoordLocation::Center));
+        for (k, &z) in field.grid().centers()[Z].iter().enumerate() {
+            let value = field.value(&Idx3::new(1, 0, k));
+            assert!(
+                (value as fgr - expected(z)).abs() < 1e-2,
+                "{} at z = {} is {}, expected {}",
+                quantity_name,
+                z,
+                value,
+                expected(z)
+            );
+        }
+    }
+
+    #[test]
+    fn ohmic_electric_field_of_force_free_field_matches_analytic_values() {
+        let (velocity, diffusivity) = (2.0, 0.5);
+        let mut provider = create_derived_provider_for_magnetized_flow(
+            [
+                Box::new(|_, _, z: fgr| z.sin() as fdt),
+                Box::new(|_, _, z: fgr| z.cos() as fdt),
+                Box::new(|_, _, _| 0.0),
+            ],
+            velocity as fdt,
+            Some(MagneticDiffusivity::Uniform(diffusivity as fdt)),
+        );
+        assert_matches_vertical_profile(&mut provider, "ex", |z| {
+            velocity * z.cos() + diffusivity * z.sin()
+        });
+        assert_matches_vertical_profile(&mut provider, "ey", |z| {
+            diffusivity * z.cos() - velocity * z.sin()
+        });
+        assert_matches_vertical_profile(&mut provider, "ez", |_| 0.0);
+        assert_matches_vertical_profile(&mut provider, "epar", |_| diffusivity);
+        assert_matches_vertical_profile(&mut provider, "jpar", |_| 1.0);
+        assert_matches_vertical_profile(&mut provider, "alpha", |_| 1.0);
+    }
+
+    #[test]
+    fn reconnection_factor_matches_analytic_values() {
+        let diffusivity = 0.5;
+        let mut provider = create_derived_provider_for_magnetized_flow(
+            [
+                Box::new(|_, _, z: fgr| z.sin() as fdt),
+                Box::new(|_, _, _| 0.0),
+                Box::new(|_, _, _| 1.0),
+            ],
+            1.0,
+            Some(MagneticDiffusivity::Uniform(diffusivity as fdt)),
+        );
+        assert_matches_vertical_profile(&mut provider, "jpar", |_| 0.0);
+        assert_matches_vertical_profile(&mut provider, "alpha", |_| 0.0);
+        assert_matches_vertical_profile(&mut provider, "krec", |z| diffusivity * z.sin().abs());
+    }
+
+    #[test]
+    fn ohmic_electric_field_requires_magnetic_diffusivity() {
+        let mut provider = create_derived_provider_for_magnetized_flow(
+            [
+                Box::new(|_, _, z: fgr| z.sin() as fdt),
+                Box::new(|_, _, z: fgr| z.cos() as fdt),
+                Box::new(|_, _, _| 0.0),
+            ],
+            1.0,
+            None,
+        );
+        for name in ["eta", "ex", "epar", "enix", "krec", "ex_cgs"] {
+            assert!(
+                !provider.has_variable(name),
+                "{} reported as available",
+                name
+            );
+        }
+        assert!(provider.has_variable("jpar"));
+        assert!(provider.produce_scalar_field("ex").is_err());
+        assert!(provider.produce_scalar_field("krec").is_err());
+    }
+
+    #[test]
+    fn magnetic_diffusivity_is_derived_from_bifrost_parameters() {
+        let mut parameters = HashMap::new();
+        parameters.insert("eta1".to_string(), ParameterValue::new_float(0.1));
+        let magnetic_diffusivity =
+            MagneticDiffusivity::from_parameters(&MapOfSnapshotParameters::new(parameters));
+        assert_eq!(
+            magnetic_diffusivity,
+            Some(MagneticDiffusivity::FastModeScaled(0.1))
+        );
+
+        let velocity = 2.0;
+        let mut provider = create_derived_provider_for_magnetized_flow(
+            [
+                Box::new(|_, _, z: fgr| z.sin() as fdt),
+                Box::new(|_, _, z: fgr| z.cos() as fdt),
+                Box::new(|_, _, _| 0.0),
+            ],
+            velocity as fdt,
+            magnetic_diffusivity,
+        );
+        assert!(provider.has_variable("krec"));
+
+        // With r = 4, p = 3 and |B| = 1, the fast mode speed is sqrt((5/3*3 + 1)/4)
+        let cell_size = (0.25 * 0.25 * 2.0 * std::f64::consts::PI / 64.0).cbrt();
+        let diffusivity = 0.1 * cell_size * (1.5_f64.sqrt() + velocity);
+        assert_matches_vertical_profile(&mut provider, "eta", |_| diffusivity);
+        assert_matches_vertical_profile(&mut provider, "ex", |z| {
+            velocity * z.cos() + diffusivity * z.sin()
+        });
+        assert_matches_vertical_profile(&mut provider, "epar", |_| diffusivity);
+    }
+
+    #[test]
+    fn iso_surface_of_column_integral_is_found_and_sampled() {
+        let grid = Arc::new(FieldGrid3::from(RegularGrid3::from_bounds(
+            In3D::new(2, 2, 10),
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(1.0, 1.0, 1.0),
+            In3D::new(true, true, false),
+        )));
+        let mut provider = CustomScalarFieldGenerator3::<fdt>::new(grid, Verbosity::Quiet)
+            .with_variable("one".to_string(), Box::new(|_, _, _| 1.0))
+            .with_variable("height".to_string(), Box::new(|_, _, z: fgr| z as fdt));
+
+        let column =
+            compute_column_integral("colonez", &mut provider, "one", Z, &Verbosity::Quiet).unwrap();
+        for k in 0..10 {
+            let expected = 0.1 * (k + 1) as fdt;
+            assert!((column.value(&Idx3::new(1, 0, k)) - expected).abs() < 1e-5);
+        }
+
+        let surface_coords = column.find_iso_surface_coords(Z, 0.55);
+        assert!(surface_coords
+            .values()
+            .iter()
+            .all(|&coord| (coord - 0.5).abs() < 1e-5));
+        assert!(column
+            .find_iso_surface_coords(Z, 2.0)
+            .values()
+            .iter()
+            .all(|coord| coord.is_nan()));
+
+        let height = provider.produce_scalar_field("height").unwrap();
+        let sampled_height = height.sample_at_surface(Z, &surface_coords);
+        assert!(sampled_height
+            .values()
+            .iter()
+            .all(|&value| (value - 0.5).abs() < 1e-5));
+    }
+}
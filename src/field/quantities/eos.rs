@@ -0,0 +1,467 @@
+//! Lookup of quantities in the tabulated equation of state used by Bifrost.
+
+use super::compute_general_double_dep_quantity;
+use crate::{
+    field::{ScalarField3, ScalarFieldProvider3},
+    io::{
+        snapshot::{fdt, native::NativeSnapshotParameters, SnapshotParameters},
+        utils, Endianness, Verbosity,
+    },
+    units::solar::{U_EE, U_KR, U_P, U_R, U_TE},
+};
+use ndarray::{Array3, Array4, ShapeBuilder};
+use regex::Regex;
+use std::{
+    borrow::Cow,
+    io,
+    path::{Path, PathBuf},
+};
+
+/// Floating-point precision of the values in the tables.
+#[allow(non_camel_case_types)]
+type ftb = f32;
+
+/// Number of quantities in the equation of state table.
+const N_EOS_TABLE_QUANTITIES: usize = 4;
+
+/// Number of quantities per radiation bin in the radiation table.
+const N_RAD_TABLE_QUANTITIES: usize = 3;
+
+/// Quantity that can be looked up in the tabulated equation of state.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TabulatedQuantity {
+    /// Gas pressure [Bifrost units].
+    GasPressure,
+    /// Temperature [K].
+    Temperature,
+    /// Electron number density [electrons/cm^3].
+    ElectronDensity,
+    /// Rosseland mean opacity [Bifrost units].
+    RosselandOpacity,
+    /// Scattering probability in the given (zero-based) radiation bin.
+    ScatteringProbability(usize),
+    /// Thermal emission in the given radiation bin [Bifrost units].
+    ThermalEmission(usize),
+    /// Opacity in the given radiation bin [Bifrost units].
+    Opacity(usize),
+}
+
+impl TabulatedQuantity {
+    /// Returns the quantity corresponding to the given variable name, if any.
+    pub fn from_name(quantity_name: &str) -> Option<Self> {
+        lazy_static::lazy_static! {
+            static ref BIN_REGEX: Regex = Regex::new(r"^(eps|temt|opa)([1-9]\d*)$").unwrap();
+        }
+        match quantity_name {
+            "p" => Some(Self::GasPressure),
+            "tg" => Some(Self::Temperature),
+            "nel" => Some(Self::ElectronDensity),
+            "kr" => Some(Self::RosselandOpacity),
+            _ => BIN_REGEX.captures(quantity_name).map(|groups| {
+                let bin_idx = groups[2].parse::<usize>().unwrap() - 1;
+                match &groups[1] {
+                    "eps" => Self::ScatteringProbability(bin_idx),
+                    "temt" => Self::ThermalEmission(bin_idx),
+                    "opa" => Self::Opacity(bin_idx),
+                    _ => unreachable!(),
+                }
+            }),
+        }
+    }
+
+    /// Returns the zero-based radiation bin index if this is a radiation
+    /// table quantity.
+    fn bin_idx(&self) -> Option<usize> {
+        match *self {
+            Self::ScatteringProbability(bin_idx)
+            | Self::ThermalEmission(bin_idx)
+            | Self::Opacity(bin_idx) => Some(bin_idx),
+            _ => None,
+        }
+    }
+
+    /// Returns the factor converting the provided value of the quantity to CGS units.
+    pub fn cgs_scale(&self) -> f64 {
+        match *self {
+            Self::GasPressure => U_P,
+            Self::RosselandOpacity | Self::Opacity(_) => U_KR,
+            Self::ThermalEmission(_) => U_TE,
+            Self::Temperature | Self::ElectronDensity | Self::ScatteringProbability(_) => 1.0,
+        }
+    }
+}
+
+/// Bifrost's tabulated equation of state, giving thermodynamic and
+/// radiative quantities as functions of mass density and internal energy.
+#[derive(Clone, Debug)]
+pub struct TabulatedEquationOfState {
+    ln_rho_min: f64,
+    ln_rho_step: f64,
+    n_rho_bins: usize,
+    ln_ei_min: f64,
+    ln_ei_step: f64,
+    n_ei_bins: usize,
+    n_rad_bins: usize,
+    eos_table: Array3<ftb>,
+    rad_table: Option<Array4<ftb>>,
+}
+
+impl TabulatedEquationOfState {
+    /// Reads the table parameters from the given `tabparam.in` file, and then
+    /// reads the equation of state table and, if it exists, the radiation table.
+    ///
+    /// The table files are assumed to be located in the same directory as the
+    /// parameter file.
+    pub fn from_tabparam_file(
+        tabparam_file_path: PathBuf,
+        endianness: Endianness,
+        verbosity: &Verbosity,
+    ) -> io::Result<Self> {
+        let parameters = NativeSnapshotParameters::new(tabparam_file_path.clone(), verbosity)?;
+
+        let n_rho_bins = get_table_int(&parameters, "nRhoBin")?;
+        let rho_min = get_table_float(&parameters, "RhoMin")?;
+        let rho_max = get_table_float(&parameters, "RhoMax")?;
+        let n_ei_bins = get_table_int(&parameters, "nEiBin")?;
+        let ei_min = get_table_float(&parameters, "EiMin")?;
+        let ei_max = get_table_float(&parameters, "EiMax")?;
+        let n_rad_bins = get_table_int(&parameters, "nRadBins").unwrap_or(0);
+
+        if n_rho_bins < 2 || n_ei_bins < 2 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Tabulated equation of state must have at least two bins per axis",
+            ));
+        }
+
+        let eos_table_path = tabparam_file_path.with_file_name(
+            get_table_string(&parameters, "EOSTableFile")
+                .unwrap_or(Cow::from("eostable.dat"))
+                .as_ref(),
+        );
+        let eos_table = Array3::from_shape_vec(
+            (n_ei_bins, n_rho_bins, N_EOS_TABLE_QUANTITIES).f(),
+            read_table(
+                &eos_table_path,
+                n_ei_bins * n_rho_bins * N_EOS_TABLE_QUANTITIES,
+                endianness,
+                verbosity,
+            )?,
+        )
+        .unwrap();
+
+        let rad_table_path = get_table_string(&parameters, "RhoEiRadTableFile")
+            .ok()
+            .map(|file_name| tabparam_file_path.with_file_name(file_name.as_ref()));
+        let rad_table = match rad_table_path {
+            Some(rad_table_path) if n_rad_bins > 0 && rad_table_path.exists() => Some(
+                Array4::from_shape_vec(
+                    (n_ei_bins, n_rho_bins, n_rad_bins, N_RAD_TABLE_QUANTITIES).f(),
+                    read_table(
+                        &rad_table_path,
+                        n_ei_bins * n_rho_bins * n_rad_bins * N_RAD_TABLE_QUANTITIES,
+                        endianness,
+                        verbosity,
+                    )?,
+                )
+                .unwrap(),
+            ),
+            _ => None,
+        };
+
+        Ok(Self {
+            ln_rho_min: f64::ln(rho_min),
+            ln_rho_step: (f64::ln(rho_max) - f64::ln(rho_min)) / ((n_rho_bins - 1) as f64),
+            n_rho_bins,
+            ln_ei_min: f64::ln(ei_min),
+            ln_ei_step: (f64::ln(ei_max) - f64::ln(ei_min)) / ((n_ei_bins - 1) as f64),
+            n_ei_bins,
+            n_rad_bins: if rad_table.is_some() { n_rad_bins } else { 0 },
+            eos_table,
+            rad_table,
+        })
+    }
+
+    /// Returns the number of radiation bins available in the radiation table.
+    pub fn n_rad_bins(&self) -> usize {
+        self.n_rad_bins
+    }
+
+    /// Whether the quantity with the given name can be looked up in the tables.
+    pub fn provides(&self, quantity_name: &str) -> bool {
+        TabulatedQuantity::from_name(quantity_name).is_some_and(|quantity| {
+            quantity
+                .bin_idx()
+                .is_none_or(|bin_idx| bin_idx < self.n_rad_bins)
+        })
+    }
+
+    /// Looks up the value of the given quantity for the given mass density and
+    /// energy density in Bifrost units.
+    ///
+    /// Values outside the table are taken from the nearest table edge.
+    pub fn lookup(&self, quantity: TabulatedQuantity, r: fdt, e: fdt) -> fdt {
+        let rho = (r as f64) * U_R;
+        let ei = ((e as f64) / (r as f64)) * U_EE;
+
+        let (ei_idx, ei_weight) =
+            Self::table_position(ei.ln(), self.ln_ei_min, self.ln_ei_step, self.n_ei_bins);
+        let (rho_idx, rho_weight) =
+            Self::table_position(rho.ln(), self.ln_rho_min, self.ln_rho_step, self.n_rho_bins);
+
+        let table_value = |i: usize, j: usize| -> f64 {
+            (match quantity {
+                TabulatedQuantity::GasPressure => self.eos_table[[i, j, 0]],
+                TabulatedQuantity::Temperature => self.eos_table[[i, j, 1]],
+                TabulatedQuantity::ElectronDensity => self.eos_table[[i, j, 2]],
+                TabulatedQuantity::RosselandOpacity => self.eos_table[[i, j, 3]],
+                TabulatedQuantity::ScatteringProbability(bin_idx) => {
+                    self.rad_table.as_ref().unwrap()[[i, j, bin_idx, 0]]
+                }
+                TabulatedQuantity::ThermalEmission(bin_idx) => {
+                    self.rad_table.as_ref().unwrap()[[i, j, bin_idx, 1]]
+                }
+                TabulatedQuantity::Opacity(bin_idx) => {
+                    self.rad_table.as_ref().unwrap()[[i, j, bin_idx, 2]]
+                }
+            }) as f64
+        };
+
+        let interpolated = (1.0 - ei_weight)
+            * ((1.0 - rho_weight) * table_value(ei_idx, rho_idx)
+                + rho_weight * table_value(ei_idx, rho_idx + 1))
+            + ei_weight
+                * ((1.0 - rho_weight) * table_value(ei_idx + 1, rho_idx)
+                    + rho_weight * table_value(ei_idx + 1, rho_idx + 1));
+
+        // All tabulated quantities except temperature are stored as natural logarithms
+        let value = if quantity == TabulatedQuantity::Temperature {
+            interpolated
+        } else {
+            interpolated.exp()
+        };
+        (value / quantity.cgs_scale()) as fdt
+    }
+
+    /// Computes the index of the lower table bin and the linear interpolation
+    /// weight of the upper bin for the given logarithmic coordinate.
+    fn table_position(ln_coord: f64, ln_min: f64, ln_step: f64, n_bins: usize) -> (usize, f64) {
+        let position = ((ln_coord - ln_min) / ln_step).clamp(0.0, (n_bins - 1) as f64);
+        let idx = usize::min(position.floor() as usize, n_bins - 2);
+        (idx, position - idx as f64)
+    }
+}
+
+/// Computes the field of the given tabulated quantity from the mass and energy
+/// densities of the given provider.
+pub fn compute_tabulated_quantity(
+    quantity_name: &str,
+    provider: &mut dyn ScalarFieldProvider3<fdt>,
+    equation_of_state: &TabulatedEquationOfState,
+    verbosity: &Verbosity,
+) -> io::Result<ScalarField3<fdt>> {
+    let quantity = TabulatedQuantity::from_name(quantity_name).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Quantity {} not available in EOS table", quantity_name),
+        )
+    })?;
+    compute_general_double_dep_quantity(
+        quantity_name,
+        provider,
+        "r",
+        "e",
+        |r, e| equation_of_state.lookup(quantity, r, e),
+        verbosity,
+    )
+}
+
+fn read_table(
+    table_path: &Path,
+    number_of_values: usize,
+    endianness: Endianness,
+    verbosity: &Verbosity,
+) -> io::Result<Vec<ftb>> {
+    if verbosity.print_messages() {
+        println!(
+            "Reading table from {}",
+            table_path.file_name().unwrap().to_string_lossy()
+        );
+    }
+    let file_size = utils::open_file_and_map_err(table_path)?.metadata()?.len() as usize;
+    if file_size < number_of_values * std::mem::size_of::<ftb>() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "Table file {} is too small for the table dimensions in the parameter file",
+                table_path.to_string_lossy()
+            ),
+        ));
+    }
+    utils::read_from_binary_file(table_path, number_of_values, 0, endianness)
+}
+
+/// Finds the value of the given table parameter, ignoring the case of the name.
+fn get_table_value<'a>(
+    parameters: &'a NativeSnapshotParameters,
+    name: &str,
+) -> io::Result<Cow<'a, str>> {
+    let actual_name = parameters
+        .names()
+        .into_iter()
+        .find(|actual_name| actual_name.eq_ignore_ascii_case(name))
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("Table parameter {} not found", name),
+            )
+        })?;
+    parameters.get_as_string(actual_name)
+}
+
+fn get_table_string<'a>(
+    parameters: &'a NativeSnapshotParameters,
+    name: &str,
+) -> io::Result<Cow<'a, str>> {
+    let value = get_table_value(parameters, name)?;
+    Ok(Cow::from(
+        value
+            .trim()
+            .trim_matches(|c| c == '\'' || c == '"')
+            .to_string(),
+    ))
+}
+
+fn get_table_int(parameters: &NativeSnapshotParameters, name: &str) -> io::Result<usize> {
+    let value = get_table_value(parameters, name)?;
+    value.trim().parse::<usize>().map_err(|err| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "Failed parsing table parameter {} as integer: {}",
+                name, err
+            ),
+        )
+    })
+}
+
+fn get_table_float(parameters: &NativeSnapshotParameters, name: &str) -> io::Result<f64> {
+    let value = get_table_value(parameters, name)?;
+    // Fortran double precision literals may use D as the exponent character
+    value
+        .trim()
+        .replace(['d', 'D'], "e")
+        .parse::<f64>()
+        .map_err(|err| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Failed parsing table parameter {} as float: {}", name, err),
+            )
+        })
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    /// Writes the given table values in the native binary format.
+    fn write_table(table_path: &Path, values: &[ftb]) {
+        let mut byte_buffer = vec![0_u8; std::mem::size_of_val(values)];
+        utils::write_into_byte_buffer(values, &mut byte_buffer, 0, Endianness::Little);
+        utils::save_data_as_binary(table_path, &byte_buffer).unwrap();
+    }
+
+    #[test]
+    fn quantities_are_interpolated_in_synthetic_tables() {
+        let dir = tempfile::tempdir().unwrap();
+        let tabparam_file_path = dir.path().join("tabparam.in");
+        utils::write_text_file(
+            "EOSTableFile = 'eostable.dat'\n\
+             RhoEiRadTableFile = 'rhoei_radtab.dat'\n\
+             nRhoBin = 2\n\
+             RhoMin = 1.0d-12\n\
+             RhoMax = 1.0d-10\n\
+             nEiBin = 3\n\
+             EiMin = 1.0d10\n\
+             EiMax = 1.0d14\n\
+             nRadBins = 1\n",
+            &tabparam_file_path,
+        )
+        .unwrap();
+
+        // Table values that are linear in the ei-index i and rho-index j, and
+        // thus interpolated exactly
+        let (n_ei_bins, n_rho_bins) = (3, 2);
+        let mut eos_values = vec![0.0; n_ei_bins * n_rho_bins * N_EOS_TABLE_QUANTITIES];
+        let mut rad_values = vec![0.0; n_ei_bins * n_rho_bins * N_RAD_TABLE_QUANTITIES];
+        for j in 0..n_rho_bins {
+            for i in 0..n_ei_bins {
+                let (fi, fj) = (i as ftb, j as ftb);
+                let idx = |quantity_idx: usize| i + n_ei_bins * (j + n_rho_bins * quantity_idx);
+                eos_values[idx(0)] = fi + 2.0 * fj; // ln p
+                eos_values[idx(1)] = 1000.0 + 500.0 * fi + 100.0 * fj; // tg
+                eos_values[idx(2)] = 10.0 + fi - fj; // ln nel
+                eos_values[idx(3)] = -fi - fj; // ln kr
+                rad_values[idx(0)] = -1.0 - 0.1 * fi; // ln eps
+                rad_values[idx(1)] = fi + fj; // ln temt
+                rad_values[idx(2)] = 2.0 * fi - fj; // ln opa
+            }
+        }
+        write_table(&dir.path().join("eostable.dat"), &eos_values);
+        write_table(&dir.path().join("rhoei_radtab.dat"), &rad_values);
+
+        let eos = TabulatedEquationOfState::from_tabparam_file(
+            tabparam_file_path,
+            Endianness::Little,
+            &Verbosity::Quiet,
+        )
+        .unwrap();
+        assert_eq!(eos.n_rad_bins(), 1);
+        assert!(eos.provides("opa1"));
+        assert!(!eos.provides("opa2"));
+
+        // Converts mass density [g/cm^3] and internal energy per mass [erg/g]
+        // to the (r, e) arguments of the lookup in Bifrost units
+        let bifrost_args = |rho: f64, ei: f64| {
+            let r = rho / U_R;
+            (r as fdt, (r * ei / U_EE) as fdt)
+        };
+        let assert_close = |value: fdt, expected: f64| {
+            assert!(
+                ((value as f64) / expected - 1.0).abs() < 1e-4,
+                "value = {}, expected = {}",
+                value,
+                expected
+            );
+        };
+
+        // Halfway between the first two bins along both axes
+        let (r, e) = bifrost_args(1e-11, 1e11);
+        let lookup = |quantity| eos.lookup(quantity, r, e);
+        assert_close(lookup(TabulatedQuantity::GasPressure), f64::exp(1.5) / U_P);
+        assert_close(lookup(TabulatedQuantity::Temperature), 1300.0);
+        assert_close(lookup(TabulatedQuantity::ElectronDensity), f64::exp(10.0));
+        assert_close(
+            lookup(TabulatedQuantity::RosselandOpacity),
+            f64::exp(-1.0) / U_KR,
+        );
+        assert_close(
+            lookup(TabulatedQuantity::ScatteringProbability(0)),
+            f64::exp(-1.05),
+        );
+        assert_close(
+            lookup(TabulatedQuantity::ThermalEmission(0)),
+            f64::exp(1.0) / U_TE,
+        );
+        assert_close(lookup(TabulatedQuantity::Opacity(0)), f64::exp(0.5) / U_KR);
+
+        // Beyond the upper table edges, where the values of the last bins are used
+        let (r, e) = bifrost_args(1e-8, 1e16);
+        let lookup = |quantity| eos.lookup(quantity, r, e);
+        assert_close(lookup(TabulatedQuantity::GasPressure), f64::exp(4.0) / U_P);
+        assert_close(lookup(TabulatedQuantity::Temperature), 2100.0);
+        assert_close(lookup(TabulatedQuantity::ElectronDensity), f64::exp(11.0));
+        assert_close(lookup(TabulatedQuantity::Opacity(0)), f64::exp(3.0) / U_KR);
+    }
+}
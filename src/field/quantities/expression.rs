@@ -0,0 +1,679 @@
+//! User-defined quantities given by algebraic expressions of other quantities.
+
+use super::compute_general_multi_dep_quantity;
+use crate::{
+    field::{ScalarField3, ScalarFieldProvider3},
+    io::{snapshot::fdt, Verbosity},
+};
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::{collections::HashMap, io, iter::Peekable, str::Chars, sync::Arc};
+
+lazy_static! {
+    static ref NAME_REGEX: Regex = Regex::new(r"^[a-z_][a-z0-9_]*$").unwrap();
+}
+
+/// A quantity defined by an algebraic expression of other quantities.
+#[derive(Clone, Debug)]
+pub struct QuantityDefinition {
+    name: String,
+    expression: Expression,
+    dependencies: Vec<String>,
+}
+
+/// Collection of user-defined quantities, where each quantity can only
+/// depend on quantities defined before it.
+#[derive(Clone, Debug, Default)]
+pub struct QuantityDefinitions {
+    definitions: HashMap<String, Arc<QuantityDefinition>>,
+    names: Vec<String>,
+}
+
+/// Node in the syntax tree of an algebraic expression.
+#[derive(Clone, Debug, PartialEq)]
+enum Expression {
+    Constant(fdt),
+    /// Value of the dependency with the given index.
+    Variable(usize),
+    Negation(Box<Expression>),
+    Binary(BinaryOperator, Box<Expression>, Box<Expression>),
+    Function(Function, Vec<Expression>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum BinaryOperator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Power,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Function {
+    Sqrt,
+    Cbrt,
+    Exp,
+    Ln,
+    Log10,
+    Abs,
+    Sin,
+    Cos,
+    Tan,
+    Asin,
+    Acos,
+    Atan,
+    Sinh,
+    Cosh,
+    Tanh,
+    Min,
+    Max,
+    Pow,
+    Atan2,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(fdt),
+    Identifier(String),
+    Operator(char),
+    Comparison(BinaryOperator),
+    LeftParenthesis,
+    RightParenthesis,
+    Comma,
+}
+
+/// Recursive descent parser for algebraic expressions.
+struct ExpressionParser {
+    tokens: Vec<Token>,
+    position: usize,
+    dependencies: Vec<String>,
+}
+
+impl QuantityDefinition {
+    /// Parses a quantity definition on the form `<name>=<expression>`.
+    ///
+    /// The expression may contain numbers, names of other quantities, the
+    /// constant `pi`, the operators `+`, `-`, `*`, `/` and `^`, parentheses
+    /// and calls to common mathematical functions like `sqrt` and `exp`.
+    /// The comparison operators `<`, `<=`, `>` and `>=` have the lowest
+    /// precedence and evaluate to 1 if the comparison holds and 0 otherwise.
+    pub fn parse(definition: &str) -> io::Result<Self> {
+        let (name, expression_string) = definition.split_once('=').ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Quantity definition {} not on the form <name>=<expression>",
+                    definition
+                ),
+            )
+        })?;
+        Self::new(name.trim().to_string(), expression_string)
+    }
+
+    /// Creates a new quantity with the given name defined by the given expression.
+    pub fn new(name: String, expression_string: &str) -> io::Result<Self> {
+        if !NAME_REGEX.is_match(&name) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Invalid name for defined quantity: {}", name),
+            ));
+        }
+        let (expression, dependencies) =
+            ExpressionParser::parse(expression_string).map_err(|err| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Invalid expression for quantity {}: {}", name, err),
+                )
+            })?;
+        if dependencies.contains(&name) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Defined quantity {} can not depend on itself", name),
+            ));
+        }
+        Ok(Self {
+            name,
+            expression,
+            dependencies,
+        })
+    }
+
+    /// Returns the name of the defined quantity.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the names of the quantities that the expression depends on.
+    pub fn dependencies(&self) -> &[String] {
+        &self.dependencies
+    }
+
+    /// Evaluates the expression using the given closure to obtain the value
+    /// of the dependency with a given index.
+    pub fn evaluate(&self, dependency_value: &impl Fn(usize) -> fdt) -> fdt {
+        self.expression.evaluate(dependency_value)
+    }
+}
+
+impl QuantityDefinitions {
+    /// Creates a collection from the given definitions.
+    ///
+    /// Returns an error if a name is defined multiple times or if a definition
+    /// depends on a quantity defined after it.
+    pub fn new(definitions: Vec<QuantityDefinition>) -> io::Result<Self> {
+        let mut collection = Self::default();
+        for definition in definitions {
+            if collection.contains(definition.name()) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Quantity {} defined multiple times", definition.name()),
+                ));
+            }
+            collection.names.push(definition.name().to_string());
+            collection
+                .definitions
+                .insert(definition.name().to_string(), Arc::new(definition));
+        }
+        for (idx, name) in collection.names.iter().enumerate() {
+            let later_names = &collection.names[idx + 1..];
+            if let Some(later_name) = collection.definitions[name]
+                .dependencies()
+                .iter()
+                .find(|dependency| later_names.contains(dependency))
+            {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "Defined quantity {} depends on {}, which is defined after it",
+                        name, later_name
+                    ),
+                ));
+            }
+        }
+        Ok(collection)
+    }
+
+    /// Whether the collection contains no definitions.
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    /// Returns the names of the defined quantities in the order they were defined.
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    /// Whether a quantity with the given name is defined.
+    pub fn contains(&self, quantity_name: &str) -> bool {
+        self.definitions.contains_key(quantity_name)
+    }
+
+    /// Returns the definition of the quantity with the given name, if present.
+    pub fn get(&self, quantity_name: &str) -> Option<&Arc<QuantityDefinition>> {
+        self.definitions.get(quantity_name)
+    }
+}
+
+impl Expression {
+    fn evaluate(&self, dependency_value: &impl Fn(usize) -> fdt) -> fdt {
+        match self {
+            Self::Constant(value) => *value,
+            Self::Variable(idx) => dependency_value(*idx),
+            Self::Negation(operand) => -operand.evaluate(dependency_value),
+            Self::Binary(operator, lhs, rhs) => {
+                let lhs = lhs.evaluate(dependency_value);
+                let rhs = rhs.evaluate(dependency_value);
+                match operator {
+                    BinaryOperator::Add => lhs + rhs,
+                    BinaryOperator::Subtract => lhs - rhs,
+                    BinaryOperator::Multiply => lhs * rhs,
+                    BinaryOperator::Divide => lhs / rhs,
+                    BinaryOperator::Power => power(lhs, rhs),
+                    BinaryOperator::Less => indicator(lhs < rhs),
+                    BinaryOperator::LessOrEqual => indicator(lhs <= rhs),
+                    BinaryOperator::Greater => indicator(lhs > rhs),
+                    BinaryOperator::GreaterOrEqual => indicator(lhs >= rhs),
+                }
+            }
+            Self::Function(function, arguments) => {
+                let arg = |idx: usize| arguments[idx].evaluate(dependency_value);
+                match function {
+                    Function::Sqrt => arg(0).sqrt(),
+                    Function::Cbrt => arg(0).cbrt(),
+                    Function::Exp => arg(0).exp(),
+                    Function::Ln => arg(0).ln(),
+                    Function::Log10 => arg(0).log10(),
+                    Function::Abs => arg(0).abs(),
+                    Function::Sin => arg(0).sin(),
+                    Function::Cos => arg(0).cos(),
+                    Function::Tan => arg(0).tan(),
+                    Function::Asin => arg(0).asin(),
+                    Function::Acos => arg(0).acos(),
+                    Function::Atan => arg(0).atan(),
+                    Function::Sinh => arg(0).sinh(),
+                    Function::Cosh => arg(0).cosh(),
+                    Function::Tanh => arg(0).tanh(),
+                    Function::Min => fdt::min(arg(0), arg(1)),
+                    Function::Max => fdt::max(arg(0), arg(1)),
+                    Function::Pow => power(arg(0), arg(1)),
+                    Function::Atan2 => fdt::atan2(arg(0), arg(1)),
+                }
+            }
+        }
+    }
+}
+
+/// Raises the base to the given exponent, using repeated multiplication
+/// for small integer exponents.
+fn power(base: fdt, exponent: fdt) -> fdt {
+    if exponent.fract() == 0.0 && exponent.abs() <= 16.0 {
+        base.powi(exponent as i32)
+    } else {
+        base.powf(exponent)
+    }
+}
+
+/// Returns 1 if the condition holds and 0 otherwise.
+fn indicator(condition: bool) -> fdt {
+    if condition {
+        1.0
+    } else {
+        0.0
+    }
+}
+
+impl Function {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "sqrt" => Some(Self::Sqrt),
+            "cbrt" => Some(Self::Cbrt),
+            "exp" => Some(Self::Exp),
+            "ln" | "log" => Some(Self::Ln),
+            "log10" => Some(Self::Log10),
+            "abs" => Some(Self::Abs),
+            "sin" => Some(Self::Sin),
+            "cos" => Some(Self::Cos),
+            "tan" => Some(Self::Tan),
+            "asin" => Some(Self::Asin),
+            "acos" => Some(Self::Acos),
+            "atan" => Some(Self::Atan),
+            "sinh" => Some(Self::Sinh),
+            "cosh" => Some(Self::Cosh),
+            "tanh" => Some(Self::Tanh),
+            "min" => Some(Self::Min),
+            "max" => Some(Self::Max),
+            "pow" => Some(Self::Pow),
+            "atan2" => Some(Self::Atan2),
+            _ => None,
+        }
+    }
+
+    fn n_arguments(&self) -> usize {
+        match self {
+            Self::Min | Self::Max | Self::Pow | Self::Atan2 => 2,
+            _ => 1,
+        }
+    }
+}
+
+impl ExpressionParser {
+    /// Parses the given expression string into a syntax tree and a list of
+    /// the names of the quantities it depends on.
+    fn parse(expression_string: &str) -> Result<(Expression, Vec<String>), String> {
+        let mut parser = Self {
+            tokens: tokenize(expression_string)?,
+            position: 0,
+            dependencies: Vec::new(),
+        };
+        if parser.tokens.is_empty() {
+            return Err("Expression is empty".to_string());
+        }
+        let expression = parser.parse_comparison()?;
+        if let Some(token) = parser.peek() {
+            return Err(format!("Unexpected token {:?}", token));
+        }
+        Ok((expression, parser.dependencies))
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), String> {
+        match self.next() {
+            Some(token) if token == expected => Ok(()),
+            Some(token) => Err(format!("Expected {:?}, found {:?}", expected, token)),
+            None => Err(format!("Expected {:?}, found end of expression", expected)),
+        }
+    }
+
+    fn parse_comparison(&mut self) -> Result<Expression, String> {
+        let expression = self.parse_sum()?;
+        if let Some(&Token::Comparison(operator)) = self.peek() {
+            self.position += 1;
+            Ok(Expression::Binary(
+                operator,
+                Box::new(expression),
+                Box::new(self.parse_sum()?),
+            ))
+        } else {
+            Ok(expression)
+        }
+    }
+
+    fn parse_sum(&mut self) -> Result<Expression, String> {
+        let mut expression = self.parse_product()?;
+        while let Some(&Token::Operator(op @ ('+' | '-'))) = self.peek() {
+            self.position += 1;
+            let operator = if op == '+' {
+                BinaryOperator::Add
+            } else {
+                BinaryOperator::Subtract
+            };
+            expression = Expression::Binary(
+                operator,
+                Box::new(expression),
+                Box::new(self.parse_product()?),
+            );
+        }
+        Ok(expression)
+    }
+
+    fn parse_product(&mut self) -> Result<Expression, String> {
+        let mut expression = self.parse_unary()?;
+        while let Some(&Token::Operator(op @ ('*' | '/'))) = self.peek() {
+            self.position += 1;
+            let operator = if op == '*' {
+                BinaryOperator::Multiply
+            } else {
+                BinaryOperator::Divide
+            };
+            expression = Expression::Binary(
+                operator,
+                Box::new(expression),
+                Box::new(self.parse_unary()?),
+            );
+        }
+        Ok(expression)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expression, String> {
+        match self.peek() {
+            Some(Token::Operator('-')) => {
+                self.position += 1;
+                Ok(Expression::Negation(Box::new(self.parse_unary()?)))
+            }
+            Some(Token::Operator('+')) => {
+                self.position += 1;
+                self.parse_unary()
+            }
+            _ => self.parse_power(),
+        }
+    }
+
+    fn parse_power(&mut self) -> Result<Expression, String> {
+        let base = self.parse_primary()?;
+        if let Some(Token::Operator('^')) = self.peek() {
+            self.position += 1;
+            // Exponentiation is right associative and binds tighter than a
+            // leading minus, so -a^-b is parsed as -(a^(-b))
+            let exponent = self.parse_unary()?;
+            Ok(Expression::Binary(
+                BinaryOperator::Power,
+                Box::new(base),
+                Box::new(exponent),
+            ))
+        } else {
+            Ok(base)
+        }
+    }
+
+    fn parse_primary(&mut self) -> Result<Expression, String> {
+        match self.next() {
+            Some(Token::Number(value)) => Ok(Expression::Constant(value)),
+            Some(Token::LeftParenthesis) => {
+                let expression = self.parse_comparison()?;
+                self.expect(Token::RightParenthesis)?;
+                Ok(expression)
+            }
+            Some(Token::Identifier(name)) => {
+                if let Some(Token::LeftParenthesis) = self.peek() {
+                    self.position += 1;
+                    self.parse_function_call(&name)
+                } else if name == "pi" {
+                    Ok(Expression::Constant(std::f64::consts::PI as fdt))
+                } else {
+                    Ok(Expression::Variable(self.dependency_idx(name)))
+                }
+            }
+            Some(token) => Err(format!("Unexpected token {:?}", token)),
+            None => Err("Unexpected end of expression".to_string()),
+        }
+    }
+
+    fn parse_function_call(&mut self, name: &str) -> Result<Expression, String> {
+        let function =
+            Function::from_name(name).ok_or_else(|| format!("Unknown function {}", name))?;
+        let mut arguments = vec![self.parse_comparison()?];
+        while let Some(Token::Comma) = self.peek() {
+            self.position += 1;
+            arguments.push(self.parse_comparison()?);
+        }
+        self.expect(Token::RightParenthesis)?;
+        if arguments.len() != function.n_arguments() {
+            return Err(format!(
+                "Function {} takes {} argument(s), got {}",
+                name,
+                function.n_arguments(),
+                arguments.len()
+            ));
+        }
+        Ok(Expression::Function(function, arguments))
+    }
+
+    fn dependency_idx(&mut self, name: String) -> usize {
+        if let Some(idx) = self.dependencies.iter().position(|dep| dep == &name) {
+            idx
+        } else {
+            self.dependencies.push(name);
+            self.dependencies.len() - 1
+        }
+    }
+}
+
+fn tokenize(expression_string: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = expression_string.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            _ if c.is_whitespace() => {
+                chars.next();
+            }
+            '+' | '-' | '*' | '/' | '^' => {
+                chars.next();
+                tokens.push(Token::Operator(c));
+            }
+            '<' | '>' => {
+                chars.next();
+                let or_equal = chars.next_if_eq(&'=').is_some();
+                tokens.push(Token::Comparison(match (c, or_equal) {
+                    ('<', false) => BinaryOperator::Less,
+                    ('<', true) => BinaryOperator::LessOrEqual,
+                    ('>', false) => BinaryOperator::Greater,
+                    _ => BinaryOperator::GreaterOrEqual,
+                }));
+            }
+            '(' => {
+                chars.next();
+                tokens.push(Token::LeftParenthesis);
+            }
+            ')' => {
+                chars.next();
+                tokens.push(Token::RightParenthesis);
+            }
+            ',' => {
+                chars.next();
+                tokens.push(Token::Comma);
+            }
+            _ if c.is_ascii_digit() || c == '.' => {
+                tokens.push(Token::Number(tokenize_number(&mut chars)?));
+            }
+            _ if c.is_ascii_alphabetic() || c == '_' => {
+                let mut identifier = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_ascii_alphanumeric() || c == '_' {
+                        identifier.push(c);
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                tokens.push(Token::Identifier(identifier));
+            }
+            _ => return Err(format!("Invalid character {}", c)),
+        }
+    }
+    Ok(tokens)
+}
+
+fn tokenize_number(chars: &mut Peekable<Chars>) -> Result<fdt, String> {
+    let mut number = String::new();
+    while let Some(&c) = chars.peek() {
+        if c.is_ascii_digit() || c == '.' {
+            number.push(c);
+            chars.next();
+        } else if c == 'e' || c == 'E' {
+            // Only treat the character as an exponent if it is followed by
+            // digits, so that e.g. 2e is not mistaken for a number
+            let mut lookahead = chars.clone();
+            lookahead.next();
+            let mut exponent = String::from("e");
+            if let Some(&sign @ ('+' | '-')) = lookahead.peek() {
+                exponent.push(sign);
+                lookahead.next();
+            }
+            if !lookahead.peek().is_some_and(|c| c.is_ascii_digit()) {
+                break;
+            }
+            while let Some(&c) = lookahead.peek() {
+                if c.is_ascii_digit() {
+                    exponent.push(c);
+                    lookahead.next();
+                } else {
+                    break;
+                }
+            }
+            number.push_str(&exponent);
+            *chars = lookahead;
+            break;
+        } else {
+            break;
+        }
+    }
+    number
+        .parse::<fdt>()
+        .map_err(|err| format!("Invalid number {}: {}", number, err))
+}
+
+/// Computes the field of the given user-defined quantity by evaluating its
+/// expression for every grid cell.
+///
+/// If the dependencies are not defined at the same locations within the grid
+/// cell, they are first resampled to the cell centers.
+pub fn compute_defined_quantity(
+    provider: &mut dyn ScalarFieldProvider3<fdt>,
+    definition: &QuantityDefinition,
+    verbosity: &Verbosity,
+) -> io::Result<ScalarField3<fdt>> {
+    let dependency_names: Vec<_> = definition
+        .dependencies()
+        .iter()
+        .map(String::as_str)
+        .collect();
+    compute_general_multi_dep_quantity(
+        definition.name(),
+        provider,
+        &dependency_names,
+        |dependency_values| definition.evaluate(&|idx| dependency_values[idx]),
+        verbosity,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn evaluate(expression: &str, values: &[(&str, fdt)]) -> fdt {
+        let definition = QuantityDefinition::new("q".to_string(), expression).unwrap();
+        definition.evaluate(&|idx| {
+            let name = &definition.dependencies()[idx];
+            values.iter().find(|(n, _)| n == name).unwrap().1
+        })
+    }
+
+    #[test]
+    fn expressions_follow_operator_precedence() {
+        assert_eq!(evaluate("1 + 2*3", &[]), 7.0);
+        assert_eq!(evaluate("(1 + 2)*3", &[]), 9.0);
+        assert_eq!(evaluate("2^3^2", &[]), 512.0);
+        assert_eq!(evaluate("-2^2", &[]), -4.0);
+        assert_eq!(evaluate("2^-1", &[]), 0.5);
+        assert_eq!(evaluate("8/4/2", &[]), 1.0);
+        assert_eq!(evaluate("1.5e2 - 1e-1*10", &[]), 149.0);
+        assert_eq!(evaluate("max(1, min(3, 2))", &[]), 2.0);
+        assert_eq!(evaluate("1 + 1 < 3", &[]), 1.0);
+        assert_eq!(evaluate("2 >= 1 + 1", &[]), 1.0);
+        assert_eq!(evaluate("(2 > 3)*5", &[]), 0.0);
+    }
+
+    #[test]
+    fn expressions_use_dependency_values() {
+        let values = [
+            ("r", 2.0),
+            ("ux", 1.0),
+            ("uy", 2.0),
+            ("uz", 2.0),
+            ("e", 3.0),
+        ];
+        assert_eq!(evaluate("0.5*r*(ux*ux + uy^2 + uz^2)", &values), 9.0);
+        assert_eq!(evaluate("e*2e1", &values), 60.0);
+        assert_eq!(evaluate("sqrt(uy*uz)", &values), 2.0);
+
+        let definition = QuantityDefinition::parse("ekin=r*ux^2 + r").unwrap();
+        assert_eq!(definition.name(), "ekin");
+        assert_eq!(definition.dependencies(), &["r", "ux"]);
+    }
+
+    #[test]
+    fn invalid_definitions_are_rejected() {
+        assert!(QuantityDefinition::parse("ekin").is_err());
+        assert!(QuantityDefinition::parse("2q=r").is_err());
+        assert!(QuantityDefinition::parse("q=r*").is_err());
+        assert!(QuantityDefinition::parse("q=(r").is_err());
+        assert!(QuantityDefinition::parse("q=foo(r)").is_err());
+        assert!(QuantityDefinition::parse("q=min(r)").is_err());
+        assert!(QuantityDefinition::parse("q=q*2").is_err());
+        assert!(QuantityDefinitions::new(vec![
+            QuantityDefinition::parse("a=b*2").unwrap(),
+            QuantityDefinition::parse("b=r").unwrap(),
+        ])
+        .is_err());
+        assert!(QuantityDefinitions::new(vec![
+            QuantityDefinition::parse("b=r").unwrap(),
+            QuantityDefinition::parse("a=b*2").unwrap(),
+        ])
+        .is_ok());
+    }
+}
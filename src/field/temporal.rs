@@ -0,0 +1,508 @@
+//! Time derivatives and time averages of quantities over a series of snapshots.
+
+use super::{DynScalarFieldProvider3, FieldGrid3, ScalarField3, ScalarFieldProvider3};
+use crate::{
+    geometry::Idx3,
+    grid::Grid3,
+    io::{snapshot::fdt, Verbosity},
+};
+use ndarray::{Array3, Zip};
+use std::{
+    collections::HashMap,
+    io,
+    sync::{Arc, Mutex},
+};
+
+/// Function opening the snapshot with the given index in a series.
+pub type SnapshotOpener =
+    Arc<dyn Fn(usize) -> io::Result<DynScalarFieldProvider3<fdt>> + Send + Sync>;
+
+/// Cache of time integrated fields that can be shared between the providers
+/// for the snapshots in a series.
+pub type SharedTimeIntegralCache = Arc<Mutex<TimeIntegralCache>>;
+
+/// Fields integrated over time, kept between snapshots so that each snapshot
+/// in the series only has to be read once for a given quantity.
+#[derive(Debug, Default)]
+pub struct TimeIntegralCache {
+    total_averages: HashMap<String, Arc<ScalarField3<fdt>>>,
+    running_integrals: HashMap<String, RunningIntegral>,
+}
+
+#[derive(Debug)]
+struct RunningIntegral {
+    snapshot_idx: usize,
+    values: Array3<fdt>,
+    last_field: Arc<ScalarField3<fdt>>,
+}
+
+/// Quantity derived from the values of another quantity at different times.
+#[derive(Clone, Debug, PartialEq)]
+enum TemporalQuantity {
+    /// Time derivative of the quantity.
+    TimeDerivative(String),
+    /// Average of the quantity over all snapshots in the series.
+    TotalAverage(String),
+    /// Average of the quantity from the first to the current snapshot.
+    RunningAverage(String),
+}
+
+impl TemporalQuantity {
+    fn from_name(quantity_name: &str) -> Option<Self> {
+        if let Some(name) = quantity_name.strip_prefix("ddt_") {
+            Some(Self::TimeDerivative(name.to_string()))
+        } else if let Some(name) = quantity_name.strip_prefix("tavg_") {
+            Some(Self::TotalAverage(name.to_string()))
+        } else {
+            quantity_name
+                .strip_prefix("ravg_")
+                .map(|name| Self::RunningAverage(name.to_string()))
+        }
+    }
+
+    fn base_name(&self) -> &str {
+        match self {
+            Self::TimeDerivative(name) | Self::TotalAverage(name) | Self::RunningAverage(name) => {
+                name
+            }
+        }
+    }
+}
+
+/// Provider of quantities for a snapshot in a time series, including time
+/// derivatives and time averages of the quantities in the snapshots.
+///
+/// Time derivatives are computed from the neighbouring snapshots, which are
+/// opened when needed and dropped afterwards. Time averages use the
+/// trapezoidal rule, and are accumulated in a cache that is shared between
+/// the providers for the different snapshots, so that only a single field
+/// per averaged quantity must be kept in memory.
+pub struct TemporalScalarFieldProvider3 {
+    provider: DynScalarFieldProvider3<fdt>,
+    snapshot_idx: usize,
+    times: Arc<Vec<f64>>,
+    open_snapshot: SnapshotOpener,
+    cache: SharedTimeIntegralCache,
+    all_variable_names: Vec<String>,
+    verbosity: Verbosity,
+}
+
+impl TemporalScalarFieldProvider3 {
+    /// Creates a provider of temporal quantities for the given snapshot,
+    /// which has the given index in the series of snapshots at the given
+    /// times.
+    ///
+    /// The given temporal quantity names are included in the list of all
+    /// variable names. Snapshots in the series other than the given one are
+    /// opened with the given function, and must have the same grid shape.
+    pub fn new(
+        provider: DynScalarFieldProvider3<fdt>,
+        snapshot_idx: usize,
+        times: Arc<Vec<f64>>,
+        open_snapshot: SnapshotOpener,
+        cache: SharedTimeIntegralCache,
+        temporal_quantity_names: Vec<String>,
+        verbosity: Verbosity,
+    ) -> Self {
+        assert!(
+            snapshot_idx < times.len(),
+            "Snapshot index outside time series."
+        );
+        assert!(
+            times.windows(2).all(|pair| pair[1] > pair[0]),
+            "Snapshot times must be strictly increasing."
+        );
+
+        let mut all_variable_names = provider.all_variable_names().to_vec();
+        for name in temporal_quantity_names {
+            if !all_variable_names.contains(&name)
+                && Self::temporal_quantity_is_available(&*provider, times.len(), &name)
+            {
+                all_variable_names.push(name);
+            }
+        }
+
+        Self {
+            provider,
+            snapshot_idx,
+            times,
+            open_snapshot,
+            cache,
+            all_variable_names,
+            verbosity,
+        }
+    }
+
+    fn temporal_quantity_is_available(
+        provider: &dyn ScalarFieldProvider3<fdt>,
+        n_snapshots: usize,
+        quantity_name: &str,
+    ) -> bool {
+        match TemporalQuantity::from_name(quantity_name) {
+            Some(TemporalQuantity::TimeDerivative(name)) => {
+                n_snapshots > 1 && provider.has_variable(&name)
+            }
+            Some(quantity) => provider.has_variable(quantity.base_name()),
+            None => false,
+        }
+    }
+
+    /// Returns the time of the current snapshot.
+    pub fn time(&self) -> f64 {
+        self.times[self.snapshot_idx]
+    }
+
+    fn read_snapshot_field(
+        &mut self,
+        snapshot_idx: usize,
+        variable_name: &str,
+    ) -> io::Result<Arc<ScalarField3<fdt>>> {
+        if snapshot_idx == self.snapshot_idx {
+            return self.provider.provide_scalar_field(variable_name);
+        }
+        if self.verbosity.print_messages() {
+            println!(
+                "Reading {} at t = {} from snapshot {} in series",
+                variable_name, self.times[snapshot_idx], snapshot_idx
+            );
+        }
+        let field = (self.open_snapshot)(snapshot_idx)?.provide_scalar_field(variable_name)?;
+        if field.shape() != self.provider.grid().shape() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Shape of {} in snapshot {} in series differs from current snapshot",
+                    variable_name, snapshot_idx
+                ),
+            ));
+        }
+        Ok(field)
+    }
+
+    fn compute_time_derivative(
+        &mut self,
+        quantity_name: &str,
+        variable_name: &str,
+    ) -> io::Result<ScalarField3<fdt>> {
+        let n_snapshots = self.times.len();
+        if n_snapshots < 2 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Time derivative {} requires at least two snapshots",
+                    quantity_name
+                ),
+            ));
+        }
+        if self.verbosity.print_messages() {
+            println!("Computing {}", quantity_name);
+        }
+
+        let idx = self.snapshot_idx;
+        let t = &self.times;
+
+        // Use one-sided differences at the ends of the series and the
+        // second-order central difference for non-uniform steps elsewhere
+        let (indices, weights) = if idx == 0 {
+            let dt = t[1] - t[0];
+            ([0, 1, 1], [-1.0 / dt, 1.0 / dt, 0.0])
+        } else if idx == n_snapshots - 1 {
+            let dt = t[idx] - t[idx - 1];
+            ([idx - 1, idx, idx], [-1.0 / dt, 1.0 / dt, 0.0])
+        } else {
+            let h0 = t[idx] - t[idx - 1];
+            let h1 = t[idx + 1] - t[idx];
+            (
+                [idx - 1, idx, idx + 1],
+                [
+                    -h1 / (h0 * (h0 + h1)),
+                    (h1 - h0) / (h0 * h1),
+                    h0 / (h1 * (h0 + h1)),
+                ],
+            )
+        };
+
+        let current_field = self.read_snapshot_field(idx, variable_name)?;
+        let mut values = Array3::zeros(current_field.values().raw_dim());
+        for (&snapshot_idx, &weight) in indices.iter().zip(weights.iter()) {
+            if weight != 0.0 {
+                let field = self.read_snapshot_field(snapshot_idx, variable_name)?;
+                let weight = weight as fdt;
+                Zip::from(&mut values)
+                    .and(field.values())
+                    .par_for_each(|derivative, &value| *derivative += weight * value);
+            }
+        }
+
+        Ok(ScalarField3::new(
+            quantity_name.to_string(),
+            self.provider.arc_with_grid(),
+            current_field.locations().clone(),
+            values,
+        ))
+    }
+
+    fn compute_total_average(
+        &mut self,
+        quantity_name: &str,
+        variable_name: &str,
+    ) -> io::Result<Arc<ScalarField3<fdt>>> {
+        let cache = Arc::clone(&self.cache);
+        let mut cache = cache.lock().unwrap();
+
+        if let Some(field) = cache.total_averages.get(quantity_name) {
+            return Ok(Arc::clone(field));
+        }
+        if self.verbosity.print_messages() {
+            println!("Computing {}", quantity_name);
+        }
+
+        let last_idx = self.times.len() - 1;
+        let (values, first_field) = self.integrate_over_time(variable_name, 0, last_idx, None)?;
+        let field = Arc::new(self.create_average_field(
+            quantity_name,
+            values,
+            &first_field,
+            self.times[last_idx] - self.times[0],
+        ));
+
+        cache
+            .total_averages
+            .insert(quantity_name.to_string(), Arc::clone(&field));
+        Ok(field)
+    }
+
+    fn compute_running_average(
+        &mut self,
+        quantity_name: &str,
+        variable_name: &str,
+    ) -> io::Result<ScalarField3<fdt>> {
+        let cache = Arc::clone(&self.cache);
+        let mut cache = cache.lock().unwrap();
+
+        if self.verbosity.print_messages() {
+            println!("Computing {}", quantity_name);
+        }
+
+        // Continue from the integral cached for an earlier snapshot if possible
+        let previous_integral = cache
+            .running_integrals
+            .remove(quantity_name)
+            .filter(|integral| integral.snapshot_idx <= self.snapshot_idx);
+
+        let (start_idx, previous_integral) = match previous_integral {
+            Some(integral) => (
+                integral.snapshot_idx,
+                Some((integral.values, integral.last_field)),
+            ),
+            None => (0, None),
+        };
+        let (values, last_field) = self.integrate_over_time(
+            variable_name,
+            start_idx,
+            self.snapshot_idx,
+            previous_integral,
+        )?;
+
+        let field = self.create_average_field(
+            quantity_name,
+            values.clone(),
+            &last_field,
+            self.time() - self.times[0],
+        );
+
+        cache.running_integrals.insert(
+            quantity_name.to_string(),
+            RunningIntegral {
+                snapshot_idx: self.snapshot_idx,
+                values,
+                last_field,
+            },
+        );
+        Ok(field)
+    }
+
+    /// Integrates the given variable over time from the start to the end
+    /// snapshot using the trapezoidal rule, optionally adding to the given
+    /// integral up to the start snapshot and the field at the start snapshot.
+    ///
+    /// Returns the integrated values and the field at the end snapshot.
+    fn integrate_over_time(
+        &mut self,
+        variable_name: &str,
+        start_idx: usize,
+        end_idx: usize,
+        initial_integral: Option<(Array3<fdt>, Arc<ScalarField3<fdt>>)>,
+    ) -> io::Result<(Array3<fdt>, Arc<ScalarField3<fdt>>)> {
+        let (mut values, mut last_field) = match initial_integral {
+            Some(initial_integral) => initial_integral,
+            None => {
+                let field = self.read_snapshot_field(start_idx, variable_name)?;
+                (Array3::zeros(field.values().raw_dim()), field)
+            }
+        };
+        for snapshot_idx in (start_idx + 1)..=end_idx {
+            let field = self.read_snapshot_field(snapshot_idx, variable_name)?;
+            let half_dt = (0.5 * (self.times[snapshot_idx] - self.times[snapshot_idx - 1])) as fdt;
+            Zip::from(&mut values)
+                .and(last_field.values())
+                .and(field.values())
+                .par_for_each(|integral, &previous, &current| {
+                    *integral += half_dt * (previous + current)
+                });
+            last_field = field;
+        }
+        Ok((values, last_field))
+    }
+
+    fn create_average_field(
+        &self,
+        quantity_name: &str,
+        mut integral: Array3<fdt>,
+        reference_field: &ScalarField3<fdt>,
+        duration: f64,
+    ) -> ScalarField3<fdt> {
+        let values = if duration > 0.0 {
+            integral.mapv_inplace(|value| value / (duration as fdt));
+            integral
+        } else {
+            reference_field.values().clone()
+        };
+        ScalarField3::new(
+            quantity_name.to_string(),
+            self.provider.arc_with_grid(),
+            reference_field.locations().clone(),
+            values,
+        )
+    }
+}
+
+impl ScalarFieldProvider3<fdt> for TemporalScalarFieldProvider3 {
+    fn grid(&self) -> &FieldGrid3 {
+        self.provider.grid()
+    }
+
+    fn arc_with_grid(&self) -> Arc<FieldGrid3> {
+        self.provider.arc_with_grid()
+    }
+
+    fn all_variable_names(&self) -> &[String] {
+        &self.all_variable_names
+    }
+
+    fn has_variable(&self, variable_name: &str) -> bool {
+        self.provider.has_variable(variable_name)
+            || Self::temporal_quantity_is_available(
+                &*self.provider,
+                self.times.len(),
+                variable_name,
+            )
+    }
+
+    fn produce_scalar_field(&mut self, variable_name: &str) -> io::Result<ScalarField3<fdt>> {
+        if self.provider.has_variable(variable_name) {
+            return self.provider.produce_scalar_field(variable_name);
+        }
+        match TemporalQuantity::from_name(variable_name) {
+            Some(TemporalQuantity::TimeDerivative(name)) => {
+                self.compute_time_derivative(variable_name, &name)
+            }
+            Some(TemporalQuantity::TotalAverage(name)) => self
+                .compute_total_average(variable_name, &name)
+                .map(|field| field.as_ref().clone()),
+            Some(TemporalQuantity::RunningAverage(name)) => {
+                self.compute_running_average(variable_name, &name)
+            }
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Quantity {} not available", variable_name),
+            )),
+        }
+    }
+
+    fn produce_scalar_subfield(
+        &mut self,
+        variable_name: &str,
+        subgrid: Arc<FieldGrid3>,
+        start_indices: &Idx3<usize>,
+    ) -> io::Result<ScalarField3<fdt>> {
+        if self.provider.has_variable(variable_name) {
+            self.provider
+                .produce_scalar_subfield(variable_name, subgrid, start_indices)
+        } else {
+            Ok(self
+                .provide_scalar_field(variable_name)?
+                .subfield(subgrid, start_indices))
+        }
+    }
+
+    fn provide_scalar_field(&mut self, variable_name: &str) -> io::Result<Arc<ScalarField3<fdt>>> {
+        if !self.provider.has_variable(variable_name) {
+            if let Some(TemporalQuantity::TotalAverage(name)) =
+                TemporalQuantity::from_name(variable_name)
+            {
+                return self.compute_total_average(variable_name, &name);
+            }
+        }
+        Ok(Arc::new(self.produce_scalar_field(variable_name)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{
+        field::CustomScalarFieldGenerator3,
+        geometry::{In3D, Vec3},
+        grid::regular::RegularGrid3,
+    };
+
+    fn create_provider(grid: Arc<FieldGrid3>, time: f64) -> DynScalarFieldProvider3<fdt> {
+        Box::new(
+            CustomScalarFieldGenerator3::new(grid, Verbosity::Quiet).with_variable(
+                "f".to_string(),
+                Box::new(move |_, _, _| (time * time) as fdt),
+            ),
+        )
+    }
+
+    #[test]
+    fn time_derivatives_and_averages_are_correct() {
+        let grid = Arc::new(FieldGrid3::from(RegularGrid3::from_bounds(
+            In3D::new(2, 2, 2),
+            Vec3::zero(),
+            Vec3::new(1.0, 1.0, 1.0),
+            In3D::same(true),
+        )));
+        let times = Arc::new(vec![0.0, 1.0, 3.0]);
+        let open_snapshot: SnapshotOpener = {
+            let grid = Arc::clone(&grid);
+            let times = Arc::clone(&times);
+            Arc::new(move |idx| Ok(create_provider(Arc::clone(&grid), times[idx])))
+        };
+        let cache = SharedTimeIntegralCache::default();
+
+        let value_at_snapshot = |idx: usize, name: &str| {
+            let mut provider = TemporalScalarFieldProvider3::new(
+                open_snapshot(idx).unwrap(),
+                idx,
+                Arc::clone(&times),
+                Arc::clone(&open_snapshot),
+                Arc::clone(&cache),
+                Vec::new(),
+                Verbosity::Quiet,
+            );
+            assert!(provider.has_variable(name));
+            provider.produce_scalar_field(name).unwrap().values()[[0, 0, 0]]
+        };
+
+        assert_eq!(value_at_snapshot(0, "ddt_f"), 1.0);
+        assert_eq!(value_at_snapshot(1, "ddt_f"), 2.0);
+        assert_eq!(value_at_snapshot(2, "ddt_f"), 4.0);
+        assert_eq!(value_at_snapshot(1, "tavg_f"), 3.5);
+        assert_eq!(value_at_snapshot(0, "ravg_f"), 0.0);
+        assert_eq!(value_at_snapshot(1, "ravg_f"), 0.5);
+        assert_eq!(value_at_snapshot(2, "ravg_f"), 3.5);
+    }
+}
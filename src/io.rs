@@ -1,7 +1,9 @@
 //! File input/output.
 
+pub mod fits;
 pub mod snapshot;
 pub mod utils;
+pub mod vtk;
 
 use atomic_counter::{AtomicCounter, RelaxedCounter};
 use indicatif::{ProgressBar, ProgressStyle};
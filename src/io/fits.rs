@@ -0,0 +1,656 @@
+//! Writing of data in the FITS file format.
+//!
+//! Fields are written as big-endian floating-point images, with world
+//! coordinate system (WCS) keywords describing the grid coordinates in Mm.
+//! Axes with uniformly spaced coordinates are described by the `CRPIX`,
+//! `CRVAL` and `CDELT` keywords. For axes with non-uniformly spaced
+//! coordinates, the coordinates are written to a `WCS-TAB` binary table
+//! extension following the image, which is referenced using the `-TAB`
+//! algorithm from the FITS WCS standard.
+//!
+//! Quantities with a known unit, like the primary MHD variables and the
+//! temperature, are converted from Bifrost code units to cgs units, and
+//! their unit is given by the `BUNIT` keyword. Other quantities are written
+//! in code units without a `BUNIT` keyword.
+
+use super::{
+    snapshot::{fdt, SnapshotMetadata},
+    utils::{self, IOContext},
+    Verbosity,
+};
+use crate::{
+    field::{ScalarField2, ScalarField3, ScalarFieldProvider3},
+    geometry::{Dim2, Dim3},
+    grid::fgr,
+    num::BFloat,
+    units::solar::{U_B, U_E, U_P, U_R, U_T, U_U},
+};
+use std::{
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+/// Number of bytes in a FITS block. Each header and data unit is padded to a
+/// whole number of blocks.
+const BLOCK_SIZE: usize = 2880;
+/// Number of characters in a FITS header record.
+const RECORD_SIZE: usize = 80;
+/// Name of the binary table extensions holding non-uniform coordinates.
+const COORDINATE_TABLE_NAME: &str = "WCS-TAB";
+/// Unit of the grid coordinates.
+const COORDINATE_UNIT: &str = "Mm";
+/// Maximum deviation from the average coordinate spacing, relative to the
+/// average spacing, for an axis to be considered uniform.
+const NONUNIFORMITY_THRESHOLD_FACTOR: fgr = 1e-3;
+
+/// Numeric types that can be written as FITS image data.
+pub trait FitsValue: Copy {
+    /// Value of the `BITPIX` keyword for the type.
+    const BITPIX: i64;
+
+    /// Writes the value as big-endian bytes to the given writer.
+    fn write_be<W: Write>(self, writer: &mut W) -> io::Result<()>;
+}
+
+macro_rules! impl_fits_value {
+    ($type:ty, $bitpix:expr) => {
+        impl FitsValue for $type {
+            const BITPIX: i64 = $bitpix;
+
+            fn write_be<W: Write>(self, writer: &mut W) -> io::Result<()> {
+                writer.write_all(&self.to_be_bytes())
+            }
+        }
+    };
+}
+
+impl_fits_value!(f32, -32);
+impl_fits_value!(f64, -64);
+
+/// Header of a FITS header and data unit, consisting of 80-character
+/// keyword records.
+#[derive(Clone, Debug, Default)]
+pub struct FitsHeader {
+    records: Vec<String>,
+}
+
+impl FitsHeader {
+    /// Creates a new empty header.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a keyword with the given logical value.
+    pub fn add_logical(&mut self, keyword: &str, value: bool, comment: &str) {
+        self.add_record(
+            keyword,
+            &format!("{:>20}", if value { "T" } else { "F" }),
+            comment,
+        );
+    }
+
+    /// Adds a keyword with the given integer value.
+    pub fn add_int(&mut self, keyword: &str, value: i64, comment: &str) {
+        self.add_record(keyword, &format!("{:>20}", value), comment);
+    }
+
+    /// Adds a keyword with the given floating-point value.
+    pub fn add_float(&mut self, keyword: &str, value: f64, comment: &str) {
+        self.add_record(keyword, &format!("{:>20}", format_float(value)), comment);
+    }
+
+    /// Adds a keyword with the given string value.
+    pub fn add_string(&mut self, keyword: &str, value: &str, comment: &str) {
+        self.add_record(
+            keyword,
+            &format!("'{:<8}'", value.replace('\'', "''")),
+            comment,
+        );
+    }
+
+    /// Appends the records of the given header to this header.
+    pub fn extend(&mut self, other: &Self) {
+        self.records.extend(other.records.iter().cloned());
+    }
+
+    fn add_record(&mut self, keyword: &str, value: &str, comment: &str) {
+        assert!(
+            keyword.len() <= 8,
+            "FITS keyword {} longer than 8 characters",
+            keyword
+        );
+        let mut record = format!("{:<8}= {}", keyword.to_uppercase(), value);
+        if !comment.is_empty() {
+            record.push_str(" / ");
+            record.push_str(comment);
+        }
+        record.truncate(RECORD_SIZE);
+        self.records.push(record);
+    }
+
+    fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let mut number_of_bytes = 0;
+        for record in self.records.iter().map(String::as_str).chain(["END"]) {
+            write!(writer, "{:<80}", record)?;
+            number_of_bytes += RECORD_SIZE;
+        }
+        write_padding(writer, number_of_bytes, b' ')
+    }
+}
+
+/// Axis of a FITS image, with the coordinates of each pixel along the axis.
+struct ImageAxis<'a> {
+    name: &'static str,
+    coords: &'a [fgr],
+}
+
+/// Writes the given 2D slice of a 3D field as a FITS image.
+///
+/// The slice axis and coordinate are included in the header together with
+/// information about the snapshot.
+pub fn write_slice<F: BFloat + FitsValue>(
+    output_file_path: &Path,
+    field: &ScalarField2<F>,
+    slice_axis: Dim3,
+    slice_coord: fgr,
+    metadata: &dyn SnapshotMetadata,
+) -> io::Result<()> {
+    let [first_axis, second_axis] = Dim3::slice_except(slice_axis);
+    let coords = field.coords();
+    let axes = [
+        ImageAxis {
+            name: axis_name(first_axis),
+            coords: coords[Dim2::X],
+        },
+        ImageAxis {
+            name: axis_name(second_axis),
+            coords: coords[Dim2::Y],
+        },
+    ];
+
+    let mut header = create_snapshot_header(metadata);
+    header.add_string("SLICEAX", axis_name(slice_axis), "Axis normal to the slice");
+    header.add_float(
+        "SLICECRD",
+        slice_coord,
+        &format!("[{}] Coordinate of the slice", COORDINATE_UNIT),
+    );
+
+    let mut writer = BufWriter::new(utils::create_file_and_required_directories(
+        output_file_path,
+    )?);
+    write_image(
+        &mut writer,
+        None,
+        field.name(),
+        &axes,
+        field.values().t().iter().copied(),
+        &header,
+    )?;
+    writer.flush()
+}
+
+/// Writes the given 3D field as a FITS image cube.
+pub fn write_cube<F: BFloat + FitsValue>(
+    output_file_path: &Path,
+    field: &ScalarField3<F>,
+    metadata: &dyn SnapshotMetadata,
+) -> io::Result<()> {
+    let mut writer = BufWriter::new(utils::create_file_and_required_directories(
+        output_file_path,
+    )?);
+    write_image(
+        &mut writer,
+        None,
+        field.name(),
+        &cube_axes(field),
+        field.values().t().iter().copied(),
+        &create_snapshot_header(metadata),
+    )?;
+    writer.flush()
+}
+
+/// Writes the given quantities of the snapshot to a FITS file.
+///
+/// The primary header holds information about the snapshot and has no data.
+/// Each quantity is written as an image cube in a separate extension named
+/// after the quantity, so staggered quantities get their own coordinates.
+pub fn write_snapshot(
+    metadata: &dyn SnapshotMetadata,
+    provider: &mut dyn ScalarFieldProvider3<fdt>,
+    quantity_names: &[String],
+    output_file_path: &Path,
+    io_context: &IOContext,
+    verbosity: &Verbosity,
+) -> io::Result<()> {
+    let atomic_output_file =
+        io_context.create_atomic_output_file(output_file_path.to_path_buf())?;
+    if !atomic_output_file.check_if_write_allowed(io_context, verbosity) {
+        return Ok(());
+    }
+
+    let output_file_name = atomic_output_file
+        .target_path()
+        .file_name()
+        .unwrap()
+        .to_string_lossy();
+
+    let mut writer = BufWriter::new(utils::create_file_and_required_directories(
+        atomic_output_file.temporary_path(),
+    )?);
+
+    let mut primary_header = FitsHeader::new();
+    primary_header.add_logical("SIMPLE", true, "File conforms to the FITS standard");
+    primary_header.add_int("BITPIX", fdt::BITPIX, "Number of bits per data value");
+    primary_header.add_int("NAXIS", 0, "No data in primary HDU");
+    primary_header.add_logical("EXTEND", true, "File may contain extensions");
+    primary_header.extend(&create_snapshot_header(metadata));
+    primary_header.write(&mut writer)?;
+
+    for (idx, name) in quantity_names.iter().enumerate() {
+        let field = provider.provide_scalar_field(name)?;
+        if verbosity.print_messages() {
+            println!("Writing {} to {}", name, output_file_name);
+        }
+        write_image(
+            &mut writer,
+            Some(idx as i64 + 1),
+            name,
+            &cube_axes(&field),
+            field.values().t().iter().copied(),
+            &FitsHeader::new(),
+        )?;
+    }
+
+    writer.flush()?;
+    drop(writer);
+    io_context.close_atomic_output_file(atomic_output_file)
+}
+
+/// Creates header records with information about the given snapshot.
+fn create_snapshot_header(metadata: &dyn SnapshotMetadata) -> FitsHeader {
+    let mut header = FitsHeader::new();
+    if !metadata.snap_name().is_empty() {
+        header.add_string("SNAPNAME", metadata.snap_name(), "Name of the snapshot");
+    }
+    if let Some(snap_num) = metadata.snap_num() {
+        header.add_int("SNAPNUM", snap_num as i64, "Number of the snapshot");
+    }
+    if let Ok(time) = metadata.parameters().get_as_float("t") {
+        header.add_float(
+            "SNAPTIME",
+            time * U_T,
+            "[s] Simulation time of the snapshot",
+        );
+    }
+    header
+}
+
+fn cube_axes<F: BFloat>(field: &ScalarField3<F>) -> [ImageAxis<'_>; 3] {
+    let coords = field.coords();
+    [Dim3::X, Dim3::Y, Dim3::Z].map(|dim| ImageAxis {
+        name: axis_name(dim),
+        coords: coords[dim],
+    })
+}
+
+fn axis_name(dim: Dim3) -> &'static str {
+    match dim {
+        Dim3::X => "X",
+        Dim3::Y => "Y",
+        Dim3::Z => "Z",
+    }
+}
+
+/// Returns the factor converting values of the given quantity from code units
+/// to cgs units, together with the FITS unit string of the converted values,
+/// if the unit of the quantity is known.
+fn cgs_unit(quantity_name: &str) -> Option<(f64, &'static str)> {
+    match quantity_name {
+        "r" => Some((U_R, "g cm-3")),
+        "px" | "py" | "pz" => Some((U_R * U_U, "g cm-2 s-1")),
+        "ux" | "uy" | "uz" => Some((U_U, "cm s-1")),
+        "e" => Some((U_E, "erg cm-3")),
+        "p" => Some((U_P, "dyn cm-2")),
+        "bx" | "by" | "bz" => Some((*U_B, "G")),
+        "tg" => Some((1.0, "K")),
+        _ => None,
+    }
+}
+
+/// Writes an image header and data unit with the given values, where the first
+/// axis varies fastest, followed by a coordinate table extension if any of the
+/// axes are non-uniform. Values of quantities with a known unit are converted
+/// to cgs units.
+///
+/// The image is written as the primary HDU if no extension version is given,
+/// otherwise as an image extension with the given version number, which is
+/// also used for the coordinate table.
+fn write_image<W, F, I>(
+    writer: &mut W,
+    extension_version: Option<i64>,
+    name: &str,
+    axes: &[ImageAxis],
+    values: I,
+    extra_header: &FitsHeader,
+) -> io::Result<()>
+where
+    W: Write,
+    F: BFloat + FitsValue,
+    I: Iterator<Item = F>,
+{
+    let table_version = extension_version.unwrap_or(1);
+    let unit = cgs_unit(name);
+
+    let mut header = FitsHeader::new();
+    if extension_version.is_some() {
+        header.add_string("XTENSION", "IMAGE", "Image extension");
+    } else {
+        header.add_logical("SIMPLE", true, "File conforms to the FITS standard");
+    }
+    header.add_int("BITPIX", F::BITPIX, "Number of bits per data value");
+    header.add_int("NAXIS", axes.len() as i64, "Number of data axes");
+    for (number, axis) in (1..).zip(axes) {
+        header.add_int(
+            &format!("NAXIS{}", number),
+            axis.coords.len() as i64,
+            &format!("Number of pixels along {}-axis", axis.name),
+        );
+    }
+    if let Some(extension_version) = extension_version {
+        header.add_int("PCOUNT", 0, "No group parameters");
+        header.add_int("GCOUNT", 1, "One data group");
+        header.add_string("EXTNAME", name, "Name of the quantity");
+        header.add_int("EXTVER", extension_version, "Extension version");
+    } else {
+        header.add_logical("EXTEND", true, "File may contain extensions");
+    }
+    header.add_string("BTYPE", name, "Name of the quantity");
+    if let Some((_, unit)) = unit {
+        header.add_string("BUNIT", unit, "Unit of the values");
+    }
+
+    let mut non_uniform_axes = Vec::new();
+    for (number, axis) in (1..).zip(axes) {
+        header.add_string(
+            &format!("CUNIT{}", number),
+            COORDINATE_UNIT,
+            "Unit of coordinates",
+        );
+        if let Some(spacing) = compute_uniform_spacing(axis.coords) {
+            header.add_string(&format!("CTYPE{}", number), axis.name, "Coordinate type");
+            header.add_float(&format!("CRPIX{}", number), 1.0, "Reference pixel");
+            header.add_float(
+                &format!("CRVAL{}", number),
+                axis.coords[0],
+                "Coordinate at reference pixel",
+            );
+            header.add_float(&format!("CDELT{}", number), spacing, "Coordinate increment");
+        } else {
+            header.add_string(
+                &format!("CTYPE{}", number),
+                &format!("{:-<4}-TAB", axis.name),
+                "Coordinates from table",
+            );
+            header.add_float(&format!("CRPIX{}", number), 1.0, "Reference pixel");
+            header.add_float(
+                &format!("CRVAL{}", number),
+                1.0,
+                "Table index at reference pixel",
+            );
+            header.add_float(&format!("CDELT{}", number), 1.0, "Table index increment");
+            header.add_string(
+                &format!("PS{}_0", number),
+                COORDINATE_TABLE_NAME,
+                "Name of coordinate table extension",
+            );
+            header.add_string(
+                &format!("PS{}_1", number),
+                axis.name,
+                "Name of coordinate table column",
+            );
+            header.add_int(
+                &format!("PV{}_1", number),
+                table_version,
+                "Version of coordinate table extension",
+            );
+            header.add_int(
+                &format!("PV{}_3", number),
+                1,
+                "Axis number in coordinate array",
+            );
+            non_uniform_axes.push(axis);
+        }
+    }
+    header.extend(extra_header);
+    header.write(writer)?;
+
+    let unit_factor = F::from(unit.map_or(1.0, |(factor, _)| factor)).unwrap();
+    let mut number_of_bytes = 0;
+    for value in values {
+        (value * unit_factor).write_be(writer)?;
+        number_of_bytes += (F::BITPIX.unsigned_abs() / 8) as usize;
+    }
+    write_padding(writer, number_of_bytes, 0)?;
+
+    if !non_uniform_axes.is_empty() {
+        write_coordinate_table(writer, table_version, &non_uniform_axes)?;
+    }
+    Ok(())
+}
+
+/// Writes a binary table extension with a single row, containing the
+/// coordinates of each given axis as a column.
+fn write_coordinate_table<W: Write>(
+    writer: &mut W,
+    table_version: i64,
+    axes: &[&ImageAxis],
+) -> io::Result<()> {
+    let row_size: usize = axes
+        .iter()
+        .map(|axis| axis.coords.len() * fgr::BITPIX.unsigned_abs() as usize / 8)
+        .sum();
+
+    let mut header = FitsHeader::new();
+    header.add_string("XTENSION", "BINTABLE", "Binary table extension");
+    header.add_int("BITPIX", 8, "Number of bits per data value");
+    header.add_int("NAXIS", 2, "Number of data axes");
+    header.add_int("NAXIS1", row_size as i64, "Number of bytes per row");
+    header.add_int("NAXIS2", 1, "Number of rows");
+    header.add_int("PCOUNT", 0, "No heap data");
+    header.add_int("GCOUNT", 1, "One data group");
+    header.add_int("TFIELDS", axes.len() as i64, "Number of columns");
+    for (number, axis) in (1..).zip(axes) {
+        let length = axis.coords.len();
+        header.add_string(&format!("TTYPE{}", number), axis.name, "Coordinate name");
+        header.add_string(
+            &format!("TFORM{}", number),
+            &format!("{}D", length),
+            "Array of double precision values",
+        );
+        header.add_string(
+            &format!("TUNIT{}", number),
+            COORDINATE_UNIT,
+            "Unit of coordinates",
+        );
+        header.add_string(
+            &format!("TDIM{}", number),
+            &format!("(1,{})", length),
+            "Dimensions of coordinate array",
+        );
+    }
+    header.add_string("EXTNAME", COORDINATE_TABLE_NAME, "Coordinate table");
+    header.add_int("EXTVER", table_version, "Extension version");
+    header.write(writer)?;
+
+    for axis in axes {
+        for &coord in axis.coords {
+            coord.write_be(writer)?;
+        }
+    }
+    write_padding(writer, row_size, 0)
+}
+
+/// Returns the spacing of the given coordinates if they are uniformly spaced.
+fn compute_uniform_spacing(coords: &[fgr]) -> Option<fgr> {
+    if coords.len() < 2 {
+        return Some(1.0);
+    }
+    let average_spacing = (coords[coords.len() - 1] - coords[0]) / ((coords.len() - 1) as fgr);
+    let threshold = NONUNIFORMITY_THRESHOLD_FACTOR * average_spacing.abs();
+    if coords
+        .iter()
+        .zip(coords.iter().skip(1))
+        .all(|(&lower, &upper)| ((upper - lower) - average_spacing).abs() <= threshold)
+    {
+        Some(average_spacing)
+    } else {
+        None
+    }
+}
+
+/// Writes the bytes required to fill up the last block, given the number of
+/// bytes written in the current header or data unit.
+fn write_padding<W: Write>(writer: &mut W, number_of_bytes: usize, byte: u8) -> io::Result<()> {
+    let remainder = number_of_bytes % BLOCK_SIZE;
+    if remainder > 0 {
+        writer.write_all(&vec![byte; BLOCK_SIZE - remainder])?;
+    }
+    Ok(())
+}
+
+/// Formats the given float in the form required for FITS header values.
+fn format_float(value: f64) -> String {
+    let formatted = format!("{:.14E}", value);
+    // Rust omits the sign of positive exponents, which FITS readers accept,
+    // but an explicit exponent sign is the most widely supported form
+    match formatted.split_once('E') {
+        Some((mantissa, exponent)) if !exponent.starts_with('-') => {
+            format!("{}E+{}", mantissa, exponent)
+        }
+        _ => formatted,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{
+        field::{FieldGrid2, FieldGrid3},
+        geometry::{Coords2, In2D, In3D, Vec3},
+        grid::{regular::RegularGrid3, CoordLocation, Grid2},
+        io::snapshot::utils::OutputSnapshotMetadata,
+    };
+    use ndarray::prelude::*;
+    use std::{fs, sync::Arc};
+
+    fn header_records(content: &[u8], start: usize) -> Vec<String> {
+        content[start..]
+            .chunks(RECORD_SIZE)
+            .map(|record| String::from_utf8_lossy(record).trim_end().to_string())
+            .take_while(|record| record != "END")
+            .collect()
+    }
+
+    fn find_value(records: &[String], keyword: &str) -> Option<String> {
+        records
+            .iter()
+            .find(|record| record.starts_with(&format!("{:<8}=", keyword)))
+            .map(|record| record[10..].split(" / ").next().unwrap().trim().to_string())
+    }
+
+    #[test]
+    fn slice_with_non_uniform_axis_gets_coordinate_table() {
+        let output_dir = tempfile::tempdir().unwrap();
+        let output_file_path = output_dir.path().join("slice.fits");
+
+        let grid = Arc::new(FieldGrid2::from_coords(
+            Coords2::new(vec![0.5, 1.5, 2.5], vec![0.0, 1.0, 3.0, 7.0]),
+            Coords2::new(vec![0.0, 1.0, 2.0], vec![-0.5, 0.5, 2.0, 5.0]),
+            In2D::new(false, false),
+        ));
+        let field = ScalarField2::new(
+            "f".to_string(),
+            grid,
+            In2D::same(CoordLocation::Center),
+            Array2::from_shape_fn((3, 4).f(), |(i, j)| (i + 10 * j) as f32),
+        );
+        write_slice(
+            &output_file_path,
+            &field,
+            Dim3::Y,
+            0.25,
+            &OutputSnapshotMetadata::new(),
+        )
+        .unwrap();
+
+        let content = fs::read(&output_file_path).unwrap();
+        assert_eq!(content.len(), 4 * BLOCK_SIZE);
+
+        let image_header = header_records(&content, 0);
+        let find = |records: &[String], keyword: &str| find_value(records, keyword).unwrap();
+        assert_eq!(find(&image_header, "BITPIX"), "-32");
+        assert_eq!(find(&image_header, "NAXIS1"), "3");
+        assert_eq!(find(&image_header, "NAXIS2"), "4");
+        assert_eq!(find(&image_header, "CTYPE1"), "'X       '");
+        assert_eq!(find(&image_header, "CTYPE2"), "'Z----TAB'");
+        assert_eq!(find(&image_header, "SLICEAX"), "'Y       '");
+        assert_eq!(find_value(&image_header, "BUNIT"), None);
+
+        let value_at = |idx: usize| {
+            let start = BLOCK_SIZE + 4 * idx;
+            f32::from_be_bytes(content[start..start + 4].try_into().unwrap())
+        };
+        assert_eq!(value_at(1), 1.0);
+        assert_eq!(value_at(3 * 3 + 2), 32.0);
+
+        let table_header = header_records(&content, 2 * BLOCK_SIZE);
+        assert_eq!(find(&table_header, "XTENSION"), "'BINTABLE'");
+        assert_eq!(find(&table_header, "NAXIS1"), "32");
+        assert_eq!(find(&table_header, "TFORM1"), "'4D      '");
+        let start = 3 * BLOCK_SIZE + 8 * 3;
+        assert_eq!(
+            f64::from_be_bytes(content[start..start + 8].try_into().unwrap()),
+            7.0
+        );
+    }
+
+    #[test]
+    fn cube_of_quantity_with_known_unit_is_written_in_cgs_units() {
+        let output_dir = tempfile::tempdir().unwrap();
+        let output_file_path = output_dir.path().join("r.fits");
+
+        let grid = Arc::new(FieldGrid3::from(RegularGrid3::from_bounds(
+            In3D::new(2, 3, 4),
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(2.0, 3.0, 4.0),
+            In3D::new(true, true, false),
+        )));
+        let field = ScalarField3::new(
+            "r".to_string(),
+            grid,
+            In3D::same(CoordLocation::Center),
+            Array3::from_shape_fn((2, 3, 4).f(), |(i, j, k)| (i + 10 * j + 100 * k) as fdt),
+        );
+        write_cube(&output_file_path, &field, &OutputSnapshotMetadata::new()).unwrap();
+
+        let content = fs::read(&output_file_path).unwrap();
+        assert_eq!(content.len(), 2 * BLOCK_SIZE);
+
+        let header = header_records(&content, 0);
+        let find = |keyword: &str| find_value(&header, keyword).unwrap();
+        assert_eq!(find("NAXIS"), "3");
+        assert_eq!(find("NAXIS3"), "4");
+        assert_eq!(find("CTYPE3"), "'Z       '");
+        assert_eq!(find("BUNIT"), "'g cm-3  '");
+
+        let value_at = |idx: usize| {
+            let start = BLOCK_SIZE + 4 * idx;
+            fdt::from_be_bytes(content[start..start + 4].try_into().unwrap())
+        };
+        let unit = U_R as fdt;
+        assert_eq!(value_at(1), 1.0 * unit);
+        assert_eq!(value_at(2 * 3 * 3 + 2 * 2 + 1), 321.0 * unit);
+    }
+}
@@ -1,10 +1,17 @@
 //! Reading and writing of Bifrost simulation data.
 
+pub mod muram;
 pub mod native;
 
 #[cfg(feature = "netcdf")]
 pub mod netcdf;
 
+#[cfg(feature = "hdf5")]
+pub mod hdf5;
+
+#[cfg(feature = "npz")]
+pub mod npz;
+
 pub mod utils;
 
 use super::{Endianness, Verbosity};
@@ -37,6 +44,8 @@ pub enum SnapshotFormat {
     Native,
     #[cfg(feature = "netcdf")]
     NetCDF,
+    #[cfg(feature = "hdf5")]
+    HDF5,
 }
 
 /// Snapshot number to assume when not inferrable.
@@ -0,0 +1,494 @@
+//! Reading and writing of Bifrost simulation data in HDF5 format.
+//!
+//! Each snapshot variable is stored as a 3D dataset in the root group, with
+//! dimensions ordered as (z, y, x). The names of the coordinate datasets the
+//! variable is defined on are stored in the `coordinates` attribute of the
+//! dataset, so that staggered quantities refer to the lower edge coordinates.
+//! The grid is stored as 1D coordinate datasets, and the snapshot parameters
+//! as attributes of the root group.
+
+mod mesh;
+mod param;
+
+use super::{
+    super::{utils, Endianness, Verbosity},
+    fdt, MapOfSnapshotParameters, SnapshotMetadata, SnapshotParameters, COORDINATE_NAMES,
+    FALLBACK_SNAP_NUM,
+};
+use crate::{
+    field::{FieldGrid3, ScalarField3, ScalarFieldProvider3},
+    geometry::{
+        Dim3::{X, Y, Z},
+        In3D,
+    },
+    grid::{CoordLocation, Grid3},
+    io::utils::IOContext,
+    io_result,
+};
+use hdf5_rs::{self as h5, types::VarLenUnicode, File, Group};
+use ndarray::prelude::*;
+use std::{
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+pub use mesh::create_grid_from_hdf5_file;
+pub use param::{read_hdf5_snapshot_parameters, HDF5SnapshotParameters};
+
+/// Name of the dataset attribute holding the names of the coordinates
+/// a variable is defined on.
+const COORDINATES_ATTRIBUTE_NAME: &str = "coordinates";
+
+/// Configuration parameters for HDF5 snapshot reader.
+#[derive(Clone, Debug)]
+pub struct HDF5SnapshotReaderConfig {
+    /// Path to the file.
+    file_path: PathBuf,
+    /// Whether and how to pass non-essential information to user while reading fields.
+    verbosity: Verbosity,
+}
+
+impl HDF5SnapshotReaderConfig {
+    /// Creates a new set of snapshot reader configuration parameters.
+    pub fn new(file_path: PathBuf, verbosity: Verbosity) -> Self {
+        HDF5SnapshotReaderConfig {
+            file_path,
+            verbosity,
+        }
+    }
+}
+
+/// Information associated with a Bifrost 3D simulation snapshot
+/// in HDF5 format.
+#[derive(Clone, Debug)]
+pub struct HDF5SnapshotMetadata {
+    snap_name: String,
+    snap_num: Option<u64>,
+    parameters: Box<MapOfSnapshotParameters>,
+}
+
+impl HDF5SnapshotMetadata {
+    fn new(file_path: &Path, parameters: MapOfSnapshotParameters) -> Self {
+        let (snap_name, snap_num) = super::extract_name_and_num_from_snapshot_path(file_path);
+        Self {
+            snap_name,
+            snap_num,
+            parameters: Box::new(parameters),
+        }
+    }
+}
+
+impl SnapshotMetadata for HDF5SnapshotMetadata {
+    fn snap_name(&self) -> &str {
+        &self.snap_name
+    }
+
+    fn snap_num(&self) -> Option<u64> {
+        self.snap_num
+    }
+
+    fn parameters(&self) -> &dyn SnapshotParameters {
+        self.parameters.as_ref()
+    }
+
+    fn endianness(&self) -> Endianness {
+        // The HDF5 library converts data to native byte order when reading
+        Endianness::Native
+    }
+}
+
+/// Reader for HDF5 files associated with Bifrost 3D simulation snapshots.
+#[derive(Debug)]
+pub struct HDF5SnapshotReader3 {
+    file: File,
+    file_path: PathBuf,
+    grid: Arc<FieldGrid3>,
+    all_variable_names: Vec<String>,
+    verbosity: Verbosity,
+}
+
+impl HDF5SnapshotReader3 {
+    /// Creates a reader for a 3D Bifrost snapshot.
+    pub fn new(config: HDF5SnapshotReaderConfig) -> io::Result<(Self, HDF5SnapshotMetadata)> {
+        let HDF5SnapshotReaderConfig {
+            file_path,
+            verbosity,
+        } = config;
+
+        let file = open_hdf5_file(&file_path)?;
+
+        let parameters = read_hdf5_snapshot_parameters(&file, &verbosity)?;
+
+        let all_variable_names = read_all_non_coord_variable_names(&file)?;
+
+        let is_periodic = parameters.determine_grid_periodicity()?;
+        let grid = mesh::create_grid_from_open_hdf5_file(&file, is_periodic, &verbosity)?;
+
+        let metadata = HDF5SnapshotMetadata::new(file_path.as_path(), parameters);
+
+        Ok((
+            Self {
+                file,
+                file_path,
+                grid: Arc::new(grid),
+                all_variable_names,
+                verbosity,
+            },
+            metadata,
+        ))
+    }
+
+    pub fn verbosity(&self) -> &Verbosity {
+        &self.verbosity
+    }
+}
+
+impl ScalarFieldProvider3<fdt> for HDF5SnapshotReader3 {
+    fn grid(&self) -> &FieldGrid3 {
+        self.grid.as_ref()
+    }
+
+    fn arc_with_grid(&self) -> Arc<FieldGrid3> {
+        Arc::clone(&self.grid)
+    }
+
+    fn all_variable_names(&self) -> &[String] {
+        &self.all_variable_names
+    }
+
+    fn has_variable(&self, variable_name: &str) -> bool {
+        self.all_variable_names()
+            .contains(&variable_name.to_string())
+    }
+
+    fn produce_scalar_field(&mut self, variable_name: &str) -> io::Result<ScalarField3<fdt>> {
+        if self.verbosity().print_messages() {
+            println!(
+                "Reading {} from {}",
+                variable_name,
+                self.file_path.file_name().unwrap().to_string_lossy()
+            );
+        }
+        let (values, locations) =
+            read_snapshot_3d_variable(&self.file, self.grid(), variable_name)?;
+        Ok(ScalarField3::new(
+            variable_name.to_string(),
+            self.arc_with_grid(),
+            locations,
+            values,
+        ))
+    }
+}
+
+/// Writes data associated with the given snapshot to a HDF5 file at the given path.
+pub fn write_new_snapshot(
+    input_metadata: &dyn SnapshotMetadata,
+    provider: &mut dyn ScalarFieldProvider3<fdt>,
+    output_file_path: &Path,
+    io_context: &IOContext,
+    verbosity: &Verbosity,
+) -> io::Result<()> {
+    let quantity_names = provider.all_variable_names().to_vec();
+    write_modified_snapshot(
+        input_metadata,
+        provider,
+        &quantity_names,
+        output_file_path,
+        false,
+        io_context,
+        verbosity,
+    )
+}
+
+/// Writes modified data associated with the given snapshot to a HDF5 file at the given path.
+pub fn write_modified_snapshot(
+    input_metadata: &dyn SnapshotMetadata,
+    provider: &mut dyn ScalarFieldProvider3<fdt>,
+    quantity_names: &[String],
+    output_file_path: &Path,
+    strip_metadata: bool,
+    io_context: &IOContext,
+    verbosity: &Verbosity,
+) -> io::Result<()> {
+    let (snap_name, snap_num) = super::extract_name_and_num_from_snapshot_path(output_file_path);
+    let snap_num = snap_num.unwrap_or(FALLBACK_SNAP_NUM) as i64;
+
+    let (_, included_auxiliary_variable_names, is_mhd) =
+        input_metadata.classify_variable_names(quantity_names);
+
+    let atomic_output_file =
+        io_context.create_atomic_output_file(output_file_path.to_path_buf())?;
+    if !atomic_output_file.check_if_write_allowed(io_context, verbosity) {
+        return Ok(());
+    }
+
+    let output_file_name = atomic_output_file
+        .target_path()
+        .file_name()
+        .unwrap()
+        .to_string_lossy();
+
+    let file = create_file(atomic_output_file.temporary_path())?;
+
+    let new_parameters = input_metadata.create_updated_parameters(
+        provider.grid(),
+        snap_name.as_str(),
+        snap_num,
+        &included_auxiliary_variable_names,
+        is_mhd,
+    );
+
+    if !strip_metadata {
+        if verbosity.print_messages() {
+            println!("Writing parameters to {}", output_file_name);
+        }
+        param::write_snapshot_parameters(&file, new_parameters.borrow())?;
+    }
+
+    if verbosity.print_messages() {
+        println!("Writing grid to {}", output_file_name);
+    }
+    mesh::write_grid(&file, provider.grid(), strip_metadata)?;
+
+    for name in quantity_names {
+        let field = provider.provide_scalar_field(name)?;
+        if verbosity.print_messages() {
+            println!("Writing {} to {}", name, output_file_name);
+        }
+        write_3d_scalar_field(&file, &field)?;
+    }
+
+    drop(file);
+    io_context.close_atomic_output_file(atomic_output_file)?;
+
+    Ok(())
+}
+
+/// Opens an existing HDF5 file at the given path.
+pub fn open_hdf5_file(path: &Path) -> io::Result<File> {
+    io_result!(File::open(path))
+}
+
+/// Creates a new HDF5 file at the given path.
+pub fn create_file(path: &Path) -> io::Result<File> {
+    utils::create_directory_if_missing(&path)?;
+    io_result!(File::create(path))
+}
+
+/// Returns a list of all non-coordinate 3D variables in the given HDF5 group.
+pub fn read_all_non_coord_variable_names(group: &Group) -> io::Result<Vec<String>> {
+    Ok(io_result!(group.member_names())?
+        .into_iter()
+        .filter(|name| {
+            !COORDINATE_NAMES.contains(&name.as_str())
+                && group
+                    .dataset(name)
+                    .map_or(false, |dataset| dataset.ndim() == 3)
+        })
+        .collect())
+}
+
+/// Reads the given 1D variable from the given HDF5 group.
+fn read_snapshot_1d_variable<F: h5::H5Type>(group: &Group, name: &str) -> io::Result<Vec<F>> {
+    let dataset = group.dataset(name).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("Variable {} not found in HDF5 file", name),
+        )
+    })?;
+    if dataset.ndim() != 1 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Variable {} in HDF5 file is not 1D", name),
+        ));
+    }
+    io_result!(dataset.read_raw::<F>())
+}
+
+/// Reads the given 3D variable from the given HDF5 group.
+fn read_snapshot_3d_variable(
+    group: &Group,
+    grid: &FieldGrid3,
+    name: &str,
+) -> io::Result<(Array3<fdt>, In3D<CoordLocation>)> {
+    let dataset = group.dataset(name).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("Variable {} not found in HDF5 file", name),
+        )
+    })?;
+
+    let dimensions = dataset.shape();
+    if dimensions.len() != 3 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Variable {} in HDF5 file is not 3D", name),
+        ));
+    }
+
+    let coordinates: VarLenUnicode =
+        io_result!(io_result!(dataset.attr(COORDINATES_ATTRIBUTE_NAME))?.read_scalar())?;
+    let coordinate_names: Vec<_> = coordinates.as_str().split_whitespace().collect();
+    if coordinate_names.len() != 3 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "Invalid coordinates attribute {} for variable {} in HDF5 file",
+                coordinates.as_str(),
+                name
+            ),
+        ));
+    }
+    let parse_location = |coordinate_name: &str, center_name: &str, lower_edge_name: &str| {
+        if coordinate_name == center_name {
+            Ok(CoordLocation::Center)
+        } else if coordinate_name == lower_edge_name {
+            Ok(CoordLocation::LowerEdge)
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Invalid coordinate {} for variable {} in HDF5 file",
+                    coordinate_name, name
+                ),
+            ))
+        }
+    };
+    let locations = In3D::new(
+        parse_location(coordinate_names[2], "xm", "xmdn")?,
+        parse_location(coordinate_names[1], "ym", "ymdn")?,
+        parse_location(coordinate_names[0], "zm", "zmdn")?,
+    );
+
+    let shape = (dimensions[2], dimensions[1], dimensions[0]);
+    let grid_shape = grid.shape();
+    if shape.0 != grid_shape[X] || shape.1 != grid_shape[Y] || shape.2 != grid_shape[Z] {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "Variable {} in HDF5 file does not have the same shape as the grid",
+                name
+            ),
+        ));
+    }
+
+    let buffer = io_result!(dataset.read_raw::<fdt>())?;
+    let values = Array::from_shape_vec(shape.f(), buffer).unwrap();
+    Ok((values, locations))
+}
+
+/// Writes a representation of the given 3D scalar field to the given HDF5 group.
+pub fn write_3d_scalar_field(group: &Group, field: &ScalarField3<fdt>) -> io::Result<()> {
+    let locations = field.locations();
+    let coordinate_names = [
+        match locations[Z] {
+            CoordLocation::Center => "zm",
+            CoordLocation::LowerEdge => "zmdn",
+        },
+        match locations[Y] {
+            CoordLocation::Center => "ym",
+            CoordLocation::LowerEdge => "ymdn",
+        },
+        match locations[X] {
+            CoordLocation::Center => "xm",
+            CoordLocation::LowerEdge => "xmdn",
+        },
+    ];
+    let shape = field.shape();
+    // The dataset is ordered as (z, y, x), so the values must be laid out
+    // in standard order with the axes reversed
+    let transposed_values = field.values().t();
+    let values = transposed_values.as_standard_layout();
+    let values = values.as_slice().unwrap();
+
+    let dataset = io_result!(group
+        .new_dataset::<fdt>()
+        .shape((shape[Z], shape[Y], shape[X]))
+        .create(field.name()))?;
+    io_result!(dataset.write_raw(values))?;
+
+    write_string_attribute(
+        &dataset,
+        COORDINATES_ATTRIBUTE_NAME,
+        &coordinate_names.join(" "),
+    )
+}
+
+/// Writes the given string as a scalar attribute of the given HDF5 object.
+fn write_string_attribute(location: &h5::Location, name: &str, value: &str) -> io::Result<()> {
+    let value: VarLenUnicode = value.parse().map_err(|err| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Invalid value for HDF5 attribute {}: {}", name, err),
+        )
+    })?;
+    io_result!(io_result!(location.new_attr::<VarLenUnicode>().create(name))?.write_scalar(&value))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{
+        field::CustomScalarFieldGenerator3,
+        geometry::Vec3,
+        grid::{fgr, regular::RegularGrid3},
+        io::snapshot::utils::OutputSnapshotMetadata,
+    };
+
+    #[test]
+    fn snapshot_variables_round_trip_regardless_of_memory_layout() {
+        let output_dir = tempfile::tempdir().unwrap();
+        let output_file_path = output_dir.path().join("snap.h5");
+
+        let grid = Arc::new(FieldGrid3::from(RegularGrid3::from_bounds(
+            In3D::new(3, 2, 4),
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(3.0, 2.0, 4.0),
+            In3D::new(true, true, false),
+        )));
+        let mut provider =
+            CustomScalarFieldGenerator3::<fdt>::new(Arc::clone(&grid), Verbosity::Quiet)
+                .with_variable(
+                    "f".to_string(),
+                    Box::new(|x: fgr, y: fgr, z: fgr| {
+                        (x.floor() + 10.0 * y.floor() + 100.0 * z.floor()) as fdt
+                    }),
+                );
+
+        write_new_snapshot(
+            &OutputSnapshotMetadata::new(),
+            &mut provider,
+            &output_file_path,
+            &IOContext::new(),
+            &Verbosity::Quiet,
+        )
+        .unwrap();
+
+        let expected_values =
+            Array3::from_shape_fn((3, 2, 4), |(i, j, k)| (i + 10 * j + 100 * k) as fdt);
+        assert!(expected_values.is_standard_layout());
+
+        let standard_layout_field = ScalarField3::new(
+            "g".to_string(),
+            grid,
+            In3D::same(CoordLocation::Center),
+            expected_values.clone(),
+        );
+        let file = File::append(&output_file_path).unwrap();
+        write_3d_scalar_field(&file, &standard_layout_field).unwrap();
+        drop(file);
+
+        let (mut reader, _) = HDF5SnapshotReader3::new(HDF5SnapshotReaderConfig::new(
+            output_file_path,
+            Verbosity::Quiet,
+        ))
+        .unwrap();
+
+        for name in ["f", "g"] {
+            let field = reader.produce_scalar_field(name).unwrap();
+            assert_eq!(field.values(), &expected_values);
+        }
+    }
+}
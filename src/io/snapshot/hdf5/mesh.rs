@@ -0,0 +1,129 @@
+//! Utilities for mesh data in HDF5 format.
+
+use super::super::super::Verbosity;
+use crate::{
+    field::FieldGrid3,
+    geometry::{
+        Coords3,
+        Dim3::{X, Y, Z},
+        In3D,
+    },
+    grid::{self, fgr, Grid3},
+    io_result,
+};
+use hdf5_rs::{File, Group};
+use std::{io, path::Path};
+
+/// Names of the datasets holding the grid coordinate derivatives.
+const DERIVATIVE_NAMES: [&str; 6] = [
+    "dxidxup", "dyidyup", "dzidzup", "dxidxdn", "dyidydn", "dzidzdn",
+];
+
+/// Tries to construct a grid from the data in the HDF5 file at the given path.
+pub fn create_grid_from_hdf5_file(
+    file_path: &Path,
+    is_periodic: In3D<bool>,
+    verbosity: &Verbosity,
+) -> io::Result<FieldGrid3> {
+    let file = super::open_hdf5_file(file_path)?;
+    create_grid_from_open_hdf5_file(&file, is_periodic, verbosity)
+}
+
+/// Tries to construct a grid from the data in the given HDF5 file.
+pub fn create_grid_from_open_hdf5_file(
+    file: &File,
+    is_periodic: In3D<bool>,
+    verbosity: &Verbosity,
+) -> io::Result<FieldGrid3> {
+    if verbosity.print_messages() {
+        println!(
+            "Reading grid from {}",
+            Path::new(&file.filename())
+                .file_name()
+                .unwrap()
+                .to_string_lossy()
+        );
+    }
+    let read_coords = |names: [&str; 3]| -> io::Result<Coords3<fgr>> {
+        Ok(Coords3::new(
+            super::read_snapshot_1d_variable::<fgr>(file, names[0])?,
+            super::read_snapshot_1d_variable::<fgr>(file, names[1])?,
+            super::read_snapshot_1d_variable::<fgr>(file, names[2])?,
+        ))
+    };
+
+    let center_coords = read_coords(["xm", "ym", "zm"])?;
+    let lower_edge_coords = read_coords(["xmdn", "ymdn", "zmdn"])?;
+
+    let detected_grid_type = grid::verify_coordinate_arrays(
+        &center_coords,
+        &lower_edge_coords,
+        verbosity.print_messages(),
+    )?;
+
+    let member_names = io_result!(file.member_names())?;
+    let derivative_count = DERIVATIVE_NAMES
+        .iter()
+        .filter(|&&name| member_names.iter().any(|member_name| member_name == name))
+        .count();
+
+    let (up_derivatives, down_derivatives) = if derivative_count == DERIVATIVE_NAMES.len() {
+        (
+            Some(read_coords(["dxidxup", "dyidyup", "dzidzup"])?),
+            Some(read_coords(["dxidxdn", "dyidydn", "dzidzdn"])?),
+        )
+    } else if derivative_count == 0 {
+        (None, None)
+    } else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Inconsistent number of grid derivatives in HDF5 file".to_string(),
+        ));
+    };
+
+    Ok(FieldGrid3::from_coords_unchecked(
+        center_coords,
+        lower_edge_coords,
+        is_periodic,
+        up_derivatives,
+        down_derivatives,
+        detected_grid_type,
+    ))
+}
+
+/// Writes a representation of the given grid to the given HDF5 group.
+pub fn write_grid<G: Grid3<fgr>>(
+    group: &Group,
+    grid: &G,
+    exclude_derivatives: bool,
+) -> io::Result<()> {
+    let centers = grid.centers();
+    let lower_edges = grid.lower_edges();
+
+    add_coordinate_variable(group, "xm", &centers[X])?;
+    add_coordinate_variable(group, "ym", &centers[Y])?;
+    add_coordinate_variable(group, "zm", &centers[Z])?;
+    add_coordinate_variable(group, "xmdn", &lower_edges[X])?;
+    add_coordinate_variable(group, "ymdn", &lower_edges[Y])?;
+    add_coordinate_variable(group, "zmdn", &lower_edges[Z])?;
+
+    if !exclude_derivatives {
+        if let Some(up_derivatives) = grid.up_derivatives() {
+            add_coordinate_variable(group, "dxidxup", &up_derivatives[X])?;
+            add_coordinate_variable(group, "dyidyup", &up_derivatives[Y])?;
+            add_coordinate_variable(group, "dzidzup", &up_derivatives[Z])?;
+        }
+        if let Some(down_derivatives) = grid.down_derivatives() {
+            add_coordinate_variable(group, "dxidxdn", &down_derivatives[X])?;
+            add_coordinate_variable(group, "dyidydn", &down_derivatives[Y])?;
+            add_coordinate_variable(group, "dzidzdn", &down_derivatives[Z])?;
+        }
+    }
+
+    Ok(())
+}
+
+fn add_coordinate_variable(group: &Group, name: &str, values: &[fgr]) -> io::Result<()> {
+    io_result!(group.new_dataset_builder().with_data(values).create(name))?;
+    Ok(())
+}
@@ -0,0 +1,104 @@
+//! Utilities for parameters in HDF5 format.
+
+use super::super::{
+    super::Verbosity, fpa, MapOfSnapshotParameters, ParameterValue, SnapshotParameters,
+};
+use crate::io_result;
+use hdf5_rs::{
+    types::{TypeDescriptor, VarLenAscii, VarLenUnicode},
+    File, Group,
+};
+use std::{io, path::Path};
+
+pub type HDF5SnapshotParameters = MapOfSnapshotParameters;
+
+pub fn read_hdf5_snapshot_parameters(
+    file: &File,
+    verbosity: &Verbosity,
+) -> io::Result<HDF5SnapshotParameters> {
+    if verbosity.print_messages() {
+        println!(
+            "Reading parameters from {}",
+            Path::new(&file.filename())
+                .file_name()
+                .unwrap()
+                .to_string_lossy()
+        );
+    }
+    let parameters = io_result!(file.attr_names())?
+        .into_iter()
+        .map(|name| read_snapshot_parameter(file, &name).map(|value| (name, value)))
+        .collect::<io::Result<_>>()?;
+    Ok(HDF5SnapshotParameters::new(parameters))
+}
+
+/// Attempts to read the given snapshot parameter from given HDF5 group.
+fn read_snapshot_parameter(group: &Group, name: &str) -> io::Result<ParameterValue> {
+    let attribute = group.attr(name).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("Parameter {} not found in HDF5 file", name),
+        )
+    })?;
+    if !attribute.is_scalar() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Parameter {} in HDF5 file is not a string or scalar", name),
+        ));
+    }
+    Ok(
+        match io_result!(io_result!(attribute.dtype())?.to_descriptor())? {
+            TypeDescriptor::Integer(_) | TypeDescriptor::Unsigned(_) => {
+                ParameterValue::Int(io_result!(attribute.read_scalar::<i64>())?)
+            }
+            TypeDescriptor::Float(_) => {
+                ParameterValue::Float(io_result!(attribute.read_scalar::<fpa>())?)
+            }
+            TypeDescriptor::VarLenUnicode => ParameterValue::String(
+                io_result!(attribute.read_scalar::<VarLenUnicode>())?.to_string(),
+            ),
+            TypeDescriptor::VarLenAscii => ParameterValue::String(
+                io_result!(attribute.read_scalar::<VarLenAscii>())?.to_string(),
+            ),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Parameter {} in HDF5 file is not a string or scalar", name),
+                ))
+            }
+        },
+    )
+}
+
+/// Writes all given snapshot parameters to the given HDF5 group.
+pub fn write_snapshot_parameters(
+    group: &Group,
+    parameters: std::cell::Ref<dyn SnapshotParameters>,
+) -> io::Result<()> {
+    for parameter_name in parameters.names() {
+        write_single_snapshot_parameter(
+            group,
+            parameter_name,
+            parameters.get_value(parameter_name)?,
+        )?;
+    }
+    Ok(())
+}
+
+/// Writes the given new snapshot parameter to the given HDF5 group.
+fn write_single_snapshot_parameter(
+    group: &Group,
+    parameter_name: &str,
+    parameter_value: &ParameterValue,
+) -> io::Result<()> {
+    match parameter_value {
+        ParameterValue::String(s) => super::write_string_attribute(group, parameter_name, s)?,
+        &ParameterValue::Int(i) => {
+            io_result!(io_result!(group.new_attr::<i64>().create(parameter_name))?.write_scalar(&i))?
+        }
+        &ParameterValue::Float(f) => {
+            io_result!(io_result!(group.new_attr::<fpa>().create(parameter_name))?.write_scalar(&f))?
+        }
+    };
+    Ok(())
+}
@@ -0,0 +1,619 @@
+//! Reading of MURaM simulation data.
+//!
+//! A MURaM snapshot consists of a text header file `Header.ITER`, holding the
+//! grid shape, grid spacing and simulation time, together with one raw binary
+//! file per variable, such as `result_prim_N.ITER` for the primitive variables
+//! and `eosT.ITER` for the temperature. Each binary file holds a 3D array of
+//! single-precision values in cgs units, with the vertical x-dimension varying
+//! fastest.
+//!
+//! The variables are mapped to the corresponding Bifrost variables in Bifrost
+//! code units. The MURaM axes are mapped to the Bifrost axes as
+//! (x, y, z) = (z_MURaM, y_MURaM, -x_MURaM), so that the Bifrost z-axis points
+//! downwards while keeping the coordinate system right-handed. The z-coordinates
+//! thus range from minus the vertical extent of the domain at the top to z = 0
+//! at the bottom. All variables are defined at the cell centers.
+
+use super::{
+    super::{utils, Endianness, Verbosity},
+    fdt, MapOfSnapshotParameters, ParameterValue, SnapshotMetadata, SnapshotParameters,
+    OUTPUT_TIME_STEP_NAME, PRIMARY_VARIABLE_NAMES_HD, PRIMARY_VARIABLE_NAMES_MHD,
+};
+use crate::{
+    field::{FieldGrid3, ScalarField3, ScalarFieldProvider3},
+    geometry::{
+        Dim3::{X, Y, Z},
+        In3D, Vec3,
+    },
+    grid::{fgr, regular::RegularGrid3, CoordLocation},
+    units::solar::{U_E, U_L, U_P, U_R, U_T, U_U},
+};
+use ndarray::prelude::*;
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+/// Base name of the MURaM header files.
+pub const HEADER_FILE_BASE_NAME: &str = "Header";
+
+/// Configuration parameters for MURaM snapshot reader.
+#[derive(Clone, Debug)]
+pub struct MURaMSnapshotReaderConfig {
+    /// Path to the header file.
+    header_file_path: PathBuf,
+    /// Order of bytes in the binary data files.
+    endianness: Endianness,
+    /// Whether and how to pass non-essential information to user while reading fields.
+    verbosity: Verbosity,
+}
+
+impl MURaMSnapshotReaderConfig {
+    /// Creates a new set of snapshot reader configuration parameters.
+    pub fn new(header_file_path: PathBuf, endianness: Endianness, verbosity: Verbosity) -> Self {
+        MURaMSnapshotReaderConfig {
+            header_file_path,
+            endianness,
+            verbosity,
+        }
+    }
+}
+
+/// Information associated with a MURaM 3D simulation snapshot.
+#[derive(Clone, Debug)]
+pub struct MURaMSnapshotMetadata {
+    snap_name: String,
+    iteration: u64,
+    endianness: Endianness,
+    parameters: Box<MapOfSnapshotParameters>,
+}
+
+impl SnapshotMetadata for MURaMSnapshotMetadata {
+    fn snap_name(&self) -> &str {
+        &self.snap_name
+    }
+
+    fn snap_num(&self) -> Option<u64> {
+        Some(self.iteration)
+    }
+
+    fn parameters(&self) -> &dyn SnapshotParameters {
+        self.parameters.as_ref()
+    }
+
+    fn endianness(&self) -> Endianness {
+        self.endianness
+    }
+}
+
+/// Reader for the output files associated with MURaM 3D simulation snapshots.
+#[derive(Clone, Debug)]
+pub struct MURaMSnapshotReader3 {
+    directory: PathBuf,
+    iteration_string: String,
+    muram_shape: [usize; 3],
+    endianness: Endianness,
+    grid: Arc<FieldGrid3>,
+    variable_descriptors: HashMap<String, VariableDescriptor>,
+    all_variable_names: Vec<String>,
+    verbosity: Verbosity,
+}
+
+impl MURaMSnapshotReader3 {
+    /// Creates a reader for a 3D MURaM snapshot.
+    pub fn new(config: MURaMSnapshotReaderConfig) -> io::Result<(Self, MURaMSnapshotMetadata)> {
+        let MURaMSnapshotReaderConfig {
+            header_file_path,
+            endianness,
+            verbosity,
+        } = config;
+
+        let iteration_string = parse_header_file_name(&header_file_path)
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "Invalid MURaM header file name {} (must be {}.<iteration>)",
+                        header_file_path.to_string_lossy(),
+                        HEADER_FILE_BASE_NAME
+                    ),
+                )
+            })?
+            .to_string();
+        let iteration = iteration_string.parse::<u64>().unwrap();
+
+        let directory = header_file_path
+            .parent()
+            .map_or_else(PathBuf::new, Path::to_path_buf);
+
+        if verbosity.print_messages() {
+            println!(
+                "Reading header from {}",
+                header_file_path.file_name().unwrap().to_string_lossy()
+            );
+        }
+        let header = MURaMHeader::read(&header_file_path)?;
+
+        let is_periodic = In3D::new(true, true, false);
+        let grid = header.create_grid(is_periodic.clone());
+
+        let mut variable_descriptors = HashMap::new();
+        let mut all_variable_names = Vec::new();
+        for (name, descriptor) in create_variable_descriptors() {
+            if descriptor.file_base_names.iter().all(|file_base_name| {
+                variable_file_path(&directory, file_base_name, &iteration_string).exists()
+            }) {
+                all_variable_names.push(name.to_string());
+                variable_descriptors.insert(name.to_string(), descriptor);
+            }
+        }
+
+        let is_mhd = ["bx", "by", "bz"]
+            .iter()
+            .all(|name| variable_descriptors.contains_key(*name));
+
+        let primary_variable_names: &[&str] = if is_mhd {
+            &PRIMARY_VARIABLE_NAMES_MHD
+        } else {
+            &PRIMARY_VARIABLE_NAMES_HD
+        };
+        let auxiliary_variable_names: Vec<_> = all_variable_names
+            .iter()
+            .filter(|name| !primary_variable_names.contains(&name.as_str()))
+            .cloned()
+            .collect();
+
+        let snap_name = directory
+            .canonicalize()
+            .ok()
+            .and_then(|directory| {
+                directory
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+            })
+            .unwrap_or_else(|| "muram".to_string());
+
+        let mut parameters = HashMap::new();
+        parameters.insert(
+            "snapname".to_string(),
+            ParameterValue::new_quoted_string(&snap_name),
+        );
+        parameters.insert(
+            "isnap".to_string(),
+            ParameterValue::new_int(iteration as i64),
+        );
+        parameters.insert(
+            "aux".to_string(),
+            ParameterValue::new_quoted_string(&auxiliary_variable_names.join(" ")),
+        );
+        parameters.insert(
+            "do_mhd".to_string(),
+            ParameterValue::new_int(i64::from(is_mhd)),
+        );
+        for (name, dim) in [("periodic_x", X), ("periodic_y", Y), ("periodic_z", Z)] {
+            parameters.insert(
+                name.to_string(),
+                ParameterValue::new_int(i64::from(is_periodic[dim])),
+            );
+        }
+        let bifrost_shape = header.bifrost_shape();
+        let bifrost_cell_extents = header.bifrost_cell_extents();
+        for (idx, dim) in ["x", "y", "z"].iter().enumerate() {
+            parameters.insert(
+                format!("m{}", dim),
+                ParameterValue::new_int(bifrost_shape[idx] as i64),
+            );
+            parameters.insert(
+                format!("d{}", dim),
+                ParameterValue::new_float(bifrost_cell_extents[idx]),
+            );
+        }
+        parameters.insert(
+            "t".to_string(),
+            ParameterValue::new_float(header.time / U_T),
+        );
+        if let Some(time_step) = header.time_step {
+            parameters.insert("dt".to_string(), ParameterValue::new_float(time_step / U_T));
+        }
+        if let Some(output_time_step) =
+            determine_output_time_step(&directory, iteration, header.time)?
+        {
+            parameters.insert(
+                OUTPUT_TIME_STEP_NAME.to_string(),
+                ParameterValue::new_float(output_time_step / U_T),
+            );
+        }
+
+        let metadata = MURaMSnapshotMetadata {
+            snap_name,
+            iteration,
+            endianness,
+            parameters: Box::new(MapOfSnapshotParameters::new(parameters)),
+        };
+
+        Ok((
+            Self {
+                directory,
+                iteration_string,
+                muram_shape: header.shape,
+                endianness,
+                grid: Arc::new(grid),
+                variable_descriptors,
+                all_variable_names,
+                verbosity,
+            },
+            metadata,
+        ))
+    }
+
+    pub fn verbosity(&self) -> &Verbosity {
+        &self.verbosity
+    }
+
+    fn get_variable_descriptor(&self, name: &str) -> io::Result<&VariableDescriptor> {
+        self.variable_descriptors.get(name).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("Variable {} not found", name),
+            )
+        })
+    }
+
+    /// Reads the array in the given MURaM output file and returns it with the
+    /// axes permuted to the Bifrost orientation.
+    fn read_variable_file(&self, file_base_name: &str) -> io::Result<Array3<fdt>> {
+        let file_path = variable_file_path(&self.directory, file_base_name, &self.iteration_string);
+        if self.verbosity().print_messages() {
+            println!(
+                "Reading {}",
+                file_path.file_name().unwrap().to_string_lossy()
+            );
+        }
+        let [nx, ny, nz] = self.muram_shape;
+        let number_of_values = nx * ny * nz;
+        let file_size = utils::open_file_and_map_err(&file_path)?.metadata()?.len() as usize;
+        if file_size < number_of_values * std::mem::size_of::<fdt>() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "MURaM file {} is too small for the grid shape in the header file",
+                    file_path.to_string_lossy()
+                ),
+            ));
+        }
+        let buffer =
+            utils::read_from_binary_file(&file_path, number_of_values, 0, self.endianness)?;
+
+        // Row-major with shape (nz, ny, nx) gives the MURaM x-index varying fastest
+        let muram_values = Array3::from_shape_vec((nz, ny, nx), buffer).unwrap();
+
+        // Reverse the vertical axis, since it becomes the downward pointing z-axis
+        let mut values = Array3::zeros((nz, ny, nx).f());
+        values.assign(&muram_values.slice(s![.., .., ..;-1]));
+        Ok(values)
+    }
+}
+
+impl ScalarFieldProvider3<fdt> for MURaMSnapshotReader3 {
+    fn grid(&self) -> &FieldGrid3 {
+        self.grid.as_ref()
+    }
+
+    fn arc_with_grid(&self) -> Arc<FieldGrid3> {
+        Arc::clone(&self.grid)
+    }
+
+    fn all_variable_names(&self) -> &[String] {
+        &self.all_variable_names
+    }
+
+    fn has_variable(&self, variable_name: &str) -> bool {
+        self.all_variable_names()
+            .contains(&variable_name.to_string())
+    }
+
+    fn produce_scalar_field(&mut self, variable_name: &str) -> io::Result<ScalarField3<fdt>> {
+        let variable_descriptor = self.get_variable_descriptor(variable_name)?.clone();
+        if self.verbosity().print_messages() {
+            println!("Reading {} from MURaM output", variable_name);
+        }
+        let mut values = self.read_variable_file(variable_descriptor.file_base_names[0])?;
+        for file_base_name in &variable_descriptor.file_base_names[1..] {
+            values *= &self.read_variable_file(file_base_name)?;
+        }
+        values.mapv_inplace(|value| value * variable_descriptor.conversion_factor);
+
+        Ok(ScalarField3::new(
+            variable_name.to_string(),
+            self.arc_with_grid(),
+            In3D::same(CoordLocation::Center),
+            values,
+        ))
+    }
+}
+
+/// Returns the paths of the header files in the directory of the given header
+/// file whose iteration numbers lie in the given range, sorted by iteration.
+pub fn find_header_files_in_range(
+    header_file_path: &Path,
+    first_iteration: u64,
+    last_iteration: u64,
+) -> io::Result<Vec<PathBuf>> {
+    let directory = header_file_path
+        .parent()
+        .map_or_else(PathBuf::new, Path::to_path_buf);
+    Ok(find_header_files(&directory)?
+        .into_iter()
+        .filter(|(iteration, _)| (first_iteration..=last_iteration).contains(iteration))
+        .map(|(_, path)| path)
+        .collect())
+}
+
+/// Whether the given path has the file name of a MURaM header file.
+pub fn is_header_file_path(file_path: &Path) -> bool {
+    parse_header_file_name(file_path).is_some()
+}
+
+/// How a Bifrost variable is computed from the MURaM output files.
+#[derive(Clone, Debug)]
+struct VariableDescriptor {
+    /// Base names of the files whose values are multiplied together.
+    file_base_names: Vec<&'static str>,
+    /// Factor converting the (product of) MURaM values to Bifrost units,
+    /// including the sign flip for vertical components.
+    conversion_factor: fdt,
+}
+
+/// Creates the descriptors for all Bifrost variables that can be read from
+/// MURaM output files.
+///
+/// The primitive MURaM variables are mass density, velocity, internal energy
+/// density and magnetic field, with the magnetic field in units of
+/// G/sqrt(4*pi).
+fn create_variable_descriptors() -> Vec<(&'static str, VariableDescriptor)> {
+    let descriptor =
+        |file_base_names: Vec<&'static str>, conversion_factor: f64| VariableDescriptor {
+            file_base_names,
+            conversion_factor: conversion_factor as fdt,
+        };
+    // Converts from G/sqrt(4*pi) to Bifrost units of U_U*sqrt(4*pi*U_R)
+    let u_b_muram = U_U * U_R.sqrt();
+    vec![
+        ("r", descriptor(vec!["result_prim_0"], 1.0 / U_R)),
+        (
+            "px",
+            descriptor(vec!["result_prim_0", "result_prim_3"], 1.0 / (U_R * U_U)),
+        ),
+        (
+            "py",
+            descriptor(vec!["result_prim_0", "result_prim_2"], 1.0 / (U_R * U_U)),
+        ),
+        (
+            "pz",
+            descriptor(vec!["result_prim_0", "result_prim_1"], -1.0 / (U_R * U_U)),
+        ),
+        ("e", descriptor(vec!["result_prim_4"], 1.0 / U_E)),
+        ("bx", descriptor(vec!["result_prim_7"], 1.0 / u_b_muram)),
+        ("by", descriptor(vec!["result_prim_6"], 1.0 / u_b_muram)),
+        ("bz", descriptor(vec!["result_prim_5"], -1.0 / u_b_muram)),
+        ("tg", descriptor(vec!["eosT"], 1.0)),
+        ("p", descriptor(vec!["eosP"], 1.0 / U_P)),
+    ]
+}
+
+/// Content of a MURaM header file.
+#[derive(Clone, Debug)]
+struct MURaMHeader {
+    /// Number of grid cells along the MURaM x-, y- and z-axis.
+    shape: [usize; 3],
+    /// Grid cell extents along the MURaM x-, y- and z-axis [cm].
+    cell_extents: [f64; 3],
+    /// Simulation time [s].
+    time: f64,
+    /// Simulation time step [s].
+    time_step: Option<f64>,
+}
+
+impl MURaMHeader {
+    /// Reads the given header file, which holds the values
+    /// `nx ny nz dx dy dz time [dt ...]` separated by whitespace.
+    fn read(header_file_path: &Path) -> io::Result<Self> {
+        let text = utils::read_text_file(header_file_path)?;
+        let invalid_header = || {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Invalid MURaM header file {}",
+                    header_file_path.to_string_lossy()
+                ),
+            )
+        };
+        let values = text
+            .split_whitespace()
+            .map(|value| value.parse::<f64>().map_err(|_| invalid_header()))
+            .collect::<io::Result<Vec<_>>>()?;
+        if values.len() < 7 || values[..3].iter().any(|&n| n < 1.0 || n.fract() != 0.0) {
+            return Err(invalid_header());
+        }
+        Ok(Self {
+            shape: [values[0] as usize, values[1] as usize, values[2] as usize],
+            cell_extents: [values[3], values[4], values[5]],
+            time: values[6],
+            time_step: values.get(7).copied(),
+        })
+    }
+
+    /// Returns the grid shape along the Bifrost x-, y- and z-axis.
+    fn bifrost_shape(&self) -> [usize; 3] {
+        [self.shape[2], self.shape[1], self.shape[0]]
+    }
+
+    /// Returns the grid cell extents along the Bifrost x-, y- and z-axis [Mm].
+    fn bifrost_cell_extents(&self) -> [fgr; 3] {
+        [
+            self.cell_extents[2] / U_L,
+            self.cell_extents[1] / U_L,
+            self.cell_extents[0] / U_L,
+        ]
+    }
+
+    /// Creates the Bifrost grid corresponding to the MURaM grid.
+    fn create_grid(&self, is_periodic: In3D<bool>) -> FieldGrid3 {
+        let [mx, my, mz] = self.bifrost_shape();
+        let [dx, dy, dz] = self.bifrost_cell_extents();
+        FieldGrid3::from(RegularGrid3::from_bounds(
+            In3D::new(mx, my, mz),
+            Vec3::new(0.0, 0.0, -(mz as fgr) * dz),
+            Vec3::new((mx as fgr) * dx, (my as fgr) * dy, 0.0),
+            is_periodic,
+        ))
+    }
+}
+
+fn variable_file_path(directory: &Path, file_base_name: &str, iteration_string: &str) -> PathBuf {
+    directory.join(format!("{}.{}", file_base_name, iteration_string))
+}
+
+/// Returns the iteration part of the given header file name, if valid.
+fn parse_header_file_name(file_path: &Path) -> Option<&str> {
+    let file_name = file_path.file_name()?.to_str()?;
+    let (base_name, iteration_string) = file_name.split_once('.')?;
+    if base_name == HEADER_FILE_BASE_NAME
+        && !iteration_string.is_empty()
+        && iteration_string.chars().all(|c| c.is_ascii_digit())
+    {
+        Some(iteration_string)
+    } else {
+        None
+    }
+}
+
+/// Returns the iteration numbers and paths of all header files in the given
+/// directory, sorted by iteration.
+fn find_header_files(directory: &Path) -> io::Result<Vec<(u64, PathBuf)>> {
+    let directory = if directory.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        directory
+    };
+    let mut header_files = Vec::new();
+    for entry in fs::read_dir(directory)? {
+        let path = entry?.path();
+        if let Some(iteration_string) = parse_header_file_name(&path) {
+            let iteration = iteration_string.parse::<u64>().unwrap();
+            header_files.push((iteration, path));
+        }
+    }
+    header_files.sort_unstable_by_key(|(iteration, _)| *iteration);
+    Ok(header_files)
+}
+
+/// Determines the time between the snapshot with the given iteration and time
+/// and the next (or, if there is none, previous) snapshot in the directory.
+fn determine_output_time_step(
+    directory: &Path,
+    iteration: u64,
+    time: f64,
+) -> io::Result<Option<f64>> {
+    let header_files = find_header_files(directory)?;
+    let neighbor = header_files
+        .iter()
+        .find(|(other_iteration, _)| *other_iteration > iteration)
+        .or_else(|| {
+            header_files
+                .iter()
+                .rev()
+                .find(|(other_iteration, _)| *other_iteration < iteration)
+        });
+    Ok(match neighbor {
+        Some((_, path)) => Some((MURaMHeader::read(path)?.time - time).abs()),
+        None => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::grid::Grid3;
+
+    fn write_values(path: &Path, values: &[f32]) {
+        let bytes: Vec<u8> = values.iter().flat_map(|v| v.to_le_bytes()).collect();
+        fs::write(path, bytes).unwrap();
+    }
+
+    #[test]
+    fn muram_variables_are_mapped_to_bifrost_orientation_and_units() {
+        let dir = tempfile::tempdir().unwrap();
+        let (nx, ny, nz) = (4, 2, 3);
+        fs::write(
+            dir.path().join("Header.000100"),
+            "4 2 3 1e6 2e6 3e6 50.0 0.1 1e7\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("Header.000200"),
+            "4 2 3 1e6 2e6 3e6 80.0 0.1 1e7\n",
+        )
+        .unwrap();
+
+        // Density increasing with the MURaM x-index (height)
+        let index = |i: usize, j: usize, k: usize| i + nx * (j + ny * k);
+        let mut density = vec![0.0; nx * ny * nz];
+        let mut vertical_velocity = vec![0.0; nx * ny * nz];
+        for k in 0..nz {
+            for j in 0..ny {
+                for i in 0..nx {
+                    density[index(i, j, k)] = 1e-7 * (1 + i + 10 * j + 100 * k) as f32;
+                    vertical_velocity[index(i, j, k)] = 1e6;
+                }
+            }
+        }
+        for n in 0..5 {
+            let values = match n {
+                0 => &density,
+                1 => &vertical_velocity,
+                _ => &density,
+            };
+            write_values(
+                &dir.path().join(format!("result_prim_{}.000100", n)),
+                values,
+            );
+        }
+
+        let (mut reader, metadata) = MURaMSnapshotReader3::new(MURaMSnapshotReaderConfig::new(
+            dir.path().join("Header.000100"),
+            Endianness::Little,
+            Verbosity::Quiet,
+        ))
+        .unwrap();
+
+        assert_eq!(metadata.snap_num(), Some(100));
+        assert_eq!(
+            reader.all_variable_names(),
+            &["r", "px", "py", "pz", "e"].map(String::from)
+        );
+        let parameters = metadata.parameters();
+        assert_eq!(parameters.get_as_int("do_mhd").unwrap(), 0);
+        assert_eq!(parameters.get_as_int("mx").unwrap(), 3);
+        assert_eq!(parameters.get_as_float("t").unwrap(), 0.5);
+        assert!((parameters.get_as_float(OUTPUT_TIME_STEP_NAME).unwrap() - 0.3).abs() < 1e-12);
+
+        let grid = reader.grid();
+        assert_eq!(grid.shape(), &In3D::new(3, 2, 4));
+        assert!((grid.lower_edges()[X][1] - 0.03).abs() < 1e-12);
+        assert!((grid.lower_edges()[Y][1] - 0.02).abs() < 1e-12);
+        assert!((grid.lower_edges()[Z][0] + 0.04).abs() < 1e-12);
+
+        // Bifrost index (k, j, n) corresponds to MURaM index (nx - 1 - n, j, k)
+        let r = reader.produce_scalar_field("r").unwrap();
+        assert!((r.values()[[2, 1, 0]] - 214.0).abs() < 1e-3);
+        assert!((r.values()[[2, 1, 3]] - 211.0).abs() < 1e-3);
+
+        // Upward MURaM velocity becomes negative vertical Bifrost momentum
+        let pz = reader.produce_scalar_field("pz").unwrap();
+        assert!((pz.values()[[0, 0, 3]] + 1.0).abs() < 1e-6);
+    }
+}
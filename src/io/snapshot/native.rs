@@ -2,6 +2,7 @@
 
 mod mesh;
 mod param;
+mod verify;
 
 use super::{
     super::{
@@ -15,7 +16,7 @@ use crate::{
     field::{FieldGrid3, ScalarField3, ScalarFieldProvider3},
     geometry::{
         Dim3::{X, Y, Z},
-        In3D,
+        Idx3, In3D,
     },
     grid::{
         CoordLocation::{self, Center, LowerEdge},
@@ -38,6 +39,7 @@ pub use mesh::{
     NATIVE_COORD_WIDTH,
 };
 pub use param::NativeSnapshotParameters;
+pub use verify::{verify_snapshot, SnapshotVerificationReport, VerificationCheck};
 
 #[cfg(feature = "for-testing")]
 pub use mesh::parsed_mesh_files_eq;
@@ -314,6 +316,14 @@ impl NativeSnapshotReader3 {
         Ok(())
     }
 
+    fn variable_file_path(&self, variable_descriptor: &VariableDescriptor) -> &Path {
+        if variable_descriptor.is_primary {
+            self.primary_variable_file_path()
+        } else {
+            self.auxiliary_variable_file_path()
+        }
+    }
+
     fn get_variable_descriptor(&self, name: &str) -> io::Result<&VariableDescriptor> {
         match self.variable_descriptors.get(name) {
             Some(variable) => Ok(variable),
@@ -345,11 +355,7 @@ impl ScalarFieldProvider3<fdt> for NativeSnapshotReader3 {
 
     fn produce_scalar_field(&mut self, variable_name: &str) -> io::Result<ScalarField3<fdt>> {
         let variable_descriptor = self.get_variable_descriptor(variable_name)?;
-        let file_path = if variable_descriptor.is_primary {
-            self.primary_variable_file_path()
-        } else {
-            self.auxiliary_variable_file_path()
-        };
+        let file_path = self.variable_file_path(variable_descriptor);
         if self.verbosity().print_messages() {
             println!(
                 "Reading {} from {}",
@@ -374,6 +380,46 @@ impl ScalarFieldProvider3<fdt> for NativeSnapshotReader3 {
             values,
         ))
     }
+
+    fn produce_scalar_subfield(
+        &mut self,
+        variable_name: &str,
+        subgrid: Arc<FieldGrid3>,
+        start_indices: &Idx3<usize>,
+    ) -> io::Result<ScalarField3<fdt>> {
+        let variable_descriptor = self.get_variable_descriptor(variable_name)?;
+        let file_path = self.variable_file_path(variable_descriptor);
+        if self.verbosity().print_messages() {
+            println!(
+                "Reading {} in subgrid from {}",
+                variable_name,
+                file_path.file_name().unwrap().to_string_lossy()
+            );
+        }
+        let shape = self.grid().shape();
+        let subgrid_shape = subgrid.shape();
+        let number_of_values = shape[X] * shape[Y] * shape[Z];
+        let byte_offset = number_of_values * variable_descriptor.index * mem::size_of::<fdt>();
+        let buffer = utils::read_3d_subarray_from_binary_file(
+            file_path,
+            &[shape[X], shape[Y], shape[Z]],
+            byte_offset,
+            &[start_indices[X], start_indices[Y], start_indices[Z]],
+            &[subgrid_shape[X], subgrid_shape[Y], subgrid_shape[Z]],
+            self.endianness,
+        )?;
+        let values = Array::from_shape_vec(
+            (subgrid_shape[X], subgrid_shape[Y], subgrid_shape[Z]).f(),
+            buffer,
+        )
+        .unwrap();
+        Ok(ScalarField3::new(
+            variable_name.to_string(),
+            subgrid,
+            variable_descriptor.locations.clone(),
+            values,
+        ))
+    }
 }
 
 /// Writes the data associated with the given snapshot to native snapshot files at the given path.
@@ -132,8 +132,14 @@ where
     utils::write_text_file(&text, mesh_path)
 }
 
-/// Parses the mesh file at the given path and returns relevant data.
-fn parse_mesh_file(mesh_path: &Path, verbosity: &Verbosity) -> io::Result<NativeGridData> {
+/// Reads the coordinate arrays in the given Bifrost mesh file without verifying them.
+///
+/// The returned arrays are the center coordinates, lower edge coordinates, up
+/// derivatives and down derivatives.
+pub fn read_mesh_file_coords(
+    mesh_path: &Path,
+    verbosity: &Verbosity,
+) -> io::Result<[Coords3<fgr>; 4]> {
     let file = utils::open_file_and_map_err(mesh_path)?;
     if verbosity.print_messages() {
         println!(
@@ -241,6 +247,19 @@ fn parse_mesh_file(mesh_path: &Path, verbosity: &Verbosity) -> io::Result<Native
         down_derivative_vecs.pop_front().unwrap(),
     );
 
+    Ok([
+        center_coords,
+        lower_edge_coords,
+        up_derivatives,
+        down_derivatives,
+    ])
+}
+
+/// Parses the mesh file at the given path and returns relevant data.
+fn parse_mesh_file(mesh_path: &Path, verbosity: &Verbosity) -> io::Result<NativeGridData> {
+    let [center_coords, lower_edge_coords, up_derivatives, down_derivatives] =
+        read_mesh_file_coords(mesh_path, verbosity)?;
+
     let detected_grid_type = grid::verify_coordinate_arrays(
         &center_coords,
         &lower_edge_coords,
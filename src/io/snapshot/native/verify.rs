@@ -0,0 +1,409 @@
+//! Integrity verification of snapshots in native format.
+
+use super::{
+    super::{
+        super::utils, fdt, Endianness, SnapshotParameters, Verbosity, PRIMARY_VARIABLE_NAMES_HD,
+        PRIMARY_VARIABLE_NAMES_MHD,
+    },
+    mesh, NativeSnapshotParameters,
+};
+use crate::{
+    geometry::{
+        Dim3::{X, Y, Z},
+        In3D,
+    },
+    grid::{self, GridType},
+};
+use std::{collections::HashSet, fs, mem, path::Path};
+
+/// Outcome of a single check performed while verifying a snapshot.
+#[derive(Clone, Debug)]
+pub struct VerificationCheck {
+    name: String,
+    passed: bool,
+    details: String,
+}
+
+impl VerificationCheck {
+    /// Returns the name of the check.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Whether the check passed.
+    pub fn passed(&self) -> bool {
+        self.passed
+    }
+
+    /// Returns a description of the outcome of the check.
+    pub fn details(&self) -> &str {
+        &self.details
+    }
+}
+
+/// Outcomes of all checks performed while verifying a snapshot.
+#[derive(Clone, Debug, Default)]
+pub struct SnapshotVerificationReport {
+    checks: Vec<VerificationCheck>,
+}
+
+impl SnapshotVerificationReport {
+    /// Returns the checks that were performed, in order.
+    pub fn checks(&self) -> &[VerificationCheck] {
+        &self.checks
+    }
+
+    /// Whether all checks passed.
+    pub fn passed(&self) -> bool {
+        self.checks.iter().all(VerificationCheck::passed)
+    }
+
+    /// Returns an iterator over the checks that failed.
+    pub fn failed_checks(&self) -> impl Iterator<Item = &VerificationCheck> {
+        self.checks.iter().filter(|check| !check.passed())
+    }
+
+    fn add(&mut self, name: &str, outcome: Result<String, String>) -> bool {
+        let passed = outcome.is_ok();
+        self.checks.push(VerificationCheck {
+            name: name.to_string(),
+            passed,
+            details: outcome.unwrap_or_else(|err| err),
+        });
+        passed
+    }
+}
+
+/// Verifies the integrity of the native snapshot with the given parameter file.
+///
+/// The checks cover the parameter file, the list of auxiliary variables, the
+/// sizes of the `.snap` and `.aux` files, the consistency of the mesh file with
+/// the grid dimensions in the parameter file, the validity of the mesh
+/// coordinates and the number of NaN and infinite values of each variable.
+/// Checks that depend on a failed check are skipped.
+pub fn verify_snapshot(
+    param_file_path: &Path,
+    endianness: Endianness,
+) -> SnapshotVerificationReport {
+    let mut report = SnapshotVerificationReport::default();
+
+    let parameters =
+        match NativeSnapshotParameters::new(param_file_path.to_path_buf(), &Verbosity::Quiet) {
+            Ok(parameters) => parameters,
+            Err(err) => {
+                report.add("parameter file", Err(err.to_string()));
+                return report;
+            }
+        };
+    let (shape, is_mhd) = match read_shape_and_mhd(&parameters) {
+        Ok((shape, is_mhd)) => {
+            report.add(
+                "parameter file",
+                Ok(format!(
+                    "{}x{}x{} grid with {} variables",
+                    shape[X],
+                    shape[Y],
+                    shape[Z],
+                    if is_mhd { "MHD" } else { "HD" }
+                )),
+            );
+            (shape, is_mhd)
+        }
+        Err(err) => {
+            report.add("parameter file", Err(err));
+            return report;
+        }
+    };
+
+    let primary_variable_names: &[&str] = if is_mhd {
+        &PRIMARY_VARIABLE_NAMES_MHD
+    } else {
+        &PRIMARY_VARIABLE_NAMES_HD
+    };
+
+    let aux_names = parameters
+        .determine_aux_names()
+        .map_err(|err| err.to_string())
+        .and_then(|aux_names| verify_aux_names(aux_names, primary_variable_names));
+    let aux_names = match aux_names {
+        Ok(aux_names) => {
+            report.add(
+                "aux list",
+                Ok(if aux_names.is_empty() {
+                    "no auxiliary variables".to_string()
+                } else {
+                    aux_names.join(" ")
+                }),
+            );
+            Some(aux_names)
+        }
+        Err(err) => {
+            report.add("aux list", Err(err));
+            None
+        }
+    };
+
+    let number_of_values = shape[X] * shape[Y] * shape[Z];
+    let variable_size = number_of_values * mem::size_of::<fdt>();
+
+    match parameters.determine_snap_path() {
+        Ok((snap_path, aux_path)) => {
+            if report.add(
+                ".snap file size",
+                verify_file_size(&snap_path, primary_variable_names.len() * variable_size),
+            ) {
+                verify_variable_values(
+                    &mut report,
+                    &snap_path,
+                    primary_variable_names.iter().copied(),
+                    number_of_values,
+                    endianness,
+                );
+            }
+            if let Some(aux_names) = aux_names {
+                let aux_file_passed = if aux_names.is_empty() && !aux_path.exists() {
+                    report.add(".aux file size", Ok("no .aux file required".to_string()))
+                } else {
+                    report.add(
+                        ".aux file size",
+                        verify_file_size(&aux_path, aux_names.len() * variable_size),
+                    )
+                };
+                if aux_file_passed {
+                    verify_variable_values(
+                        &mut report,
+                        &aux_path,
+                        aux_names.iter().map(String::as_str),
+                        number_of_values,
+                        endianness,
+                    );
+                }
+            }
+        }
+        Err(err) => {
+            report.add(".snap file size", Err(err.to_string()));
+        }
+    }
+
+    match parameters.determine_mesh_path() {
+        Ok(mesh_path) => verify_mesh(&mut report, &mesh_path, &shape),
+        Err(err) => {
+            report.add("mesh dimensions", Err(err.to_string()));
+        }
+    }
+
+    report
+}
+
+fn read_shape_and_mhd(
+    parameters: &NativeSnapshotParameters,
+) -> Result<(In3D<usize>, bool), String> {
+    let read_dimension = |name: &str| -> Result<usize, String> {
+        match parameters.get_as_int(name) {
+            Ok(value) if value > 0 => Ok(value as usize),
+            Ok(value) => Err(format!("invalid value {} for {}", value, name)),
+            Err(err) => Err(err.to_string()),
+        }
+    };
+    let shape = In3D::new(
+        read_dimension("mx")?,
+        read_dimension("my")?,
+        read_dimension("mz")?,
+    );
+    let is_mhd = parameters
+        .determine_if_mhd()
+        .map_err(|err| err.to_string())?;
+    Ok((shape, is_mhd))
+}
+
+/// Checks that the given auxiliary variable names are unique and distinct from
+/// the primary variable names.
+fn verify_aux_names(
+    aux_names: Vec<String>,
+    primary_variable_names: &[&str],
+) -> Result<Vec<String>, String> {
+    let mut unique_names = HashSet::new();
+    for name in &aux_names {
+        if primary_variable_names.contains(&name.as_str()) {
+            return Err(format!("{} is also a primary variable", name));
+        }
+        if !unique_names.insert(name.as_str()) {
+            return Err(format!("{} is listed more than once", name));
+        }
+    }
+    Ok(aux_names)
+}
+
+fn verify_file_size(file_path: &Path, expected_size: usize) -> Result<String, String> {
+    let file_name = file_path.file_name().unwrap().to_string_lossy();
+    let size = fs::metadata(file_path)
+        .map_err(|err| format!("could not access {}: {}", file_name, err))?
+        .len() as usize;
+    if size == expected_size {
+        Ok(format!("{} has {} bytes", file_name, size))
+    } else {
+        Err(format!(
+            "{} has {} bytes, expected {}",
+            file_name, size, expected_size
+        ))
+    }
+}
+
+/// Counts the NaN and infinite values of each of the given variables, stored
+/// in order in the given file, and adds a check for each variable.
+fn verify_variable_values<'a>(
+    report: &mut SnapshotVerificationReport,
+    file_path: &Path,
+    variable_names: impl Iterator<Item = &'a str>,
+    number_of_values: usize,
+    endianness: Endianness,
+) {
+    for (index, name) in variable_names.enumerate() {
+        let check_name = format!("finite values of {}", name);
+        let outcome = utils::read_from_binary_file::<_, fdt>(
+            file_path,
+            number_of_values,
+            index * number_of_values * mem::size_of::<fdt>(),
+            endianness,
+        )
+        .map_err(|err| format!("could not read values: {}", err))
+        .and_then(|values| {
+            let nan_count = values.iter().filter(|value| value.is_nan()).count();
+            let inf_count = values.iter().filter(|value| value.is_infinite()).count();
+            if nan_count == 0 && inf_count == 0 {
+                Ok("no NaN or infinite values".to_string())
+            } else {
+                Err(format!(
+                    "{} NaN and {} infinite values",
+                    nan_count, inf_count
+                ))
+            }
+        });
+        report.add(&check_name, outcome);
+    }
+}
+
+fn verify_mesh(report: &mut SnapshotVerificationReport, mesh_path: &Path, shape: &In3D<usize>) {
+    let [center_coords, lower_edge_coords, up_derivatives, down_derivatives] =
+        match mesh::read_mesh_file_coords(mesh_path, &Verbosity::Quiet) {
+            Ok(coords) => coords,
+            Err(err) => {
+                report.add("mesh dimensions", Err(err.to_string()));
+                return;
+            }
+        };
+
+    let mut mismatches = Vec::new();
+    for (dim, name) in [(X, "x"), (Y, "y"), (Z, "z")] {
+        for (coords, kind) in [
+            (&center_coords, "center"),
+            (&lower_edge_coords, "lower edge"),
+            (&up_derivatives, "up derivative"),
+            (&down_derivatives, "down derivative"),
+        ] {
+            if coords[dim].len() != shape[dim] {
+                mismatches.push(format!(
+                    "{} {} {}-values (expected {})",
+                    coords[dim].len(),
+                    kind,
+                    name,
+                    shape[dim]
+                ));
+            }
+        }
+    }
+    if !report.add(
+        "mesh dimensions",
+        if mismatches.is_empty() {
+            Ok("consistent with parameter file".to_string())
+        } else {
+            Err(mismatches.join(", "))
+        },
+    ) {
+        return;
+    }
+
+    report.add(
+        "mesh coordinates",
+        grid::verify_coordinate_arrays(&center_coords, &lower_edge_coords, false)
+            .map(|grid_type| {
+                match grid_type {
+                    GridType::Regular => "monotonic and regular",
+                    GridType::HorRegular => "monotonic and horizontally regular",
+                }
+                .to_string()
+            })
+            .map_err(|err| err.to_string()),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{
+        field::{CustomScalarFieldGenerator3, FieldGrid3},
+        geometry::Vec3,
+        grid::{fgr, regular::RegularGrid3},
+        io::{
+            snapshot::{native, utils::OutputSnapshotMetadata},
+            utils::IOContext,
+        },
+    };
+    use std::{
+        io::{Seek, SeekFrom, Write},
+        sync::Arc,
+    };
+
+    #[test]
+    fn verification_detects_non_finite_values_and_truncated_files() {
+        let output_dir = tempfile::tempdir().unwrap();
+        let param_file_path = output_dir.path().join("snap_001.idl");
+
+        let grid = Arc::new(FieldGrid3::from(RegularGrid3::from_bounds(
+            In3D::new(3, 2, 4),
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(3.0, 2.0, 4.0),
+            In3D::new(true, true, false),
+        )));
+        let mut provider = CustomScalarFieldGenerator3::<fdt>::new(grid, Verbosity::Quiet);
+        for name in ["r", "px", "py", "pz", "e", "tg"] {
+            provider = provider.with_variable(
+                name.to_string(),
+                Box::new(|x: fgr, y: fgr, z: fgr| (x + y + z) as fdt),
+            );
+        }
+        native::write_new_snapshot(
+            &OutputSnapshotMetadata::new(),
+            &mut provider,
+            &param_file_path,
+            &IOContext::new(),
+            &Verbosity::Quiet,
+        )
+        .unwrap();
+
+        let report = verify_snapshot(&param_file_path, Endianness::Little);
+        assert!(report.passed(), "{:?}", report);
+
+        // Replace the first value of px with NaN
+        let snap_path = param_file_path.with_extension("snap");
+        let mut file = fs::OpenOptions::new().write(true).open(&snap_path).unwrap();
+        file.seek(SeekFrom::Start(3 * 2 * 4 * 4)).unwrap();
+        file.write_all(&fdt::NAN.to_le_bytes()).unwrap();
+        drop(file);
+
+        let report = verify_snapshot(&param_file_path, Endianness::Little);
+        let failed_names: Vec<_> = report.failed_checks().map(|check| check.name()).collect();
+        assert_eq!(failed_names, vec!["finite values of px"]);
+
+        // Truncate the auxiliary file
+        let aux_path = param_file_path.with_extension("aux");
+        let aux_file = fs::OpenOptions::new().write(true).open(&aux_path).unwrap();
+        aux_file.set_len(10).unwrap();
+        drop(aux_file);
+
+        let report = verify_snapshot(&param_file_path, Endianness::Little);
+        let failed_names: Vec<_> = report.failed_checks().map(|check| check.name()).collect();
+        assert_eq!(failed_names, vec!["finite values of px", ".aux file size"]);
+    }
+}
@@ -12,7 +12,7 @@ use super::{
     FALLBACK_SNAP_NUM,
 };
 use crate::{
-    field::{FieldGrid3, ScalarField3, ScalarFieldProvider3},
+    field::{FieldGrid3, ScalarField1, ScalarField3, ScalarFieldProvider3},
     geometry::{
         Dim3::{X, Y, Z},
         In3D,
@@ -30,7 +30,7 @@ use std::{
     sync::Arc,
 };
 
-pub use mesh::create_grid_from_netcdf_file;
+pub use mesh::{create_grid_from_netcdf_file, write_vertical_grid};
 pub use param::{read_netcdf_snapshot_parameters, NetCDFSnapshotParameters};
 
 /// Configuration parameters for NetCDF snapshot reader.
@@ -450,3 +450,22 @@ pub fn write_3d_scalar_field(group: &mut GroupMut, field: &ScalarField3<fdt>) ->
     )?;
     Ok(())
 }
+
+/// Writes a representation of the given vertical 1D scalar field to the given NetCDF group.
+///
+/// The group must already contain the vertical grid dimensions.
+pub fn write_1d_scalar_field(group: &mut GroupMut, field: &ScalarField1<fdt>) -> io::Result<()> {
+    let dimension_name = match field.location() {
+        CoordLocation::Center => "zm",
+        CoordLocation::LowerEdge => "zmdn",
+    };
+    let values = field
+        .values()
+        .as_slice_memory_order()
+        .expect("Values array not contiguous");
+    io_result!(
+        io_result!(group.add_variable::<fdt>(field.name(), &[dimension_name]))?
+            .put_values(values, None, None)
+    )?;
+    Ok(())
+}
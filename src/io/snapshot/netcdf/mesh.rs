@@ -2,13 +2,13 @@
 
 use super::super::super::{Endianness, Verbosity};
 use crate::{
-    field::FieldGrid3,
+    field::{FieldGrid1, FieldGrid3},
     geometry::{
         Coords3,
         Dim3::{X, Y, Z},
         In3D,
     },
-    grid::{self, fgr, Grid3, GridType},
+    grid::{self, fgr, Grid1, Grid3, GridType},
     io_result,
 };
 use netcdf_rs::{self, File, GroupMut};
@@ -344,6 +344,34 @@ pub fn write_grid<G: Grid3<fgr>>(
     Ok(())
 }
 
+/// Writes a representation of the given vertical 1D grid to the given NetCDF group.
+pub fn write_vertical_grid(group: &mut GroupMut, grid: &FieldGrid1) -> io::Result<()> {
+    io_result!(group.add_dimension("zm", grid.size()))?;
+    io_result!(group.add_dimension("zmdn", grid.size()))?;
+
+    add_coordinate_variable(
+        group,
+        "zm",
+        "zm",
+        "Z",
+        "z-coordinates of Cartesian grid cell centers",
+        Some("Mm"),
+        None,
+        grid.centers(),
+    )?;
+    add_coordinate_variable(
+        group,
+        "zmdn",
+        "zmdn",
+        "Z",
+        "z-coordinates of Cartesian grid lower edges",
+        Some("Mm"),
+        None,
+        grid.lower_edges(),
+    )?;
+    Ok(())
+}
+
 fn add_coordinate_variable(
     group: &mut GroupMut,
     name: &str,
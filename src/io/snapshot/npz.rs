@@ -0,0 +1,284 @@
+//! Writing of Bifrost simulation data in NumPy `.npz` format.
+//!
+//! Each snapshot variable is stored as a 3D array in Fortran order, indexed
+//! as `[i, j, k]` for the x-, y- and z-dimension. The grid is stored as the
+//! standard 1D coordinate arrays, and the names of the coordinate arrays each
+//! variable is defined on are stored as a JSON object under `coordinates`.
+//! The snapshot parameters are stored as a JSON object under `parameters`.
+//! Both JSON entries are arrays of UTF-8 bytes, which can be decoded with
+//! `json.loads(npz[name].tobytes())`.
+
+use super::{
+    super::{utils, Verbosity},
+    fdt, ParameterValue, SnapshotMetadata, SnapshotParameters, FALLBACK_SNAP_NUM,
+};
+use crate::{
+    field::{ScalarField3, ScalarFieldProvider3},
+    geometry::Dim3::{X, Y, Z},
+    grid::{fgr, CoordLocation, Grid3},
+    io::utils::IOContext,
+};
+use ndarray::prelude::*;
+use ndarray_npy::NpzWriter;
+use serde_json::{Map, Value};
+use std::{
+    io::{self, Seek, Write},
+    path::Path,
+};
+
+/// Name of the entry holding the JSON encoded snapshot parameters.
+pub const PARAMETERS_ENTRY_NAME: &str = "parameters";
+/// Name of the entry holding the JSON encoded coordinate names of each variable.
+pub const COORDINATES_ENTRY_NAME: &str = "coordinates";
+
+/// Writes data associated with the given snapshot to a `.npz` file at the given path.
+pub fn write_new_snapshot(
+    input_metadata: &dyn SnapshotMetadata,
+    provider: &mut dyn ScalarFieldProvider3<fdt>,
+    output_file_path: &Path,
+    io_context: &IOContext,
+    verbosity: &Verbosity,
+) -> io::Result<()> {
+    let quantity_names = provider.all_variable_names().to_vec();
+    write_modified_snapshot(
+        input_metadata,
+        provider,
+        &quantity_names,
+        output_file_path,
+        false,
+        io_context,
+        verbosity,
+    )
+}
+
+/// Writes modified data associated with the given snapshot to a `.npz` file at the given path.
+pub fn write_modified_snapshot(
+    input_metadata: &dyn SnapshotMetadata,
+    provider: &mut dyn ScalarFieldProvider3<fdt>,
+    quantity_names: &[String],
+    output_file_path: &Path,
+    strip_metadata: bool,
+    io_context: &IOContext,
+    verbosity: &Verbosity,
+) -> io::Result<()> {
+    let (snap_name, snap_num) = super::extract_name_and_num_from_snapshot_path(output_file_path);
+    let snap_num = snap_num.unwrap_or(FALLBACK_SNAP_NUM) as i64;
+
+    let (_, included_auxiliary_variable_names, is_mhd) =
+        input_metadata.classify_variable_names(quantity_names);
+
+    let atomic_output_file =
+        io_context.create_atomic_output_file(output_file_path.to_path_buf())?;
+    if !atomic_output_file.check_if_write_allowed(io_context, verbosity) {
+        return Ok(());
+    }
+
+    let output_file_name = atomic_output_file
+        .target_path()
+        .file_name()
+        .unwrap()
+        .to_string_lossy();
+
+    let mut writer = utils::create_npz_writer(atomic_output_file.temporary_path())?;
+
+    if !strip_metadata {
+        let new_parameters = input_metadata.create_updated_parameters(
+            provider.grid(),
+            snap_name.as_str(),
+            snap_num,
+            &included_auxiliary_variable_names,
+            is_mhd,
+        );
+        if verbosity.print_messages() {
+            println!("Writing parameters to {}", output_file_name);
+        }
+        utils::add_json_to_npz(
+            &mut writer,
+            PARAMETERS_ENTRY_NAME,
+            &create_json_parameters(&*new_parameters.borrow())?,
+        )?;
+    }
+
+    if verbosity.print_messages() {
+        println!("Writing grid to {}", output_file_name);
+    }
+    write_grid(&mut writer, provider.grid(), strip_metadata)?;
+
+    let mut coordinates = Map::new();
+    for name in quantity_names {
+        let field = provider.provide_scalar_field(name)?;
+        if verbosity.print_messages() {
+            println!("Writing {} to {}", name, output_file_name);
+        }
+        coordinates.insert(name.to_string(), create_json_coordinate_names(&field));
+        utils::add_array_to_npz(&mut writer, name, field.values())?;
+    }
+    utils::add_json_to_npz(
+        &mut writer,
+        COORDINATES_ENTRY_NAME,
+        &Value::Object(coordinates),
+    )?;
+
+    utils::finish_npz(writer)?;
+    io_context.close_atomic_output_file(atomic_output_file)?;
+
+    Ok(())
+}
+
+/// Writes the coordinate arrays of the given grid to the given `.npz` writer.
+fn write_grid<W, G>(
+    writer: &mut NpzWriter<W>,
+    grid: &G,
+    exclude_derivatives: bool,
+) -> io::Result<()>
+where
+    W: Write + Seek,
+    G: Grid3<fgr>,
+{
+    let centers = grid.centers();
+    let lower_edges = grid.lower_edges();
+
+    let mut add_coords = |name: &str, values: &[fgr]| {
+        utils::add_array_to_npz(writer, name, &ArrayView::from(values))
+    };
+
+    add_coords("xm", &centers[X])?;
+    add_coords("ym", &centers[Y])?;
+    add_coords("zm", &centers[Z])?;
+    add_coords("xmdn", &lower_edges[X])?;
+    add_coords("ymdn", &lower_edges[Y])?;
+    add_coords("zmdn", &lower_edges[Z])?;
+
+    if !exclude_derivatives {
+        if let Some(up_derivatives) = grid.up_derivatives() {
+            add_coords("dxidxup", &up_derivatives[X])?;
+            add_coords("dyidyup", &up_derivatives[Y])?;
+            add_coords("dzidzup", &up_derivatives[Z])?;
+        }
+        if let Some(down_derivatives) = grid.down_derivatives() {
+            add_coords("dxidxdn", &down_derivatives[X])?;
+            add_coords("dyidydn", &down_derivatives[Y])?;
+            add_coords("dzidzdn", &down_derivatives[Z])?;
+        }
+    }
+    Ok(())
+}
+
+/// Creates a JSON object mapping each parameter name to its value.
+fn create_json_parameters(parameters: &dyn SnapshotParameters) -> io::Result<Value> {
+    let mut object = Map::new();
+    for name in parameters.names() {
+        let value = match parameters.get_value(name)? {
+            value @ ParameterValue::String(_) => {
+                Value::String(value.as_unquoted_string().into_owned())
+            }
+            &ParameterValue::Int(i) => Value::from(i),
+            &ParameterValue::Float(f) => Value::from(f),
+        };
+        object.insert(name.to_string(), value);
+    }
+    Ok(Value::Object(object))
+}
+
+/// Creates a JSON array with the names of the x-, y- and z-coordinate arrays
+/// the given field is defined on.
+fn create_json_coordinate_names(field: &ScalarField3<fdt>) -> Value {
+    let locations = field.locations();
+    Value::from(vec![
+        match locations[X] {
+            CoordLocation::Center => "xm",
+            CoordLocation::LowerEdge => "xmdn",
+        },
+        match locations[Y] {
+            CoordLocation::Center => "ym",
+            CoordLocation::LowerEdge => "ymdn",
+        },
+        match locations[Z] {
+            CoordLocation::Center => "zm",
+            CoordLocation::LowerEdge => "zmdn",
+        },
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{
+        field::{CustomScalarFieldGenerator3, FieldGrid3},
+        geometry::{In3D, Vec3},
+        grid::regular::RegularGrid3,
+        io::snapshot::utils::OutputSnapshotMetadata,
+    };
+    use ndarray_npy::NpzReader;
+    use std::{fs, sync::Arc};
+
+    #[test]
+    fn snapshot_variables_are_written_in_fortran_order_with_metadata() {
+        let output_dir = tempfile::tempdir().unwrap();
+        let output_file_path = output_dir.path().join("snap.npz");
+
+        let grid = Arc::new(FieldGrid3::from(RegularGrid3::from_bounds(
+            In3D::new(3, 2, 4),
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(3.0, 2.0, 4.0),
+            In3D::new(true, true, false),
+        )));
+        let mut provider = CustomScalarFieldGenerator3::<fdt>::new(grid, Verbosity::Quiet)
+            .with_variable(
+                "f".to_string(),
+                Box::new(|x: fgr, y: fgr, z: fgr| {
+                    (x.floor() + 10.0 * y.floor() + 100.0 * z.floor()) as fdt
+                }),
+            );
+
+        write_new_snapshot(
+            &OutputSnapshotMetadata::new(),
+            &mut provider,
+            &output_file_path,
+            &IOContext::new(),
+            &Verbosity::Quiet,
+        )
+        .unwrap();
+
+        let mut reader = NpzReader::new(fs::File::open(&output_file_path).unwrap()).unwrap();
+
+        let values: Array3<fdt> = reader.by_name("f.npy").unwrap();
+        assert!(values.t().is_standard_layout());
+        assert_eq!(values.shape(), &[3, 2, 4]);
+        assert_eq!(values[[2, 1, 3]], 312.0);
+
+        let zm: Array1<fgr> = reader.by_name("zm.npy").unwrap();
+        assert_eq!(zm.to_vec(), vec![0.5, 1.5, 2.5, 3.5]);
+
+        let read_json = |reader: &mut NpzReader<fs::File>, name: &str| -> Value {
+            let bytes: Array1<u8> = reader.by_name(&format!("{}.npy", name)).unwrap();
+            serde_json::from_slice(bytes.as_slice().unwrap()).unwrap()
+        };
+        let parameters = read_json(&mut reader, PARAMETERS_ENTRY_NAME);
+        assert_eq!(parameters["mz"], Value::from(4));
+        assert_eq!(parameters["snapname"], Value::from("snap"));
+        let coordinates = read_json(&mut reader, COORDINATES_ENTRY_NAME);
+        assert_eq!(coordinates["f"], Value::from(vec!["xm", "ym", "zm"]));
+
+        // Fields in standard layout must also end up in Fortran order
+        let field_file_path = output_dir.path().join("field.npz");
+        let standard_layout_values =
+            Array3::from_shape_fn((3, 2, 4), |(i, j, k)| (i + 10 * j + 100 * k) as fdt);
+        assert!(standard_layout_values.is_standard_layout());
+        ScalarField3::new(
+            "g".to_string(),
+            provider.arc_with_grid(),
+            In3D::same(CoordLocation::Center),
+            standard_layout_values,
+        )
+        .save_as_npz(&field_file_path)
+        .unwrap();
+
+        let mut reader = NpzReader::new(fs::File::open(&field_file_path).unwrap()).unwrap();
+        let values: Array3<fdt> = reader.by_name("values.npy").unwrap();
+        assert!(values.t().is_standard_layout());
+        assert_eq!(values.shape(), &[3, 2, 4]);
+        assert_eq!(values[[2, 1, 3]], 312.0);
+    }
+}
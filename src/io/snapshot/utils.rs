@@ -2,6 +2,7 @@
 
 use super::{
     fdt,
+    muram::{self, MURaMSnapshotReader3, MURaMSnapshotReaderConfig},
     native::{NativeSnapshotReader3, NativeSnapshotReaderConfig},
     MapOfSnapshotParameters, SnapshotMetadata, SnapshotParameters,
 };
@@ -20,6 +21,9 @@ use std::{
 #[cfg(feature = "netcdf")]
 use super::netcdf::{NetCDFSnapshotReader3, NetCDFSnapshotReaderConfig};
 
+#[cfg(feature = "hdf5")]
+use super::hdf5::{HDF5SnapshotReader3, HDF5SnapshotReaderConfig};
+
 /// Dummy metadata type that can be used for writing a snapshot
 /// generated in Backstaff rather than read from files.
 pub struct OutputSnapshotMetadata {
@@ -64,6 +68,9 @@ pub enum SnapshotInputType {
     Native(NativeSnapshotInputType),
     #[cfg(feature = "netcdf")]
     NetCDF,
+    #[cfg(feature = "hdf5")]
+    HDF5,
+    MURaM,
 }
 
 /// Type of input files for snapshots in native format.
@@ -83,6 +90,9 @@ impl SnapshotInputType {
                 Self::valid_extensions_string()
             )
         }));
+        if muram::is_header_file_path(file_name) {
+            return Self::MURaM;
+        }
         let final_extension = file_name.extension().unwrap().to_string_lossy();
         let extension = if final_extension.as_ref() == "scr" {
             match Path::new(file_name.file_stem().unwrap()).extension() {
@@ -113,6 +123,15 @@ impl SnapshotInputType {
                 exit_with_error!("Error: Compile with netcdf feature in order to read NetCDF files\n\
                                   Tip: Use cargo flag --features=netcdf and make sure the NetCDF library is available");
             }
+            "h5" => {
+                #[cfg(feature = "hdf5")]
+                {
+                    Self::HDF5
+                }
+                #[cfg(not(feature = "hdf5"))]
+                exit_with_error!("Error: Compile with hdf5 feature in order to read HDF5 files\n\
+                                  Tip: Use cargo flag --features=hdf5 and make sure the HDF5 library is available");
+            }
             invalid => exit_with_error!(
                 "Error: Invalid extension {} for input file\n\
                  Valid extensions are: {}",
@@ -125,11 +144,17 @@ impl SnapshotInputType {
     /// Returns a string listing valid extensions for input files.
     pub fn valid_extensions_string() -> String {
         format!(
-            "idl[.scr]{}",
-            if cfg!(feature = "netcdf") { ", nc" } else { "" }
+            "idl[.scr]{}{} (or a MURaM Header.<iteration> file)",
+            if cfg!(feature = "netcdf") { ", nc" } else { "" },
+            if cfg!(feature = "hdf5") { ", h5" } else { "" }
         )
     }
 
+    /// Whether this input type represents MURaM snapshot files.
+    pub fn is_muram(&self) -> bool {
+        matches!(self, Self::MURaM)
+    }
+
     /// Whether this input type represents native snapshot scratch files.
     pub fn is_scratch(&self) -> bool {
         matches!(self, Self::Native(NativeSnapshotInputType::Scratch))
@@ -146,6 +171,9 @@ impl fmt::Display for SnapshotInputType {
                 Self::Native(NativeSnapshotInputType::Scratch) => "idl.scr",
                 #[cfg(feature = "netcdf")]
                 Self::NetCDF => "nc",
+                #[cfg(feature = "hdf5")]
+                Self::HDF5 => "h5",
+                Self::MURaM => "muram",
             }
         )
     }
@@ -170,7 +198,7 @@ impl SnapNumInRange {
         );
         Self {
             current_offset: current_snap_num - start_snap_num,
-            final_offset: end_snap_num - current_snap_num,
+            final_offset: end_snap_num - start_snap_num,
         }
     }
 
@@ -212,6 +240,28 @@ pub fn new_snapshot_reader(
                     )
                 })
         }
+        #[cfg(feature = "hdf5")]
+        SnapshotInputType::HDF5 => {
+            HDF5SnapshotReader3::new(HDF5SnapshotReaderConfig::new(input_file_path, verbosity)).map(
+                |(reader, metadata)| {
+                    (
+                        Box::new(reader) as DynScalarFieldProvider3<fdt>,
+                        Box::new(metadata) as Box<dyn SnapshotMetadata>,
+                    )
+                },
+            )
+        }
+        SnapshotInputType::MURaM => MURaMSnapshotReader3::new(MURaMSnapshotReaderConfig::new(
+            input_file_path,
+            endianness,
+            verbosity,
+        ))
+        .map(|(reader, metadata)| {
+            (
+                Box::new(reader) as DynScalarFieldProvider3<fdt>,
+                Box::new(metadata) as Box<dyn SnapshotMetadata>,
+            )
+        }),
     }
 }
 
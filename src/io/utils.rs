@@ -21,6 +21,12 @@ use serde_json;
 #[cfg(feature = "pickle")]
 use serde_pickle;
 
+#[cfg(feature = "npz")]
+use ndarray::{Array, ArrayBase, ArrayView1, Data, Dimension, ShapeBuilder};
+
+#[cfg(feature = "npz")]
+use ndarray_npy::{NpzWriter, WritableElement};
+
 #[macro_export]
 macro_rules! io_result {
     ($result:expr) => {
@@ -447,6 +453,75 @@ pub fn read_from_binary_file<P: AsRef<Path>, T: ByteorderData>(
     Ok(buffer)
 }
 
+/// Reads and returns a buffer with the values inside a box-shaped region of a
+/// 3D array stored in column-major order in the specified binary file.
+///
+/// The region has the given shape and starts at the given indices in the full
+/// array, which starts at the given byte offset. Only the contiguous runs of
+/// values inside the region are read, and the values are returned in
+/// column-major order.
+pub fn read_3d_subarray_from_binary_file<P: AsRef<Path>, T: ByteorderData>(
+    file_path: P,
+    array_shape: &[usize; 3],
+    byte_offset: usize,
+    start_indices: &[usize; 3],
+    subarray_shape: &[usize; 3],
+    endianness: Endianness,
+) -> io::Result<Vec<T>> {
+    let [nx, ny, _] = *array_shape;
+    let [i_start, j_start, k_start] = *start_indices;
+    let [sub_nx, sub_ny, sub_nz] = *subarray_shape;
+
+    if (0..3).any(|dim| start_indices[dim] + subarray_shape[dim] > array_shape[dim]) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Subarray extends outside of array",
+        ));
+    }
+
+    let mut buffer = vec![T::default(); sub_nx * sub_ny * sub_nz];
+    if buffer.is_empty() {
+        return Ok(buffer);
+    }
+
+    // Runs of values along x are contiguous, and so are whole xy-layers
+    // and consecutive layers when the region spans the full extent
+    let flat_idx = |i, j, k| i + nx * (j + ny * k);
+    let run_start_indices: Vec<_> = if sub_nx == nx && sub_ny == ny {
+        vec![flat_idx(0, 0, k_start)]
+    } else if sub_nx == nx {
+        (k_start..k_start + sub_nz)
+            .map(|k| flat_idx(0, j_start, k))
+            .collect()
+    } else {
+        (k_start..k_start + sub_nz)
+            .flat_map(|k| (j_start..j_start + sub_ny).map(move |j| flat_idx(i_start, j, k)))
+            .collect()
+    };
+    let run_length = buffer.len() / run_start_indices.len();
+    let type_size = mem::size_of::<T>();
+
+    let mut file = open_file_and_map_err(file_path)?;
+
+    if let [run_start_idx] = run_start_indices[..] {
+        file.seek(SeekFrom::Start(
+            (byte_offset + run_start_idx * type_size) as u64,
+        ))?;
+        T::read_from_binary_file(&mut file, &mut buffer, endianness)?;
+        return Ok(buffer);
+    }
+
+    let mut run_buffer = vec![T::default(); run_length];
+    for (run, run_start_idx) in buffer.chunks_exact_mut(run_length).zip(run_start_indices) {
+        file.seek(SeekFrom::Start(
+            (byte_offset + run_start_idx * type_size) as u64,
+        ))?;
+        T::read_from_binary_file(&mut file, &mut run_buffer, endianness)?;
+        run.copy_from_slice(&run_buffer);
+    }
+    Ok(buffer)
+}
+
 /// Writes the given source buffer of values into the given byte buffer,
 /// starting at the specified offset. Returns the number of bytes written.
 pub fn write_into_byte_buffer<T: ByteorderData>(
@@ -515,6 +590,73 @@ pub fn write_data_as_pickle<T: Serialize, W: io::Write>(
     }
 }
 
+/// Creates a writer for an uncompressed NumPy `.npz` file at the given path.
+#[cfg(feature = "npz")]
+pub fn create_npz_writer<P: AsRef<Path>>(output_file_path: P) -> io::Result<NpzWriter<fs::File>> {
+    let file = create_file_and_required_directories(output_file_path)?;
+    Ok(NpzWriter::new(file))
+}
+
+/// Adds the given array with the given name to the given `.npz` writer.
+///
+/// As with `numpy.savez`, the array is stored in the archive as `<name>.npy`.
+/// The array is always stored in Fortran order, so arrays not already laid out
+/// in column-major order are copied into column-major layout first.
+#[cfg(feature = "npz")]
+pub fn add_array_to_npz<W, S, D>(
+    writer: &mut NpzWriter<W>,
+    name: &str,
+    array: &ArrayBase<S, D>,
+) -> io::Result<()>
+where
+    W: Write + Seek,
+    S: Data,
+    S::Elem: WritableElement + Clone,
+    D: Dimension,
+{
+    let result = if array.t().is_standard_layout() {
+        writer.add_array(format!("{}.npy", name), array)
+    } else {
+        let fortran_array =
+            Array::from_shape_vec(array.raw_dim().f(), array.t().iter().cloned().collect())
+                .unwrap();
+        writer.add_array(format!("{}.npy", name), &fortran_array)
+    };
+    result.map_err(|err| {
+        io::Error::other(format!(
+            "Failed to write array {} to .npz file: {}",
+            name, err
+        ))
+    })
+}
+
+/// Adds the JSON representation of the given value with the given name to the
+/// given `.npz` writer.
+///
+/// The JSON text is stored as an array of UTF-8 bytes, which can be decoded in
+/// Python with `json.loads(npz[name].tobytes())`.
+#[cfg(feature = "npz")]
+pub fn add_json_to_npz<W: Write + Seek>(
+    writer: &mut NpzWriter<W>,
+    name: &str,
+    value: &serde_json::Value,
+) -> io::Result<()> {
+    add_array_to_npz(
+        writer,
+        name,
+        &ArrayView1::from(value.to_string().as_bytes()),
+    )
+}
+
+/// Finishes writing the given `.npz` writer.
+#[cfg(feature = "npz")]
+pub fn finish_npz<W: Write + Seek>(writer: NpzWriter<W>) -> io::Result<()> {
+    writer
+        .finish()
+        .map(|_| ())
+        .map_err(|err| io::Error::other(format!("Failed to finish writing .npz file: {}", err)))
+}
+
 impl ByteorderData for f32 {
     fn write_into_byte_buffer(source: &[Self], dest: &mut [u8], endianness: Endianness) {
         match endianness {
@@ -660,3 +802,48 @@ impl ByteorderData for u64 {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn reading_3d_subarray_gives_same_values_as_slicing() {
+        let shape = [4, 3, 5];
+        let values: Vec<f32> = (0..60).map(|value| value as f32).collect();
+        let mut byte_buffer = vec![0_u8; 4 * (7 + values.len())];
+        write_into_byte_buffer(&values, &mut byte_buffer, 4 * 7, Endianness::Little);
+
+        let directory = tempfile::tempdir().unwrap();
+        let file_path = directory.path().join("values.dat");
+        save_data_as_binary(&file_path, &byte_buffer).unwrap();
+
+        for (start_indices, subarray_shape) in [
+            ([1, 0, 2], [2, 3, 2]),
+            ([0, 1, 1], [4, 2, 3]),
+            ([0, 0, 3], [4, 3, 2]),
+            ([3, 2, 4], [1, 1, 1]),
+        ] {
+            let subarray: Vec<f32> = read_3d_subarray_from_binary_file(
+                &file_path,
+                &shape,
+                4 * 7,
+                &start_indices,
+                &subarray_shape,
+                Endianness::Little,
+            )
+            .unwrap();
+
+            let mut expected = Vec::new();
+            for k in start_indices[2]..start_indices[2] + subarray_shape[2] {
+                for j in start_indices[1]..start_indices[1] + subarray_shape[1] {
+                    for i in start_indices[0]..start_indices[0] + subarray_shape[0] {
+                        expected.push(values[i + shape[0] * (j + shape[1] * k)]);
+                    }
+                }
+            }
+            assert_eq!(subarray, expected);
+        }
+    }
+}
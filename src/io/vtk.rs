@@ -0,0 +1,568 @@
+//! Writing of data in the VTK XML file formats.
+//!
+//! All data is written as raw little-endian binary in the appended data
+//! section of the file, which ParaView and other VTK based tools read
+//! without any conversion.
+
+use super::{
+    utils::{self, IOContext},
+    Verbosity,
+};
+use crate::{
+    field::{ResampledCoordLocation, ResamplingMethod, ScalarField2, ScalarFieldProvider3},
+    geometry::{Dim2, Dim3, In3D},
+    grid::{fgr, CoordLocation, Grid3},
+    interpolation::poly_fit::{PolyFitInterpolator3, PolyFitInterpolatorConfig},
+    io::snapshot::fdt,
+    num::BFloat,
+};
+use ndarray::prelude::*;
+use std::{
+    fmt::Write as FmtWrite,
+    io::{self, BufWriter, Write},
+    mem,
+    path::Path,
+};
+
+/// Numeric types that can be written to VTK data arrays.
+pub trait VtkValue: Copy {
+    /// Name of the type in VTK XML files.
+    const TYPE_NAME: &'static str;
+
+    /// Writes the value as little-endian bytes to the given writer.
+    fn write_le<W: Write>(self, writer: &mut W) -> io::Result<()>;
+}
+
+macro_rules! impl_vtk_value {
+    ($type:ty, $name:expr) => {
+        impl VtkValue for $type {
+            const TYPE_NAME: &'static str = $name;
+
+            fn write_le<W: Write>(self, writer: &mut W) -> io::Result<()> {
+                writer.write_all(&self.to_le_bytes())
+            }
+        }
+    };
+}
+
+impl_vtk_value!(f32, "Float32");
+impl_vtk_value!(f64, "Float64");
+impl_vtk_value!(i64, "Int64");
+
+/// Values of a VTK data array.
+#[derive(Clone, Debug)]
+pub enum VtkArrayValues {
+    Float32(Vec<f32>),
+    Float64(Vec<f64>),
+    Int64(Vec<i64>),
+}
+
+impl VtkArrayValues {
+    fn len(&self) -> usize {
+        match self {
+            Self::Float32(values) => values.len(),
+            Self::Float64(values) => values.len(),
+            Self::Int64(values) => values.len(),
+        }
+    }
+
+    fn type_name(&self) -> &'static str {
+        match self {
+            Self::Float32(_) => f32::TYPE_NAME,
+            Self::Float64(_) => f64::TYPE_NAME,
+            Self::Int64(_) => i64::TYPE_NAME,
+        }
+    }
+
+    fn value_size(&self) -> usize {
+        match self {
+            Self::Float32(_) => mem::size_of::<f32>(),
+            Self::Float64(_) => mem::size_of::<f64>(),
+            Self::Int64(_) => mem::size_of::<i64>(),
+        }
+    }
+
+    fn write_le<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        match self {
+            Self::Float32(values) => write_values(writer, values.iter().copied()),
+            Self::Float64(values) => write_values(writer, values.iter().copied()),
+            Self::Int64(values) => write_values(writer, values.iter().copied()),
+        }
+    }
+}
+
+impl From<Vec<f32>> for VtkArrayValues {
+    fn from(values: Vec<f32>) -> Self {
+        Self::Float32(values)
+    }
+}
+
+impl From<Vec<f64>> for VtkArrayValues {
+    fn from(values: Vec<f64>) -> Self {
+        Self::Float64(values)
+    }
+}
+
+impl From<Vec<i64>> for VtkArrayValues {
+    fn from(values: Vec<i64>) -> Self {
+        Self::Int64(values)
+    }
+}
+
+/// Named VTK data array where each tuple has a given number of components.
+#[derive(Clone, Debug)]
+pub struct VtkDataArray {
+    name: String,
+    number_of_components: usize,
+    values: VtkArrayValues,
+}
+
+impl VtkDataArray {
+    /// Creates a new data array with the given name, number of components
+    /// per tuple and flat array of values (with the components of each tuple
+    /// stored contiguously).
+    pub fn new<V: Into<VtkArrayValues>>(
+        name: String,
+        number_of_components: usize,
+        values: V,
+    ) -> Self {
+        let values = values.into();
+        assert_eq!(
+            values.len() % number_of_components,
+            0,
+            "Number of values not divisible by number of components."
+        );
+        Self {
+            name,
+            number_of_components,
+            values,
+        }
+    }
+
+    /// Returns the number of tuples in the array.
+    pub fn number_of_tuples(&self) -> usize {
+        self.values.len() / self.number_of_components
+    }
+
+    fn header(&self) -> ArrayHeader {
+        ArrayHeader {
+            name: self.name.clone(),
+            type_name: self.values.type_name(),
+            number_of_components: self.number_of_components,
+            number_of_bytes: self.values.len() * self.values.value_size(),
+        }
+    }
+}
+
+/// Writes the given 3D scalar fields as point data in a VTK XML
+/// rectilinear grid (`.vtr`) file.
+///
+/// # Parameters
+///
+/// - `output_file_path`: Path where the output file should be written.
+/// - `coords`: Coordinates of the grid points along each dimension.
+/// - `variable_names`: Names of the variables to write.
+/// - `variable_value_producer`: Closure producing an array of values given the variable name.
+///
+/// # Returns
+///
+/// A `Result` which is either:
+///
+/// - `Ok`: Writing was completed successfully.
+/// - `Err`: Contains an error encountered while trying to produce the values or write to the file.
+pub fn write_rectilinear_grid<F: VtkValue>(
+    output_file_path: &Path,
+    coords: &In3D<&[fgr]>,
+    variable_names: &[String],
+    variable_value_producer: &mut dyn FnMut(&str) -> io::Result<Array3<F>>,
+) -> io::Result<()> {
+    let shape = In3D::with_each_component(|dim| coords[dim].len());
+    let number_of_points = shape[Dim3::X] * shape[Dim3::Y] * shape[Dim3::Z];
+    let extent = format!(
+        "0 {} 0 {} 0 {}",
+        shape[Dim3::X] - 1,
+        shape[Dim3::Y] - 1,
+        shape[Dim3::Z] - 1
+    );
+
+    let point_data_headers: Vec<_> = variable_names
+        .iter()
+        .map(|name| ArrayHeader {
+            name: name.clone(),
+            type_name: F::TYPE_NAME,
+            number_of_components: 1,
+            number_of_bytes: number_of_points * mem::size_of::<F>(),
+        })
+        .collect();
+    let coordinate_arrays: Vec<_> = Dim3::slice()
+        .iter()
+        .map(|&dim| VtkDataArray::new(format!("{}", dim), 1, coords[dim].to_vec()))
+        .collect();
+
+    let mut writer = BufWriter::new(utils::create_file_and_required_directories(
+        output_file_path,
+    )?);
+
+    write_xml_header(
+        &mut writer,
+        "RectilinearGrid",
+        &format!("WholeExtent=\"{}\"", extent),
+        &format!("Extent=\"{}\"", extent),
+        &[
+            ("PointData", point_data_headers),
+            (
+                "Coordinates",
+                coordinate_arrays.iter().map(VtkDataArray::header).collect(),
+            ),
+        ],
+    )?;
+
+    for name in variable_names {
+        let values = variable_value_producer(name)?;
+        if values.len() != number_of_points {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Shape of {} does not match the shape of the grid", name),
+            ));
+        }
+        // Iterating over the transposed array gives the x-fastest ordering used by VTK
+        write_appended_array(
+            &mut writer,
+            number_of_points * mem::size_of::<F>(),
+            |writer| write_values(writer, values.t().iter().copied()),
+        )?;
+    }
+    for array in &coordinate_arrays {
+        write_appended_data_array(&mut writer, array)?;
+    }
+
+    write_xml_footer(&mut writer)
+}
+
+/// Writes a set of poly-lines as a VTK XML poly data (`.vtp`) file.
+///
+/// # Parameters
+///
+/// - `output_file_path`: Path where the output file should be written.
+/// - `line_lengths`: Number of points in each line.
+/// - `points`: Array with the three coordinates of every point, for one line after the other.
+/// - `point_data`: Arrays of values defined for every point.
+/// - `cell_data`: Arrays of values defined for every line.
+///
+/// # Returns
+///
+/// A `Result` which is either:
+///
+/// - `Ok`: Writing was completed successfully.
+/// - `Err`: Contains an error encountered while trying to write to the file.
+pub fn write_poly_lines(
+    output_file_path: &Path,
+    line_lengths: &[usize],
+    points: VtkDataArray,
+    point_data: &[VtkDataArray],
+    cell_data: &[VtkDataArray],
+) -> io::Result<()> {
+    let number_of_lines = line_lengths.len();
+    let number_of_points = points.number_of_tuples();
+
+    if points.number_of_components != 3 || line_lengths.iter().sum::<usize>() != number_of_points {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Number of points does not match line lengths",
+        ));
+    }
+    for (arrays, number_of_tuples) in [(point_data, number_of_points), (cell_data, number_of_lines)]
+    {
+        if let Some(array) = arrays
+            .iter()
+            .find(|array| array.number_of_tuples() != number_of_tuples)
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Wrong number of values for {}", array.name),
+            ));
+        }
+    }
+
+    let connectivity = VtkDataArray::new(
+        "connectivity".to_string(),
+        1,
+        (0..number_of_points as i64).collect::<Vec<_>>(),
+    );
+    let offsets = VtkDataArray::new(
+        "offsets".to_string(),
+        1,
+        line_lengths
+            .iter()
+            .scan(0, |offset, &length| {
+                *offset += length as i64;
+                Some(*offset)
+            })
+            .collect::<Vec<_>>(),
+    );
+
+    let mut writer = BufWriter::new(utils::create_file_and_required_directories(
+        output_file_path,
+    )?);
+
+    write_xml_header(
+        &mut writer,
+        "PolyData",
+        "",
+        &format!(
+            "NumberOfPoints=\"{}\" NumberOfVerts=\"0\" NumberOfLines=\"{}\" \
+             NumberOfStrips=\"0\" NumberOfPolys=\"0\"",
+            number_of_points, number_of_lines
+        ),
+        &[
+            (
+                "PointData",
+                point_data.iter().map(VtkDataArray::header).collect(),
+            ),
+            (
+                "CellData",
+                cell_data.iter().map(VtkDataArray::header).collect(),
+            ),
+            ("Points", vec![points.header()]),
+            ("Lines", vec![connectivity.header(), offsets.header()]),
+        ],
+    )?;
+
+    for array in point_data
+        .iter()
+        .chain(cell_data)
+        .chain([&points, &connectivity, &offsets])
+    {
+        write_appended_data_array(&mut writer, array)?;
+    }
+
+    write_xml_footer(&mut writer)
+}
+
+/// Writes the given quantities of the snapshot as point data in a VTK XML
+/// rectilinear grid (`.vtr`) file.
+///
+/// The points of the grid are located at the cell centers. Unless
+/// `center_staggered` is set, the values of staggered quantities are written
+/// as they are, so they are effectively shifted half a grid cell. Otherwise
+/// they are interpolated to the cell centers before writing.
+pub fn write_snapshot(
+    provider: &mut dyn ScalarFieldProvider3<fdt>,
+    quantity_names: &[String],
+    output_file_path: &Path,
+    center_staggered: bool,
+    io_context: &IOContext,
+    verbosity: &Verbosity,
+) -> io::Result<()> {
+    let atomic_output_file =
+        io_context.create_atomic_output_file(output_file_path.to_path_buf())?;
+    if !atomic_output_file.check_if_write_allowed(io_context, verbosity) {
+        return Ok(());
+    }
+
+    let output_file_name = atomic_output_file
+        .target_path()
+        .file_name()
+        .unwrap()
+        .to_string_lossy();
+
+    let grid = provider.arc_with_grid();
+    let centers = grid.centers();
+    let coords = In3D::with_each_component(|dim| &centers[dim][..]);
+
+    let interpolator = PolyFitInterpolator3::new(PolyFitInterpolatorConfig::default());
+
+    write_rectilinear_grid(
+        atomic_output_file.temporary_path(),
+        &coords,
+        quantity_names,
+        &mut |name| {
+            let field = provider.produce_scalar_field(name)?;
+            let field = if center_staggered
+                && Dim3::slice()
+                    .iter()
+                    .any(|&dim| field.locations()[dim] != CoordLocation::Center)
+            {
+                if verbosity.print_messages() {
+                    println!("Centering {}", name);
+                }
+                field.resampled_to_grid(
+                    grid.clone(),
+                    In3D::same(ResampledCoordLocation::center()),
+                    &interpolator,
+                    ResamplingMethod::DirectSampling,
+                    &Verbosity::Quiet,
+                )
+            } else {
+                field
+            };
+            if verbosity.print_messages() {
+                println!("Writing {} to {}", name, output_file_name);
+            }
+            Ok(field.into_values())
+        },
+    )?;
+
+    io_context.close_atomic_output_file(atomic_output_file)
+}
+
+/// Writes the given 2D slice of a 3D field as a VTK XML rectilinear grid
+/// (`.vtr`) file, with a single point along the slice axis.
+pub fn write_slice<F: VtkValue + BFloat>(
+    output_file_path: &Path,
+    field: &ScalarField2<F>,
+    slice_axis: Dim3,
+    slice_coord: fgr,
+) -> io::Result<()> {
+    let [first_axis, second_axis] = Dim3::slice_except(slice_axis);
+    let slice_coords = [slice_coord];
+    let field_coords = field.coords();
+    let mut coords = In3D::same(&slice_coords[..]);
+    coords[first_axis] = field_coords[Dim2::X];
+    coords[second_axis] = field_coords[Dim2::Y];
+
+    write_rectilinear_grid(
+        output_file_path,
+        &coords,
+        &[field.name().to_string()],
+        &mut |_| Ok(field.values().clone().insert_axis(Axis(slice_axis.num()))),
+    )
+}
+
+/// Header information for a data array in a VTK XML file.
+struct ArrayHeader {
+    name: String,
+    type_name: &'static str,
+    number_of_components: usize,
+    number_of_bytes: usize,
+}
+
+/// Writes the XML part of a VTK file, where each given section contains the
+/// headers of the arrays that will be written to the appended data section
+/// in the same order.
+fn write_xml_header<W: Write>(
+    writer: &mut W,
+    data_set_type: &str,
+    data_set_attributes: &str,
+    piece_attributes: &str,
+    sections: &[(&str, Vec<ArrayHeader>)],
+) -> io::Result<()> {
+    let mut xml = String::new();
+    let mut offset = 0;
+
+    // Formatting into a `String` cannot fail, so the results are ignored
+    let _ = writeln!(xml, "<?xml version=\"1.0\"?>");
+    let _ = writeln!(
+        xml,
+        "<VTKFile type=\"{}\" version=\"1.0\" byte_order=\"LittleEndian\" header_type=\"UInt64\">",
+        data_set_type
+    );
+    let _ = writeln!(xml, "  <{} {}>", data_set_type, data_set_attributes);
+    let _ = writeln!(xml, "    <Piece {}>", piece_attributes);
+    for (section_name, headers) in sections {
+        let _ = writeln!(xml, "      <{}>", section_name);
+        for header in headers {
+            let _ = writeln!(
+                xml,
+                "        <DataArray type=\"{}\" Name=\"{}\" NumberOfComponents=\"{}\" \
+                 format=\"appended\" offset=\"{}\"/>",
+                header.type_name, header.name, header.number_of_components, offset
+            );
+            offset += mem::size_of::<u64>() + header.number_of_bytes;
+        }
+        let _ = writeln!(xml, "      </{}>", section_name);
+    }
+    let _ = writeln!(xml, "    </Piece>");
+    let _ = writeln!(xml, "  </{}>", data_set_type);
+    let _ = write!(xml, "  <AppendedData encoding=\"raw\">\n   _");
+
+    writer.write_all(xml.as_bytes())
+}
+
+fn write_xml_footer<W: Write>(writer: &mut W) -> io::Result<()> {
+    writer.write_all(b"\n  </AppendedData>\n</VTKFile>\n")?;
+    writer.flush()
+}
+
+fn write_appended_data_array<W: Write>(writer: &mut W, array: &VtkDataArray) -> io::Result<()> {
+    write_appended_array(writer, array.header().number_of_bytes, |writer| {
+        array.values.write_le(writer)
+    })
+}
+
+fn write_appended_array<W, P>(writer: &mut W, number_of_bytes: usize, write: P) -> io::Result<()>
+where
+    W: Write,
+    P: FnOnce(&mut W) -> io::Result<()>,
+{
+    writer.write_all(&(number_of_bytes as u64).to_le_bytes())?;
+    write(writer)
+}
+
+fn write_values<W, F, I>(writer: &mut W, values: I) -> io::Result<()>
+where
+    W: Write,
+    F: VtkValue,
+    I: Iterator<Item = F>,
+{
+    for value in values {
+        value.write_le(writer)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn poly_line_file_has_correct_offsets_and_data() {
+        let output_dir = tempfile::tempdir().unwrap();
+        let output_file_path = output_dir.path().join("lines.vtp");
+
+        let points = VtkDataArray::new(
+            "points".to_string(),
+            3,
+            vec![
+                0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 1.0, 0.0, 1.0, 2.0,
+            ],
+        );
+        let point_data = [VtkDataArray::new(
+            "s".to_string(),
+            1,
+            vec![1.0_f32, 2.0, 3.0, 4.0, 5.0],
+        )];
+        let cell_data = [VtkDataArray::new("id".to_string(), 1, vec![7_i64, 8])];
+        write_poly_lines(&output_file_path, &[2, 3], points, &point_data, &cell_data).unwrap();
+
+        let content = fs::read(&output_file_path).unwrap();
+        let marker = b"<AppendedData encoding=\"raw\">\n   _";
+        let data_start = content
+            .windows(marker.len())
+            .position(|window| window == marker)
+            .unwrap()
+            + marker.len();
+        let header = String::from_utf8_lossy(&content[..data_start]);
+        assert!(header.contains("NumberOfPoints=\"5\""));
+        assert!(header.contains("NumberOfLines=\"2\""));
+
+        // Point data, cell data, points, connectivity and offsets follow in order
+        let array_sizes = [5 * 4, 2 * 8, 15 * 8, 5 * 8, 2 * 8];
+        let mut offset = 0;
+        for (idx, &size) in array_sizes.iter().enumerate() {
+            assert!(header.contains(&format!("offset=\"{}\"", offset)));
+            let start = data_start + offset;
+            let byte_count = u64::from_le_bytes(content[start..start + 8].try_into().unwrap());
+            assert_eq!(byte_count as usize, size);
+            if idx == 4 {
+                let last_offset =
+                    i64::from_le_bytes(content[start + 16..start + 24].try_into().unwrap());
+                assert_eq!(last_offset, 5);
+            }
+            offset += 8 + size;
+        }
+        assert!(content[data_start + offset..].starts_with(b"\n  </AppendedData>"));
+    }
+}
@@ -1,10 +1,13 @@
 //! Generation of seed points for field line tracing.
 
 pub mod criterion;
+#[cfg(feature = "tracing")]
+pub mod evenly_spaced;
 pub mod manual;
 pub mod slice;
 pub mod volume;
 
+use self::slice::SeedSliceGrid;
 use crate::{
     field::FieldGrid3,
     geometry::{Idx3, Point3},
@@ -26,6 +29,12 @@ pub trait Seeder3 {
     /// Creates a list of seed indices from the seed points by looking up the grid cells
     /// of the given grid containing the seed points.
     fn to_index_seeder(&self, grid: &FieldGrid3) -> Vec<Idx3<usize>>;
+
+    /// Returns the regular slice grid with a seed point at every cell center, or
+    /// `None` if the seed points do not form such a grid.
+    fn seed_grid(&self) -> Option<&SeedSliceGrid> {
+        None
+    }
 }
 
 /// Defines the properties of a 3D seed index generator.
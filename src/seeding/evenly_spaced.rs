@@ -0,0 +1,409 @@
+//! Placement of evenly spaced seed points by tracing field lines while seeding.
+
+use crate::{
+    field::VectorField3,
+    geometry::{
+        Dim3::{self, X, Y, Z},
+        Point3, Vec3,
+    },
+    grid::{fgr, GridPointQuery3},
+    interpolation::Interpolator3,
+    num::BFloat,
+    tracing::{
+        self, ftr,
+        stepping::{
+            rkf::{rkf45::RKF45Stepper3, RKFStepperConfig},
+            StepperInstruction, SteppingSense,
+        },
+    },
+};
+use std::collections::{HashMap, VecDeque};
+
+/// Configuration parameters for evenly spaced seeding.
+#[derive(Clone, Debug)]
+pub struct EvenlySpacedSeedingConfig {
+    /// Distance to keep between a new seed point and the existing field lines.
+    pub separation: fgr,
+    /// Fraction of the separation distance at which tracing of a field line
+    /// is stopped when it approaches an existing field line.
+    pub test_separation_fraction: fgr,
+    /// Optional modulation of the separation distance by the field strength.
+    pub strength_modulation: Option<SeparationModulation>,
+}
+
+/// Modulation of the separation distance by the local field strength.
+///
+/// The separation distance at a point is scaled by
+/// `(reference_strength/strength)^exponent`, limited to lie between
+/// `1/max_scale` and `max_scale`.
+#[derive(Clone, Debug)]
+pub struct SeparationModulation {
+    /// Field strength where the separation distance is not scaled.
+    pub reference_strength: fgr,
+    /// Exponent of the ratio between the reference and the local field strength.
+    pub exponent: fgr,
+    /// Largest allowed scaling of the separation distance.
+    pub max_scale: fgr,
+}
+
+/// How candidate seed points are generated from accepted field lines.
+#[derive(Clone, Copy, Debug)]
+pub(super) enum CandidatePlacement {
+    /// Candidates are placed around each seed point, at the separation
+    /// distance within the plane normal to the given axis.
+    AroundSeedsInPlane(Dim3),
+    /// Candidates are placed at the separation distance perpendicular to
+    /// each point along the field lines.
+    PerpendicularToLines,
+}
+
+/// A point on a traced field line, stored in the spatial hash.
+#[derive(Clone, Debug)]
+struct LinePoint {
+    position: Point3<fgr>,
+    line_idx: usize,
+    arc_length: ftr,
+}
+
+/// Spatial hash of the points on all traced field lines, for fast lookup of
+/// the points close to a given position.
+#[derive(Clone, Debug)]
+struct LinePointHash {
+    cell_size: fgr,
+    cells: HashMap<[i64; 3], Vec<LinePoint>>,
+}
+
+impl EvenlySpacedSeedingConfig {
+    pub const DEFAULT_TEST_SEPARATION_FRACTION: fgr = 0.5;
+
+    fn validate(&self) {
+        assert!(
+            self.separation > 0.0,
+            "Separation distance must be larger than zero."
+        );
+        assert!(
+            self.test_separation_fraction > 0.0 && self.test_separation_fraction <= 1.0,
+            "Test separation fraction must be in the range (0, 1]."
+        );
+        if let Some(modulation) = &self.strength_modulation {
+            assert!(
+                modulation.reference_strength > 0.0,
+                "Reference field strength must be larger than zero."
+            );
+            assert!(
+                modulation.max_scale >= 1.0,
+                "Maximum separation scale must be at least one."
+            );
+        }
+    }
+
+    /// Computes the separation distance for the given field vector.
+    fn separation_for_field_vector(&self, field_vector: &Vec3<ftr>) -> fgr {
+        match &self.strength_modulation {
+            Some(modulation) => {
+                let strength = field_vector.length();
+                let scale = if strength > 0.0 {
+                    (modulation.reference_strength / strength)
+                        .powf(modulation.exponent)
+                        .max(1.0 / modulation.max_scale)
+                        .min(modulation.max_scale)
+                } else {
+                    modulation.max_scale
+                };
+                self.separation * scale
+            }
+            None => self.separation,
+        }
+    }
+
+    /// Returns the largest separation distance that can occur.
+    fn max_separation(&self) -> fgr {
+        match &self.strength_modulation {
+            Some(modulation) => self.separation * modulation.max_scale,
+            None => self.separation,
+        }
+    }
+
+    /// Returns the smallest separation distance that can occur.
+    fn min_separation(&self) -> fgr {
+        match &self.strength_modulation {
+            Some(modulation) => self.separation / modulation.max_scale,
+            None => self.separation,
+        }
+    }
+}
+
+impl Default for EvenlySpacedSeedingConfig {
+    fn default() -> Self {
+        EvenlySpacedSeedingConfig {
+            separation: 1.0,
+            test_separation_fraction: Self::DEFAULT_TEST_SEPARATION_FRACTION,
+            strength_modulation: None,
+        }
+    }
+}
+
+impl LinePointHash {
+    fn new(cell_size: fgr) -> Self {
+        Self {
+            cell_size,
+            cells: HashMap::new(),
+        }
+    }
+
+    fn cell_of(&self, position: &Point3<fgr>) -> [i64; 3] {
+        [X, Y, Z].map(|dim| (position[dim] / self.cell_size).floor() as i64)
+    }
+
+    fn insert(&mut self, point: LinePoint) {
+        self.cells
+            .entry(self.cell_of(&point.position))
+            .or_default()
+            .push(point);
+    }
+
+    /// Whether any stored point closer than the given distance to the given
+    /// position satisfies the given predicate.
+    fn any_within<P>(&self, position: &Point3<fgr>, distance: fgr, predicate: P) -> bool
+    where
+        P: Fn(&LinePoint) -> bool,
+    {
+        let lower = self.cell_of(&Point3::with_each_component(|dim| position[dim] - distance));
+        let upper = self.cell_of(&Point3::with_each_component(|dim| position[dim] + distance));
+        let squared_distance = distance * distance;
+        for i in lower[0]..=upper[0] {
+            for j in lower[1]..=upper[1] {
+                for k in lower[2]..=upper[2] {
+                    if let Some(points) = self.cells.get(&[i, j, k]) {
+                        if points.iter().any(|point| {
+                            (&point.position - position).squared_length() < squared_distance
+                                && predicate(point)
+                        }) {
+                            return true;
+                        }
+                    }
+                }
+            }
+        }
+        false
+    }
+}
+
+/// Places evenly spaced seed points by tracing field lines from accepted
+/// seed points and generating new candidate seed points next to them.
+///
+/// A candidate is accepted if it satisfies the constraints and lies at least
+/// the local separation distance away from all points on the existing field
+/// lines. Field lines are traced in both directions until they come closer
+/// than the test separation distance to another field line (or to an earlier
+/// part of themselves), or leave the region. When no candidates remain, the
+/// fallback candidates are tried in order.
+pub(super) fn place_evenly_spaced_seed_points<F, R, S>(
+    field: &VectorField3<F>,
+    interpolator: &dyn Interpolator3<F>,
+    config: &EvenlySpacedSeedingConfig,
+    placement: CandidatePlacement,
+    initial_candidate: Point3<fgr>,
+    fallback_candidates: Vec<Point3<fgr>>,
+    is_in_region: &R,
+    satisfies_constraints: &S,
+) -> Vec<Point3<fgr>>
+where
+    F: BFloat,
+    R: Fn(&Point3<fgr>) -> bool,
+    S: Fn(&Point3<fgr>) -> bool,
+{
+    config.validate();
+
+    let field_vector =
+        |position: &Point3<fgr>| match interpolator.interp_vector_field(field, position) {
+            GridPointQuery3::Inside(vector) | GridPointQuery3::MovedInside((vector, _)) => {
+                Some(Vec3::from(&vector))
+            }
+            GridPointQuery3::Outside => None,
+        };
+
+    let stepper_config = RKFStepperConfig {
+        dense_step_length: 0.5 * config.test_separation_fraction * config.min_separation(),
+        ..RKFStepperConfig::default()
+    };
+    // Points on the same field line closer than this along the line are not
+    // considered when testing the separation
+    let self_separation_lag = 2.0 * config.max_separation();
+
+    let mut line_points = LinePointHash::new(config.separation);
+    let mut seed_points = Vec::new();
+
+    let mut candidates = VecDeque::from([initial_candidate]);
+    let mut fallback_candidates = fallback_candidates.into_iter();
+
+    while let Some(candidate) = candidates
+        .pop_front()
+        .or_else(|| fallback_candidates.next())
+    {
+        if !is_in_region(&candidate) || !satisfies_constraints(&candidate) {
+            continue;
+        }
+        let separation = match field_vector(&candidate) {
+            Some(vector) if !vector.is_zero() => config.separation_for_field_vector(&vector),
+            _ => continue,
+        };
+        if line_points.any_within(&candidate, separation, |_| true) {
+            continue;
+        }
+
+        let line_idx = seed_points.len();
+        let mut new_line_points = vec![(candidate.clone(), Vec3::zero())];
+        line_points.insert(LinePoint {
+            position: candidate.clone(),
+            line_idx,
+            arc_length: 0.0,
+        });
+
+        for (sense, sign) in [(SteppingSense::Same, 1.0), (SteppingSense::Opposite, -1.0)] {
+            tracing::trace_3d_field_line_dense(
+                field,
+                interpolator,
+                Box::new(RKF45Stepper3::new(stepper_config.clone())),
+                &candidate,
+                sense,
+                &mut |_, direction, position, distance| {
+                    if distance == 0.0 {
+                        return StepperInstruction::Continue;
+                    }
+                    if !is_in_region(position) {
+                        return StepperInstruction::Terminate;
+                    }
+                    let arc_length = sign * distance;
+                    let test_separation = config.test_separation_fraction
+                        * field_vector(position).map_or(config.separation, |vector| {
+                            config.separation_for_field_vector(&vector)
+                        });
+                    if line_points.any_within(position, test_separation, |point| {
+                        point.line_idx != line_idx
+                            || (point.arc_length - arc_length).abs() > self_separation_lag
+                    }) {
+                        return StepperInstruction::Terminate;
+                    }
+                    line_points.insert(LinePoint {
+                        position: position.clone(),
+                        line_idx,
+                        arc_length,
+                    });
+                    new_line_points.push((position.clone(), direction.clone()));
+                    StepperInstruction::Continue
+                },
+            );
+        }
+
+        match placement {
+            CandidatePlacement::AroundSeedsInPlane(axis) => {
+                let [first_axis, second_axis] = Dim3::slice_except(axis);
+                for k in 0..6 {
+                    let angle = (k as fgr) * std::f64::consts::FRAC_PI_3;
+                    let mut neighbor = candidate.clone();
+                    neighbor[first_axis] += separation * angle.cos();
+                    neighbor[second_axis] += separation * angle.sin();
+                    candidates.push_back(neighbor);
+                }
+            }
+            CandidatePlacement::PerpendicularToLines => {
+                for (position, direction) in new_line_points.iter().skip(1) {
+                    let separation = field_vector(position).map_or(config.separation, |vector| {
+                        config.separation_for_field_vector(&vector)
+                    });
+                    for offset in perpendicular_unit_vectors(direction) {
+                        candidates.push_back(position + &offset * separation);
+                        candidates.push_back(position - &offset * separation);
+                    }
+                }
+            }
+        }
+
+        seed_points.push(candidate);
+    }
+    seed_points
+}
+
+/// Computes two unit vectors perpendicular to the given non-zero direction
+/// and to each other.
+fn perpendicular_unit_vectors(direction: &Vec3<ftr>) -> [Vec3<ftr>; 2] {
+    let mut tangent = direction.clone();
+    tangent.normalize();
+
+    // Use the coordinate axis least aligned with the tangent as reference
+    let reference_dim = [X, Y, Z]
+        .into_iter()
+        .min_by(|&a, &b| tangent[a].abs().total_cmp(&tangent[b].abs()))
+        .unwrap();
+    let mut reference = Vec3::zero();
+    reference[reference_dim] = 1.0;
+
+    let mut first = cross(&tangent, &reference);
+    first.normalize();
+    let second = cross(&tangent, &first);
+    [first, second]
+}
+
+fn cross(a: &Vec3<ftr>, b: &Vec3<ftr>) -> Vec3<ftr> {
+    Vec3::new(
+        a[Y] * b[Z] - a[Z] * b[Y],
+        a[Z] * b[X] - a[X] * b[Z],
+        a[X] * b[Y] - a[Y] * b[X],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{
+        field::{CustomScalarFieldGenerator3, FieldGrid3, ScalarFieldProvider3},
+        geometry::In3D,
+        grid::regular::RegularGrid3,
+        interpolation::poly_fit::{PolyFitInterpolator3, PolyFitInterpolatorConfig},
+        io::{snapshot::fdt, Verbosity},
+        seeding::{volume::VolumeSeeder3, Seeder3},
+    };
+    use std::sync::Arc;
+
+    #[test]
+    fn seed_points_in_uniform_field_are_separated() {
+        let grid = Arc::new(FieldGrid3::from(RegularGrid3::from_bounds(
+            In3D::new(8, 8, 8),
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(1.0, 1.0, 1.0),
+            In3D::same(false),
+        )));
+        let mut generator = CustomScalarFieldGenerator3::<fdt>::new(grid, Verbosity::Quiet)
+            .with_variable("bx".to_string(), Box::new(|_, _, _: fgr| 0.0))
+            .with_variable("by".to_string(), Box::new(|_, _, _| 0.0))
+            .with_variable("bz".to_string(), Box::new(|_, _, _| 1.0));
+        let field = generator.provide_vector_field("b").unwrap();
+        let interpolator = PolyFitInterpolator3::new(PolyFitInterpolatorConfig {
+            order: 1,
+            ..PolyFitInterpolatorConfig::default()
+        });
+
+        let separation = 0.2;
+        let seeder = VolumeSeeder3::evenly_spaced(
+            field.as_ref(),
+            &interpolator,
+            &Vec3::new(0.0, 0.0, 0.0),
+            &Vec3::new(1.0, 1.0, 1.0),
+            &EvenlySpacedSeedingConfig {
+                separation,
+                ..EvenlySpacedSeedingConfig::default()
+            },
+            &|_| true,
+        );
+
+        let points = seeder.points();
+        assert!(points.len() >= 16);
+        for (idx, point) in points.iter().enumerate() {
+            for other_point in &points[idx + 1..] {
+                assert!((point - other_point).length() >= separation * (1.0 - 1e-9));
+            }
+        }
+    }
+}
@@ -1,8 +1,10 @@
 //! Generation of seed points in a slice through a field.
 
+#[cfg(feature = "tracing")]
+use super::evenly_spaced::{self, CandidatePlacement, EvenlySpacedSeedingConfig};
 use super::Seeder3;
 use crate::{
-    field::{FieldGrid3, ScalarField3, VectorField3},
+    field::{FieldGrid2, FieldGrid3, ScalarField3, VectorField3},
     geometry::{
         Dim2,
         Dim3::{self, X, Y, Z},
@@ -15,12 +17,24 @@ use crate::{
 };
 use rand::distributions::{uniform::SampleUniform, Distribution, Uniform};
 use rayon::{self, prelude::*};
-use std::{collections::HashSet, iter::FromIterator};
+use std::{collections::HashSet, iter::FromIterator, sync::Arc};
 
 /// Generator for seed points in a slice of a 3D field.
 #[derive(Clone, Debug)]
 pub struct SliceSeeder3 {
     seed_points: Vec<Point3<fgr>>,
+    seed_grid: Option<SeedSliceGrid>,
+}
+
+/// Regular 2D grid in a slice of a 3D grid, with a seed point at the center of
+/// every grid cell.
+///
+/// The seed points are ordered with the first slice coordinate varying fastest.
+#[derive(Clone, Debug)]
+pub struct SeedSliceGrid {
+    grid: Arc<FieldGrid2>,
+    axis: Dim3,
+    coord: fgr,
 }
 
 impl SliceSeeder3 {
@@ -53,13 +67,26 @@ impl SliceSeeder3 {
     {
         let slice_grid = grid.regular_slice_across_axis(axis).reshaped(shape);
         let slice_centers = slice_grid.create_point_list(CoordLocation::Center);
-        SliceSeeder3 {
-            seed_points: Self::construct_seed_points_from_slice_points(
-                slice_centers,
+        let number_of_slice_points = slice_centers.len();
+        let seed_points = Self::construct_seed_points_from_slice_points(
+            slice_centers,
+            axis,
+            coord,
+            satisfies_constraints,
+        );
+        // The seed points only cover the slice grid if none were rejected
+        let seed_grid = if seed_points.len() == number_of_slice_points {
+            Some(SeedSliceGrid {
+                grid: Arc::new(slice_grid.into()),
                 axis,
                 coord,
-                satisfies_constraints,
-            ),
+            })
+        } else {
+            None
+        };
+        SliceSeeder3 {
+            seed_points,
+            seed_grid,
         }
     }
 
@@ -171,6 +198,7 @@ impl SliceSeeder3 {
                 coord,
                 satisfies_constraints,
             ),
+            seed_grid: None,
         }
     }
 
@@ -240,6 +268,7 @@ impl SliceSeeder3 {
                 coord,
                 satisfies_constraints,
             ),
+            seed_grid: None,
         }
     }
 
@@ -311,6 +340,91 @@ impl SliceSeeder3 {
                 coord,
                 satisfies_constraints,
             ),
+            seed_grid: None,
+        }
+    }
+
+    /// Creates a new seeder producing evenly spaced seed points in a 2D slice of a 3D
+    /// vector field, by tracing field lines from the seed points while seeding.
+    ///
+    /// Starting from the center of the slice, new candidate seed points are placed
+    /// around each accepted seed point at the separation distance within the slice,
+    /// and a candidate is only accepted if no field line passes closer than the
+    /// separation distance. Field lines are traced through the whole grid until they
+    /// approach another field line, so a field line returning to the slice prevents
+    /// seeding near its other end. Any part of the slice not reached this way is seeded
+    /// from a regular grid of candidates with the separation distance as spacing.
+    ///
+    /// # Parameters
+    ///
+    /// - `field`: Vector field to trace.
+    /// - `interpolator`: Interpolator to use for sampling field values.
+    /// - `axis`: Axis to slice across.
+    /// - `coord`: Coordinate of the slice along `axis`.
+    /// - `config`: Configuration parameters for the seeding.
+    /// - `satisfies_constraints`: Closure taking a potential seed point and returning whether the point is accepted.
+    ///
+    /// # Returns
+    ///
+    /// A new `SliceSeeder3`.
+    ///
+    /// # Type parameters
+    ///
+    /// - `F`: Floating point type of the field data.
+    /// - `S`: Function type taking a reference to a 2D point and returning a boolean value.
+    #[cfg(feature = "tracing")]
+    pub fn evenly_spaced<F, S>(
+        field: &VectorField3<F>,
+        interpolator: &dyn Interpolator3<F>,
+        axis: Dim3,
+        coord: fgr,
+        config: &EvenlySpacedSeedingConfig,
+        satisfies_constraints: &S,
+    ) -> Self
+    where
+        F: BFloat,
+        S: Fn(&Point2<fgr>) -> bool + Sync,
+    {
+        let grid = field.grid();
+        let slice_axes = Dim3::slice_except(axis);
+        let to_slice_point =
+            |point: &Point3<fgr>| Point2::new(point[slice_axes[0]], point[slice_axes[1]]);
+        let is_in_region = |point: &Point3<fgr>| grid.point_is_inside(point);
+        let satisfies_slice_constraints =
+            |point: &Point3<fgr>| satisfies_constraints(&to_slice_point(point));
+
+        let slice_grid = grid.regular_slice_across_axis(axis);
+        let slice_extents = slice_grid.extents();
+        let shape = In2D::with_each_component(|dim| {
+            usize::max(1, (slice_extents[dim] / config.separation).round() as usize)
+        });
+        let slice_grid = slice_grid.reshaped(shape);
+        let fallback_candidates = Self::construct_seed_points_from_slice_points(
+            slice_grid.create_point_list(CoordLocation::Center),
+            axis,
+            coord,
+            &|_| true,
+        );
+
+        let lower_bounds = slice_grid.lower_bounds();
+        let upper_bounds = slice_grid.upper_bounds();
+        let mut initial_candidate = Point3::new(coord, coord, coord);
+        for (dim, slice_axis) in Dim2::slice().into_iter().zip(slice_axes) {
+            initial_candidate[slice_axis] = 0.5 * (lower_bounds[dim] + upper_bounds[dim]);
+        }
+
+        SliceSeeder3 {
+            seed_points: evenly_spaced::place_evenly_spaced_seed_points(
+                field,
+                interpolator,
+                config,
+                CandidatePlacement::AroundSeedsInPlane(axis),
+                initial_candidate,
+                fallback_candidates,
+                &is_in_region,
+                &satisfies_slice_constraints,
+            ),
+            seed_grid: None,
         }
     }
 
@@ -358,6 +472,23 @@ impl SliceSeeder3 {
     }
 }
 
+impl SeedSliceGrid {
+    /// Returns the 2D grid whose cell centers are the seed points.
+    pub fn grid(&self) -> &Arc<FieldGrid2> {
+        &self.grid
+    }
+
+    /// Returns the axis the slice is normal to.
+    pub fn axis(&self) -> Dim3 {
+        self.axis
+    }
+
+    /// Returns the coordinate of the slice along its normal axis.
+    pub fn coord(&self) -> fgr {
+        self.coord
+    }
+}
+
 impl Seeder3 for SliceSeeder3 {
     fn number_of_points(&self) -> usize {
         self.seed_points.len()
@@ -367,6 +498,10 @@ impl Seeder3 for SliceSeeder3 {
         &self.seed_points
     }
 
+    fn seed_grid(&self) -> Option<&SeedSliceGrid> {
+        self.seed_grid.as_ref()
+    }
+
     fn to_index_seeder(&self, grid: &FieldGrid3) -> Vec<Idx3<usize>> {
         self.seed_points.to_index_seeder(grid)
     }
@@ -1,5 +1,7 @@
 //! Generation of seed points in a volume of a 3D field.
 
+#[cfg(feature = "tracing")]
+use super::evenly_spaced::{self, CandidatePlacement, EvenlySpacedSeedingConfig};
 use super::Seeder3;
 use crate::{
     field::{FieldGrid3, ScalarField3, VectorField3},
@@ -267,6 +269,82 @@ impl VolumeSeeder3 {
         }
     }
 
+    /// Creates a new seeder producing evenly spaced seed points in a volume of a 3D
+    /// vector field, by tracing field lines from the seed points while seeding.
+    ///
+    /// Starting from the center of the volume, new candidate seed points are placed at
+    /// the separation distance perpendicular to the already traced field lines, and a
+    /// candidate is only accepted if no field line passes closer than the separation
+    /// distance. Field lines are traced within the volume until they approach another
+    /// field line. Any part of the volume not reached this way is seeded from a regular
+    /// grid of candidates with the separation distance as spacing.
+    ///
+    /// # Parameters
+    ///
+    /// - `field`: Vector field to trace.
+    /// - `interpolator`: Interpolator to use for sampling field values.
+    /// - `lower_bounds`: Vector specifying the lower bounds of the volume to place seed points in.
+    /// - `upper_bounds`: Vector specifying the upper bounds of the volume to place seed points in.
+    /// - `config`: Configuration parameters for the seeding.
+    /// - `satisfies_constraints`: Closure taking a potential seed point and returning whether the point is accepted.
+    ///
+    /// # Returns
+    ///
+    /// A new `VolumeSeeder3`.
+    ///
+    /// # Type parameters
+    ///
+    /// - `F`: Floating point type of the field data.
+    /// - `S`: Function type taking a reference to a 3D point and returning a boolean value.
+    #[cfg(feature = "tracing")]
+    pub fn evenly_spaced<F, S>(
+        field: &VectorField3<F>,
+        interpolator: &dyn Interpolator3<F>,
+        lower_bounds: &Vec3<fgr>,
+        upper_bounds: &Vec3<fgr>,
+        config: &EvenlySpacedSeedingConfig,
+        satisfies_constraints: &S,
+    ) -> Self
+    where
+        F: BFloat,
+        S: Fn(&Point3<fgr>) -> bool + Sync,
+    {
+        let field_grid = field.grid();
+        let is_in_region = |point: &Point3<fgr>| {
+            field_grid.point_is_inside(point)
+                && [X, Y, Z]
+                    .into_iter()
+                    .all(|dim| point[dim] >= lower_bounds[dim] && point[dim] <= upper_bounds[dim])
+        };
+
+        let shape = In3D::with_each_component(|dim| {
+            usize::max(
+                1,
+                ((upper_bounds[dim] - lower_bounds[dim]) / config.separation).round() as usize,
+            )
+        });
+        let fallback_candidates = RegularGrid3::from_bounds(
+            shape,
+            lower_bounds.clone(),
+            upper_bounds.clone(),
+            In3D::same(false),
+        )
+        .create_point_list(CoordLocation::Center);
+
+        Self {
+            seed_points: evenly_spaced::place_evenly_spaced_seed_points(
+                field,
+                interpolator,
+                config,
+                CandidatePlacement::PerpendicularToLines,
+                ((lower_bounds + upper_bounds) * 0.5).to_point3(),
+                fallback_candidates,
+                &is_in_region,
+                satisfies_constraints,
+            ),
+        }
+    }
+
     fn apply_constraints<S>(points: Vec<Point3<fgr>>, satisfies_constraints: &S) -> Vec<Point3<fgr>>
     where
         S: Fn(&Point3<fgr>) -> bool + Sync,
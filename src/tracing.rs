@@ -1,10 +1,20 @@
 //! Tracing field lines of a vector field.
 
 pub mod field_line;
+pub mod null;
+pub mod qsl;
 pub mod stepping;
 
-use self::stepping::{DynStepper3, StepperResult, SteppingCallback, SteppingSense, StoppingCause};
-use crate::{field::VectorField3, geometry::Point3, interpolation::Interpolator3, num::BFloat};
+use self::stepping::{
+    DynStepper2, DynStepper3, StepperResult, SteppingCallback, SteppingCallback2, SteppingSense,
+    StoppingCause,
+};
+use crate::{
+    field::{VectorField2, VectorField3},
+    geometry::{Point2, Point3},
+    interpolation::{Interpolator2, Interpolator3},
+    num::BFloat,
+};
 
 /// Floating-point precision to use for tracing.
 #[allow(non_camel_case_types)]
@@ -108,3 +118,93 @@ where
         }
     }
 }
+
+/// Traces a field line through a 2D vector field.
+///
+/// # Parameters
+///
+/// - `field`: Vector field to trace.
+/// - `interpolator`: Interpolator to use.
+/// - `stepper`: Stepper to use (will be consumed).
+/// - `start_position`: Position where the tracing should start.
+/// - `sense`: Whether the field line should be traced in the same or opposite direction as the field.
+/// - `callback`: Closure that for each natural step will be called with the displacement vector from the
+///   previous to the current position, the current position and the total traced distance.
+///
+/// # Returns
+///
+/// A `TracerResult` which is either:
+///
+/// - `Ok`: Contains an `Option<StoppingCause>`, possibly indicating why tracing was terminated.
+/// - `Void`: No field line was traced.
+///
+/// # Type parameters
+///
+/// - `F`: Floating point type of the field data.
+pub fn trace_2d_field_line<F>(
+    field: &VectorField2<F>,
+    interpolator: &dyn Interpolator2<F>,
+    mut stepper: DynStepper2<F>,
+    start_position: &Point2<ftr>,
+    sense: SteppingSense,
+    callback: &mut SteppingCallback2,
+) -> TracerResult
+where
+    F: BFloat,
+{
+    match stepper.place(field, interpolator, sense, start_position, callback) {
+        StepperResult::Ok(_) => {}
+        StepperResult::Stopped(_) => return TracerResult::Void,
+    };
+    loop {
+        if let StepperResult::Stopped(cause) = stepper.step(field, interpolator, sense, callback) {
+            return TracerResult::Ok(Some(cause));
+        }
+    }
+}
+
+/// Traces a field line through a 2D vector field, producing regularly spaced output.
+///
+/// # Parameters
+///
+/// - `field`: Vector field to trace.
+/// - `interpolator`: Interpolator to use.
+/// - `stepper`: Stepper to use (will be consumed).
+/// - `start_position`: Position where the tracing should start.
+/// - `sense`: Whether the field line should be traced in the same or opposite direction as the field.
+/// - `callback`: Closure that for each regularly spaced step will be called with the displacement vector from the
+///   previous to the current position, the current position and the total traced distance.
+///
+/// # Returns
+///
+/// A `TracerResult` which is either:
+///
+/// - `Ok`: Contains an `Option<StoppingCause>`, possibly indicating why tracing was terminated.
+/// - `Void`: No field line was traced.
+///
+/// # Type parameters
+///
+/// - `F`: Floating point type of the field data.
+pub fn trace_2d_field_line_dense<F>(
+    field: &VectorField2<F>,
+    interpolator: &dyn Interpolator2<F>,
+    mut stepper: DynStepper2<F>,
+    start_position: &Point2<ftr>,
+    sense: SteppingSense,
+    callback: &mut SteppingCallback2,
+) -> TracerResult
+where
+    F: BFloat,
+{
+    match stepper.place(field, interpolator, sense, start_position, callback) {
+        StepperResult::Ok(_) => {}
+        StepperResult::Stopped(_) => return TracerResult::Void,
+    };
+    loop {
+        if let StepperResult::Stopped(cause) =
+            stepper.step_dense_output(field, interpolator, sense, callback)
+        {
+            return TracerResult::Ok(Some(cause));
+        }
+    }
+}
@@ -1,17 +1,33 @@
 //! Field lines in vector fields.
 
 pub mod basic;
-
-use super::{ftr, stepping::DynStepper3};
+pub mod deposition;
+pub mod loops;
+#[cfg(feature = "corks")]
+pub mod tracking;
+pub mod twist;
+
+use self::basic::BasicFieldLineTracer2;
+use super::{
+    ftr,
+    stepping::{DynStepper2, DynStepper3},
+};
 use crate::{
-    field::{CachingScalarFieldProvider3, ScalarField3, VectorField3},
-    geometry::{Dim3, Point3, Vec3},
-    grid::Grid3,
-    interpolation::Interpolator3,
-    io::{snapshot::fdt, utils, Endianness, Verbosity},
+    field::{CachingScalarFieldProvider3, ScalarField2, ScalarField3, VectorField2, VectorField3},
+    geometry::{Dim2, Dim3, In2D, Point2, Point3, Vec2, Vec3},
+    grid::{fgr, CoordLocation, Grid2, Grid3},
+    interpolation::{fip, Interpolator2, Interpolator3},
+    io::{
+        snapshot::fdt,
+        utils,
+        vtk::{self, VtkDataArray},
+        Endianness, Verbosity,
+    },
     num::BFloat,
-    seeding::Seeder3,
+    seeding::{slice::SeedSliceGrid, Seeder3},
 };
+use byteorder::{LittleEndian, ReadBytesExt};
+use ndarray::{Array, ShapeBuilder};
 use rayon::prelude::*;
 use std::{collections::HashMap, fs, io, mem, path::Path};
 
@@ -102,6 +118,14 @@ impl FieldLineSet3 {
         }
     }
 
+    /// Reads a field line set from a file in the custom binary format at the given path.
+    pub fn from_custom_binary(input_file_path: &Path, verbosity: Verbosity) -> io::Result<Self> {
+        let file = utils::open_file_and_map_err(input_file_path)?;
+        let (lower_bounds, upper_bounds, properties) =
+            read_field_line_data_from_custom_binary(&mut io::BufReader::new(file))?;
+        Ok(Self::new(lower_bounds, upper_bounds, properties, verbosity))
+    }
+
     /// Traces all the field lines in the set from positions generated by the given seeder.
     ///
     /// # Parameters
@@ -170,6 +194,78 @@ impl FieldLineSet3 {
         Self::new(lower_bounds, upper_bounds, properties, verbosity)
     }
 
+    /// Traces all the field lines in the set from the given start positions in a
+    /// 2D vector field, and embeds them in the plane normal to the given axis at
+    /// the given coordinate.
+    ///
+    /// # Parameters
+    ///
+    /// - `field`: Vector field to trace, holding the in-plane components.
+    /// - `normal_axis`: Axis normal to the plane of the field.
+    /// - `normal_coord`: Coordinate of the plane along `normal_axis`.
+    /// - `start_positions`: Start positions in the plane.
+    /// - `tracer`: Field line tracer to use.
+    /// - `interpolator`: Interpolator to use.
+    /// - `stepper`: Stepper for field line tracing.
+    /// - `verbosity`: Whether and how to pass non-essential information to user.
+    ///
+    /// # Returns
+    ///
+    /// A new `FieldLineSet3` with traced field lines.
+    #[allow(clippy::too_many_arguments)]
+    pub fn trace_in_plane(
+        field: &VectorField2<fdt>,
+        normal_axis: Dim3,
+        normal_coord: ftr,
+        start_positions: &[Point2<fgr>],
+        tracer: &BasicFieldLineTracer2,
+        interpolator: &dyn Interpolator2<fdt>,
+        stepper: DynStepper2<fdt>,
+        verbosity: Verbosity,
+    ) -> Self {
+        let number_of_points = start_positions.len();
+        if verbosity.print_messages() {
+            println!("Found {} start positions", number_of_points);
+        }
+        let progress_bar = verbosity.create_progress_bar(number_of_points);
+
+        let properties: FieldLineSetProperties3 = start_positions
+            .par_iter()
+            .filter_map(|start_position| {
+                let field_line = tracer.trace(
+                    field,
+                    interpolator,
+                    stepper.heap_clone(),
+                    &Point2::from(start_position),
+                );
+                progress_bar.inc();
+                field_line.map(|field_line| field_line.embedded_in_plane(normal_axis, normal_coord))
+            })
+            .collect();
+
+        if verbosity.print_messages() {
+            println!(
+                "Successfully traced {} field lines",
+                properties.number_of_field_lines
+            );
+        }
+
+        let embed_bounds = |bounds: &Vec2<fgr>| {
+            let mut embedded_bounds = Vec3::new(normal_coord, normal_coord, normal_coord);
+            for (dim, axis) in Dim2::slice()
+                .into_iter()
+                .zip(Dim3::slice_except(normal_axis))
+            {
+                embedded_bounds[axis] = bounds[dim];
+            }
+            embedded_bounds
+        };
+        let lower_bounds = embed_bounds(field.grid().lower_bounds());
+        let upper_bounds = embed_bounds(field.grid().upper_bounds());
+
+        Self::new(lower_bounds, upper_bounds, properties, verbosity)
+    }
+
     /// Whether the field line set is verbosity.
     pub fn verbosity(&self) -> &Verbosity {
         &self.verbosity
@@ -180,6 +276,62 @@ impl FieldLineSet3 {
         self.properties.number_of_field_lines
     }
 
+    /// Whether the field line set contains the paths of the field lines, which
+    /// is not the case for sets traced in connectivity mode.
+    pub fn has_paths(&self) -> bool {
+        self.properties.varying_scalar_values.contains_key("x")
+    }
+
+    /// Arranges the connectivity classes of field lines traced in connectivity
+    /// mode into a map on the grid of the seed points the field lines were
+    /// traced from.
+    pub fn connectivity_map(&self, seed_grid: &SeedSliceGrid) -> io::Result<ScalarField2<fdt>> {
+        let codes = self
+            .properties
+            .fixed_scalar_values
+            .get("connectivity")
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Connectivity maps require field lines traced in connectivity mode",
+                )
+            })?;
+        let shape = seed_grid.grid().shape();
+        if codes.len() != shape[Dim2::X] * shape[Dim2::Y] {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Number of field lines does not match the number of seed points in the slice",
+            ));
+        }
+        let values = Array::from_shape_vec(
+            (shape[Dim2::X], shape[Dim2::Y]).f(),
+            codes.iter().map(|&code| code as fdt).collect(),
+        )
+        .expect("Shape of grid does not match number of values");
+        Ok(ScalarField2::new(
+            "connectivity".to_string(),
+            seed_grid.grid().clone(),
+            In2D::same(CoordLocation::Center),
+            values,
+        ))
+    }
+
+    /// Returns the positions along the path of the given field line, with jumps
+    /// across periodic boundaries removed (see `unwrap_path_positions`).
+    fn unwrapped_path_positions(&self, field_line_idx: usize) -> Vec<Vec3<ftr>> {
+        let varying_scalar_values = &self.properties.varying_scalar_values;
+        unwrap_path_positions(
+            &varying_scalar_values["x"][field_line_idx],
+            &varying_scalar_values["y"][field_line_idx],
+            &varying_scalar_values["z"][field_line_idx],
+            &self.domain_extents(),
+        )
+    }
+
+    fn domain_extents(&self) -> Vec3<ftr> {
+        &self.upper_bounds - &self.lower_bounds
+    }
+
     /// Extracts and stores the value of the given scalar field at the initial position for each field line.
     pub fn extract_fixed_scalars<F>(
         &mut self,
@@ -258,6 +410,48 @@ impl FieldLineSet3 {
             .insert(format!("{}0", field.name()), vectors);
     }
 
+    /// Extracts and stores the value of the given scalar field at both end positions
+    /// for each field line traced in connectivity mode.
+    ///
+    /// Ends outside the grid get the value NaN.
+    pub fn extract_end_scalars<F>(
+        &mut self,
+        field: &ScalarField3<F>,
+        interpolator: &dyn Interpolator3<F>,
+    ) where
+        F: BFloat,
+    {
+        if self.verbosity.print_messages() {
+            println!("Extracting {} at end positions", field.name());
+        }
+        let progress_bar = self
+            .verbosity()
+            .create_progress_bar(2 * self.number_of_field_lines());
+
+        for end_name in ["backward", "forward"] {
+            let end_coords_x = &self.properties.fixed_scalar_values[&format!("x_{}", end_name)];
+            let end_coords_y = &self.properties.fixed_scalar_values[&format!("y_{}", end_name)];
+            let end_coords_z = &self.properties.fixed_scalar_values[&format!("z_{}", end_name)];
+
+            let values = end_coords_x
+                .into_par_iter()
+                .zip(end_coords_y)
+                .zip(end_coords_z)
+                .map(|((&end_x, &end_y), &end_z)| {
+                    let value = interpolator
+                        .interp_scalar_field(field, &Point3::new(end_x, end_y, end_z))
+                        .inside_or_moved_or_default(fip::NAN);
+                    let value = num::NumCast::from(value).expect("Conversion failed");
+                    progress_bar.inc();
+                    value
+                })
+                .collect();
+            self.properties
+                .fixed_scalar_values
+                .insert(format!("{}_{}", field.name(), end_name), values);
+        }
+    }
+
     /// Extracts and stores the value of the given scalar field at each position for each field line.
     pub fn extract_varying_scalars<F>(
         &mut self,
@@ -478,6 +672,11 @@ impl FieldLineSet3 {
         )
     }
 
+    /// Serializes the field line data into VTK XML poly data format and saves at the given path.
+    pub fn save_as_vtk(&self, output_file_path: &Path) -> io::Result<()> {
+        save_field_line_data_as_vtk(output_file_path, self.properties.clone())
+    }
+
     /// Serializes the field line data into a custom binary format and writes to the given writer,
     /// consuming the field line set in the process.
     pub fn write_into_custom_binary<W: io::Write>(self, writer: &mut W) -> io::Result<()> {
@@ -501,6 +700,12 @@ impl FieldLineSet3 {
         .map(|_| ())
     }
 
+    /// Serializes the field line data into VTK XML poly data format and saves at the given path,
+    /// consuming the field line set in the process.
+    pub fn save_into_vtk(self, output_file_path: &Path) -> io::Result<()> {
+        save_field_line_data_as_vtk(output_file_path, self.properties)
+    }
+
     /// Serializes the field line data into a H5Part format and saves to the given path,
     /// consuming the field line set in the process.
     #[cfg(feature = "hdf5")]
@@ -540,6 +745,39 @@ impl Serialize for FieldLineSet3 {
     }
 }
 
+/// Returns the positions along the given field line path with jumps across
+/// periodic boundaries removed.
+///
+/// Field line positions are wrapped back into the domain when the field line
+/// crosses a periodic boundary. Since the steps along a field line are much
+/// shorter than the domain, a change in a coordinate by more than half the
+/// domain extent is taken to be such a jump, and is undone by shifting all
+/// subsequent positions by the extent. The unwrapped positions may thus lie
+/// outside the domain.
+pub fn unwrap_path_positions(
+    coords_x: &[ftr],
+    coords_y: &[ftr],
+    coords_z: &[ftr],
+    domain_extents: &Vec3<ftr>,
+) -> Vec<Vec3<ftr>> {
+    let mut offset: Vec3<ftr> = Vec3::zero();
+    let mut positions: Vec<Vec3<ftr>> = Vec::with_capacity(coords_x.len());
+    for ((&x, &y), &z) in coords_x.iter().zip(coords_y).zip(coords_z) {
+        let wrapped_position = Vec3::new(x, y, z);
+        if let Some(previous_position) = positions.last() {
+            for dim in Dim3::slice() {
+                let extent = domain_extents[dim];
+                let jump = wrapped_position[dim] + offset[dim] - previous_position[dim];
+                if extent > 0.0 && jump.abs() > 0.5 * extent {
+                    offset[dim] -= extent * jump.signum();
+                }
+            }
+        }
+        positions.push(&wrapped_position + &offset);
+    }
+    positions
+}
+
 /// Writes the given field line data in a custom binary format at the
 /// given path.
 pub fn save_field_line_data_as_custom_binary(
@@ -855,6 +1093,131 @@ pub fn write_field_line_data_as_custom_binary<W: io::Write>(
     Ok(())
 }
 
+/// Reads field line data in the custom binary format written by
+/// `write_field_line_data_as_custom_binary` from the given reader.
+///
+/// Returns the lower and upper bounds of the field line set along with its
+/// properties.
+pub fn read_field_line_data_from_custom_binary<R: io::BufRead>(
+    reader: &mut R,
+) -> io::Result<(Vec3<ftr>, Vec3<ftr>, FieldLineSetProperties3)> {
+    let invalid_data = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message);
+
+    let mut header = [0_u64; 7];
+    reader.read_u64_into::<LittleEndian>(&mut header)?;
+    let [float_size, number_of_field_lines, number_of_field_line_elements, number_of_fixed_scalar_quantities, number_of_fixed_vector_quantities, number_of_varying_scalar_quantities, number_of_varying_vector_quantities] =
+        header.map(|value| value as usize);
+
+    if float_size != mem::size_of::<ftr>() {
+        return Err(invalid_data(&format!(
+            "Unsupported float size {} in field line file",
+            float_size
+        )));
+    }
+
+    let mut bounds = [0.0; 6];
+    reader.read_f64_into::<LittleEndian>(&mut bounds)?;
+    let lower_bounds = Vec3::new(bounds[0], bounds[2], bounds[4]);
+    let upper_bounds = Vec3::new(bounds[1], bounds[3], bounds[5]);
+
+    let number_of_names = number_of_fixed_scalar_quantities
+        + number_of_fixed_vector_quantities
+        + number_of_varying_scalar_quantities
+        + number_of_varying_vector_quantities;
+    let mut names = Vec::with_capacity(number_of_names);
+    for _ in 0..number_of_names {
+        let mut name = String::new();
+        reader.read_line(&mut name)?;
+        if !name.ends_with('\n') {
+            return Err(invalid_data("Unexpected end of names in field line file"));
+        }
+        name.pop();
+        names.push(name);
+    }
+    let mut names = names.into_iter();
+
+    let field_line_lengths: Vec<_> = if number_of_field_line_elements > 0 {
+        let mut start_indices = vec![0_u64; number_of_field_lines];
+        reader.read_u64_into::<LittleEndian>(&mut start_indices)?;
+        start_indices
+            .iter()
+            .map(|&idx| idx as usize)
+            .chain(std::iter::once(number_of_field_line_elements))
+            .collect::<Vec<_>>()
+            .windows(2)
+            .map(|indices| indices[1].checked_sub(indices[0]))
+            .collect::<Option<_>>()
+            .ok_or_else(|| invalid_data("Invalid start indices in field line file"))?
+    } else {
+        vec![0; number_of_field_lines]
+    };
+
+    let mut read_values = |number_of_values: usize| -> io::Result<Vec<ftr>> {
+        let mut values = vec![0.0; number_of_values];
+        reader.read_f64_into::<LittleEndian>(&mut values)?;
+        Ok(values)
+    };
+    let split_into_field_lines = |values: &[ftr], values_per_element: usize| -> Vec<Vec<ftr>> {
+        let mut offset = 0;
+        field_line_lengths
+            .iter()
+            .map(|&length| {
+                let field_line_values =
+                    values[offset..offset + length * values_per_element].to_vec();
+                offset += length * values_per_element;
+                field_line_values
+            })
+            .collect()
+    };
+    let to_vectors = |values: &[ftr]| -> Vec<Vec3<ftr>> {
+        values
+            .chunks_exact(3)
+            .map(|components| Vec3::new(components[0], components[1], components[2]))
+            .collect()
+    };
+
+    let mut fixed_scalar_values = HashMap::new();
+    for _ in 0..number_of_fixed_scalar_quantities {
+        fixed_scalar_values.insert(names.next().unwrap(), read_values(number_of_field_lines)?);
+    }
+
+    let mut fixed_vector_values = HashMap::new();
+    for _ in 0..number_of_fixed_vector_quantities {
+        let values = read_values(number_of_field_lines * 3)?;
+        fixed_vector_values.insert(names.next().unwrap(), to_vectors(&values));
+    }
+
+    let mut varying_scalar_values = HashMap::new();
+    for _ in 0..number_of_varying_scalar_quantities {
+        let values = read_values(number_of_field_line_elements)?;
+        varying_scalar_values.insert(names.next().unwrap(), split_into_field_lines(&values, 1));
+    }
+
+    let mut varying_vector_values = HashMap::new();
+    for _ in 0..number_of_varying_vector_quantities {
+        let values = read_values(number_of_field_line_elements * 3)?;
+        varying_vector_values.insert(
+            names.next().unwrap(),
+            split_into_field_lines(&values, 3)
+                .iter()
+                .map(|field_line_values| to_vectors(field_line_values))
+                .collect(),
+        );
+    }
+
+    Ok((
+        lower_bounds,
+        upper_bounds,
+        FieldLineSetProperties3 {
+            number_of_field_lines,
+            fixed_scalar_values,
+            fixed_vector_values,
+            varying_scalar_values,
+            varying_vector_values,
+        },
+    ))
+}
+
 /// Saves the given field line data as a H5Part file at the given path.
 #[cfg(feature = "hdf5")]
 pub fn save_field_line_data_as_h5part(
@@ -942,3 +1305,145 @@ pub fn save_field_line_data_as_h5part(
 
     Ok(())
 }
+
+/// Saves the given field line data as a VTK XML poly data file at the given path.
+///
+/// Each field line becomes a poly-line cell. Varying values are written as
+/// point data and fixed values as cell data.
+pub fn save_field_line_data_as_vtk(
+    file_path: &Path,
+    properties: FieldLineSetProperties3,
+) -> io::Result<()> {
+    let FieldLineSetProperties3 {
+        fixed_scalar_values,
+        fixed_vector_values,
+        mut varying_scalar_values,
+        varying_vector_values,
+        ..
+    } = properties;
+
+    let mut take_coords = |name: &str| {
+        varying_scalar_values.remove(name).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Missing field line coordinate {}", name),
+            )
+        })
+    };
+    let (coords_x, coords_y, coords_z) = (take_coords("x")?, take_coords("y")?, take_coords("z")?);
+
+    let line_lengths: Vec<_> = coords_x.iter().map(Vec::len).collect();
+    let points = VtkDataArray::new(
+        "points".to_string(),
+        3,
+        coords_x
+            .into_iter()
+            .flatten()
+            .zip(coords_y.into_iter().flatten())
+            .zip(coords_z.into_iter().flatten())
+            .flat_map(|((x, y), z)| [x, y, z])
+            .collect::<Vec<_>>(),
+    );
+
+    let point_data: Vec<_> = varying_scalar_values
+        .into_iter()
+        .map(|(name, values)| {
+            VtkDataArray::new(name, 1, values.into_iter().flatten().collect::<Vec<_>>())
+        })
+        .chain(varying_vector_values.into_iter().map(|(name, values)| {
+            VtkDataArray::new(
+                name,
+                3,
+                values
+                    .into_iter()
+                    .flatten()
+                    .flat_map(|vector| [vector[Dim3::X], vector[Dim3::Y], vector[Dim3::Z]])
+                    .collect::<Vec<_>>(),
+            )
+        }))
+        .collect();
+
+    let cell_data: Vec<_> = fixed_scalar_values
+        .into_iter()
+        .map(|(name, values)| VtkDataArray::new(name, 1, values))
+        .chain(fixed_vector_values.into_iter().map(|(name, values)| {
+            VtkDataArray::new(
+                name,
+                3,
+                values
+                    .into_iter()
+                    .flat_map(|vector| [vector[Dim3::X], vector[Dim3::Y], vector[Dim3::Z]])
+                    .collect::<Vec<_>>(),
+            )
+        }))
+        .collect();
+
+    vtk::write_poly_lines(file_path, &line_lengths, points, &point_data, &cell_data)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn custom_binary_round_trip_preserves_field_lines() {
+        let mut properties = FieldLineSetProperties3 {
+            number_of_field_lines: 2,
+            ..FieldLineSetProperties3::default()
+        };
+        properties
+            .fixed_scalar_values
+            .insert("x0".to_string(), vec![0.5, 1.5]);
+        properties.fixed_vector_values.insert(
+            "b0".to_string(),
+            vec![Vec3::new(1.0, 2.0, 3.0), Vec3::new(4.0, 5.0, 6.0)],
+        );
+        properties
+            .varying_scalar_values
+            .insert("x".to_string(), vec![vec![0.5, 0.6, 0.7], vec![1.5]]);
+        properties
+            .varying_scalar_values
+            .insert("r".to_string(), vec![vec![1.0, 2.0, 3.0], vec![4.0]]);
+        properties.varying_vector_values.insert(
+            "b".to_string(),
+            vec![
+                vec![Vec3::new(1.0, 0.0, 0.0); 3],
+                vec![Vec3::new(0.0, 1.0, 0.0)],
+            ],
+        );
+        let lower_bounds = Vec3::new(0.0, -1.0, -2.0);
+        let upper_bounds = Vec3::new(1.0, 2.0, 3.0);
+
+        let mut bytes = Vec::new();
+        write_field_line_data_as_custom_binary(
+            &mut bytes,
+            &lower_bounds,
+            &upper_bounds,
+            properties.clone(),
+        )
+        .unwrap();
+        let (read_lower_bounds, read_upper_bounds, read_properties) =
+            read_field_line_data_from_custom_binary(&mut bytes.as_slice()).unwrap();
+
+        assert_eq!(read_lower_bounds, lower_bounds);
+        assert_eq!(read_upper_bounds, upper_bounds);
+        assert_eq!(read_properties.number_of_field_lines, 2);
+        assert_eq!(
+            read_properties.fixed_scalar_values,
+            properties.fixed_scalar_values
+        );
+        assert_eq!(
+            read_properties.fixed_vector_values,
+            properties.fixed_vector_values
+        );
+        assert_eq!(
+            read_properties.varying_scalar_values,
+            properties.varying_scalar_values
+        );
+        assert_eq!(
+            read_properties.varying_vector_values,
+            properties.varying_vector_values
+        );
+    }
+}
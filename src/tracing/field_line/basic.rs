@@ -1,28 +1,91 @@
 //! Basic field line tracing.
 
 use super::{
-    super::stepping::{DynStepper3, StepperInstruction, SteppingSense},
+    super::stepping::{DynStepper2, DynStepper3, StepperInstruction, SteppingSense, StoppingCause},
     FieldLinePath3, FieldLineSetProperties3, FieldLineTracer3,
 };
 use crate::{
-    field::CachingScalarFieldProvider3,
+    field::{CachingScalarFieldProvider3, ScalarField3, VectorField2, VectorField3},
     geometry::{
-        Dim3::{X, Y, Z},
-        Point3, Vec3,
+        Dim2,
+        Dim3::{self, X, Y, Z},
+        Point2, Point3, Vec2, Vec3,
     },
-    interpolation::Interpolator3,
+    grid::Grid3,
+    interpolation::{Interpolator2, Interpolator3},
     io::snapshot::fdt,
     tracing::{self, ftr, TracerResult},
 };
 use rayon::prelude::*;
 use std::collections::{HashMap, VecDeque};
 
+type FieldLinePointCallback<'a> = dyn 'a + FnMut(&Vec3<ftr>, &Point3<ftr>, ftr);
+
 /// Data required to represent a basic 3D field line.
 pub struct BasicFieldLineData3 {
     path: FieldLinePath3,
     total_length: ftr,
 }
 
+impl BasicFieldLineData3 {
+    /// Returns the number of points along the path of the field line.
+    pub fn number_of_points(&self) -> usize {
+        self.path.0.len()
+    }
+
+    /// Returns the position of the point with the given index along the path of the field line.
+    pub fn point(&self, idx: usize) -> Point3<ftr> {
+        Point3::new(self.path.0[idx], self.path.1[idx], self.path.2[idx])
+    }
+
+    /// Returns the total length of the field line.
+    pub fn total_length(&self) -> ftr {
+        self.total_length
+    }
+}
+
+/// Data required to represent a basic 2D field line.
+pub struct BasicFieldLineData2 {
+    path: (Vec<ftr>, Vec<ftr>),
+    total_length: ftr,
+}
+
+impl BasicFieldLineData2 {
+    /// Returns the number of points along the path of the field line.
+    pub fn number_of_points(&self) -> usize {
+        self.path.0.len()
+    }
+
+    /// Returns the position of the point with the given index along the path of the field line.
+    pub fn point(&self, idx: usize) -> Point2<ftr> {
+        Point2::new(self.path.0[idx], self.path.1[idx])
+    }
+
+    /// Returns the total length of the field line.
+    pub fn total_length(&self) -> ftr {
+        self.total_length
+    }
+
+    /// Converts the field line into a 3D field line lying in the plane normal
+    /// to the given axis at the given coordinate.
+    ///
+    /// The plane coordinates correspond to the remaining two axes, in the order
+    /// used for slices across the normal axis.
+    pub fn embedded_in_plane(self, normal_axis: Dim3, normal_coord: ftr) -> BasicFieldLineData3 {
+        let normal_coords = vec![normal_coord; self.number_of_points()];
+        let (first_coords, second_coords) = self.path;
+        let path = match normal_axis {
+            X => (normal_coords, first_coords, second_coords),
+            Y => (first_coords, normal_coords, second_coords),
+            Z => (first_coords, second_coords, normal_coords),
+        };
+        BasicFieldLineData3 {
+            path,
+            total_length: self.total_length,
+        }
+    }
+}
+
 /// Whether to trace a field line a specified direction or in both directions.
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum FieldLineTracingSense {
@@ -46,6 +109,27 @@ pub struct BasicFieldLineTracerConfig {
     pub point_spacing: FieldLinePointSpacing,
     /// Field lines reaching lengths larger than this will be terminated.
     pub max_length: Option<ftr>,
+    /// Additional criteria for terminating field lines.
+    pub stopping_criteria: Vec<FieldLineStoppingCriterion>,
+}
+
+/// Criterion for terminating a field line where it crosses a threshold or
+/// leaves a region.
+///
+/// The criteria are checked after every accepted step, and the field line is
+/// terminated exactly at the crossing point, which is found along the final
+/// step. Tracing can still stop out of bounds first if a trial step near the
+/// boundary leaves the grid. The quantity names `x`, `y` and `z` refer to the
+/// coordinates, and any other name to a scalar quantity that must be cached in
+/// the snapshot before tracing.
+#[derive(Clone, Debug, PartialEq)]
+pub enum FieldLineStoppingCriterion {
+    /// Stop where the given quantity falls below the given value.
+    Below(String, ftr),
+    /// Stop where the given quantity rises above the given value.
+    Above(String, ftr),
+    /// Stop where the field line leaves the box with the given lower and upper bounds.
+    OutsideRegion(Vec3<ftr>, Vec3<ftr>),
 }
 
 /// A basic field line tracer for a 3D vector fields.
@@ -54,6 +138,15 @@ pub struct BasicFieldLineTracer3 {
     config: BasicFieldLineTracerConfig,
 }
 
+/// A basic field line tracer for 2D vector fields.
+///
+/// Stopping criteria are not supported, so the field lines are only limited
+/// by the maximum length and the boundaries of the field.
+#[derive(Clone, Debug)]
+pub struct BasicFieldLineTracer2 {
+    config: BasicFieldLineTracerConfig,
+}
+
 impl BasicFieldLineTracer3 {
     /// Creates a new basic field line tracer.
     pub fn new(config: BasicFieldLineTracerConfig) -> Self {
@@ -74,70 +167,27 @@ impl FieldLineTracer3 for BasicFieldLineTracer3 {
         start_position: &Point3<ftr>,
     ) -> Option<Self::Data> {
         let field = snapshot.cached_vector_field(field_name);
+        let criterion_fields = self.criterion_fields(snapshot);
 
         let mut backward_path = (VecDeque::new(), VecDeque::new(), VecDeque::new());
         let mut backward_length = 0.0;
 
         if let FieldLineTracingSense::Both = self.config.tracing_sense {
-            let tracer_result = if let Some(length) = self.config.max_length {
-                let mut callback =
-                    |_: &Vec3<ftr>, _: &Vec3<ftr>, position: &Point3<ftr>, distance: ftr| {
-                        if distance <= length {
-                            backward_path.0.push_front(position[X]);
-                            backward_path.1.push_front(position[Y]);
-                            backward_path.2.push_front(position[Z]);
-                            backward_length = distance;
-                            StepperInstruction::Continue
-                        } else {
-                            StepperInstruction::Terminate
-                        }
-                    };
-                match self.config.point_spacing {
-                    FieldLinePointSpacing::Regular => tracing::trace_3d_field_line_dense(
-                        field,
-                        interpolator,
-                        stepper.heap_clone(),
-                        start_position,
-                        SteppingSense::Opposite,
-                        &mut callback,
-                    ),
-                    FieldLinePointSpacing::Natural => tracing::trace_3d_field_line(
-                        field,
-                        interpolator,
-                        stepper.heap_clone(),
-                        start_position,
-                        SteppingSense::Opposite,
-                        &mut callback,
-                    ),
-                }
-            } else {
-                let mut callback =
-                    |_: &Vec3<ftr>, _: &Vec3<ftr>, position: &Point3<ftr>, distance: ftr| {
-                        backward_path.0.push_front(position[X]);
-                        backward_path.1.push_front(position[Y]);
-                        backward_path.2.push_front(position[Z]);
-                        backward_length = distance;
-                        StepperInstruction::Continue
-                    };
-                match self.config.point_spacing {
-                    FieldLinePointSpacing::Regular => tracing::trace_3d_field_line_dense(
-                        field,
-                        interpolator,
-                        stepper.heap_clone(),
-                        start_position,
-                        SteppingSense::Opposite,
-                        &mut callback,
-                    ),
-                    FieldLinePointSpacing::Natural => tracing::trace_3d_field_line(
-                        field,
-                        interpolator,
-                        stepper.heap_clone(),
-                        start_position,
-                        SteppingSense::Opposite,
-                        &mut callback,
-                    ),
-                }
-            };
+            let tracer_result = self.trace_in_sense(
+                field,
+                &criterion_fields,
+                interpolator,
+                stepper.heap_clone(),
+                start_position,
+                SteppingSense::Opposite,
+                self.config.point_spacing,
+                &mut |_, position, distance| {
+                    backward_path.0.push_front(position[X]);
+                    backward_path.1.push_front(position[Y]);
+                    backward_path.2.push_front(position[Z]);
+                    backward_length = distance;
+                },
+            );
 
             if let TracerResult::Void = tracer_result {
                 return None;
@@ -157,65 +207,21 @@ impl FieldLineTracer3 for BasicFieldLineTracer3 {
             FieldLineTracingSense::One(sense) => sense,
         };
 
-        let tracer_result = if let Some(length) = self.config.max_length {
-            let mut callback =
-                |_: &Vec3<ftr>, _: &Vec3<ftr>, position: &Point3<ftr>, distance: ftr| {
-                    if distance <= length {
-                        forward_path.0.push(position[X]);
-                        forward_path.1.push(position[Y]);
-                        forward_path.2.push(position[Z]);
-                        forward_length = distance;
-                        StepperInstruction::Continue
-                    } else {
-                        StepperInstruction::Terminate
-                    }
-                };
-            match self.config.point_spacing {
-                FieldLinePointSpacing::Regular => tracing::trace_3d_field_line_dense(
-                    field,
-                    interpolator,
-                    stepper,
-                    start_position,
-                    sense,
-                    &mut callback,
-                ),
-                FieldLinePointSpacing::Natural => tracing::trace_3d_field_line(
-                    field,
-                    interpolator,
-                    stepper,
-                    start_position,
-                    sense,
-                    &mut callback,
-                ),
-            }
-        } else {
-            let mut callback =
-                |_: &Vec3<ftr>, _: &Vec3<ftr>, position: &Point3<ftr>, distance: ftr| {
-                    forward_path.0.push(position[X]);
-                    forward_path.1.push(position[Y]);
-                    forward_path.2.push(position[Z]);
-                    forward_length = distance;
-                    StepperInstruction::Continue
-                };
-            match self.config.point_spacing {
-                FieldLinePointSpacing::Regular => tracing::trace_3d_field_line_dense(
-                    field,
-                    interpolator,
-                    stepper,
-                    start_position,
-                    sense,
-                    &mut callback,
-                ),
-                FieldLinePointSpacing::Natural => tracing::trace_3d_field_line(
-                    field,
-                    interpolator,
-                    stepper,
-                    start_position,
-                    sense,
-                    &mut callback,
-                ),
-            }
-        };
+        let tracer_result = self.trace_in_sense(
+            field,
+            &criterion_fields,
+            interpolator,
+            stepper,
+            start_position,
+            sense,
+            self.config.point_spacing,
+            &mut |_, position, distance| {
+                forward_path.0.push(position[X]);
+                forward_path.1.push(position[Y]);
+                forward_path.2.push(position[Z]);
+                forward_length = distance;
+            },
+        );
 
         if let TracerResult::Void = tracer_result {
             return None;
@@ -241,6 +247,382 @@ impl FieldLineTracer3 for BasicFieldLineTracer3 {
     }
 }
 
+/// Boundary region where an end of a field line is located.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FieldLineEndLocation {
+    /// The field line terminated inside the domain.
+    Inside,
+    /// The field line left through the bottom boundary (upper z-bound).
+    Bottom,
+    /// The field line left through the top boundary (lower z-bound).
+    Top,
+    /// The field line left through a non-periodic horizontal boundary.
+    Side,
+}
+
+/// Connectivity class of a field line traced in connectivity mode, based on
+/// which boundaries its ends are located at and, for open field lines, on the
+/// polarity of the magnetic field at the footpoint in the bottom boundary.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FieldLineConnectivity {
+    /// At least one end terminated inside the domain.
+    Undetermined,
+    /// Both ends are located at the bottom boundary.
+    Closed,
+    /// One end is located at the bottom boundary, where the field points
+    /// into the domain, and the other at the top or a side boundary.
+    OpenPositive,
+    /// One end is located at the bottom boundary, where the field points
+    /// out of the domain, and the other at the top or a side boundary.
+    OpenNegative,
+    /// Both ends are located at the top or side boundaries.
+    Disconnected,
+}
+
+/// One end of a field line traced in connectivity mode.
+#[derive(Clone, Debug)]
+pub struct FieldLineEnd3 {
+    position: Point3<ftr>,
+    length: ftr,
+    location: FieldLineEndLocation,
+    stopping_cause: Option<StoppingCause>,
+}
+
+/// Data required to represent the connectivity of a basic 3D field line.
+pub struct BasicFieldLineConnectivityData3 {
+    start_position: Point3<ftr>,
+    ends: [FieldLineEnd3; 2],
+}
+
+/// A basic field line tracer for 3D vector fields running in connectivity mode.
+///
+/// Instead of the full path, only the two ends of each field line are recorded,
+/// along with where they are located and why tracing stopped. Field lines are
+/// always traced in both directions and no seed is ever discarded, so seeds
+/// from a regular slice seeder produce a complete connectivity map of the slice.
+///
+/// The field lines are output in the same order as the seeds, which for a
+/// regular slice seeder without coordinate bounds is with the first slice
+/// coordinate varying fastest. The 2D map is thus recovered by reshaping any
+/// of the output quantities to the (second, first) shape of the seeder.
+#[derive(Clone, Debug)]
+pub struct BasicFieldLineConnectivityTracer3 {
+    tracer: BasicFieldLineTracer3,
+}
+
+impl FieldLineEnd3 {
+    /// Returns the position of the field line end.
+    pub fn position(&self) -> &Point3<ftr> {
+        &self.position
+    }
+
+    /// Returns the length of the field line from the start position to the end.
+    pub fn length(&self) -> ftr {
+        self.length
+    }
+
+    /// Returns the boundary region where the end is located.
+    pub fn location(&self) -> FieldLineEndLocation {
+        self.location
+    }
+
+    /// Returns the reason tracing stopped at the end, or `None` if the
+    /// field line could not be traced from the start position.
+    pub fn stopping_cause(&self) -> Option<StoppingCause> {
+        self.stopping_cause
+    }
+}
+
+impl BasicFieldLineConnectivityData3 {
+    /// Returns the position where tracing started.
+    pub fn start_position(&self) -> &Point3<ftr> {
+        &self.start_position
+    }
+
+    /// Returns the end reached by tracing opposite to the field direction.
+    pub fn backward_end(&self) -> &FieldLineEnd3 {
+        &self.ends[0]
+    }
+
+    /// Returns the end reached by tracing along the field direction.
+    pub fn forward_end(&self) -> &FieldLineEnd3 {
+        &self.ends[1]
+    }
+
+    /// Returns the connectivity class of the field line.
+    ///
+    /// The backward end is the one where the field points away from the
+    /// boundary, so an open field line with its backward end at the bottom
+    /// is rooted in positive (upward) polarity.
+    pub fn connectivity(&self) -> FieldLineConnectivity {
+        use FieldLineEndLocation::{Bottom, Inside};
+        match (self.ends[0].location, self.ends[1].location) {
+            (Inside, _) | (_, Inside) => FieldLineConnectivity::Undetermined,
+            (Bottom, Bottom) => FieldLineConnectivity::Closed,
+            (Bottom, _) => FieldLineConnectivity::OpenPositive,
+            (_, Bottom) => FieldLineConnectivity::OpenNegative,
+            _ => FieldLineConnectivity::Disconnected,
+        }
+    }
+}
+
+impl BasicFieldLineConnectivityTracer3 {
+    /// Creates a new basic field line tracer in connectivity mode.
+    ///
+    /// The tracing sense and point spacing of the configuration are ignored.
+    pub fn new(config: BasicFieldLineTracerConfig) -> Self {
+        Self {
+            tracer: BasicFieldLineTracer3::new(config),
+        }
+    }
+}
+
+impl BasicFieldLineTracer3 {
+    /// Looks up the cached scalar fields required by the stopping criteria.
+    fn criterion_fields<'a>(
+        &self,
+        snapshot: &'a dyn CachingScalarFieldProvider3<fdt>,
+    ) -> Vec<Option<&'a ScalarField3<fdt>>> {
+        self.config
+            .stopping_criteria
+            .iter()
+            .map(|criterion| {
+                criterion
+                    .required_quantity()
+                    .map(|name| snapshot.cached_scalar_field(name))
+            })
+            .collect()
+    }
+
+    /// Traces the field line from the start position in the given sense,
+    /// calling `add_point` with the tracing direction, position and traced
+    /// distance for each output point.
+    ///
+    /// The tracing terminates at the maximum length or where any of the
+    /// stopping criteria is met.
+    #[allow(clippy::too_many_arguments)]
+    fn trace_in_sense(
+        &self,
+        field: &VectorField3<fdt>,
+        criterion_fields: &[Option<&ScalarField3<fdt>>],
+        interpolator: &dyn Interpolator3<fdt>,
+        stepper: DynStepper3<fdt>,
+        start_position: &Point3<ftr>,
+        sense: SteppingSense,
+        point_spacing: FieldLinePointSpacing,
+        add_point: &mut FieldLinePointCallback,
+    ) -> TracerResult {
+        let max_length = self.config.max_length;
+        let mut met_criterion_idx = None;
+
+        let mut callback = |displacement: &Vec3<ftr>,
+                            direction: &Vec3<ftr>,
+                            position: &Point3<ftr>,
+                            distance: ftr| {
+            if max_length.is_some_and(|max_length| distance > max_length) {
+                return StepperInstruction::Terminate;
+            }
+            // The position is wrapped after crossing a periodic boundary, in
+            // which case the start of the step lies outside the grid
+            let step_wrapped = !field.grid().point_is_inside(&(position - displacement));
+            let crossing = self
+                .config
+                .stopping_criteria
+                .iter()
+                .zip(criterion_fields)
+                .enumerate()
+                .filter_map(|(criterion_idx, (criterion, criterion_field))| {
+                    criterion
+                        .find_crossing(
+                            *criterion_field,
+                            interpolator,
+                            displacement,
+                            position,
+                            step_wrapped,
+                        )
+                        .map(|fraction| (criterion_idx, fraction))
+                })
+                .min_by(|(_, a), (_, b)| a.total_cmp(b));
+
+            if let Some((criterion_idx, fraction)) = crossing {
+                let remaining_fraction = 1.0 - fraction;
+                add_point(
+                    direction,
+                    &(position - displacement * remaining_fraction),
+                    distance - displacement.length() * remaining_fraction,
+                );
+                met_criterion_idx = Some(criterion_idx);
+                StepperInstruction::Terminate
+            } else {
+                add_point(direction, position, distance);
+                StepperInstruction::Continue
+            }
+        };
+
+        let tracer_result = match point_spacing {
+            FieldLinePointSpacing::Regular => tracing::trace_3d_field_line_dense(
+                field,
+                interpolator,
+                stepper,
+                start_position,
+                sense,
+                &mut callback,
+            ),
+            FieldLinePointSpacing::Natural => tracing::trace_3d_field_line(
+                field,
+                interpolator,
+                stepper,
+                start_position,
+                sense,
+                &mut callback,
+            ),
+        };
+
+        match (tracer_result, met_criterion_idx) {
+            (TracerResult::Ok(Some(StoppingCause::StoppedByCallback)), Some(criterion_idx)) => {
+                TracerResult::Ok(Some(StoppingCause::CriterionMet(criterion_idx)))
+            }
+            (tracer_result, _) => tracer_result,
+        }
+    }
+
+    fn trace_end(
+        &self,
+        field: &VectorField3<fdt>,
+        criterion_fields: &[Option<&ScalarField3<fdt>>],
+        interpolator: &dyn Interpolator3<fdt>,
+        stepper: DynStepper3<fdt>,
+        start_position: &Point3<ftr>,
+        sense: SteppingSense,
+    ) -> FieldLineEnd3 {
+        let mut position = start_position.clone();
+        let mut direction = Vec3::zero();
+        let mut length = 0.0;
+
+        let tracer_result = self.trace_in_sense(
+            field,
+            criterion_fields,
+            interpolator,
+            stepper,
+            start_position,
+            sense,
+            FieldLinePointSpacing::Natural,
+            &mut |current_direction, current_position, distance| {
+                position = current_position.clone();
+                direction = current_direction.clone();
+                length = distance;
+            },
+        );
+
+        let stopping_cause = match tracer_result {
+            TracerResult::Ok(stopping_cause) => stopping_cause,
+            TracerResult::Void => None,
+        };
+        let location = if let Some(StoppingCause::OutOfBounds) = stopping_cause {
+            FieldLineEndLocation::of_exit(field.grid(), &position, &direction)
+        } else {
+            FieldLineEndLocation::Inside
+        };
+
+        FieldLineEnd3 {
+            position,
+            length,
+            location,
+            stopping_cause,
+        }
+    }
+}
+
+impl FieldLineTracer3 for BasicFieldLineConnectivityTracer3 {
+    type Data = BasicFieldLineConnectivityData3;
+
+    fn trace(
+        &self,
+        field_name: &str,
+        snapshot: &dyn CachingScalarFieldProvider3<fdt>,
+        interpolator: &dyn Interpolator3<fdt>,
+        stepper: DynStepper3<fdt>,
+        start_position: &Point3<ftr>,
+    ) -> Option<Self::Data> {
+        let field = snapshot.cached_vector_field(field_name);
+        let criterion_fields = self.tracer.criterion_fields(snapshot);
+
+        let backward_end = self.tracer.trace_end(
+            field,
+            &criterion_fields,
+            interpolator,
+            stepper.heap_clone(),
+            start_position,
+            SteppingSense::Opposite,
+        );
+        let forward_end = self.tracer.trace_end(
+            field,
+            &criterion_fields,
+            interpolator,
+            stepper,
+            start_position,
+            SteppingSense::Same,
+        );
+
+        Some(Self::Data {
+            start_position: start_position.clone(),
+            ends: [backward_end, forward_end],
+        })
+    }
+}
+
+impl FromParallelIterator<BasicFieldLineConnectivityData3> for FieldLineSetProperties3 {
+    fn from_par_iter<I>(par_iter: I) -> Self
+    where
+        I: IntoParallelIterator<Item = BasicFieldLineConnectivityData3>,
+    {
+        let field_lines: Vec<_> = par_iter.into_par_iter().collect();
+
+        let number_of_field_lines = field_lines.len();
+        let mut fixed_scalar_values = HashMap::new();
+
+        let mut insert_values =
+            |name: String, value: &(dyn Fn(&BasicFieldLineConnectivityData3) -> ftr + Sync)| {
+                fixed_scalar_values.insert(name, field_lines.par_iter().map(value).collect());
+            };
+
+        insert_values("x0".to_string(), &|field_line| field_line.start_position[X]);
+        insert_values("y0".to_string(), &|field_line| field_line.start_position[Y]);
+        insert_values("z0".to_string(), &|field_line| field_line.start_position[Z]);
+        insert_values("total_length".to_string(), &|field_line| {
+            field_line.ends[0].length + field_line.ends[1].length
+        });
+
+        for (end_idx, end_name) in ["backward", "forward"].into_iter().enumerate() {
+            for dim in Dim3::slice() {
+                insert_values(
+                    format!("{}_{}", ["x", "y", "z"][dim.num()], end_name),
+                    &|field_line| field_line.ends[end_idx].position[dim],
+                );
+            }
+            insert_values(format!("length_{}", end_name), &|field_line| {
+                field_line.ends[end_idx].length
+            });
+            insert_values(format!("location_{}", end_name), &|field_line| {
+                field_line.ends[end_idx].location.code()
+            });
+            insert_values(format!("stopping_cause_{}", end_name), &|field_line| {
+                stopping_cause_code(field_line.ends[end_idx].stopping_cause)
+            });
+        }
+
+        insert_values("connectivity".to_string(), &|field_line| {
+            field_line.connectivity().code()
+        });
+
+        FieldLineSetProperties3 {
+            number_of_field_lines,
+            fixed_scalar_values,
+            ..FieldLineSetProperties3::default()
+        }
+    }
+}
+
 impl FromParallelIterator<BasicFieldLineData3> for FieldLineSetProperties3 {
     fn from_par_iter<I>(par_iter: I) -> Self
     where
@@ -293,6 +675,148 @@ impl FromParallelIterator<BasicFieldLineData3> for FieldLineSetProperties3 {
     }
 }
 
+impl BasicFieldLineTracer2 {
+    /// Creates a new basic 2D field line tracer.
+    pub fn new(config: BasicFieldLineTracerConfig) -> Self {
+        config.validate();
+        assert!(
+            config.stopping_criteria.is_empty(),
+            "Stopping criteria are not supported for 2D field lines."
+        );
+        BasicFieldLineTracer2 { config }
+    }
+
+    /// Traces a field line through a 2D vector field.
+    ///
+    /// # Parameters
+    ///
+    /// - `field`: Vector field to trace.
+    /// - `interpolator`: Interpolator to use.
+    /// - `stepper`: Stepper to use (will be consumed).
+    /// - `start_position`: Position where the tracing should start.
+    ///
+    /// # Returns
+    ///
+    /// An `Option` which is either:
+    ///
+    /// - `Some`: Contains a `BasicFieldLineData2` object representing the traced field line.
+    /// - `None`: No field line was traced.
+    pub fn trace(
+        &self,
+        field: &VectorField2<fdt>,
+        interpolator: &dyn Interpolator2<fdt>,
+        stepper: DynStepper2<fdt>,
+        start_position: &Point2<ftr>,
+    ) -> Option<BasicFieldLineData2> {
+        let mut backward_path = (VecDeque::new(), VecDeque::new());
+        let mut backward_length = 0.0;
+
+        if let FieldLineTracingSense::Both = self.config.tracing_sense {
+            let tracer_result = self.trace_in_sense(
+                field,
+                interpolator,
+                stepper.heap_clone(),
+                start_position,
+                SteppingSense::Opposite,
+                &mut |position, distance| {
+                    backward_path.0.push_front(position[Dim2::X]);
+                    backward_path.1.push_front(position[Dim2::Y]);
+                    backward_length = distance;
+                },
+            );
+
+            if let TracerResult::Void = tracer_result {
+                return None;
+            }
+
+            // Remove start position
+            backward_path.0.pop_back().unwrap();
+            backward_path.1.pop_back().unwrap();
+        }
+
+        let mut forward_path = (Vec::new(), Vec::new());
+        let mut forward_length = 0.0;
+
+        let sense = match self.config.tracing_sense {
+            FieldLineTracingSense::Both => SteppingSense::Same,
+            FieldLineTracingSense::One(sense) => sense,
+        };
+
+        let tracer_result = self.trace_in_sense(
+            field,
+            interpolator,
+            stepper,
+            start_position,
+            sense,
+            &mut |position, distance| {
+                forward_path.0.push(position[Dim2::X]);
+                forward_path.1.push(position[Dim2::Y]);
+                forward_length = distance;
+            },
+        );
+
+        if let TracerResult::Void = tracer_result {
+            return None;
+        }
+
+        let path = if let FieldLineTracingSense::Both = self.config.tracing_sense {
+            let mut path = (Vec::from(backward_path.0), Vec::from(backward_path.1));
+            path.0.extend(forward_path.0);
+            path.1.extend(forward_path.1);
+            path
+        } else {
+            forward_path
+        };
+
+        let total_length = backward_length + forward_length;
+
+        Some(BasicFieldLineData2 { path, total_length })
+    }
+
+    /// Traces the field line from the start position in the given sense,
+    /// calling `add_point` with the position and traced distance for each
+    /// output point.
+    fn trace_in_sense(
+        &self,
+        field: &VectorField2<fdt>,
+        interpolator: &dyn Interpolator2<fdt>,
+        stepper: DynStepper2<fdt>,
+        start_position: &Point2<ftr>,
+        sense: SteppingSense,
+        add_point: &mut dyn FnMut(&Point2<ftr>, ftr),
+    ) -> TracerResult {
+        let max_length = self.config.max_length;
+
+        let mut callback = |_: &Vec2<ftr>, _: &Vec2<ftr>, position: &Point2<ftr>, distance: ftr| {
+            if max_length.is_some_and(|max_length| distance > max_length) {
+                StepperInstruction::Terminate
+            } else {
+                add_point(position, distance);
+                StepperInstruction::Continue
+            }
+        };
+
+        match self.config.point_spacing {
+            FieldLinePointSpacing::Regular => tracing::trace_2d_field_line_dense(
+                field,
+                interpolator,
+                stepper,
+                start_position,
+                sense,
+                &mut callback,
+            ),
+            FieldLinePointSpacing::Natural => tracing::trace_2d_field_line(
+                field,
+                interpolator,
+                stepper,
+                start_position,
+                sense,
+                &mut callback,
+            ),
+        }
+    }
+}
+
 impl FieldLineTracingSense {
     pub fn same() -> Self {
         FieldLineTracingSense::One(SteppingSense::Same)
@@ -302,6 +826,204 @@ impl FieldLineTracingSense {
     }
 }
 
+impl FieldLineEndLocation {
+    /// Returns the numerical code used for the location in output data
+    /// (0: inside, 1: bottom, 2: top, 3: side).
+    pub fn code(self) -> ftr {
+        match self {
+            Self::Inside => 0.0,
+            Self::Bottom => 1.0,
+            Self::Top => 2.0,
+            Self::Side => 3.0,
+        }
+    }
+
+    /// Determines which boundary a field line ending at the given position with
+    /// the given direction left the grid through.
+    ///
+    /// The stepper stops as soon as a trial step leaves the grid, so the end
+    /// position can lie some distance from the boundary. The boundary is taken
+    /// to be the first non-periodic boundary hit when continuing along the final
+    /// direction.
+    fn of_exit<G: Grid3<ftr>>(grid: &G, position: &Point3<ftr>, direction: &Vec3<ftr>) -> Self {
+        let lower_bounds = grid.lower_bounds();
+        let upper_bounds = grid.upper_bounds();
+
+        let mut exit = None;
+        for dim in Dim3::slice() {
+            if grid.is_periodic(dim) || direction[dim] == 0.0 {
+                continue;
+            }
+            let (bound, is_upper) = if direction[dim] > 0.0 {
+                (upper_bounds[dim], true)
+            } else {
+                (lower_bounds[dim], false)
+            };
+            let distance = ((bound - position[dim]) / direction[dim]).max(0.0);
+            if exit.is_none_or(|(min_distance, _, _)| distance < min_distance) {
+                exit = Some((distance, dim, is_upper));
+            }
+        }
+
+        match exit {
+            Some((_, Z, true)) => Self::Bottom,
+            Some((_, Z, false)) => Self::Top,
+            Some(_) => Self::Side,
+            None => Self::Inside,
+        }
+    }
+}
+
+impl FieldLineConnectivity {
+    /// Returns the numerical code used for the connectivity class in output data
+    /// (0: undetermined, 1: closed, 2: open from positive polarity, 3: open from
+    /// negative polarity, 4: disconnected from the bottom).
+    pub fn code(self) -> ftr {
+        match self {
+            Self::Undetermined => 0.0,
+            Self::Closed => 1.0,
+            Self::OpenPositive => 2.0,
+            Self::OpenNegative => 3.0,
+            Self::Disconnected => 4.0,
+        }
+    }
+}
+
+fn stopping_cause_code(stopping_cause: Option<StoppingCause>) -> ftr {
+    match stopping_cause {
+        None => 0.0,
+        Some(StoppingCause::Null) => 1.0,
+        Some(StoppingCause::Sink) => 2.0,
+        Some(StoppingCause::OutOfBounds) => 3.0,
+        Some(StoppingCause::TooManyAttempts) => 4.0,
+        Some(StoppingCause::StoppedByCallback) => 5.0,
+        Some(StoppingCause::CriterionMet(criterion_idx)) => 6.0 + criterion_idx as ftr,
+    }
+}
+
+impl FieldLineStoppingCriterion {
+    /// Number of bisection iterations used to locate threshold crossings of
+    /// interpolated quantities.
+    const CROSSING_BISECTION_ITERATIONS: usize = 40;
+
+    /// Returns the name of the scalar quantity that must be cached for
+    /// evaluating the criterion, if any.
+    pub fn required_quantity(&self) -> Option<&str> {
+        match self {
+            Self::Below(name, _) | Self::Above(name, _) if coordinate_dim(name).is_none() => {
+                Some(name)
+            }
+            _ => None,
+        }
+    }
+
+    fn is_met(
+        &self,
+        field: Option<&ScalarField3<fdt>>,
+        interpolator: &dyn Interpolator3<fdt>,
+        position: &Point3<ftr>,
+    ) -> bool {
+        match self {
+            Self::Below(name, threshold) => {
+                Self::evaluate(name, field, interpolator, position) < *threshold
+            }
+            Self::Above(name, threshold) => {
+                Self::evaluate(name, field, interpolator, position) > *threshold
+            }
+            Self::OutsideRegion(lower_bounds, upper_bounds) => Dim3::slice()
+                .into_iter()
+                .any(|dim| position[dim] < lower_bounds[dim] || position[dim] > upper_bounds[dim]),
+        }
+    }
+
+    fn evaluate(
+        name: &str,
+        field: Option<&ScalarField3<fdt>>,
+        interpolator: &dyn Interpolator3<fdt>,
+        position: &Point3<ftr>,
+    ) -> ftr {
+        match (coordinate_dim(name), field) {
+            (Some(dim), _) => position[dim],
+            (None, Some(field)) => interpolator
+                .interp_scalar_field(field, position)
+                .inside_or_moved_or_default(ftr::NAN),
+            (None, None) => panic!("No field provided for stopping criterion quantity {}", name),
+        }
+    }
+
+    /// Determines whether the criterion is met at the given position, and if so,
+    /// returns the fraction along the step with the given displacement where it
+    /// first became met.
+    ///
+    /// If the step wrapped across a periodic boundary, the start of the step is
+    /// not a valid position, so the criterion is taken to be met at the end of
+    /// the step.
+    fn find_crossing(
+        &self,
+        field: Option<&ScalarField3<fdt>>,
+        interpolator: &dyn Interpolator3<fdt>,
+        displacement: &Vec3<ftr>,
+        position: &Point3<ftr>,
+        step_wrapped: bool,
+    ) -> Option<ftr> {
+        if !self.is_met(field, interpolator, position) {
+            return None;
+        }
+        if step_wrapped {
+            return Some(1.0);
+        }
+        let previous_position = position - displacement;
+        if self.is_met(field, interpolator, &previous_position) {
+            return Some(0.0);
+        }
+        let fraction = match self {
+            Self::OutsideRegion(lower_bounds, upper_bounds) => Dim3::slice()
+                .into_iter()
+                .filter_map(|dim| {
+                    if position[dim] > upper_bounds[dim] {
+                        Some((upper_bounds[dim] - previous_position[dim]) / displacement[dim])
+                    } else if position[dim] < lower_bounds[dim] {
+                        Some((lower_bounds[dim] - previous_position[dim]) / displacement[dim])
+                    } else {
+                        None
+                    }
+                })
+                .fold(1.0, ftr::min),
+            Self::Below(name, threshold) | Self::Above(name, threshold) => {
+                if let Some(dim) = coordinate_dim(name) {
+                    (threshold - previous_position[dim]) / displacement[dim]
+                } else {
+                    let mut lower_fraction = 0.0;
+                    let mut upper_fraction = 1.0;
+                    for _ in 0..Self::CROSSING_BISECTION_ITERATIONS {
+                        let fraction = 0.5 * (lower_fraction + upper_fraction);
+                        if self.is_met(
+                            field,
+                            interpolator,
+                            &(&previous_position + &(displacement * fraction)),
+                        ) {
+                            upper_fraction = fraction;
+                        } else {
+                            lower_fraction = fraction;
+                        }
+                    }
+                    upper_fraction
+                }
+            }
+        };
+        Some(fraction.clamp(0.0, 1.0))
+    }
+}
+
+fn coordinate_dim(name: &str) -> Option<Dim3> {
+    match name {
+        "x" => Some(X),
+        "y" => Some(Y),
+        "z" => Some(Z),
+        _ => None,
+    }
+}
+
 impl BasicFieldLineTracerConfig {
     pub const DEFAULT_TRACING_SENSE: FieldLineTracingSense = FieldLineTracingSense::Both;
     pub const DEFAULT_POINT_SPACING: FieldLinePointSpacing = FieldLinePointSpacing::Regular;
@@ -314,6 +1036,17 @@ impl BasicFieldLineTracerConfig {
                 "Maximum field line length must be non-negative."
             );
         }
+        for criterion in &self.stopping_criteria {
+            if let FieldLineStoppingCriterion::OutsideRegion(lower_bounds, upper_bounds) = criterion
+            {
+                assert!(
+                    Dim3::slice()
+                        .into_iter()
+                        .all(|dim| lower_bounds[dim] <= upper_bounds[dim]),
+                    "Lower bounds of stopping region must not exceed upper bounds."
+                );
+            }
+        }
     }
 }
 
@@ -323,6 +1056,342 @@ impl Default for BasicFieldLineTracerConfig {
             tracing_sense: Self::DEFAULT_TRACING_SENSE,
             point_spacing: Self::DEFAULT_POINT_SPACING,
             max_length: Self::DEFAULT_MAX_LENGTH,
+            stopping_criteria: Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{
+        field::{
+            CustomScalarFieldGenerator3, FieldGrid3, ScalarFieldCacher3, ScalarFieldProvider3,
+        },
+        geometry::{Dim2, Idx2, In2D, In3D},
+        grid::{fgr, regular::RegularGrid3, Grid2},
+        interpolation::poly_fit::{
+            PolyFitInterpolator2, PolyFitInterpolator3, PolyFitInterpolatorConfig,
+        },
+        io::Verbosity,
+        seeding::{slice::SliceSeeder3, Seeder3},
+        tracing::{
+            field_line::FieldLineSet3,
+            stepping::{
+                rkf::{
+                    rkf45::{RKF45Stepper2, RKF45Stepper3},
+                    RKFStepperConfig,
+                },
+                DynStepper2,
+            },
+        },
+    };
+    use std::sync::Arc;
+
+    #[test]
+    fn connectivity_of_inclined_field_is_classified() {
+        let grid = Arc::new(FieldGrid3::from(RegularGrid3::from_bounds(
+            In3D::new(16, 8, 8),
+            Vec3::new(-2.0, -0.5, 0.0),
+            Vec3::new(2.0, 0.5, 1.0),
+            In3D::new(false, true, false),
+        )));
+        let generator = CustomScalarFieldGenerator3::<fdt>::new(grid, Verbosity::Quiet)
+            .with_variable("bx".to_string(), Box::new(|_, _, _: fgr| 2.0))
+            .with_variable("by".to_string(), Box::new(|_, _, _| 0.0))
+            .with_variable("bz".to_string(), Box::new(|_, _, _| 1.0));
+        let mut snapshot =
+            ScalarFieldCacher3::new_manual_cacher(Box::new(generator), Verbosity::Quiet);
+        snapshot.cache_vector_field("b").unwrap();
+        let interpolator = PolyFitInterpolator3::new(PolyFitInterpolatorConfig {
+            order: 1,
+            ..PolyFitInterpolatorConfig::default()
+        });
+        let stepper: DynStepper3<fdt> = Box::new(RKF45Stepper3::new(RKFStepperConfig::default()));
+
+        let trace = |start_position: Point3<ftr>, max_length: Option<ftr>| {
+            BasicFieldLineConnectivityTracer3::new(BasicFieldLineTracerConfig {
+                max_length,
+                ..BasicFieldLineTracerConfig::default()
+            })
+            .trace(
+                "b",
+                &snapshot,
+                &interpolator,
+                stepper.heap_clone(),
+                &start_position,
+            )
+            .unwrap()
+        };
+
+        // Leaves through the bottom when traced along the field and through
+        // the top when traced opposite to it
+        let field_line = trace(Point3::new(0.0, 0.0, 0.5), None);
+        assert_eq!(
+            field_line.forward_end().location(),
+            FieldLineEndLocation::Bottom
+        );
+        assert_eq!(
+            field_line.backward_end().location(),
+            FieldLineEndLocation::Top
+        );
+        assert_eq!(
+            field_line.forward_end().stopping_cause(),
+            Some(StoppingCause::OutOfBounds)
+        );
+        assert_eq!(
+            field_line.connectivity(),
+            FieldLineConnectivity::OpenNegative
+        );
+
+        // Leaves through the side boundary before reaching the bottom
+        let field_line = trace(Point3::new(1.5, 0.0, 0.5), None);
+        assert_eq!(
+            field_line.forward_end().location(),
+            FieldLineEndLocation::Side
+        );
+        assert_eq!(
+            field_line.connectivity(),
+            FieldLineConnectivity::Disconnected
+        );
+
+        // Stops inside the domain when reaching the maximum length
+        let field_line = trace(Point3::new(0.0, 0.0, 0.5), Some(0.1));
+        assert_eq!(
+            field_line.forward_end().location(),
+            FieldLineEndLocation::Inside
+        );
+        assert_eq!(
+            field_line.backward_end().stopping_cause(),
+            Some(StoppingCause::StoppedByCallback)
+        );
+        assert!(field_line.forward_end().length() <= 0.1);
+        assert_eq!(
+            field_line.connectivity(),
+            FieldLineConnectivity::Undetermined
+        );
+    }
+
+    #[test]
+    fn connectivity_map_is_arranged_on_seed_grid() {
+        let grid = Arc::new(FieldGrid3::from(RegularGrid3::from_bounds(
+            In3D::new(16, 8, 8),
+            Vec3::new(-2.0, -0.5, 0.0),
+            Vec3::new(2.0, 0.5, 1.0),
+            In3D::new(false, true, false),
+        )));
+        let generator = CustomScalarFieldGenerator3::<fdt>::new(grid.clone(), Verbosity::Quiet)
+            .with_variable("bx".to_string(), Box::new(|_, _, _: fgr| 2.0))
+            .with_variable("by".to_string(), Box::new(|_, _, _| 0.0))
+            .with_variable("bz".to_string(), Box::new(|_, _, _| 1.0));
+        let mut snapshot =
+            ScalarFieldCacher3::new_manual_cacher(Box::new(generator), Verbosity::Quiet);
+        snapshot.cache_vector_field("b").unwrap();
+        let interpolator = PolyFitInterpolator3::new(PolyFitInterpolatorConfig {
+            order: 1,
+            ..PolyFitInterpolatorConfig::default()
+        });
+        let stepper: DynStepper3<fdt> = Box::new(RKF45Stepper3::new(RKFStepperConfig::default()));
+
+        let seeder = SliceSeeder3::regular(&grid, Dim3::Y, 0.0, In2D::new(8, 5), &|_| true);
+        let field_lines = FieldLineSet3::trace(
+            "b",
+            &snapshot,
+            &seeder,
+            &BasicFieldLineConnectivityTracer3::new(BasicFieldLineTracerConfig::default()),
+            &interpolator,
+            stepper,
+            Verbosity::Quiet,
+        );
+        let seed_grid = seeder.seed_grid().unwrap();
+        let map = field_lines.connectivity_map(seed_grid).unwrap();
+
+        // Traced along the field, a field line starting at (x, z) reaches the
+        // bottom boundary at x + 2(1 - z), which is inside the domain if x < 2z
+        let centers = seed_grid.grid().centers();
+        for i in 0..8 {
+            for j in 0..5 {
+                let (x, z) = (centers[Dim2::X][i], centers[Dim2::Y][j]);
+                let expected = if x < 2.0 * z {
+                    FieldLineConnectivity::OpenNegative
+                } else {
+                    FieldLineConnectivity::Disconnected
+                };
+                assert_eq!(map.value(&Idx2::new(i, j)), expected.code() as fdt);
+            }
         }
+
+        // Seed points rejected by constraints leave no grid to map onto
+        let seeder = SliceSeeder3::regular(&grid, Dim3::Y, 0.0, In2D::new(8, 5), &|point| {
+            point[Dim2::X] < 0.0
+        });
+        assert!(seeder.seed_grid().is_none());
+    }
+
+    #[test]
+    fn stopping_criteria_terminate_at_crossing() {
+        let grid = Arc::new(FieldGrid3::from(RegularGrid3::from_bounds(
+            In3D::new(8, 8, 16),
+            Vec3::new(-0.5, -0.5, 0.0),
+            Vec3::new(0.5, 0.5, 1.0),
+            In3D::new(true, true, false),
+        )));
+        let generator = CustomScalarFieldGenerator3::<fdt>::new(grid, Verbosity::Quiet)
+            .with_variable("bx".to_string(), Box::new(|_, _, _: fgr| 0.0))
+            .with_variable("by".to_string(), Box::new(|_, _, _| 0.0))
+            .with_variable("bz".to_string(), Box::new(|_, _, _| 1.0))
+            .with_variable("tg".to_string(), Box::new(|_, _, z| (1e4 + 1e5 * z) as fdt));
+        let mut snapshot =
+            ScalarFieldCacher3::new_manual_cacher(Box::new(generator), Verbosity::Quiet);
+        snapshot.cache_vector_field("b").unwrap();
+        snapshot.cache_scalar_field("tg").unwrap();
+        let interpolator = PolyFitInterpolator3::new(PolyFitInterpolatorConfig {
+            order: 1,
+            ..PolyFitInterpolatorConfig::default()
+        });
+        // Limit step growth so that no trial step leaves the grid before the
+        // criteria are met
+        let stepper: DynStepper3<fdt> = Box::new(RKF45Stepper3::new(RKFStepperConfig {
+            max_step_scale: 1.5,
+            ..RKFStepperConfig::default()
+        }));
+
+        let config = BasicFieldLineTracerConfig {
+            stopping_criteria: vec![
+                FieldLineStoppingCriterion::Below("tg".to_string(), 5e4),
+                FieldLineStoppingCriterion::OutsideRegion(
+                    Vec3::new(-1.0, -1.0, 0.0),
+                    Vec3::new(1.0, 1.0, 0.9),
+                ),
+            ],
+            ..BasicFieldLineTracerConfig::default()
+        };
+
+        let field_line = BasicFieldLineConnectivityTracer3::new(config.clone())
+            .trace(
+                "b",
+                &snapshot,
+                &interpolator,
+                stepper.heap_clone(),
+                &Point3::new(0.0, 0.0, 0.8),
+            )
+            .unwrap();
+        assert_eq!(
+            field_line.backward_end().stopping_cause(),
+            Some(StoppingCause::CriterionMet(0))
+        );
+        assert!((field_line.backward_end().position()[Z] - 0.4).abs() < 1e-6);
+        assert!((field_line.backward_end().length() - 0.4).abs() < 1e-6);
+        assert_eq!(
+            field_line.forward_end().stopping_cause(),
+            Some(StoppingCause::CriterionMet(1))
+        );
+        assert!((field_line.forward_end().position()[Z] - 0.9).abs() < 1e-9);
+
+        let field_line = BasicFieldLineTracer3::new(config)
+            .trace(
+                "b",
+                &snapshot,
+                &interpolator,
+                stepper,
+                &Point3::new(0.0, 0.0, 0.8),
+            )
+            .unwrap();
+        let last_idx = field_line.number_of_points() - 1;
+        assert!((field_line.point(0)[Z] - 0.4).abs() < 1e-6);
+        assert!((field_line.point(last_idx)[Z] - 0.9).abs() < 1e-9);
+        assert!((field_line.total_length() - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn stopping_criteria_ignore_start_of_wrapped_steps() {
+        let grid = Arc::new(FieldGrid3::from(RegularGrid3::from_bounds(
+            In3D::new(16, 8, 8),
+            Vec3::new(0.0, -0.5, 0.0),
+            Vec3::new(1.0, 0.5, 1.0),
+            In3D::new(true, true, false),
+        )));
+        let generator = CustomScalarFieldGenerator3::<fdt>::new(grid, Verbosity::Quiet)
+            .with_variable("bx".to_string(), Box::new(|_, _, _: fgr| 1.0))
+            .with_variable("by".to_string(), Box::new(|_, _, _| 0.0))
+            .with_variable("bz".to_string(), Box::new(|_, _, _| 0.0));
+        let mut snapshot =
+            ScalarFieldCacher3::new_manual_cacher(Box::new(generator), Verbosity::Quiet);
+        snapshot.cache_vector_field("b").unwrap();
+        let interpolator = PolyFitInterpolator3::new(PolyFitInterpolatorConfig {
+            order: 1,
+            ..PolyFitInterpolatorConfig::default()
+        });
+        // Limit step growth so that the field line does not skip past the
+        // region where the criterion is met
+        let stepper: DynStepper3<fdt> = Box::new(RKF45Stepper3::new(RKFStepperConfig {
+            initial_step_length: 1e-2,
+            max_step_scale: 1.1,
+            ..RKFStepperConfig::default()
+        }));
+
+        // The criterion is met directly after wrapping across the periodic
+        // boundary in x, where the start of the step lies outside the grid
+        let field_line = BasicFieldLineConnectivityTracer3::new(BasicFieldLineTracerConfig {
+            stopping_criteria: vec![FieldLineStoppingCriterion::Below("x".to_string(), 0.3)],
+            ..BasicFieldLineTracerConfig::default()
+        })
+        .trace(
+            "b",
+            &snapshot,
+            &interpolator,
+            stepper,
+            &Point3::new(0.5, 0.0, 0.5),
+        )
+        .unwrap();
+        let end = field_line.forward_end();
+        assert_eq!(end.stopping_cause(), Some(StoppingCause::CriterionMet(0)));
+        assert!(end.position()[X] >= 0.0 && end.position()[X] < 0.3);
+        assert!(end.length() >= 0.5);
+    }
+
+    #[test]
+    fn tracing_in_layer_follows_in_plane_field() {
+        let grid = Arc::new(FieldGrid3::from(RegularGrid3::from_bounds(
+            In3D::new(16, 1, 16),
+            Vec3::new(-1.0, -0.5, 0.0),
+            Vec3::new(1.0, 0.5, 1.0),
+            In3D::new(false, true, false),
+        )));
+        let mut generator = CustomScalarFieldGenerator3::<fdt>::new(grid, Verbosity::Quiet)
+            .with_variable("bx".to_string(), Box::new(|_, _, _: fgr| 1.0))
+            .with_variable("by".to_string(), Box::new(|_, _, _| 5.0))
+            .with_variable("bz".to_string(), Box::new(|_, _, _| 1.0));
+        let field = generator
+            .provide_vector_field("b")
+            .unwrap()
+            .layer_across_axis(Y, 0)
+            .in_plane_vector_field(Y);
+        let interpolator = PolyFitInterpolator2::new(PolyFitInterpolatorConfig {
+            order: 1,
+            ..PolyFitInterpolatorConfig::default()
+        });
+        // Limit step growth so that trial steps do not leave the grid far
+        // from the boundaries
+        let stepper: DynStepper2<fdt> = Box::new(RKF45Stepper2::new(RKFStepperConfig {
+            max_step_scale: 1.5,
+            ..RKFStepperConfig::default()
+        }));
+
+        let field_line = BasicFieldLineTracer2::new(BasicFieldLineTracerConfig::default())
+            .trace(&field, &interpolator, stepper, &Point2::new(0.0, 0.3))
+            .unwrap();
+
+        // The out-of-plane component is ignored, so the field line is a
+        // straight diagonal line through the plane
+        for idx in 0..field_line.number_of_points() {
+            let point = field_line.point(idx);
+            assert!((point[Dim2::Y] - point[Dim2::X] - 0.3).abs() < 1e-6);
+        }
+        assert!(field_line.total_length() > 0.5 && field_line.total_length() < ftr::sqrt(2.0));
+
+        let field_line = field_line.embedded_in_plane(Y, 0.0);
+        assert!((0..field_line.number_of_points()).all(|idx| field_line.point(idx)[Y] == 0.0));
     }
 }
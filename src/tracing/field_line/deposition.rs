@@ -0,0 +1,424 @@
+//! Deposition of field line properties onto a grid.
+
+use super::{unwrap_path_positions, FieldLineSet3, VaryingScalarValues};
+use crate::{
+    field::{DynScalarFieldProvider3, FieldGrid3, ScalarField3, ScalarFieldProvider3},
+    geometry::{
+        Dim3::{X, Y, Z},
+        Idx3, In3D, Point3, Vec3,
+    },
+    grid::{CoordLocation, Grid3, GridPointQuery3},
+    io::{snapshot::fdt, Verbosity},
+    tracing::ftr,
+};
+use ndarray::{Array, ShapeBuilder};
+use rayon::prelude::*;
+use std::{collections::HashMap, io, sync::Arc};
+
+/// Largest length of the path pieces used for assigning field line paths to
+/// grid cells, as a fraction of the smallest grid cell extent.
+const MAX_PIECE_LENGTH_FRACTION: ftr = 0.25;
+
+/// Grid quantity obtained by depositing field lines onto a grid.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FieldLineDepositionQuantity {
+    /// Number of distinct field lines passing through each grid cell.
+    Count,
+    /// Total length of the field line paths inside each grid cell.
+    Length,
+    /// Average of the given varying scalar quantity inside each grid cell,
+    /// weighted by path length.
+    Average(String),
+}
+
+impl FieldLineDepositionQuantity {
+    /// Returns the deposition quantity with the given variable name, or `None`
+    /// if the name does not correspond to a deposition quantity.
+    ///
+    /// The valid names are `fl_count`, `fl_length` and `fl_avg_<name>`.
+    pub fn from_variable_name(variable_name: &str) -> Option<Self> {
+        match variable_name {
+            "fl_count" => Some(Self::Count),
+            "fl_length" => Some(Self::Length),
+            _ => variable_name
+                .strip_prefix("fl_avg_")
+                .filter(|name| !name.is_empty())
+                .map(|name| Self::Average(name.to_string())),
+        }
+    }
+
+    /// Returns the variable name of the deposition quantity.
+    pub fn variable_name(&self) -> String {
+        match self {
+            Self::Count => "fl_count".to_string(),
+            Self::Length => "fl_length".to_string(),
+            Self::Average(name) => format!("fl_avg_{}", name),
+        }
+    }
+}
+
+impl FieldLineSet3 {
+    /// Deposits the field lines onto the given grid, producing the given
+    /// deposition quantity as a cell-centered scalar field.
+    pub fn deposit_onto_grid(
+        &self,
+        grid: Arc<FieldGrid3>,
+        quantity: &FieldLineDepositionQuantity,
+    ) -> io::Result<ScalarField3<fdt>> {
+        deposit_field_lines_onto_grid(
+            grid,
+            &self.properties.varying_scalar_values,
+            &self.domain_extents(),
+            quantity,
+            &self.verbosity,
+        )
+    }
+
+    /// Whether the given deposition quantity can be computed from the field lines.
+    pub fn supports_deposition_quantity(&self, quantity: &FieldLineDepositionQuantity) -> bool {
+        self.has_paths()
+            && match quantity {
+                FieldLineDepositionQuantity::Average(name) => self
+                    .properties
+                    .varying_scalar_values
+                    .contains_key(name.as_str()),
+                _ => true,
+            }
+    }
+}
+
+/// Deposits the field lines with the given varying scalar values onto the given
+/// grid, producing the given deposition quantity as a cell-centered scalar field.
+///
+/// The paths are first unwrapped across the periodic boundaries of the domain
+/// with the given extents, so that segments crossing a periodic boundary are
+/// deposited along the actual step rather than across the domain. Each path segment is split into pieces much shorter than the grid cells, and
+/// each piece is assigned to the grid cell containing its midpoint. Parts of
+/// the paths outside of non-periodic grid boundaries are ignored. Averages are
+/// NaN in cells that no field line passes through.
+pub fn deposit_field_lines_onto_grid(
+    grid: Arc<FieldGrid3>,
+    varying_scalar_values: &VaryingScalarValues,
+    domain_extents: &Vec3<ftr>,
+    quantity: &FieldLineDepositionQuantity,
+    verbosity: &Verbosity,
+) -> io::Result<ScalarField3<fdt>> {
+    let coordinate_values = |name: &str| {
+        varying_scalar_values.get(name).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Field line paths are required for deposition onto a grid",
+            )
+        })
+    };
+    let coords_x = coordinate_values("x")?;
+    let coords_y = coordinate_values("y")?;
+    let coords_z = coordinate_values("z")?;
+
+    let averaged_values = match quantity {
+        FieldLineDepositionQuantity::Average(name) => {
+            Some(varying_scalar_values.get(name.as_str()).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("Quantity {} not present along field lines", name),
+                )
+            })?)
+        }
+        _ => None,
+    };
+
+    if verbosity.print_messages() {
+        println!("Depositing {} onto grid", quantity.variable_name());
+    }
+
+    let max_piece_length = MAX_PIECE_LENGTH_FRACTION * smallest_grid_cell_extent(&grid);
+    let progress_bar = verbosity.create_progress_bar(coords_x.len());
+
+    // Maps each flat grid cell index to the number of field lines, the path
+    // length and the length-weighted sum of values in the cell
+    let cell_contributions = (0..coords_x.len())
+        .into_par_iter()
+        .map(|field_line_idx| {
+            let positions = unwrap_path_positions(
+                &coords_x[field_line_idx],
+                &coords_y[field_line_idx],
+                &coords_z[field_line_idx],
+                domain_extents,
+            );
+            let contributions = compute_field_line_contributions(
+                &grid,
+                max_piece_length,
+                &positions,
+                averaged_values.map(|values| values[field_line_idx].as_slice()),
+            );
+            progress_bar.inc();
+            contributions
+        })
+        .reduce(HashMap::new, |mut accumulated, contributions| {
+            for (flat_idx, (count, length, weighted_sum)) in contributions {
+                let entry = accumulated.entry(flat_idx).or_insert((0, 0.0, 0.0));
+                entry.0 += count;
+                entry.1 += length;
+                entry.2 += weighted_sum;
+            }
+            accumulated
+        });
+
+    let shape = grid.shape();
+    let empty_value = match quantity {
+        FieldLineDepositionQuantity::Average(_) => fdt::NAN,
+        _ => 0.0,
+    };
+    let mut values = vec![empty_value; shape[X] * shape[Y] * shape[Z]];
+    for (flat_idx, (count, length, weighted_sum)) in cell_contributions {
+        values[flat_idx] = match quantity {
+            FieldLineDepositionQuantity::Count => count as fdt,
+            FieldLineDepositionQuantity::Length => length as fdt,
+            FieldLineDepositionQuantity::Average(_) => {
+                if length > 0.0 {
+                    (weighted_sum / length) as fdt
+                } else {
+                    fdt::NAN
+                }
+            }
+        };
+    }
+    let values = Array::from_shape_vec((shape[X], shape[Y], shape[Z]).f(), values)
+        .expect("Shape of grid does not match number of values");
+
+    Ok(ScalarField3::new(
+        quantity.variable_name(),
+        grid,
+        In3D::same(CoordLocation::Center),
+        values,
+    ))
+}
+
+/// Provider of scalar fields that adds quantities deposited from a set of field
+/// lines to the quantities of an underlying provider.
+pub struct FieldLineDepositionProvider3 {
+    provider: DynScalarFieldProvider3<fdt>,
+    field_lines: FieldLineSet3,
+    all_variable_names: Vec<String>,
+}
+
+impl FieldLineDepositionProvider3 {
+    /// Creates a provider of deposition quantities for the given field lines.
+    ///
+    /// The given deposition quantity names are included in the list of all
+    /// variable names if they are available.
+    pub fn new(
+        provider: DynScalarFieldProvider3<fdt>,
+        field_lines: FieldLineSet3,
+        quantity_names: Vec<String>,
+    ) -> Self {
+        let mut all_variable_names = provider.all_variable_names().to_vec();
+        all_variable_names.extend(quantity_names.into_iter().filter(|name| {
+            FieldLineDepositionQuantity::from_variable_name(name)
+                .is_some_and(|quantity| field_lines.supports_deposition_quantity(&quantity))
+        }));
+        Self {
+            provider,
+            field_lines,
+            all_variable_names,
+        }
+    }
+
+    fn deposition_quantity(&self, variable_name: &str) -> io::Result<FieldLineDepositionQuantity> {
+        FieldLineDepositionQuantity::from_variable_name(variable_name).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("Quantity {} not available", variable_name),
+            )
+        })
+    }
+}
+
+impl ScalarFieldProvider3<fdt> for FieldLineDepositionProvider3 {
+    fn grid(&self) -> &FieldGrid3 {
+        self.provider.grid()
+    }
+
+    fn arc_with_grid(&self) -> Arc<FieldGrid3> {
+        self.provider.arc_with_grid()
+    }
+
+    fn all_variable_names(&self) -> &[String] {
+        &self.all_variable_names
+    }
+
+    fn has_variable(&self, variable_name: &str) -> bool {
+        self.provider.has_variable(variable_name)
+            || FieldLineDepositionQuantity::from_variable_name(variable_name)
+                .is_some_and(|quantity| self.field_lines.supports_deposition_quantity(&quantity))
+    }
+
+    fn produce_scalar_field(&mut self, variable_name: &str) -> io::Result<ScalarField3<fdt>> {
+        if self.provider.has_variable(variable_name) {
+            self.provider.produce_scalar_field(variable_name)
+        } else {
+            let quantity = self.deposition_quantity(variable_name)?;
+            self.field_lines
+                .deposit_onto_grid(self.arc_with_grid(), &quantity)
+        }
+    }
+
+    fn produce_scalar_subfield(
+        &mut self,
+        variable_name: &str,
+        subgrid: Arc<FieldGrid3>,
+        start_indices: &Idx3<usize>,
+    ) -> io::Result<ScalarField3<fdt>> {
+        if self.provider.has_variable(variable_name) {
+            self.provider
+                .produce_scalar_subfield(variable_name, subgrid, start_indices)
+        } else {
+            let quantity = self.deposition_quantity(variable_name)?;
+            self.field_lines.deposit_onto_grid(subgrid, &quantity)
+        }
+    }
+}
+
+fn smallest_grid_cell_extent(grid: &FieldGrid3) -> ftr {
+    let lower_edges = grid.lower_edges();
+    let upper_bounds = grid.upper_bounds();
+    [X, Y, Z]
+        .into_iter()
+        .flat_map(|dim| {
+            let edges = &lower_edges[dim];
+            edges
+                .windows(2)
+                .map(|edges| edges[1] - edges[0])
+                .chain(std::iter::once(upper_bounds[dim] - edges[edges.len() - 1]))
+                .collect::<Vec<_>>()
+        })
+        .fold(ftr::INFINITY, ftr::min)
+}
+
+fn compute_field_line_contributions(
+    grid: &FieldGrid3,
+    max_piece_length: ftr,
+    positions: &[Vec3<ftr>],
+    values: Option<&[ftr]>,
+) -> HashMap<usize, (usize, ftr, ftr)> {
+    let shape = grid.shape();
+    let mut contributions = HashMap::new();
+
+    for idx in 1..positions.len() {
+        let start = &positions[idx - 1];
+        let end = &positions[idx];
+        let segment_length = (end - start).length();
+        if segment_length <= 0.0 || segment_length.is_nan() {
+            continue;
+        }
+        let number_of_pieces = (segment_length / max_piece_length).ceil().max(1.0) as usize;
+        let piece_length = segment_length / number_of_pieces as ftr;
+
+        for piece_idx in 0..number_of_pieces {
+            let fraction = (piece_idx as ftr + 0.5) / number_of_pieces as ftr;
+            let midpoint = Point3::new(
+                start[X] + fraction * (end[X] - start[X]),
+                start[Y] + fraction * (end[Y] - start[Y]),
+                start[Z] + fraction * (end[Z] - start[Z]),
+            );
+            let indices = match grid.find_grid_cell(&midpoint) {
+                GridPointQuery3::Inside(indices) => indices,
+                GridPointQuery3::MovedInside((indices, _)) => indices,
+                GridPointQuery3::Outside => continue,
+            };
+            let flat_idx = indices[X] + shape[X] * (indices[Y] + shape[Y] * indices[Z]);
+            let value = values.map_or(0.0, |values| {
+                values[idx - 1] + fraction * (values[idx] - values[idx - 1])
+            });
+
+            let entry = contributions.entry(flat_idx).or_insert((1, 0.0, 0.0));
+            entry.1 += piece_length;
+            entry.2 += value * piece_length;
+        }
+    }
+    contributions
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::grid::regular::RegularGrid3;
+
+    #[test]
+    fn straight_field_lines_are_deposited_in_crossed_cells() {
+        let grid = Arc::new(FieldGrid3::from(RegularGrid3::from_bounds(
+            In3D::new(4, 4, 4),
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(4.0, 4.0, 4.0),
+            In3D::same(false),
+        )));
+
+        // Two field lines along z through the same column, one with a
+        // constant value of 1 and one with a value of 3
+        let mut varying_scalar_values = VaryingScalarValues::new();
+        varying_scalar_values.insert("x".to_string(), vec![vec![0.5; 3], vec![0.6; 3]]);
+        varying_scalar_values.insert("y".to_string(), vec![vec![1.5; 3], vec![1.5; 3]]);
+        varying_scalar_values.insert(
+            "z".to_string(),
+            vec![vec![0.0, 2.0, 4.0], vec![0.0, 1.5, 3.0]],
+        );
+        varying_scalar_values.insert("v".to_string(), vec![vec![1.0; 3], vec![3.0; 3]]);
+
+        let deposit = |quantity| {
+            deposit_field_lines_onto_grid(
+                grid.clone(),
+                &varying_scalar_values,
+                &Vec3::new(4.0, 4.0, 4.0),
+                &quantity,
+                &Verbosity::Quiet,
+            )
+            .unwrap()
+        };
+        let count = deposit(FieldLineDepositionQuantity::Count);
+        let length = deposit(FieldLineDepositionQuantity::Length);
+        let average = deposit(FieldLineDepositionQuantity::Average("v".to_string()));
+
+        for k in 0..4 {
+            let idx = Idx3::new(0, 1, k);
+            let n_lines = if k < 3 { 2.0 } else { 1.0 };
+            assert_eq!(count.value(&idx), n_lines);
+            assert!((length.value(&idx) - n_lines).abs() < 1e-6);
+            let expected_average = if k < 3 { 2.0 } else { 1.0 };
+            assert!((average.value(&idx) - expected_average).abs() < 1e-6);
+        }
+        assert_eq!(count.value(&Idx3::new(1, 1, 0)), 0.0);
+        assert!(average.value(&Idx3::new(1, 1, 0)).is_nan());
+    }
+
+    #[test]
+    fn field_line_crossing_periodic_boundary_is_deposited_along_step() {
+        let grid = Arc::new(FieldGrid3::from(RegularGrid3::from_bounds(
+            In3D::new(4, 4, 4),
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(4.0, 4.0, 4.0),
+            In3D::new(true, false, false),
+        )));
+
+        // Field line along x from x = 3 to x = 4.5, wrapped across the
+        // periodic x-boundary at x = 4
+        let mut varying_scalar_values = VaryingScalarValues::new();
+        varying_scalar_values.insert("x".to_string(), vec![vec![3.0, 3.5, 0.0, 0.5]]);
+        varying_scalar_values.insert("y".to_string(), vec![vec![1.5; 4]]);
+        varying_scalar_values.insert("z".to_string(), vec![vec![1.5; 4]]);
+
+        let length = deposit_field_lines_onto_grid(
+            grid,
+            &varying_scalar_values,
+            &Vec3::new(4.0, 4.0, 4.0),
+            &FieldLineDepositionQuantity::Length,
+            &Verbosity::Quiet,
+        )
+        .unwrap();
+
+        for (i, expected_length) in [0.5, 0.0, 0.0, 1.0].into_iter().enumerate() {
+            let idx = Idx3::new(i, 1, 1);
+            assert!((length.value(&idx) - expected_length).abs() < 1e-6);
+        }
+    }
+}
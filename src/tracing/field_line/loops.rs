@@ -0,0 +1,521 @@
+//! Catalogs of coronal loops found among traced field lines.
+
+use super::{unwrap_path_positions, FieldLineSet3};
+use crate::{
+    constants::{AMU, G_SUN, KBOLTZMANN},
+    geometry::Vec3,
+    io::utils,
+    tracing::ftr,
+    units::solar::{U_L, U_R},
+};
+use rayon::prelude::*;
+use std::{io, path::Path};
+
+#[cfg(feature = "serialization")]
+use serde::{
+    ser::{SerializeStruct, Serializer},
+    Serialize,
+};
+
+/// Configuration parameters for building loop catalogs.
+#[derive(Clone, Debug)]
+pub struct LoopCatalogConfig {
+    /// Both ends of a field line must lie below this height [Mm] for the field line
+    /// to be considered a closed loop.
+    pub max_footpoint_height: ftr,
+    /// Name of the extracted temperature quantity [K].
+    pub temperature_quantity: String,
+    /// Name of the extracted mass density quantity [Bifrost units].
+    pub density_quantity: String,
+    /// Mean molecular weight used for computing gas pressures.
+    pub mean_molecular_weight: ftr,
+    /// Largest relative deviation from the hydrostatic scale height or RTV apex
+    /// temperature for which a loop is considered to be in equilibrium.
+    pub equilibrium_tolerance: ftr,
+}
+
+/// Catalog of coronal loops, with one value for each loop in every column.
+#[derive(Clone, Debug)]
+pub struct LoopCatalog {
+    column_names: Vec<String>,
+    columns: Vec<Vec<ftr>>,
+}
+
+/// Path and extracted quantities along a single field line, with heights
+/// measured upward (opposite to the Bifrost z-axis) and distances measured
+/// along the path unwrapped across periodic boundaries.
+struct LoopPath<'a> {
+    x: &'a [ftr],
+    y: &'a [ftr],
+    z: &'a [ftr],
+    heights: Vec<ftr>,
+    distances: Vec<ftr>,
+}
+
+impl LoopCatalog {
+    /// Builds a catalog of all the closed loops in the given field line set.
+    ///
+    /// A field line counts as a closed loop if both of its ends lie below the
+    /// maximum footpoint height and its highest point lies above both ends. Every
+    /// varying scalar quantity extracted along the field lines is included with
+    /// its value at the apex, its length-weighted mean and its values at the two
+    /// footpoints. If temperature and/or density have been extracted, their scale
+    /// heights are included, along with the pressure scale height, the hydrostatic
+    /// scale height, the apex temperature predicted by the RTV scaling law and
+    /// whether the loop is in approximate hydrostatic and RTV equilibrium (1 if so,
+    /// 0 otherwise).
+    ///
+    /// Scale heights are found by a least-squares fit of the logarithm of the
+    /// quantity against height, and are positive when the quantity decreases with
+    /// height.
+    pub fn from_field_lines(
+        field_lines: &FieldLineSet3,
+        config: &LoopCatalogConfig,
+    ) -> io::Result<Self> {
+        config.validate();
+
+        if !field_lines.has_paths() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Loop catalogs require the paths of the field lines",
+            ));
+        }
+        let varying_scalar_values = &field_lines.properties.varying_scalar_values;
+        let domain_extents = field_lines.domain_extents();
+
+        let mut quantity_names: Vec<_> = varying_scalar_values
+            .keys()
+            .filter(|name| !["x", "y", "z"].contains(&name.as_str()))
+            .cloned()
+            .collect();
+        quantity_names.sort();
+
+        let has_temperature = quantity_names.contains(&config.temperature_quantity);
+        let has_density = quantity_names.contains(&config.density_quantity);
+
+        let mut column_names: Vec<String> = [
+            "field_line_idx",
+            "x_footpoint0",
+            "y_footpoint0",
+            "z_footpoint0",
+            "x_footpoint1",
+            "y_footpoint1",
+            "z_footpoint1",
+            "x_apex",
+            "y_apex",
+            "z_apex",
+            "apex_height",
+            "length",
+        ]
+        .iter()
+        .map(|name| name.to_string())
+        .collect();
+        for name in &quantity_names {
+            for suffix in ["apex", "mean", "footpoint0", "footpoint1"] {
+                column_names.push(format!("{}_{}", name, suffix));
+            }
+        }
+        if has_temperature {
+            column_names.push("temperature_scale_height".to_string());
+        }
+        if has_density {
+            column_names.push("density_scale_height".to_string());
+        }
+        if has_temperature && has_density {
+            for name in [
+                "pressure_scale_height",
+                "hydrostatic_scale_height",
+                "rtv_temperature",
+                "hydrostatic",
+                "rtv",
+            ] {
+                column_names.push(name.to_string());
+            }
+        }
+
+        let rows: Vec<Vec<ftr>> = (0..field_lines.number_of_field_lines())
+            .into_par_iter()
+            .filter_map(|field_line_idx| {
+                let path = LoopPath::new(
+                    &varying_scalar_values["x"][field_line_idx],
+                    &varying_scalar_values["y"][field_line_idx],
+                    &varying_scalar_values["z"][field_line_idx],
+                    &domain_extents,
+                );
+                let apex_idx = path.find_apex(config.max_footpoint_height)?;
+                let last_idx = path.heights.len() - 1;
+                let length = path.distances[last_idx];
+
+                let mut row = Vec::with_capacity(column_names.len());
+                row.push(field_line_idx as ftr);
+                for idx in [0, last_idx, apex_idx] {
+                    row.extend([path.x[idx], path.y[idx], path.z[idx]]);
+                }
+                row.push(path.heights[apex_idx]);
+                row.push(length);
+
+                for name in &quantity_names {
+                    let values = &varying_scalar_values[name][field_line_idx];
+                    row.extend([
+                        values[apex_idx],
+                        path.mean(values),
+                        values[0],
+                        values[last_idx],
+                    ]);
+                }
+
+                let temperatures = varying_scalar_values
+                    .get(&config.temperature_quantity)
+                    .map(|values| &values[field_line_idx]);
+                let densities = varying_scalar_values
+                    .get(&config.density_quantity)
+                    .map(|values| &values[field_line_idx]);
+
+                if let Some(temperatures) = temperatures {
+                    row.push(path.scale_height(temperatures));
+                }
+                if let Some(densities) = densities {
+                    row.push(path.scale_height(densities));
+                }
+                if let (Some(temperatures), Some(densities)) = (temperatures, densities) {
+                    let pressure_scale = U_R * KBOLTZMANN / (config.mean_molecular_weight * AMU);
+                    let pressures: Vec<_> = temperatures
+                        .iter()
+                        .zip(densities.iter())
+                        .map(|(&temperature, &density)| pressure_scale * density * temperature)
+                        .collect();
+                    let pressure_scale_height = path.scale_height(&pressures);
+
+                    let hydrostatic_scale_height = KBOLTZMANN * path.mean(temperatures)
+                        / (config.mean_molecular_weight * AMU * G_SUN * U_L);
+
+                    // Rosner, Tucker & Vaiana (1978): T_max = 1400 (p L)^(1/3),
+                    // with p in dyn/cm^2 and the loop half-length L in cm
+                    let rtv_temperature =
+                        1400.0 * (path.mean(&pressures) * 0.5 * length * U_L).cbrt();
+                    let max_temperature = temperatures
+                        .iter()
+                        .cloned()
+                        .fold(ftr::NEG_INFINITY, ftr::max);
+
+                    let is_within_tolerance = |value: ftr, reference: ftr| {
+                        if value.is_finite() && reference.is_finite() {
+                            if (value / reference - 1.0).abs() <= config.equilibrium_tolerance {
+                                1.0
+                            } else {
+                                0.0
+                            }
+                        } else {
+                            ftr::NAN
+                        }
+                    };
+
+                    row.extend([
+                        pressure_scale_height,
+                        hydrostatic_scale_height,
+                        rtv_temperature,
+                        is_within_tolerance(pressure_scale_height, hydrostatic_scale_height),
+                        is_within_tolerance(max_temperature, rtv_temperature),
+                    ]);
+                }
+                Some(row)
+            })
+            .collect();
+
+        let mut columns = vec![Vec::with_capacity(rows.len()); column_names.len()];
+        for row in rows {
+            for (column, value) in columns.iter_mut().zip(row) {
+                column.push(value);
+            }
+        }
+
+        Ok(Self {
+            column_names,
+            columns,
+        })
+    }
+
+    /// Returns the number of loops in the catalog.
+    pub fn number_of_loops(&self) -> usize {
+        self.columns[0].len()
+    }
+
+    /// Returns the values of the column with the given name, if it exists.
+    pub fn column(&self, name: &str) -> Option<&[ftr]> {
+        self.column_names
+            .iter()
+            .position(|column_name| column_name == name)
+            .map(|idx| self.columns[idx].as_slice())
+    }
+
+    /// Saves the catalog as a CSV file at the given path, with a header line
+    /// containing the column names.
+    pub fn save_as_csv(&self, output_file_path: &Path) -> io::Result<()> {
+        let mut text = self.column_names.join(",");
+        text.push('\n');
+        for loop_idx in 0..self.number_of_loops() {
+            text.push_str(
+                &self
+                    .columns
+                    .iter()
+                    .map(|column| format!("{}", column[loop_idx]))
+                    .collect::<Vec<_>>()
+                    .join(","),
+            );
+            text.push('\n');
+        }
+        utils::write_text_file(&text, output_file_path)
+    }
+
+    /// Serializes the catalog into JSON format and saves at the given path.
+    #[cfg(feature = "json")]
+    pub fn save_as_json(&self, output_file_path: &Path) -> io::Result<()> {
+        utils::save_data_as_json(output_file_path, &self)
+    }
+
+    /// Serializes the catalog into pickle format and saves at the given path.
+    #[cfg(feature = "pickle")]
+    pub fn save_as_pickle(&self, output_file_path: &Path) -> io::Result<()> {
+        utils::save_data_as_pickle(output_file_path, &self)
+    }
+}
+
+#[cfg(feature = "serialization")]
+impl Serialize for LoopCatalog {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let values: std::collections::HashMap<_, _> =
+            self.column_names.iter().zip(self.columns.iter()).collect();
+        let mut s = serializer.serialize_struct("LoopCatalog", 2)?;
+        s.serialize_field("number_of_loops", &self.number_of_loops())?;
+        s.serialize_field("values", &values)?;
+        s.end()
+    }
+}
+
+impl<'a> LoopPath<'a> {
+    fn new(x: &'a [ftr], y: &'a [ftr], z: &'a [ftr], domain_extents: &Vec3<ftr>) -> Self {
+        let heights = z.iter().map(|&z| -z).collect();
+        let positions = unwrap_path_positions(x, y, z, domain_extents);
+        let distances = std::iter::once(0.0)
+            .chain(positions.windows(2).scan(0.0, |distance, positions| {
+                *distance += (&positions[1] - &positions[0]).length();
+                Some(*distance)
+            }))
+            .collect();
+        Self {
+            x,
+            y,
+            z,
+            heights,
+            distances,
+        }
+    }
+
+    /// Returns the index of the apex if the path is a closed loop.
+    fn find_apex(&self, max_footpoint_height: ftr) -> Option<usize> {
+        let last_idx = self.heights.len().checked_sub(1)?;
+        let (apex_idx, &apex_height) = self
+            .heights
+            .iter()
+            .enumerate()
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))?;
+        if self.heights[0] <= max_footpoint_height
+            && self.heights[last_idx] <= max_footpoint_height
+            && apex_height > self.heights[0]
+            && apex_height > self.heights[last_idx]
+        {
+            Some(apex_idx)
+        } else {
+            None
+        }
+    }
+
+    /// Computes the length-weighted mean of the given values along the path.
+    fn mean(&self, values: &[ftr]) -> ftr {
+        let length = self.distances[self.distances.len() - 1];
+        if length > 0.0 {
+            self.distances
+                .windows(2)
+                .zip(values.windows(2))
+                .map(|(distances, values)| {
+                    0.5 * (values[0] + values[1]) * (distances[1] - distances[0])
+                })
+                .sum::<ftr>()
+                / length
+        } else {
+            values[0]
+        }
+    }
+
+    /// Computes the scale height [Mm] of the given positive values along the path.
+    fn scale_height(&self, values: &[ftr]) -> ftr {
+        let (n, sum_h, sum_v, sum_hh, sum_hv) = self
+            .heights
+            .iter()
+            .zip(values)
+            .filter(|(_, &value)| value > 0.0 && value.is_finite())
+            .fold(
+                (0.0, 0.0, 0.0, 0.0, 0.0),
+                |(n, sum_h, sum_v, sum_hh, sum_hv), (&height, &value)| {
+                    let log_value = value.ln();
+                    (
+                        n + 1.0,
+                        sum_h + height,
+                        sum_v + log_value,
+                        sum_hh + height * height,
+                        sum_hv + height * log_value,
+                    )
+                },
+            );
+        let slope = (n * sum_hv - sum_h * sum_v) / (n * sum_hh - sum_h * sum_h);
+        if n >= 2.0 && slope.is_finite() && slope != 0.0 {
+            -1.0 / slope
+        } else {
+            ftr::NAN
+        }
+    }
+}
+
+impl LoopCatalogConfig {
+    pub const DEFAULT_MAX_FOOTPOINT_HEIGHT: ftr = 2.0; // [Mm]
+    pub const DEFAULT_TEMPERATURE_QUANTITY: &'static str = "tg";
+    pub const DEFAULT_DENSITY_QUANTITY: &'static str = "r";
+    pub const DEFAULT_MEAN_MOLECULAR_WEIGHT: ftr = 0.6;
+    pub const DEFAULT_EQUILIBRIUM_TOLERANCE: ftr = 0.3;
+
+    fn validate(&self) {
+        assert!(
+            self.mean_molecular_weight > 0.0,
+            "Mean molecular weight must be larger than zero."
+        );
+        assert!(
+            self.equilibrium_tolerance >= 0.0,
+            "Equilibrium tolerance must be non-negative."
+        );
+    }
+}
+
+impl Default for LoopCatalogConfig {
+    fn default() -> Self {
+        LoopCatalogConfig {
+            max_footpoint_height: Self::DEFAULT_MAX_FOOTPOINT_HEIGHT,
+            temperature_quantity: Self::DEFAULT_TEMPERATURE_QUANTITY.to_string(),
+            density_quantity: Self::DEFAULT_DENSITY_QUANTITY.to_string(),
+            mean_molecular_weight: Self::DEFAULT_MEAN_MOLECULAR_WEIGHT,
+            equilibrium_tolerance: Self::DEFAULT_EQUILIBRIUM_TOLERANCE,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{geometry::Vec3, io::Verbosity, tracing::field_line::FieldLineSetProperties3};
+    use std::collections::HashMap;
+
+    #[test]
+    fn hydrostatic_loop_is_cataloged() {
+        let temperature = 1e6;
+        let hydrostatic_scale_height = KBOLTZMANN * temperature
+            / (LoopCatalogConfig::DEFAULT_MEAN_MOLECULAR_WEIGHT * AMU * G_SUN * U_L);
+
+        // Semicircular loop of radius 10 Mm with footpoints at z = 0
+        let n = 201;
+        let angles: Vec<_> = (0..n)
+            .map(|i| std::f64::consts::PI * i as ftr / (n - 1) as ftr)
+            .collect();
+        let x: Vec<_> = angles.iter().map(|a| -10.0 * a.cos()).collect();
+        let y = vec![0.0; n];
+        let z: Vec<_> = angles.iter().map(|a| -10.0 * a.sin()).collect();
+        let r: Vec<_> = z
+            .iter()
+            .map(|&z| (z / hydrostatic_scale_height).exp())
+            .collect();
+        let tg = vec![temperature; n];
+
+        // A second field line that never comes back down
+        let open_z: Vec<_> = (0..n).map(|i| -(i as ftr) * 0.1).collect();
+
+        let mut varying_scalar_values = HashMap::new();
+        varying_scalar_values.insert("x".to_string(), vec![x.clone(), x]);
+        varying_scalar_values.insert("y".to_string(), vec![y.clone(), y]);
+        varying_scalar_values.insert("z".to_string(), vec![z, open_z]);
+        varying_scalar_values.insert("r".to_string(), vec![r.clone(), r]);
+        varying_scalar_values.insert("tg".to_string(), vec![tg.clone(), tg]);
+
+        let field_lines = FieldLineSet3::new(
+            Vec3::new(-20.0, -1.0, -20.0),
+            Vec3::new(20.0, 1.0, 0.0),
+            FieldLineSetProperties3 {
+                number_of_field_lines: 2,
+                varying_scalar_values,
+                ..FieldLineSetProperties3::default()
+            },
+            Verbosity::Quiet,
+        );
+
+        let catalog =
+            LoopCatalog::from_field_lines(&field_lines, &LoopCatalogConfig::default()).unwrap();
+        assert_eq!(catalog.number_of_loops(), 1);
+
+        let apex_height = catalog.column("apex_height").unwrap()[0];
+        assert!((apex_height - 10.0).abs() < 1e-9);
+        let length = catalog.column("length").unwrap()[0];
+        assert!((length - 10.0 * std::f64::consts::PI).abs() < 1e-2);
+        let density_scale_height = catalog.column("density_scale_height").unwrap()[0];
+        assert!((density_scale_height / hydrostatic_scale_height - 1.0).abs() < 1e-6);
+        let temperature_scale_height = catalog.column("temperature_scale_height").unwrap()[0];
+        assert!(temperature_scale_height.is_nan() || temperature_scale_height.abs() > 1e6);
+        assert_eq!(catalog.column("hydrostatic").unwrap()[0], 1.0);
+        assert_eq!(catalog.column("tg_apex").unwrap()[0], temperature);
+    }
+
+    #[test]
+    fn loop_crossing_periodic_boundary_has_unwrapped_length() {
+        // Semicircular loop of radius 10 Mm centered at x = 15 Mm, which
+        // crosses the periodic x-boundary at x = 20 Mm and is wrapped to the
+        // other side of the domain
+        let n = 201;
+        let angles: Vec<_> = (0..n)
+            .map(|i| std::f64::consts::PI * i as ftr / (n - 1) as ftr)
+            .collect();
+        let x: Vec<_> = angles
+            .iter()
+            .map(|a| {
+                let x = 15.0 - 10.0 * a.cos();
+                if x >= 20.0 {
+                    x - 40.0
+                } else {
+                    x
+                }
+            })
+            .collect();
+        let y = vec![0.0; n];
+        let z: Vec<_> = angles.iter().map(|a| -10.0 * a.sin()).collect();
+
+        let mut varying_scalar_values = HashMap::new();
+        varying_scalar_values.insert("x".to_string(), vec![x]);
+        varying_scalar_values.insert("y".to_string(), vec![y]);
+        varying_scalar_values.insert("z".to_string(), vec![z]);
+
+        let field_lines = FieldLineSet3::new(
+            Vec3::new(-20.0, -1.0, -20.0),
+            Vec3::new(20.0, 1.0, 0.0),
+            FieldLineSetProperties3 {
+                number_of_field_lines: 1,
+                varying_scalar_values,
+                ..FieldLineSetProperties3::default()
+            },
+            Verbosity::Quiet,
+        );
+
+        let catalog =
+            LoopCatalog::from_field_lines(&field_lines, &LoopCatalogConfig::default()).unwrap();
+        assert_eq!(catalog.number_of_loops(), 1);
+
+        let length = catalog.column("length").unwrap()[0];
+        assert!((length - 10.0 * std::f64::consts::PI).abs() < 1e-2);
+        assert!((catalog.column("x_footpoint1").unwrap()[0] + 15.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,136 @@
+//! Tracking of field lines through a series of snapshots.
+
+use super::{FieldLineSet3, FieldLineSetProperties3, FieldLineTracer3};
+use crate::{
+    corks::{fco, CorkSet, CorkStepper, HorizontalHeunCorkStepper},
+    field::CachingScalarFieldProvider3,
+    geometry::{Point3, Vec3},
+    grid::Grid3,
+    interpolation::Interpolator3,
+    io::{snapshot::fdt, Verbosity},
+    seeding::Seeder3,
+    tracing::{ftr, stepping::DynStepper3},
+};
+use rayon::prelude::*;
+use std::io;
+
+/// Footpoints of field lines that are followed through a series of snapshots.
+///
+/// The footpoints are advected as corks with the horizontal plasma velocity
+/// between snapshots, and the field lines are re-traced from the advected
+/// footpoints in each snapshot. Each field line is identified by the index of
+/// its footpoint, which stays the same throughout the series.
+#[derive(Clone, Debug)]
+pub struct FieldLineFootpointTracker3 {
+    footpoints: CorkSet,
+}
+
+impl FieldLineFootpointTracker3 {
+    /// Creates a new tracker with footpoints at the positions produced by the
+    /// given seeder in the initial snapshot.
+    pub fn new(
+        seeder: &dyn Seeder3,
+        initial_snapshot: &mut dyn CachingScalarFieldProvider3<fdt>,
+        interpolator: &dyn Interpolator3<fdt>,
+        verbosity: Verbosity,
+    ) -> io::Result<Self> {
+        let footpoints = CorkSet::new(
+            seeder.number_of_points(),
+            seeder,
+            initial_snapshot,
+            interpolator,
+            Vec::new(),
+            Vec::new(),
+            verbosity,
+        )?;
+        Ok(Self { footpoints })
+    }
+
+    /// Returns the time that the footpoints have been advected to, relative to
+    /// the initial snapshot.
+    pub fn current_time(&self) -> fco {
+        self.footpoints.current_time()
+    }
+
+    /// Advects the footpoints to the given snapshot, which lies the given
+    /// duration after the snapshot the footpoints were last advected to.
+    ///
+    /// Footpoints that leave the grid through a non-periodic boundary are no
+    /// longer tracked.
+    pub fn advect(
+        &mut self,
+        snapshot: &mut dyn CachingScalarFieldProvider3<fdt>,
+        interpolator: &dyn Interpolator3<fdt>,
+        step_duration: fco,
+    ) -> io::Result<()> {
+        HorizontalHeunCorkStepper.step_all_corks(
+            &mut self.footpoints,
+            snapshot,
+            interpolator,
+            step_duration,
+        )
+    }
+
+    /// Traces the field lines from the current footpoints in the given snapshot.
+    ///
+    /// Besides the quantities produced by the tracer, the resulting field line
+    /// set has the fixed scalar values `field_line_id`, holding the index of the
+    /// footpoint of each field line, and `time`.
+    pub fn trace<Tr>(
+        &self,
+        field_name: &str,
+        snapshot: &dyn CachingScalarFieldProvider3<fdt>,
+        tracer: &Tr,
+        interpolator: &dyn Interpolator3<fdt>,
+        stepper: DynStepper3<fdt>,
+    ) -> FieldLineSet3
+    where
+        Tr: FieldLineTracer3 + Sync,
+        <Tr as FieldLineTracer3>::Data: Send,
+        FieldLineSetProperties3: FromParallelIterator<<Tr as FieldLineTracer3>::Data>,
+    {
+        let verbosity = self.footpoints.verbosity().clone();
+        let footpoints: Vec<_> = self.footpoints.active_cork_positions().collect();
+        if verbosity.print_messages() {
+            println!("Tracing field lines from {} footpoints", footpoints.len());
+        }
+        let progress_bar = verbosity.create_progress_bar(footpoints.len());
+
+        let (field_line_ids, field_lines): (Vec<_>, Vec<_>) = footpoints
+            .into_par_iter()
+            .filter_map(|(idx, position)| {
+                let field_line = tracer.trace(
+                    field_name,
+                    snapshot,
+                    interpolator,
+                    stepper.heap_clone(),
+                    &Point3::from(position),
+                );
+                progress_bar.inc();
+                field_line.map(|field_line| (idx as ftr, field_line))
+            })
+            .unzip();
+
+        let mut properties: FieldLineSetProperties3 = field_lines.into_par_iter().collect();
+
+        if verbosity.print_messages() {
+            println!(
+                "Successfully traced {} field lines",
+                properties.number_of_field_lines
+            );
+        }
+
+        properties.fixed_scalar_values.insert(
+            "time".to_string(),
+            vec![self.current_time(); field_line_ids.len()],
+        );
+        properties
+            .fixed_scalar_values
+            .insert("field_line_id".to_string(), field_line_ids);
+
+        let lower_bounds = Vec3::from(snapshot.grid().lower_bounds());
+        let upper_bounds = Vec3::from(snapshot.grid().upper_bounds());
+
+        FieldLineSet3::new(lower_bounds, upper_bounds, properties, verbosity)
+    }
+}
@@ -0,0 +1,425 @@
+//! Twist and writhe diagnostics for traced field lines.
+
+use super::FieldLineSet3;
+use crate::{
+    field::VectorField3,
+    geometry::{
+        Dim3::{X, Y, Z},
+        Vec3,
+    },
+    interpolation::Interpolator3,
+    num::BFloat,
+    tracing::ftr,
+};
+use rayon::prelude::*;
+use std::f64::consts::PI;
+
+/// Twist number, integrated parallel current and polar writhe of a field line.
+struct TwistDiagnostics {
+    twist_number: ftr,
+    integrated_current: ftr,
+    writhe: ftr,
+}
+
+impl FieldLineSet3 {
+    /// Computes integral twist diagnostics along the path of each field line and
+    /// stores them as the fixed scalar values `twist_number`, `integrated_current`
+    /// and `writhe`.
+    ///
+    /// The twist number is `T_w = (1/4π) ∫ α dl` with `α = J·B/B²`, and the
+    /// integrated current is `∫ J·B/|B| dl`. The current density `J` must be the
+    /// curl of the magnetic field, so that `α` has units of inverse length. The
+    /// writhe is the polar writhe of Berger & Prior (2006), computed relative to
+    /// planes normal to the z-axis. The paths are unwrapped across periodic
+    /// boundaries before integrating.
+    pub fn compute_twist_diagnostics<F>(
+        &mut self,
+        magnetic_field: &VectorField3<F>,
+        current_density: &VectorField3<F>,
+        interpolator: &dyn Interpolator3<F>,
+    ) where
+        F: BFloat,
+    {
+        if self.verbosity.print_messages() {
+            println!("Computing twist diagnostics along field line paths");
+        }
+        let progress_bar = self
+            .verbosity()
+            .create_progress_bar(self.number_of_field_lines());
+
+        let diagnostics: Vec<_> = (0..self.number_of_field_lines())
+            .into_par_iter()
+            .map(|field_line_idx| {
+                let positions = self.unwrapped_path_positions(field_line_idx);
+                let diagnostics =
+                    compute_diagnostics(&positions, magnetic_field, current_density, interpolator);
+                progress_bar.inc();
+                diagnostics
+            })
+            .collect();
+
+        let fixed_scalar_values = &mut self.properties.fixed_scalar_values;
+        fixed_scalar_values.insert(
+            "twist_number".to_string(),
+            diagnostics.iter().map(|d| d.twist_number).collect(),
+        );
+        fixed_scalar_values.insert(
+            "integrated_current".to_string(),
+            diagnostics.iter().map(|d| d.integrated_current).collect(),
+        );
+        fixed_scalar_values.insert(
+            "writhe".to_string(),
+            diagnostics.iter().map(|d| d.writhe).collect(),
+        );
+    }
+}
+
+fn compute_diagnostics<F>(
+    positions: &[Vec3<ftr>],
+    magnetic_field: &VectorField3<F>,
+    current_density: &VectorField3<F>,
+    interpolator: &dyn Interpolator3<F>,
+) -> TwistDiagnostics
+where
+    F: BFloat,
+{
+    let (alphas, parallel_currents): (Vec<_>, Vec<_>) = positions
+        .iter()
+        .map(|position| {
+            let position = position.to_point3();
+            let b = interpolator
+                .interp_vector_field(magnetic_field, &position)
+                .expect_inside_or_moved();
+            let j = interpolator
+                .interp_vector_field(current_density, &position)
+                .expect_inside_or_moved();
+            let b_squared = b.squared_length();
+            let j_dot_b: ftr = j.dot(&b);
+            (j_dot_b / b_squared, j_dot_b / b_squared.sqrt())
+        })
+        .unzip();
+
+    let integrate = |values: &[ftr]| -> ftr {
+        positions
+            .windows(2)
+            .zip(values.windows(2))
+            .map(|(positions, values)| {
+                0.5 * (values[0] + values[1]) * (&positions[1] - &positions[0]).length()
+            })
+            .sum()
+    };
+
+    TwistDiagnostics {
+        twist_number: integrate(&alphas) / (4.0 * PI),
+        integrated_current: integrate(&parallel_currents),
+        writhe: compute_polar_writhe(positions),
+    }
+}
+
+/// Computes the polar writhe of the given curve relative to planes normal to
+/// the z-axis (Berger & Prior 2006).
+///
+/// The curve is split into pieces that are monotonic in z. The non-local part
+/// sums the winding of every pair of pieces about each other over their common
+/// z-range, and the local part integrates the rotation of the tangent within
+/// each piece.
+fn compute_polar_writhe(positions: &[Vec3<ftr>]) -> ftr {
+    let tangents: Vec<_> = positions
+        .windows(2)
+        .filter_map(|positions| {
+            let segment = &positions[1] - &positions[0];
+            let length = segment.length();
+            if length > 0.0 {
+                Some(segment / length)
+            } else {
+                None
+            }
+        })
+        .collect();
+
+    let local_writhe: ftr = tangents
+        .windows(2)
+        .map(|tangents| {
+            let mean_tangent_z = 0.5 * (tangents[0][Z] + tangents[1][Z]);
+            let cross_product_z = tangents[0][X] * tangents[1][Y] - tangents[0][Y] * tangents[1][X];
+            cross_product_z / (1.0 + mean_tangent_z.abs())
+        })
+        .sum::<ftr>()
+        / (2.0 * PI);
+
+    let pieces = split_into_monotonic_pieces(positions);
+
+    let mut nonlocal_writhe = 0.0;
+    for (i, piece_i) in pieces.iter().enumerate() {
+        for piece_j in pieces.iter().skip(i + 1) {
+            // Both orderings of the pair contribute equally
+            nonlocal_writhe +=
+                2.0 * piece_i.sign * piece_j.sign * compute_winding_angle(piece_i, piece_j)
+                    / (2.0 * PI);
+        }
+    }
+
+    local_writhe + nonlocal_writhe
+}
+
+/// Part of a curve where the z-coordinate changes monotonically.
+struct MonotonicPiece<'a> {
+    positions: &'a [Vec3<ftr>],
+    sign: ftr,
+}
+
+impl MonotonicPiece<'_> {
+    fn z_range(&self) -> (ftr, ftr) {
+        let first = self.positions[0][Z];
+        let last = self.positions[self.positions.len() - 1][Z];
+        (first.min(last), first.max(last))
+    }
+
+    /// Returns the horizontal position of the piece at the given height, which
+    /// must be within the z-range of the piece.
+    fn horizontal_position_at(&self, z: ftr) -> (ftr, ftr) {
+        let segment_idx = self
+            .positions
+            .windows(2)
+            .position(|positions| {
+                let (lower, upper) = if positions[0][Z] <= positions[1][Z] {
+                    (positions[0][Z], positions[1][Z])
+                } else {
+                    (positions[1][Z], positions[0][Z])
+                };
+                z >= lower && z <= upper
+            })
+            .unwrap_or(0);
+        let start = &self.positions[segment_idx];
+        let end = &self.positions[segment_idx + 1];
+        let dz = end[Z] - start[Z];
+        let fraction = if dz != 0.0 { (z - start[Z]) / dz } else { 0.0 };
+        (
+            start[X] + fraction * (end[X] - start[X]),
+            start[Y] + fraction * (end[Y] - start[Y]),
+        )
+    }
+}
+
+fn split_into_monotonic_pieces(positions: &[Vec3<ftr>]) -> Vec<MonotonicPiece<'_>> {
+    let mut pieces = Vec::new();
+    let mut start_idx = 0;
+    let mut sign = 0.0;
+    for idx in 1..positions.len() {
+        let dz = positions[idx][Z] - positions[idx - 1][Z];
+        if dz == 0.0 {
+            continue;
+        }
+        let step_sign = dz.signum();
+        if sign == 0.0 {
+            sign = step_sign;
+        } else if step_sign != sign {
+            pieces.push(MonotonicPiece {
+                positions: &positions[start_idx..idx],
+                sign,
+            });
+            start_idx = idx - 1;
+            sign = step_sign;
+        }
+    }
+    if sign != 0.0 {
+        pieces.push(MonotonicPiece {
+            positions: &positions[start_idx..],
+            sign,
+        });
+    }
+    pieces
+}
+
+/// Computes the net change in the horizontal angle of the vector from the first
+/// to the second piece over the common z-range of the pieces.
+fn compute_winding_angle(piece_i: &MonotonicPiece, piece_j: &MonotonicPiece) -> ftr {
+    let (lower_i, upper_i) = piece_i.z_range();
+    let (lower_j, upper_j) = piece_j.z_range();
+    let lower = lower_i.max(lower_j);
+    let upper = upper_i.min(upper_j);
+    if lower >= upper {
+        return 0.0;
+    }
+
+    let mut heights: Vec<_> = piece_i
+        .positions
+        .iter()
+        .chain(piece_j.positions)
+        .map(|position| position[Z])
+        .filter(|&z| z > lower && z < upper)
+        .chain([lower, upper])
+        .collect();
+    heights.sort_by(ftr::total_cmp);
+
+    let angles = heights.into_iter().filter_map(|z| {
+        let (x_i, y_i) = piece_i.horizontal_position_at(z);
+        let (x_j, y_j) = piece_j.horizontal_position_at(z);
+        let (dx, dy) = (x_j - x_i, y_j - y_i);
+        // The angle is undefined where the pieces meet
+        if dx.abs() + dy.abs() > 0.0 {
+            Some(dy.atan2(dx))
+        } else {
+            None
+        }
+    });
+
+    let mut total_change = 0.0;
+    let mut previous_angle = None;
+    for angle in angles {
+        if let Some(previous_angle) = previous_angle {
+            let mut change = angle - previous_angle;
+            if change > PI {
+                change -= 2.0 * PI;
+            } else if change < -PI {
+                change += 2.0 * PI;
+            }
+            total_change += change;
+        }
+        previous_angle = Some(angle);
+    }
+    total_change
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{
+        field::{CustomScalarFieldGenerator3, FieldGrid3, ScalarFieldProvider3},
+        geometry::In3D,
+        grid::{fgr, regular::RegularGrid3},
+        interpolation::poly_fit::{PolyFitInterpolator3, PolyFitInterpolatorConfig},
+        io::Verbosity,
+        tracing::field_line::FieldLineSetProperties3,
+    };
+    use std::{collections::HashMap, sync::Arc};
+
+    #[test]
+    fn twist_number_of_uniformly_twisted_flux_tube_matches_analytical_value() {
+        // Gold-Hoyle flux tube along z, where field lines wind about the axis
+        // with the same rate b at every radius r. With α = 2b/(1 + b²r²) and
+        // dl = sqrt(1 + b²r²) dz, the twist number of a field line of height L
+        // is T_w = bL/(2π sqrt(1 + b²r²))
+        let b = 0.5 * PI;
+        let field_line_radius = 0.5;
+        let domain_height = 4.0;
+
+        let grid = Arc::new(FieldGrid3::from(RegularGrid3::from_bounds(
+            In3D::new(32, 32, 8),
+            Vec3::new(-1.0, -1.0, 0.0),
+            Vec3::new(1.0, 1.0, domain_height),
+            In3D::new(false, false, true),
+        )));
+        let mut provider = CustomScalarFieldGenerator3::<f32>::new(grid, Verbosity::Quiet)
+            .with_variable(
+                "bx".to_string(),
+                Box::new(move |x: fgr, y: fgr, _| {
+                    (-b * y / (1.0 + b * b * (x * x + y * y))) as f32
+                }),
+            )
+            .with_variable(
+                "by".to_string(),
+                Box::new(move |x: fgr, y: fgr, _| (b * x / (1.0 + b * b * (x * x + y * y))) as f32),
+            )
+            .with_variable(
+                "bz".to_string(),
+                Box::new(move |x: fgr, y: fgr, _| (1.0 / (1.0 + b * b * (x * x + y * y))) as f32),
+            )
+            .with_variable(
+                "jx".to_string(),
+                Box::new(move |x: fgr, y: fgr, _| {
+                    (-2.0 * b * b * y / (1.0 + b * b * (x * x + y * y)).powi(2)) as f32
+                }),
+            )
+            .with_variable(
+                "jy".to_string(),
+                Box::new(move |x: fgr, y: fgr, _| {
+                    (2.0 * b * b * x / (1.0 + b * b * (x * x + y * y)).powi(2)) as f32
+                }),
+            )
+            .with_variable(
+                "jz".to_string(),
+                Box::new(move |x: fgr, y: fgr, _| {
+                    (2.0 * b / (1.0 + b * b * (x * x + y * y)).powi(2)) as f32
+                }),
+            );
+        let magnetic_field = provider.produce_vector_field("b").unwrap();
+        let current_density = provider.produce_vector_field("j").unwrap();
+        let interpolator = PolyFitInterpolator3::new(PolyFitInterpolatorConfig::default());
+
+        // Helical field line through the full height of the domain, starting
+        // halfway up and wrapped across the periodic z-boundary
+        let n = 2001;
+        let unwrapped_z: Vec<_> = (0..n)
+            .map(|i| 0.5 * domain_height + domain_height * i as ftr / (n - 1) as ftr)
+            .collect();
+        let x: Vec<_> = unwrapped_z
+            .iter()
+            .map(|&z| field_line_radius * (b * z).cos())
+            .collect();
+        let y: Vec<_> = unwrapped_z
+            .iter()
+            .map(|&z| field_line_radius * (b * z).sin())
+            .collect();
+        let z: Vec<_> = unwrapped_z
+            .iter()
+            .map(|&z| {
+                if z >= domain_height {
+                    z - domain_height
+                } else {
+                    z
+                }
+            })
+            .collect();
+
+        let mut varying_scalar_values = HashMap::new();
+        varying_scalar_values.insert("x".to_string(), vec![x]);
+        varying_scalar_values.insert("y".to_string(), vec![y]);
+        varying_scalar_values.insert("z".to_string(), vec![z]);
+        let mut field_lines = FieldLineSet3::new(
+            Vec3::new(-1.0, -1.0, 0.0),
+            Vec3::new(1.0, 1.0, domain_height),
+            FieldLineSetProperties3 {
+                number_of_field_lines: 1,
+                varying_scalar_values,
+                ..FieldLineSetProperties3::default()
+            },
+            Verbosity::Quiet,
+        );
+        field_lines.compute_twist_diagnostics(&magnetic_field, &current_density, &interpolator);
+
+        let twist_number = field_lines.properties.fixed_scalar_values["twist_number"][0];
+        let expected = b * domain_height
+            / (2.0 * PI * (1.0 + b * b * field_line_radius * field_line_radius).sqrt());
+        assert!(
+            (twist_number - expected).abs() < 1e-3 * expected,
+            "twist number = {}, expected = {}",
+            twist_number,
+            expected
+        );
+    }
+
+    #[test]
+    fn writhe_of_helix_matches_analytical_value() {
+        // For a helix of radius r and pitch 2πp, the polar writhe per turn is
+        // 1 - p/sqrt(r² + p²) (Berger & Prior 2006)
+        let (radius, pitch) = (1.0, 0.5);
+        let turns = 3.0;
+        let n = 3000;
+        let positions: Vec<_> = (0..=n)
+            .map(|i| {
+                let angle = 2.0 * PI * turns * i as ftr / n as ftr;
+                Vec3::new(radius * angle.cos(), radius * angle.sin(), pitch * angle)
+            })
+            .collect();
+        let writhe = compute_polar_writhe(&positions);
+        let expected = turns * (1.0 - pitch / (radius * radius + pitch * pitch as ftr).sqrt());
+        assert!(
+            (writhe - expected).abs() < 1e-3,
+            "writhe = {}, expected = {}",
+            writhe,
+            expected
+        );
+    }
+}
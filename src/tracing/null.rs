@@ -0,0 +1,937 @@
+//! Detection of magnetic null points and tracing of the topological
+//! skeleton formed by their spine and fan field lines.
+
+use super::{
+    field_line::{
+        basic::{BasicFieldLineTracer3, BasicFieldLineTracerConfig, FieldLineTracingSense},
+        FieldLineSet3, FieldLineSetProperties3, FieldLineTracer3,
+    },
+    ftr,
+    stepping::{DynStepper3, SteppingSense},
+};
+use crate::{
+    field::{CachingScalarFieldProvider3, VectorField3},
+    geometry::{
+        Dim3::{X, Y, Z},
+        Point3, Vec3,
+    },
+    grid::Grid3,
+    interpolation::Interpolator3,
+    io::{snapshot::fdt, utils, Verbosity},
+    num::BFloat,
+};
+use rayon::prelude::*;
+use std::{f64::consts::PI, fmt::Write, io, path::Path};
+
+/// Imaginary parts of the fan eigenvalues smaller than this fraction of the
+/// largest eigenvalue magnitude are considered round-off errors.
+const SPIRAL_EIGENVALUE_TOLERANCE: ftr = 1e-6;
+
+type Vector = [ftr; 3];
+/// Matrix stored by rows, so that `matrix[i][j]` is the derivative of
+/// component `i` with respect to coordinate `j` for a Jacobian.
+type Matrix = [[ftr; 3]; 3];
+
+/// Configuration parameters for the magnetic null finder.
+#[derive(Clone, Debug)]
+pub struct MagneticNullFinderConfig {
+    /// Maximum number of Newton iterations to use when locating a null.
+    pub max_newton_iterations: usize,
+    /// Newton iteration is considered converged when the position update is
+    /// smaller than this fraction of the grid cell extent.
+    pub position_tolerance: ftr,
+}
+
+/// Configuration parameters for tracing the skeleton of magnetic nulls.
+#[derive(Clone, Debug)]
+pub struct NullSkeletonTracerConfig {
+    /// Number of field lines to trace from each null in its fan plane.
+    pub number_of_fan_field_lines: usize,
+    /// Distance from the null where the spine and fan field lines start,
+    /// as a fraction of the smallest extent of the grid cell containing the null.
+    pub seed_distance: ftr,
+}
+
+/// Whether the fan field lines of a null point away from (positive) or
+/// toward (negative) the null.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MagneticNullSign {
+    Positive,
+    Negative,
+}
+
+/// A located magnetic null point with its linear topology.
+#[derive(Clone, Debug)]
+pub struct MagneticNull3 {
+    position: Point3<ftr>,
+    cell_extent: ftr,
+    spine_eigenvalue: ftr,
+    fan_eigenvalues: [(ftr, ftr); 2],
+    spine_direction: Vec3<ftr>,
+    fan_normal: Vec3<ftr>,
+}
+
+impl MagneticNull3 {
+    /// Returns the position of the null.
+    pub fn position(&self) -> &Point3<ftr> {
+        &self.position
+    }
+
+    /// Returns the smallest extent of the grid cell containing the null.
+    pub fn cell_extent(&self) -> ftr {
+        self.cell_extent
+    }
+
+    /// Returns the real eigenvalue of the field Jacobian associated with the spine.
+    pub fn spine_eigenvalue(&self) -> ftr {
+        self.spine_eigenvalue
+    }
+
+    /// Returns the real and imaginary parts of the two eigenvalues of the
+    /// field Jacobian associated with the fan.
+    pub fn fan_eigenvalues(&self) -> &[(ftr, ftr); 2] {
+        &self.fan_eigenvalues
+    }
+
+    /// Returns the unit vector along the spine.
+    pub fn spine_direction(&self) -> &Vec3<ftr> {
+        &self.spine_direction
+    }
+
+    /// Returns the unit normal vector of the fan plane.
+    pub fn fan_normal(&self) -> &Vec3<ftr> {
+        &self.fan_normal
+    }
+
+    /// Returns whether the null is positive or negative.
+    pub fn sign(&self) -> MagneticNullSign {
+        if self.spine_eigenvalue < 0.0 {
+            MagneticNullSign::Positive
+        } else {
+            MagneticNullSign::Negative
+        }
+    }
+
+    /// Whether field lines spiral around the spine in the fan plane
+    /// (the fan eigenvalues are complex).
+    pub fn is_spiral(&self) -> bool {
+        let largest_eigenvalue_magnitude = self
+            .fan_eigenvalues
+            .iter()
+            .map(|&(re, im)| re.hypot(im))
+            .fold(self.spine_eigenvalue.abs(), ftr::max);
+        self.fan_eigenvalues[0].1.abs() > SPIRAL_EIGENVALUE_TOLERANCE * largest_eigenvalue_magnitude
+    }
+
+    fn fan_basis(&self) -> (Vector, Vector) {
+        let normal = vector_from_vec3(&self.fan_normal);
+        let least_aligned_axis = (0..3)
+            .min_by(|&a, &b| normal[a].abs().partial_cmp(&normal[b].abs()).unwrap())
+            .unwrap();
+        let mut axis = [0.0; 3];
+        axis[least_aligned_axis] = 1.0;
+        let first = normalized(cross(&normal, &axis));
+        let second = cross(&normal, &first);
+        (first, second)
+    }
+}
+
+impl MagneticNullFinderConfig {
+    pub const DEFAULT_MAX_NEWTON_ITERATIONS: usize = 20;
+    pub const DEFAULT_POSITION_TOLERANCE: ftr = 1e-6;
+
+    fn validate(&self) {
+        assert!(
+            self.max_newton_iterations > 0,
+            "Maximum number of Newton iterations must be larger than zero."
+        );
+        assert!(
+            self.position_tolerance > 0.0,
+            "Position tolerance must be larger than zero."
+        );
+    }
+}
+
+impl Default for MagneticNullFinderConfig {
+    fn default() -> Self {
+        MagneticNullFinderConfig {
+            max_newton_iterations: Self::DEFAULT_MAX_NEWTON_ITERATIONS,
+            position_tolerance: Self::DEFAULT_POSITION_TOLERANCE,
+        }
+    }
+}
+
+impl NullSkeletonTracerConfig {
+    pub const DEFAULT_NUMBER_OF_FAN_FIELD_LINES: usize = 16;
+    pub const DEFAULT_SEED_DISTANCE: ftr = 0.1;
+
+    fn validate(&self) {
+        assert!(
+            self.seed_distance > 0.0,
+            "Seed distance must be larger than zero."
+        );
+    }
+}
+
+impl Default for NullSkeletonTracerConfig {
+    fn default() -> Self {
+        NullSkeletonTracerConfig {
+            number_of_fan_field_lines: Self::DEFAULT_NUMBER_OF_FAN_FIELD_LINES,
+            seed_distance: Self::DEFAULT_SEED_DISTANCE,
+        }
+    }
+}
+
+/// Finds the magnetic null points of the given vector field.
+///
+/// The field is sampled at the grid cell centers, and each cell of the
+/// grid formed by these points is searched for a null under the assumption
+/// that the field varies trilinearly within it. Cells where a field component
+/// does not change sign are skipped. A null is first located with Newton
+/// iteration on the trilinear approximation and then refined with Newton
+/// iteration using the given interpolator. Cells crossing periodic boundaries
+/// are not searched.
+///
+/// # Parameters
+///
+/// - `field`: Vector field to search for nulls.
+/// - `interpolator`: Interpolator to use.
+/// - `config`: Configuration parameters for the finder.
+/// - `verbosity`: Whether and how to pass non-essential information to user.
+///
+/// # Returns
+///
+/// A list of the located nulls, sorted by position along z, y and x.
+///
+/// # Type parameters
+///
+/// - `F`: Floating point type of the field data.
+pub fn find_magnetic_nulls<F>(
+    field: &VectorField3<F>,
+    interpolator: &dyn Interpolator3<F>,
+    config: &MagneticNullFinderConfig,
+    verbosity: &Verbosity,
+) -> Vec<MagneticNull3>
+where
+    F: BFloat,
+{
+    config.validate();
+
+    let grid = field.grid();
+    let shape = grid.shape();
+    let centers = grid.centers();
+
+    if shape[X] < 2 || shape[Y] < 2 || shape[Z] < 2 {
+        return Vec::new();
+    }
+
+    let sample_layer = |k: usize| -> Vec<Vector> {
+        (0..shape[X] * shape[Y])
+            .into_par_iter()
+            .map(|idx| {
+                let point = Point3::new(
+                    centers[X][idx % shape[X]],
+                    centers[Y][idx / shape[X]],
+                    centers[Z][k],
+                );
+                sample_field(field, interpolator, &point)
+            })
+            .collect()
+    };
+
+    let progress_bar = verbosity.create_progress_bar(shape[Z] - 1);
+
+    let mut nulls = Vec::new();
+    let mut upper_layer = sample_layer(0);
+
+    for k in 0..shape[Z] - 1 {
+        let lower_layer = upper_layer;
+        upper_layer = sample_layer(k + 1);
+
+        let layer_nulls: Vec<_> = (0..(shape[X] - 1) * (shape[Y] - 1))
+            .into_par_iter()
+            .filter_map(|idx| {
+                let i = idx % (shape[X] - 1);
+                let j = idx / (shape[X] - 1);
+                let corner_values: [Vector; 8] = std::array::from_fn(|corner| {
+                    let layer = if corner & 4 == 0 {
+                        &lower_layer
+                    } else {
+                        &upper_layer
+                    };
+                    layer[(j + ((corner >> 1) & 1)) * shape[X] + i + (corner & 1)]
+                });
+                let lower_corner = [centers[X][i], centers[Y][j], centers[Z][k]];
+                let upper_corner = [centers[X][i + 1], centers[Y][j + 1], centers[Z][k + 1]];
+                find_null_in_cell(
+                    field,
+                    interpolator,
+                    &corner_values,
+                    &lower_corner,
+                    &upper_corner,
+                    config,
+                )
+            })
+            .collect();
+
+        nulls.extend(layer_nulls);
+        progress_bar.inc();
+    }
+
+    let mut nulls = remove_duplicate_nulls(nulls, config.position_tolerance);
+    nulls.sort_by(|a, b| {
+        [Z, Y, X]
+            .iter()
+            .map(|&dim| a.position[dim].partial_cmp(&b.position[dim]).unwrap())
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+
+    if verbosity.print_messages() {
+        println!("Found {} magnetic nulls", nulls.len());
+    }
+
+    nulls
+}
+
+/// Traces the spine and fan field lines of the given magnetic nulls.
+///
+/// Two spine field lines and the configured number of fan field lines start
+/// a short distance away from each null, and are traced away from the null.
+/// The index of the null and whether the field line is a spine (1) or fan (0)
+/// field line are stored as the fixed scalar values `null_idx` and `is_spine`.
+///
+/// # Parameters
+///
+/// - `field_name`: Name of the vector field to trace.
+/// - `snapshot`: Snapshot cacher where the vector field to trace is cached.
+/// - `nulls`: Magnetic nulls to trace the skeleton of.
+/// - `config`: Configuration parameters for the skeleton tracing.
+/// - `tracer_config`: Configuration parameters for the field line tracer (the tracing sense is ignored).
+/// - `interpolator`: Interpolator to use.
+/// - `stepper`: Stepper for field line tracing.
+/// - `verbosity`: Whether and how to pass non-essential information to user.
+///
+/// # Returns
+///
+/// A new `FieldLineSet3` with traced spine and fan field lines.
+#[allow(clippy::too_many_arguments)]
+pub fn trace_null_skeleton(
+    field_name: &str,
+    snapshot: &dyn CachingScalarFieldProvider3<fdt>,
+    nulls: &[MagneticNull3],
+    config: &NullSkeletonTracerConfig,
+    tracer_config: &BasicFieldLineTracerConfig,
+    interpolator: &dyn Interpolator3<fdt>,
+    stepper: DynStepper3<fdt>,
+    verbosity: Verbosity,
+) -> FieldLineSet3 {
+    config.validate();
+
+    let create_tracer = |sense| {
+        BasicFieldLineTracer3::new(BasicFieldLineTracerConfig {
+            tracing_sense: FieldLineTracingSense::One(sense),
+            ..tracer_config.clone()
+        })
+    };
+    let same_sense_tracer = create_tracer(SteppingSense::Same);
+    let opposite_sense_tracer = create_tracer(SteppingSense::Opposite);
+
+    let seeds: Vec<_> = nulls
+        .iter()
+        .enumerate()
+        .flat_map(|(null_idx, null)| {
+            let position = vector_from_point3(null.position());
+            let distance = config.seed_distance * null.cell_extent();
+            let spine_direction = vector_from_vec3(null.spine_direction());
+            let (first_fan_direction, second_fan_direction) = null.fan_basis();
+
+            // Field lines along the fan of a positive null point away from
+            // the null, while those along the spine point toward it
+            let (spine_sense, fan_sense) = match null.sign() {
+                MagneticNullSign::Positive => (SteppingSense::Opposite, SteppingSense::Same),
+                MagneticNullSign::Negative => (SteppingSense::Same, SteppingSense::Opposite),
+            };
+
+            let spine_seeds = [1.0, -1.0].into_iter().map(move |direction| {
+                (
+                    null_idx,
+                    true,
+                    spine_sense,
+                    add_scaled(&position, direction * distance, &spine_direction),
+                )
+            });
+            let fan_seeds = (0..config.number_of_fan_field_lines).map(move |line_idx| {
+                let angle =
+                    2.0 * PI * (line_idx as ftr) / (config.number_of_fan_field_lines as ftr);
+                let start_position = add_scaled(
+                    &add_scaled(&position, distance * angle.cos(), &first_fan_direction),
+                    distance * angle.sin(),
+                    &second_fan_direction,
+                );
+                (null_idx, false, fan_sense, start_position)
+            });
+            spine_seeds.chain(fan_seeds)
+        })
+        .collect();
+
+    if verbosity.print_messages() {
+        println!(
+            "Tracing {} spine and fan field lines from {} nulls",
+            seeds.len(),
+            nulls.len()
+        );
+    }
+    let progress_bar = verbosity.create_progress_bar(seeds.len());
+
+    let (labels, field_lines): (Vec<_>, Vec<_>) = seeds
+        .into_par_iter()
+        .filter_map(|(null_idx, is_spine, sense, start_position)| {
+            let tracer = match sense {
+                SteppingSense::Same => &same_sense_tracer,
+                SteppingSense::Opposite => &opposite_sense_tracer,
+            };
+            let field_line = tracer.trace(
+                field_name,
+                snapshot,
+                interpolator,
+                stepper.heap_clone(),
+                &Point3::new(start_position[0], start_position[1], start_position[2]),
+            );
+            progress_bar.inc();
+            field_line.map(|field_line| ((null_idx as ftr, is_spine as u8 as ftr), field_line))
+        })
+        .unzip();
+
+    let mut properties: FieldLineSetProperties3 = field_lines.into_par_iter().collect();
+    let (null_indices, is_spine): (Vec<_>, Vec<_>) = labels.into_iter().unzip();
+    properties
+        .fixed_scalar_values
+        .insert("null_idx".to_string(), null_indices);
+    properties
+        .fixed_scalar_values
+        .insert("is_spine".to_string(), is_spine);
+
+    if verbosity.print_messages() {
+        println!(
+            "Successfully traced {} field lines",
+            properties.number_of_field_lines
+        );
+    }
+
+    let lower_bounds = Vec3::from(snapshot.grid().lower_bounds());
+    let upper_bounds = Vec3::from(snapshot.grid().upper_bounds());
+
+    FieldLineSet3::new(lower_bounds, upper_bounds, properties, verbosity)
+}
+
+/// Saves a catalog of the given magnetic nulls as a whitespace separated
+/// text table at the given path.
+///
+/// Each row describes one null, with the columns listed in the header line.
+/// The sign is 1 for positive and -1 for negative nulls.
+pub fn save_magnetic_null_catalog(
+    output_file_path: &Path,
+    nulls: &[MagneticNull3],
+) -> io::Result<()> {
+    let mut text = String::from(
+        "# x y z sign is_spiral spine_eigenvalue fan_eigenvalue_1_re fan_eigenvalue_1_im \
+         fan_eigenvalue_2_re fan_eigenvalue_2_im spine_x spine_y spine_z \
+         fan_normal_x fan_normal_y fan_normal_z\n",
+    );
+    for null in nulls {
+        let position = null.position();
+        let spine = null.spine_direction();
+        let normal = null.fan_normal();
+        let [(re_1, im_1), (re_2, im_2)] = *null.fan_eigenvalues();
+        // Formatting into a `String` cannot fail
+        let _ = writeln!(
+            text,
+            "{:e} {:e} {:e} {} {} {:e} {:e} {:e} {:e} {:e} {:e} {:e} {:e} {:e} {:e} {:e}",
+            position[X],
+            position[Y],
+            position[Z],
+            match null.sign() {
+                MagneticNullSign::Positive => 1,
+                MagneticNullSign::Negative => -1,
+            },
+            null.is_spiral() as u8,
+            null.spine_eigenvalue(),
+            re_1,
+            im_1,
+            re_2,
+            im_2,
+            spine[X],
+            spine[Y],
+            spine[Z],
+            normal[X],
+            normal[Y],
+            normal[Z]
+        );
+    }
+    utils::write_text_file(&text, output_file_path)
+}
+
+fn find_null_in_cell<F: BFloat>(
+    field: &VectorField3<F>,
+    interpolator: &dyn Interpolator3<F>,
+    corner_values: &[Vector; 8],
+    lower_corner: &Vector,
+    upper_corner: &Vector,
+    config: &MagneticNullFinderConfig,
+) -> Option<MagneticNull3> {
+    // Every component must change sign somewhere in the cell for a null to exist
+    for dim in 0..3 {
+        let (min, max) = corner_values
+            .iter()
+            .fold((ftr::INFINITY, ftr::NEG_INFINITY), |(min, max), value| {
+                (min.min(value[dim]), max.max(value[dim]))
+            });
+        if !(min <= 0.0 && max >= 0.0) {
+            return None;
+        }
+    }
+
+    let tolerance = config.position_tolerance;
+
+    // Locate the null of the trilinear approximation in normalized cell coordinates
+    let mut unit_position = [0.5; 3];
+    let mut converged = false;
+    for _ in 0..config.max_newton_iterations {
+        let (value, jacobian) = evaluate_trilinear(corner_values, &unit_position);
+        let step = solve(&jacobian, &value)?;
+        for dim in 0..3 {
+            unit_position[dim] -= step[dim];
+        }
+        if unit_position
+            .iter()
+            .any(|&coord| !(-1.0..=2.0).contains(&coord))
+        {
+            return None;
+        }
+        if step.iter().all(|&step| step.abs() < tolerance) {
+            converged = true;
+            break;
+        }
+    }
+    // Only accept nulls in the half-open unit cube, so that a null on a
+    // cell face is not found in both adjacent cells
+    if !converged
+        || unit_position
+            .iter()
+            .any(|&coord| !(-tolerance..1.0).contains(&coord))
+    {
+        return None;
+    }
+
+    let cell_extents: Vector = std::array::from_fn(|dim| upper_corner[dim] - lower_corner[dim]);
+    let cell_extent = cell_extents.iter().copied().fold(ftr::INFINITY, ftr::min);
+    let trilinear_position: Vector = std::array::from_fn(|dim| {
+        lower_corner[dim] + unit_position[dim].max(0.0) * cell_extents[dim]
+    });
+    let differentiation_steps: Vector = std::array::from_fn(|dim| 1e-3 * cell_extents[dim]);
+
+    // Refine the position with the full interpolator, and keep the trilinear
+    // estimate if the refinement does not converge near the cell
+    let mut position = trilinear_position;
+    for _ in 0..config.max_newton_iterations {
+        let value = sample_field(field, interpolator, &point_from_vector(&position));
+        let jacobian = compute_jacobian(field, interpolator, &position, &differentiation_steps);
+        let step = match solve(&jacobian, &value) {
+            Some(step) => step,
+            None => {
+                position = trilinear_position;
+                break;
+            }
+        };
+        for dim in 0..3 {
+            position[dim] -= step[dim];
+        }
+        if (0..3).any(|dim| {
+            position[dim] < lower_corner[dim] - cell_extents[dim]
+                || position[dim] > upper_corner[dim] + cell_extents[dim]
+        }) {
+            position = trilinear_position;
+            break;
+        }
+        if (0..3).all(|dim| step[dim].abs() < tolerance * cell_extents[dim]) {
+            break;
+        }
+    }
+
+    let jacobian = compute_jacobian(field, interpolator, &position, &differentiation_steps);
+    Some(analyze_null(position, cell_extent, &jacobian))
+}
+
+/// Determines the spine and fan of a null from the eigenvalues and
+/// eigenvectors of the field Jacobian at the null.
+fn analyze_null(position: Vector, cell_extent: ftr, jacobian: &Matrix) -> MagneticNull3 {
+    let (spine_eigenvalue, fan_eigenvalues) = match compute_eigenvalues(jacobian) {
+        Eigenvalues::Real(mut eigenvalues) => {
+            eigenvalues.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            // The spine eigenvalue has the opposite sign of the two others
+            if eigenvalues[1] >= 0.0 {
+                (
+                    eigenvalues[0],
+                    [(eigenvalues[1], 0.0), (eigenvalues[2], 0.0)],
+                )
+            } else {
+                (
+                    eigenvalues[2],
+                    [(eigenvalues[0], 0.0), (eigenvalues[1], 0.0)],
+                )
+            }
+        }
+        Eigenvalues::OneRealTwoComplex(real, (re, im)) => (real, [(re, im), (re, -im)]),
+    };
+
+    let shifted_jacobian: Matrix = std::array::from_fn(|i| {
+        std::array::from_fn(|j| jacobian[i][j] - if i == j { spine_eigenvalue } else { 0.0 })
+    });
+    let spine_direction = compute_null_space_direction(&shifted_jacobian);
+    // The fan plane is orthogonal to the left eigenvector of the spine eigenvalue
+    let fan_normal = compute_null_space_direction(&transposed(&shifted_jacobian));
+
+    MagneticNull3 {
+        position: point_from_vector(&position),
+        cell_extent,
+        spine_eigenvalue,
+        fan_eigenvalues,
+        spine_direction: Vec3::new(spine_direction[0], spine_direction[1], spine_direction[2]),
+        fan_normal: Vec3::new(fan_normal[0], fan_normal[1], fan_normal[2]),
+    }
+}
+
+fn remove_duplicate_nulls(nulls: Vec<MagneticNull3>, tolerance: ftr) -> Vec<MagneticNull3> {
+    let mut unique_nulls: Vec<MagneticNull3> = Vec::with_capacity(nulls.len());
+    for null in nulls {
+        let is_duplicate = unique_nulls.iter().any(|other| {
+            let max_distance = 10.0 * tolerance * ftr::min(null.cell_extent, other.cell_extent);
+            (0..3).all(|dim| {
+                let dim = [X, Y, Z][dim];
+                (null.position[dim] - other.position[dim]).abs() < max_distance
+            })
+        });
+        if !is_duplicate {
+            unique_nulls.push(null);
+        }
+    }
+    unique_nulls
+}
+
+fn sample_field<F: BFloat>(
+    field: &VectorField3<F>,
+    interpolator: &dyn Interpolator3<F>,
+    point: &Point3<ftr>,
+) -> Vector {
+    let value = interpolator
+        .interp_extrap_vector_field(field, point)
+        .expect_inside_or_moved();
+    [value[X], value[Y], value[Z]]
+}
+
+/// Computes the Jacobian of the interpolated field using centered differences.
+fn compute_jacobian<F: BFloat>(
+    field: &VectorField3<F>,
+    interpolator: &dyn Interpolator3<F>,
+    position: &Vector,
+    steps: &Vector,
+) -> Matrix {
+    let mut jacobian = [[0.0; 3]; 3];
+    for j in 0..3 {
+        let mut upper = *position;
+        let mut lower = *position;
+        upper[j] += steps[j];
+        lower[j] -= steps[j];
+        let upper_value = sample_field(field, interpolator, &point_from_vector(&upper));
+        let lower_value = sample_field(field, interpolator, &point_from_vector(&lower));
+        for i in 0..3 {
+            jacobian[i][j] = (upper_value[i] - lower_value[i]) / (2.0 * steps[j]);
+        }
+    }
+    jacobian
+}
+
+/// Evaluates the trilinear interpolant of the given corner values and its
+/// Jacobian with respect to the normalized cell coordinates.
+///
+/// Corner `c` has offset `(c & 1, (c >> 1) & 1, (c >> 2) & 1)` from the lower corner.
+fn evaluate_trilinear(corner_values: &[Vector; 8], unit_position: &Vector) -> (Vector, Matrix) {
+    let mut value = [0.0; 3];
+    let mut jacobian = [[0.0; 3]; 3];
+    for (corner, corner_value) in corner_values.iter().enumerate() {
+        let weights: Vector = std::array::from_fn(|dim| {
+            if (corner >> dim) & 1 == 1 {
+                unit_position[dim]
+            } else {
+                1.0 - unit_position[dim]
+            }
+        });
+        let weight_derivatives: Vector =
+            std::array::from_fn(|dim| if (corner >> dim) & 1 == 1 { 1.0 } else { -1.0 });
+        let weight = weights[0] * weights[1] * weights[2];
+        let weight_gradient = [
+            weight_derivatives[0] * weights[1] * weights[2],
+            weights[0] * weight_derivatives[1] * weights[2],
+            weights[0] * weights[1] * weight_derivatives[2],
+        ];
+        for i in 0..3 {
+            value[i] += weight * corner_value[i];
+            for j in 0..3 {
+                jacobian[i][j] += weight_gradient[j] * corner_value[i];
+            }
+        }
+    }
+    (value, jacobian)
+}
+
+/// Solves the linear system `matrix*x = rhs` with Cramer's rule, returning
+/// `None` if the matrix is singular.
+fn solve(matrix: &Matrix, rhs: &Vector) -> Option<Vector> {
+    let det = determinant(matrix);
+    let scale = matrix
+        .iter()
+        .flatten()
+        .fold(0.0, |max: ftr, &element| max.max(element.abs()));
+    if scale == 0.0 || det.abs() <= 1e-14 * scale * scale * scale {
+        return None;
+    }
+    Some(std::array::from_fn(|col| {
+        let mut replaced = *matrix;
+        for row in 0..3 {
+            replaced[row][col] = rhs[row];
+        }
+        determinant(&replaced) / det
+    }))
+}
+
+fn determinant(matrix: &Matrix) -> ftr {
+    matrix[0][0] * (matrix[1][1] * matrix[2][2] - matrix[1][2] * matrix[2][1])
+        - matrix[0][1] * (matrix[1][0] * matrix[2][2] - matrix[1][2] * matrix[2][0])
+        + matrix[0][2] * (matrix[1][0] * matrix[2][1] - matrix[1][1] * matrix[2][0])
+}
+
+fn transposed(matrix: &Matrix) -> Matrix {
+    std::array::from_fn(|i| std::array::from_fn(|j| matrix[j][i]))
+}
+
+enum Eigenvalues {
+    Real([ftr; 3]),
+    OneRealTwoComplex(ftr, (ftr, ftr)),
+}
+
+/// Computes the eigenvalues of the given matrix by solving the cubic
+/// characteristic equation.
+fn compute_eigenvalues(matrix: &Matrix) -> Eigenvalues {
+    // Characteristic polynomial: λ³ + aλ² + bλ + c
+    let a = -(matrix[0][0] + matrix[1][1] + matrix[2][2]);
+    let b = matrix[0][0] * matrix[1][1] - matrix[0][1] * matrix[1][0] + matrix[0][0] * matrix[2][2]
+        - matrix[0][2] * matrix[2][0]
+        + matrix[1][1] * matrix[2][2]
+        - matrix[1][2] * matrix[2][1];
+    let c = -determinant(matrix);
+
+    // Substituting λ = t - a/3 gives the depressed cubic t³ + pt + q
+    let shift = -a / 3.0;
+    let p = b - a * a / 3.0;
+    let q = 2.0 * a * a * a / 27.0 - a * b / 3.0 + c;
+    let discriminant = 0.25 * q * q + p * p * p / 27.0;
+
+    if discriminant > 0.0 {
+        let sqrt_discriminant = discriminant.sqrt();
+        let u = (-0.5 * q + sqrt_discriminant).cbrt();
+        let v = (-0.5 * q - sqrt_discriminant).cbrt();
+        Eigenvalues::OneRealTwoComplex(
+            u + v + shift,
+            (-0.5 * (u + v) + shift, 0.5 * (3.0 as ftr).sqrt() * (u - v)),
+        )
+    } else if p == 0.0 {
+        Eigenvalues::Real([shift; 3])
+    } else {
+        let radius = 2.0 * (-p / 3.0).sqrt();
+        let angle = (1.5 * q / p * (-3.0 / p).sqrt()).clamp(-1.0, 1.0).acos() / 3.0;
+        Eigenvalues::Real(std::array::from_fn(|k| {
+            radius * (angle - 2.0 * PI * (k as ftr) / 3.0).cos() + shift
+        }))
+    }
+}
+
+/// Computes a unit vector spanning the null space of the given singular matrix,
+/// using the largest cross product of two of its rows.
+fn compute_null_space_direction(matrix: &Matrix) -> Vector {
+    let direction = [(0, 1), (0, 2), (1, 2)]
+        .iter()
+        .map(|&(first, second)| cross(&matrix[first], &matrix[second]))
+        .max_by(|a, b| squared_norm(a).partial_cmp(&squared_norm(b)).unwrap())
+        .unwrap();
+    if squared_norm(&direction) > 0.0 {
+        normalized(direction)
+    } else {
+        [0.0, 0.0, 1.0]
+    }
+}
+
+fn cross(a: &Vector, b: &Vector) -> Vector {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn squared_norm(a: &Vector) -> ftr {
+    a[0] * a[0] + a[1] * a[1] + a[2] * a[2]
+}
+
+fn normalized(a: Vector) -> Vector {
+    let norm = squared_norm(&a).sqrt();
+    [a[0] / norm, a[1] / norm, a[2] / norm]
+}
+
+fn add_scaled(a: &Vector, scale: ftr, b: &Vector) -> Vector {
+    [
+        a[0] + scale * b[0],
+        a[1] + scale * b[1],
+        a[2] + scale * b[2],
+    ]
+}
+
+fn vector_from_point3(point: &Point3<ftr>) -> Vector {
+    [point[X], point[Y], point[Z]]
+}
+
+fn vector_from_vec3(vector: &Vec3<ftr>) -> Vector {
+    [vector[X], vector[Y], vector[Z]]
+}
+
+fn point_from_vector(vector: &Vector) -> Point3<ftr> {
+    Point3::new(vector[0], vector[1], vector[2])
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{
+        field::{CustomScalarFieldGenerator3, FieldGrid3, ScalarFieldProvider3},
+        geometry::In3D,
+        grid::{fgr, regular::RegularGrid3},
+        interpolation::poly_fit::{PolyFitInterpolator3, PolyFitInterpolatorConfig},
+    };
+    use std::sync::Arc;
+
+    #[test]
+    fn spiral_null_is_found_and_classified() {
+        let grid = Arc::new(FieldGrid3::from(RegularGrid3::from_bounds(
+            In3D::new(12, 12, 12),
+            Vec3::new(-1.0, -1.0, -1.0),
+            Vec3::new(1.0, 1.0, 1.0),
+            In3D::new(false, false, false),
+        )));
+        let null_position = [0.13, -0.21, 0.07];
+        // B = M (r - r0) with spine eigenvalue -2 along z and fan eigenvalues 1 ± i
+        let mut provider = CustomScalarFieldGenerator3::<f32>::new(grid, Verbosity::Quiet)
+            .with_variable(
+                "bx".to_string(),
+                Box::new(move |x: fgr, y: fgr, _| {
+                    ((x - null_position[0]) - (y - null_position[1])) as f32
+                }),
+            )
+            .with_variable(
+                "by".to_string(),
+                Box::new(move |x: fgr, y: fgr, _| {
+                    ((x - null_position[0]) + (y - null_position[1])) as f32
+                }),
+            )
+            .with_variable(
+                "bz".to_string(),
+                Box::new(move |_, _, z: fgr| (-2.0 * (z - null_position[2])) as f32),
+            );
+        let field = provider.produce_vector_field("b").unwrap();
+        let interpolator = PolyFitInterpolator3::new(PolyFitInterpolatorConfig::default());
+
+        let nulls = find_magnetic_nulls(
+            &field,
+            &interpolator,
+            &MagneticNullFinderConfig::default(),
+            &Verbosity::Quiet,
+        );
+        assert_eq!(nulls.len(), 1);
+
+        let null = &nulls[0];
+        for (dim, &coord) in [X, Y, Z].iter().zip(null_position.iter()) {
+            assert!((null.position()[*dim] - coord).abs() < 1e-4);
+        }
+        assert_eq!(null.sign(), MagneticNullSign::Positive);
+        assert!(null.is_spiral());
+        assert!((null.spine_eigenvalue() + 2.0).abs() < 1e-3);
+        assert!((null.fan_eigenvalues()[0].0 - 1.0).abs() < 1e-3);
+        assert!((null.fan_eigenvalues()[0].1.abs() - 1.0).abs() < 1e-3);
+        assert!((null.spine_direction()[Z].abs() - 1.0).abs() < 1e-3);
+        assert!((null.fan_normal()[Z].abs() - 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn radial_null_is_not_spiral() {
+        // Jacobian with spine eigenvalue -2 and two fan eigenvalues 1, rotated
+        // about the x-axis so that round-off gives tiny imaginary parts
+        let (sin, cos) = (1.8 as ftr).sin_cos();
+        let rotation = [[1.0, 0.0, 0.0], [0.0, cos, -sin], [0.0, sin, cos]];
+        let eigenvalues = [1.0, 1.0, -2.0];
+        let jacobian: Matrix = std::array::from_fn(|i| {
+            std::array::from_fn(|j| {
+                (0..3)
+                    .map(|k| rotation[i][k] * eigenvalues[k] * rotation[j][k])
+                    .sum()
+            })
+        });
+
+        let null = analyze_null([0.0; 3], 1.0, &jacobian);
+        assert!(!null.is_spiral());
+        assert!((null.spine_eigenvalue() + 2.0).abs() < 1e-6);
+        assert!((null.fan_eigenvalues()[0].0 - 1.0).abs() < 1e-6);
+        assert!((null.fan_eigenvalues()[1].0 - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn nulls_are_sorted_by_position() {
+        let grid = Arc::new(FieldGrid3::from(RegularGrid3::from_bounds(
+            In3D::new(12, 12, 12),
+            Vec3::new(-1.0, -1.0, -1.0),
+            Vec3::new(1.0, 1.0, 1.0),
+            In3D::new(false, false, false),
+        )));
+        // Nulls near (0.1, -0.5, 0.06) and (0.1, 0.5, 0.02), which lie in the
+        // same layer of grid cells
+        let mut provider = CustomScalarFieldGenerator3::<f32>::new(grid, Verbosity::Quiet)
+            .with_variable(
+                "bx".to_string(),
+                Box::new(|x: fgr, _, _| (-2.0 * (x - 0.1)) as f32),
+            )
+            .with_variable(
+                "by".to_string(),
+                Box::new(|_, y: fgr, _| (y * y - 0.25) as f32),
+            )
+            .with_variable(
+                "bz".to_string(),
+                Box::new(|_, y: fgr, z: fgr| (z - 0.04 + 0.04 * y) as f32),
+            );
+        let field = provider.produce_vector_field("b").unwrap();
+        let interpolator = PolyFitInterpolator3::new(PolyFitInterpolatorConfig::default());
+
+        let nulls = find_magnetic_nulls(
+            &field,
+            &interpolator,
+            &MagneticNullFinderConfig::default(),
+            &Verbosity::Quiet,
+        );
+        assert_eq!(nulls.len(), 2);
+
+        for (null, expected_position) in nulls.iter().zip([[0.1, 0.5, 0.02], [0.1, -0.5, 0.06]]) {
+            for (dim, &coord) in [X, Y, Z].iter().zip(expected_position.iter()) {
+                assert!((null.position()[*dim] - coord).abs() < 1e-2);
+            }
+            assert!(!null.is_spiral());
+        }
+    }
+}
@@ -0,0 +1,636 @@
+//! Computation of the squashing factor Q of the field line mapping, for
+//! detecting quasi-separatrix layers (QSLs).
+
+use super::{
+    field_line::{
+        basic::{
+            BasicFieldLineData3, BasicFieldLineTracer3, BasicFieldLineTracerConfig,
+            FieldLinePointSpacing, FieldLineTracingSense,
+        },
+        FieldLineTracer3,
+    },
+    ftr,
+    stepping::DynStepper3,
+};
+use crate::{
+    field::{
+        compute_3d_array_indices_from_flat_idx, CachingScalarFieldProvider3,
+        DynCachingScalarFieldProvider3, FieldGrid3, ScalarField3, ScalarFieldProvider3,
+    },
+    geometry::{
+        Dim3::{self, X, Y, Z},
+        Idx3, In3D, Point3,
+    },
+    grid::{CoordLocation, Grid3},
+    interpolation::Interpolator3,
+    io::{snapshot::fdt, Verbosity},
+};
+use ndarray::prelude::*;
+use rayon::prelude::*;
+use std::{io, sync::Arc};
+
+/// Field lines whose last point lies farther from the boundary than this
+/// number of final step lengths are not considered to end at the boundary.
+///
+/// Tracing stops when a step attempt leaves the grid, and adaptive steppers
+/// may attempt steps several times longer than the previous one, so the last
+/// point can lie a few steps away from the boundary.
+const MAX_FOOTPOINT_EXTRAPOLATION: ftr = 10.0;
+
+/// Configuration parameters for computing the squashing factor.
+#[derive(Clone, Debug)]
+pub struct SquashingFactorConfig {
+    /// Distance from the sample point to the neighbouring field lines used
+    /// for estimating the Jacobian of the footpoint mapping, as a fraction
+    /// of the smallest extent of the grid cell containing the sample point.
+    pub offset_distance: ftr,
+    /// Field lines reaching lengths larger than this are considered not to
+    /// connect to the boundaries.
+    pub max_length: Option<ftr>,
+}
+
+/// Boundary plane where a field line has its footpoint.
+#[derive(Clone, Copy, Debug)]
+struct BoundaryPlane {
+    dim: Dim3,
+    coord: ftr,
+}
+
+/// Quantity computed from the mapping of field lines between their footpoints.
+#[derive(Clone, Debug, PartialEq)]
+enum SquashingQuantity {
+    /// Squashing factor Q of the given vector field.
+    SquashingFactor(String),
+    /// Logarithm of Q with the sign of the z-component of the given vector field.
+    SignedLogSquashingFactor(String),
+}
+
+impl SquashingQuantity {
+    fn from_name(quantity_name: &str) -> Option<Self> {
+        if let Some(name) = quantity_name.strip_prefix("q_") {
+            Some(Self::SquashingFactor(name.to_string()))
+        } else {
+            quantity_name
+                .strip_prefix("slogq_")
+                .map(|name| Self::SignedLogSquashingFactor(name.to_string()))
+        }
+    }
+
+    fn field_name(&self) -> &str {
+        match self {
+            Self::SquashingFactor(name) | Self::SignedLogSquashingFactor(name) => name,
+        }
+    }
+}
+
+/// Provider of squashing factor quantities in addition to the quantities
+/// of an underlying provider.
+///
+/// For any vector quantity `<v>` of the underlying provider, the quantities
+/// `q_<v>` (the squashing factor Q of the field line mapping) and `slogq_<v>`
+/// (log10(Q) with the sign of the z-component of `<v>`) become available.
+/// Q is computed at each grid cell center by tracing the field line through
+/// the center together with four neighbouring field lines to their footpoints
+/// at the non-periodic boundaries. Values are NaN where the field line does
+/// not connect to the boundary at both ends. Since the computation is costly,
+/// only the points in the requested subgrid are computed when a subfield is
+/// produced.
+pub struct SquashingFactorProvider3 {
+    provider: DynCachingScalarFieldProvider3<fdt>,
+    interpolator: Box<dyn Interpolator3<fdt>>,
+    stepper: DynStepper3<fdt>,
+    config: SquashingFactorConfig,
+    all_variable_names: Vec<String>,
+    verbosity: Verbosity,
+}
+
+impl SquashingFactorConfig {
+    pub const DEFAULT_OFFSET_DISTANCE: ftr = 0.1;
+    pub const DEFAULT_MAX_LENGTH: Option<ftr> = None;
+
+    fn validate(&self) {
+        assert!(
+            self.offset_distance > 0.0,
+            "Offset distance must be larger than zero."
+        );
+        if let Some(length) = self.max_length {
+            assert!(
+                length >= 0.0,
+                "Maximum field line length must be non-negative."
+            );
+        }
+    }
+}
+
+impl Default for SquashingFactorConfig {
+    fn default() -> Self {
+        SquashingFactorConfig {
+            offset_distance: Self::DEFAULT_OFFSET_DISTANCE,
+            max_length: Self::DEFAULT_MAX_LENGTH,
+        }
+    }
+}
+
+impl SquashingFactorProvider3 {
+    /// Creates a provider of squashing factor quantities using the given
+    /// interpolator and stepper for tracing field lines.
+    ///
+    /// The given squashing factor quantity names are included in the list of
+    /// all variable names if they are available.
+    pub fn new(
+        provider: DynCachingScalarFieldProvider3<fdt>,
+        interpolator: Box<dyn Interpolator3<fdt>>,
+        stepper: DynStepper3<fdt>,
+        config: SquashingFactorConfig,
+        quantity_names: Vec<String>,
+        verbosity: Verbosity,
+    ) -> Self {
+        config.validate();
+
+        let mut all_variable_names = provider.all_variable_names().to_vec();
+        for name in quantity_names {
+            if !all_variable_names.contains(&name)
+                && Self::squashing_quantity_is_available(&*provider, &name)
+            {
+                all_variable_names.push(name);
+            }
+        }
+
+        Self {
+            provider,
+            interpolator,
+            stepper,
+            config,
+            all_variable_names,
+            verbosity,
+        }
+    }
+
+    fn squashing_quantity_is_available(
+        provider: &dyn CachingScalarFieldProvider3<fdt>,
+        quantity_name: &str,
+    ) -> bool {
+        SquashingQuantity::from_name(quantity_name).is_some_and(|quantity| {
+            let field_name = quantity.field_name();
+            ["x", "y", "z"].iter().all(|component| {
+                provider.has_variable(&format!("{}{}", field_name, component))
+                    || provider.has_variable(&format!("{}{}c", field_name, component))
+            })
+        })
+    }
+
+    fn compute_squashing_quantity(
+        &mut self,
+        quantity_name: &str,
+        subgrid: Arc<FieldGrid3>,
+        start_indices: &Idx3<usize>,
+    ) -> io::Result<ScalarField3<fdt>> {
+        let quantity = match SquashingQuantity::from_name(quantity_name) {
+            Some(quantity)
+                if Self::squashing_quantity_is_available(&*self.provider, quantity_name) =>
+            {
+                quantity
+            }
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Quantity {} not available", quantity_name),
+                ))
+            }
+        };
+        let field_name = quantity.field_name();
+
+        self.provider.cache_vector_field(field_name)?;
+
+        if self.verbosity.print_messages() {
+            println!("Computing {}", quantity_name);
+        }
+
+        let snapshot = &*self.provider;
+        let interpolator = &*self.interpolator;
+        let stepper = &self.stepper;
+        let grid = snapshot.grid();
+        let field = snapshot.cached_vector_field(field_name);
+
+        let tracer = BasicFieldLineTracer3::new(BasicFieldLineTracerConfig {
+            tracing_sense: FieldLineTracingSense::Both,
+            point_spacing: FieldLinePointSpacing::Natural,
+            max_length: self.config.max_length,
+            stopping_criteria: Vec::new(),
+        });
+
+        let shape = subgrid.shape();
+        let centers = subgrid.centers();
+
+        let progress_bar = self
+            .verbosity
+            .create_progress_bar(shape[X] * shape[Y] * shape[Z]);
+
+        let mut values = Array3::uninit(shape.to_tuple().f());
+        let values_buffer = values.as_slice_memory_order_mut().unwrap();
+
+        values_buffer
+            .par_iter_mut()
+            .enumerate()
+            .for_each(|(idx, value)| {
+                let indices = compute_3d_array_indices_from_flat_idx(shape, idx);
+                let position = Point3::new(
+                    centers[X][indices[X]],
+                    centers[Y][indices[Y]],
+                    centers[Z][indices[Z]],
+                );
+                let cell_extents = grid.grid_cell_extents(&Idx3::with_each_component(|dim| {
+                    start_indices[dim] + indices[dim]
+                }));
+                let offset_distance = self.config.offset_distance
+                    * cell_extents[X].min(cell_extents[Y]).min(cell_extents[Z]);
+
+                let squashing_factor = compute_squashing_factor(
+                    field_name,
+                    snapshot,
+                    &tracer,
+                    interpolator,
+                    stepper,
+                    &position,
+                    offset_distance,
+                );
+
+                let computed_value = match quantity {
+                    SquashingQuantity::SquashingFactor(_) => squashing_factor,
+                    SquashingQuantity::SignedLogSquashingFactor(_) => {
+                        let field_value = interpolator
+                            .interp_extrap_vector_field(field, &position)
+                            .expect_inside_or_moved();
+                        squashing_factor.log10().copysign(field_value[Z])
+                    }
+                };
+                value.write(computed_value as fdt);
+                progress_bar.inc();
+            });
+        let values = unsafe { values.assume_init() };
+
+        Ok(ScalarField3::new(
+            quantity_name.to_string(),
+            subgrid,
+            In3D::same(CoordLocation::Center),
+            values,
+        ))
+    }
+}
+
+impl ScalarFieldProvider3<fdt> for SquashingFactorProvider3 {
+    fn grid(&self) -> &FieldGrid3 {
+        self.provider.grid()
+    }
+
+    fn arc_with_grid(&self) -> Arc<FieldGrid3> {
+        self.provider.arc_with_grid()
+    }
+
+    fn all_variable_names(&self) -> &[String] {
+        &self.all_variable_names
+    }
+
+    fn has_variable(&self, variable_name: &str) -> bool {
+        self.provider.has_variable(variable_name)
+            || Self::squashing_quantity_is_available(&*self.provider, variable_name)
+    }
+
+    fn produce_scalar_field(&mut self, variable_name: &str) -> io::Result<ScalarField3<fdt>> {
+        if self.provider.has_variable(variable_name) {
+            self.provider.produce_scalar_field(variable_name)
+        } else {
+            let grid = self.arc_with_grid();
+            self.compute_squashing_quantity(variable_name, grid, &Idx3::origin())
+        }
+    }
+
+    fn produce_scalar_subfield(
+        &mut self,
+        variable_name: &str,
+        subgrid: Arc<FieldGrid3>,
+        start_indices: &Idx3<usize>,
+    ) -> io::Result<ScalarField3<fdt>> {
+        if self.provider.has_variable(variable_name) {
+            self.provider
+                .produce_scalar_subfield(variable_name, subgrid, start_indices)
+        } else {
+            self.compute_squashing_quantity(variable_name, subgrid, start_indices)
+        }
+    }
+}
+
+/// Computes the squashing factor Q of the field line mapping at the given position.
+///
+/// The field line through the position and four field lines offset by the given
+/// distance in the plane perpendicular to the field are traced in both directions
+/// to their footpoints. The footpoints are found by extrapolating the field lines
+/// from their last points onto the boundary plane where the central field line
+/// ends, and the Jacobian of the mapping between the two footpoint planes is
+/// estimated from the footpoint displacements.
+///
+/// # Parameters
+///
+/// - `field_name`: Name of the vector field to trace.
+/// - `snapshot`: Snapshot cacher where the vector field to trace is cached.
+/// - `tracer`: Field line tracer to use (must trace in both directions).
+/// - `interpolator`: Interpolator to use.
+/// - `stepper`: Stepper to use for each field line.
+/// - `position`: Position where Q should be computed.
+/// - `offset_distance`: Distance from the position to the neighbouring field lines.
+///
+/// # Returns
+///
+/// The value of Q, or NaN if the field line does not have footpoints on the
+/// boundaries at both ends.
+pub fn compute_squashing_factor(
+    field_name: &str,
+    snapshot: &dyn CachingScalarFieldProvider3<fdt>,
+    tracer: &BasicFieldLineTracer3,
+    interpolator: &dyn Interpolator3<fdt>,
+    stepper: &DynStepper3<fdt>,
+    position: &Point3<ftr>,
+    offset_distance: ftr,
+) -> ftr {
+    let trace = |start_position: &Point3<ftr>| -> Option<BasicFieldLineData3> {
+        tracer
+            .trace(
+                field_name,
+                snapshot,
+                interpolator,
+                stepper.heap_clone(),
+                start_position,
+            )
+            .filter(|field_line| field_line.number_of_points() > 1)
+    };
+    let grid = snapshot.grid();
+
+    let compute = || -> Option<ftr> {
+        let field_value = interpolator
+            .interp_extrap_vector_field(snapshot.cached_vector_field(field_name), position)
+            .expect_inside_or_moved();
+        let field_direction = normalized([field_value[X], field_value[Y], field_value[Z]])?;
+        let (first_offset_direction, second_offset_direction) =
+            perpendicular_directions(&field_direction);
+
+        let central_field_line = trace(position)?;
+        let n_points = central_field_line.number_of_points();
+        let backward_plane = find_boundary_plane(
+            grid,
+            &central_field_line.point(1),
+            &central_field_line.point(0),
+        )?;
+        let forward_plane = find_boundary_plane(
+            grid,
+            &central_field_line.point(n_points - 2),
+            &central_field_line.point(n_points - 1),
+        )?;
+
+        let footpoints = |direction: &[ftr; 3], sign: ftr| -> Option<([ftr; 2], [ftr; 2])> {
+            let start_position = Point3::new(
+                position[X] + sign * offset_distance * direction[0],
+                position[Y] + sign * offset_distance * direction[1],
+                position[Z] + sign * offset_distance * direction[2],
+            );
+            let field_line = trace(&start_position)?;
+            let n_points = field_line.number_of_points();
+            Some((
+                extrapolate_to_plane(&backward_plane, &field_line.point(1), &field_line.point(0))?,
+                extrapolate_to_plane(
+                    &forward_plane,
+                    &field_line.point(n_points - 2),
+                    &field_line.point(n_points - 1),
+                )?,
+            ))
+        };
+
+        let (first_upper_backward, first_upper_forward) = footpoints(&first_offset_direction, 1.0)?;
+        let (first_lower_backward, first_lower_forward) =
+            footpoints(&first_offset_direction, -1.0)?;
+        let (second_upper_backward, second_upper_forward) =
+            footpoints(&second_offset_direction, 1.0)?;
+        let (second_lower_backward, second_lower_forward) =
+            footpoints(&second_offset_direction, -1.0)?;
+
+        // Columns are the derivatives of the footpoint positions with respect to
+        // displacements along the two offset directions
+        let derivatives = |plane: &BoundaryPlane, upper: &[ftr; 2], lower: &[ftr; 2]| {
+            let difference = footpoint_difference(grid, plane, upper, lower);
+            [
+                difference[0] / (2.0 * offset_distance),
+                difference[1] / (2.0 * offset_distance),
+            ]
+        };
+        let backward_first = derivatives(
+            &backward_plane,
+            &first_upper_backward,
+            &first_lower_backward,
+        );
+        let backward_second = derivatives(
+            &backward_plane,
+            &second_upper_backward,
+            &second_lower_backward,
+        );
+        let forward_first = derivatives(&forward_plane, &first_upper_forward, &first_lower_forward);
+        let forward_second =
+            derivatives(&forward_plane, &second_upper_forward, &second_lower_forward);
+
+        let backward_jacobian = [
+            [backward_first[0], backward_second[0]],
+            [backward_first[1], backward_second[1]],
+        ];
+        let forward_jacobian = [
+            [forward_first[0], forward_second[0]],
+            [forward_first[1], forward_second[1]],
+        ];
+
+        Some(compute_squashing_factor_from_jacobians(
+            &backward_jacobian,
+            &forward_jacobian,
+        ))
+    };
+
+    compute().unwrap_or(ftr::NAN)
+}
+
+/// Computes Q for the mapping from the backward to the forward footpoint plane,
+/// given the Jacobians of the footpoint positions with respect to the same
+/// displacements of the field lines.
+///
+/// The Jacobian of the mapping is `D = F*B^-1`, and `Q = |D|^2/|det(D)|`. Writing
+/// `B^-1 = adj(B)/det(B)` gives `Q = |F*adj(B)|^2/|det(F)*det(B)|`.
+fn compute_squashing_factor_from_jacobians(
+    backward_jacobian: &[[ftr; 2]; 2],
+    forward_jacobian: &[[ftr; 2]; 2],
+) -> ftr {
+    let b = backward_jacobian;
+    let f = forward_jacobian;
+    let adjugate = [[b[1][1], -b[0][1]], [-b[1][0], b[0][0]]];
+    let mapping: [[ftr; 2]; 2] = std::array::from_fn(|i| {
+        std::array::from_fn(|j| f[i][0] * adjugate[0][j] + f[i][1] * adjugate[1][j])
+    });
+    let squared_norm: ftr = mapping
+        .iter()
+        .flatten()
+        .map(|element| element * element)
+        .sum();
+    let determinant =
+        ((f[0][0] * f[1][1] - f[0][1] * f[1][0]) * (b[0][0] * b[1][1] - b[0][1] * b[1][0])).abs();
+    squared_norm / determinant
+}
+
+/// Finds the non-periodic boundary plane that the field line ending with the
+/// given two points reaches first when extended along its final step.
+fn find_boundary_plane(
+    grid: &FieldGrid3,
+    previous_point: &Point3<ftr>,
+    last_point: &Point3<ftr>,
+) -> Option<BoundaryPlane> {
+    let lower_bounds = grid.lower_bounds();
+    let upper_bounds = grid.upper_bounds();
+
+    let mut closest: Option<(ftr, BoundaryPlane)> = None;
+    for dim in Dim3::slice() {
+        let displacement = last_point[dim] - previous_point[dim];
+        if grid.is_periodic(dim) || displacement == 0.0 {
+            continue;
+        }
+        let coord = if displacement > 0.0 {
+            upper_bounds[dim]
+        } else {
+            lower_bounds[dim]
+        };
+        let steps = (coord - last_point[dim]) / displacement;
+        if closest.is_none_or(|(closest_steps, _)| steps < closest_steps) {
+            closest = Some((steps, BoundaryPlane { dim, coord }));
+        }
+    }
+    closest
+        .filter(|&(steps, _)| steps <= MAX_FOOTPOINT_EXTRAPOLATION)
+        .map(|(_, plane)| plane)
+}
+
+/// Extends the field line ending with the given two points linearly to the
+/// given plane, and returns the in-plane coordinates of the intersection.
+fn extrapolate_to_plane(
+    plane: &BoundaryPlane,
+    previous_point: &Point3<ftr>,
+    last_point: &Point3<ftr>,
+) -> Option<[ftr; 2]> {
+    let displacement = last_point[plane.dim] - previous_point[plane.dim];
+    if displacement == 0.0 {
+        return None;
+    }
+    let steps = (plane.coord - last_point[plane.dim]) / displacement;
+    let [first_dim, second_dim] = Dim3::slice_except(plane.dim);
+    let extrapolate = |dim: Dim3| last_point[dim] + steps * (last_point[dim] - previous_point[dim]);
+    Some([extrapolate(first_dim), extrapolate(second_dim)])
+}
+
+/// Computes the difference between two footpoints in the given plane, using
+/// the shortest distance across periodic boundaries.
+fn footpoint_difference(
+    grid: &FieldGrid3,
+    plane: &BoundaryPlane,
+    upper: &[ftr; 2],
+    lower: &[ftr; 2],
+) -> [ftr; 2] {
+    let dims = Dim3::slice_except(plane.dim);
+    std::array::from_fn(|idx| {
+        let difference = upper[idx] - lower[idx];
+        if grid.is_periodic(dims[idx]) {
+            let extent = grid.extents()[dims[idx]];
+            difference - extent * (difference / extent).round()
+        } else {
+            difference
+        }
+    })
+}
+
+fn normalized(vector: [ftr; 3]) -> Option<[ftr; 3]> {
+    let norm = (vector[0] * vector[0] + vector[1] * vector[1] + vector[2] * vector[2]).sqrt();
+    if norm > 0.0 && norm.is_finite() {
+        Some([vector[0] / norm, vector[1] / norm, vector[2] / norm])
+    } else {
+        None
+    }
+}
+
+/// Returns two orthogonal unit vectors perpendicular to the given unit vector.
+fn perpendicular_directions(direction: &[ftr; 3]) -> ([ftr; 3], [ftr; 3]) {
+    let cross = |a: &[ftr; 3], b: &[ftr; 3]| {
+        [
+            a[1] * b[2] - a[2] * b[1],
+            a[2] * b[0] - a[0] * b[2],
+            a[0] * b[1] - a[1] * b[0],
+        ]
+    };
+    let least_aligned_axis = (0..3)
+        .min_by(|&a, &b| direction[a].abs().partial_cmp(&direction[b].abs()).unwrap())
+        .unwrap();
+    let mut axis = [0.0; 3];
+    axis[least_aligned_axis] = 1.0;
+    let first = normalized(cross(direction, &axis)).unwrap();
+    let second = cross(direction, &first);
+    (first, second)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{
+        field::{CustomScalarFieldGenerator3, ScalarFieldCacher3},
+        geometry::Vec3,
+        grid::{fgr, regular::RegularGrid3},
+        interpolation::poly_fit::{PolyFitInterpolator3, PolyFitInterpolatorConfig},
+        tracing::stepping::rkf::{rkf45::RKF45Stepper3, RKFStepperConfig},
+    };
+
+    #[test]
+    fn squashing_factor_of_sheared_field_is_correct() {
+        let grid = Arc::new(FieldGrid3::from(RegularGrid3::from_bounds(
+            In3D::new(16, 8, 8),
+            Vec3::new(-2.0, -0.5, 0.0),
+            Vec3::new(2.0, 0.5, 1.0),
+            In3D::new(false, false, false),
+        )));
+        // B = (y, 0, 1) maps (x, y) at z = 0 to (x + y, y) at z = 1, giving Q = 3
+        let generator = CustomScalarFieldGenerator3::<fdt>::new(grid, Verbosity::Quiet)
+            .with_variable("bx".to_string(), Box::new(|_, y: fgr, _| y as fdt))
+            .with_variable("by".to_string(), Box::new(|_, _, _| 0.0))
+            .with_variable("bz".to_string(), Box::new(|_, _, _| 1.0));
+        let mut provider = SquashingFactorProvider3::new(
+            Box::new(ScalarFieldCacher3::new_manual_cacher(
+                Box::new(generator),
+                Verbosity::Quiet,
+            )),
+            Box::new(PolyFitInterpolator3::new(PolyFitInterpolatorConfig {
+                order: 1,
+                ..PolyFitInterpolatorConfig::default()
+            })),
+            Box::new(RKF45Stepper3::new(RKFStepperConfig::default())),
+            SquashingFactorConfig::default(),
+            vec!["q_b".to_string()],
+            Verbosity::Quiet,
+        );
+        assert!(provider.all_variable_names().contains(&"q_b".to_string()));
+        assert!(provider.has_variable("slogq_b"));
+        assert!(!provider.has_variable("q_u"));
+
+        let start_indices = Idx3::new(6, 2, 2);
+        let subgrid = Arc::new(provider.grid().subgrid(&start_indices, &Idx3::new(9, 5, 5)));
+        let q = provider
+            .produce_scalar_subfield("q_b", Arc::clone(&subgrid), &start_indices)
+            .unwrap();
+        let slogq = provider
+            .produce_scalar_subfield("slogq_b", subgrid, &start_indices)
+            .unwrap();
+
+        assert_eq!(q.shape(), &In3D::new(4, 4, 4));
+        for (&q, &slogq) in q.values().iter().zip(slogq.values().iter()) {
+            assert!((q - 3.0).abs() < 1e-3, "Q = {}", q);
+            assert!((slogq - (3.0 as fdt).log10()).abs() < 1e-3);
+        }
+    }
+}
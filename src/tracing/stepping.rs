@@ -4,9 +4,9 @@ pub mod rkf;
 
 use super::ftr;
 use crate::{
-    field::VectorField3,
-    geometry::{Point3, Vec3},
-    interpolation::Interpolator3,
+    field::{VectorField2, VectorField3},
+    geometry::{Point2, Point3, Vec2, Vec3},
+    interpolation::{Interpolator2, Interpolator3},
     num::BFloat,
 };
 
@@ -32,6 +32,9 @@ pub enum StoppingCause {
     OutOfBounds,
     TooManyAttempts,
     StoppedByCallback,
+    /// A stopping criterion of the field line tracer was met. Contains the
+    /// index of the criterion.
+    CriterionMet(usize),
 }
 
 /// Lets the stepper callback communicate whether tracing should
@@ -43,10 +46,14 @@ pub enum StepperInstruction {
 }
 
 pub type DynStepper3<F> = Box<dyn Stepper3<F>>;
+pub type DynStepper2<F> = Box<dyn Stepper2<F>>;
 
 pub type SteppingCallback<'a> =
     dyn 'a + FnMut(&Vec3<ftr>, &Vec3<ftr>, &Point3<ftr>, ftr) -> StepperInstruction;
 
+pub type SteppingCallback2<'a> =
+    dyn 'a + FnMut(&Vec2<ftr>, &Vec2<ftr>, &Point2<ftr>, ftr) -> StepperInstruction;
+
 /// Defines the properties of a stepping scheme.
 ///
 /// # Type parameters
@@ -150,3 +157,56 @@ pub trait Stepper3<F>: Sync {
     /// Returns a mutable reference to a clone of this stepper living on the heap.
     fn heap_clone(&self) -> DynStepper3<F>;
 }
+
+/// Defines the properties of a stepping scheme for 2D vector fields.
+///
+/// The methods correspond to those of `Stepper3`, but operate on 2D vector fields
+/// and call the callback with 2D displacements and positions.
+///
+/// # Type parameters
+///
+/// - `F`: Floating point type of the field data.
+pub trait Stepper2<F>: Sync {
+    /// Places the stepper inside the field.
+    fn place(
+        &mut self,
+        field: &VectorField2<F>,
+        interpolator: &dyn Interpolator2<F>,
+        sense: SteppingSense,
+        position: &Point2<ftr>,
+        callback: &mut SteppingCallback2,
+    ) -> StepperResult<()>
+    where
+        F: BFloat;
+
+    /// Performs a step.
+    fn step(
+        &mut self,
+        field: &VectorField2<F>,
+        interpolator: &dyn Interpolator2<F>,
+        sense: SteppingSense,
+        callback: &mut SteppingCallback2,
+    ) -> StepperResult<()>
+    where
+        F: BFloat;
+
+    /// Performs a step, producing regularly spaced output positions.
+    fn step_dense_output(
+        &mut self,
+        field: &VectorField2<F>,
+        interpolator: &dyn Interpolator2<F>,
+        sense: SteppingSense,
+        callback: &mut SteppingCallback2,
+    ) -> StepperResult<()>
+    where
+        F: BFloat;
+
+    /// Returns the current stepper position.
+    fn position(&self) -> Point2<ftr>;
+
+    /// Retuns the current distance of the stepper along the field line.
+    fn distance(&self) -> ftr;
+
+    /// Returns a mutable reference to a clone of this stepper living on the heap.
+    fn heap_clone(&self) -> DynStepper2<F>;
+}
@@ -5,15 +5,19 @@
 pub mod rkf23;
 pub mod rkf45;
 
-use super::{StepperInstruction, StepperResult, SteppingCallback, SteppingSense, StoppingCause};
+use super::{
+    StepperInstruction, StepperResult, SteppingCallback, SteppingCallback2, SteppingSense,
+    StoppingCause,
+};
 use crate::{
-    field::{FieldGrid3, VectorField3},
+    field::{VectorField2, VectorField3},
     geometry::{
+        Dim2,
         Dim3::{X, Y, Z},
-        Point3, Vec3,
+        Point2, Point3, Vec2, Vec3,
     },
-    grid::{Grid3, GridPointQuery3},
-    interpolation::Interpolator3,
+    grid::{Grid2, Grid3, GridPointQuery2, GridPointQuery3},
+    interpolation::{Interpolator2, Interpolator3},
     num::BFloat,
     tracing::ftr,
 };
@@ -120,18 +124,40 @@ struct StepAttempt3 {
     step_wrapped: bool,
 }
 
+/// Vector field that the RKF steppers can step through.
+trait SteppingField3 {
+    /// Computes the field vector at the given position, wrapping the position
+    /// around any periodic boundaries.
+    fn vector(&self, position: &Point3<ftr>) -> GridPointQuery3<ftr, Vec3<ftr>>;
+
+    /// Returns the extents of the domain of the field.
+    fn extents(&self) -> Vec3<ftr>;
+
+    /// Wraps the given position around any periodic boundaries, or returns
+    /// `None` if the position is outside a non-periodic boundary.
+    fn wrap_point(&self, position: &Point3<ftr>) -> Option<Point3<ftr>>;
+}
+
+/// A 3D vector field together with the interpolator to evaluate it with.
+struct InterpolatedVectorField3<'a, F: BFloat> {
+    field: &'a VectorField3<F>,
+    interpolator: &'a dyn Interpolator3<F>,
+}
+
+/// A 2D vector field together with the interpolator to evaluate it with,
+/// with the plane of the field embedded as the xy-plane of 3D space.
+struct InterpolatedVectorField2<'a, F: BFloat> {
+    field: &'a VectorField2<F>,
+    interpolator: &'a dyn Interpolator2<F>,
+}
+
 trait RKFStepper3 {
     fn state(&self) -> &RKFStepperState3;
     fn state_mut(&mut self) -> &mut RKFStepperState3;
 
-    fn attempt_step<F>(
-        &self,
-        field: &VectorField3<F>,
-        interpolator: &dyn Interpolator3<F>,
-        sense: SteppingSense,
-    ) -> StepperResult<StepAttempt3>
+    fn attempt_step<S>(&self, field: &S, sense: SteppingSense) -> StepperResult<StepAttempt3>
     where
-        F: BFloat;
+        S: SteppingField3;
 
     fn compute_error_deltas(&self, attempt: &StepAttempt3) -> Vec3<ftr>;
 
@@ -162,18 +188,17 @@ trait RKFStepper3 {
         state.previous_unwrapped_output_position = position.clone();
     }
 
-    fn place_with_callback<F>(
+    fn place_with_callback<S>(
         &mut self,
-        field: &VectorField3<F>,
-        interpolator: &dyn Interpolator3<F>,
+        field: &S,
         sense: SteppingSense,
         position: &Point3<ftr>,
         callback: &mut SteppingCallback,
     ) -> StepperResult<()>
     where
-        F: BFloat,
+        S: SteppingField3,
     {
-        let place_result = self.perform_place(field, interpolator, sense, position);
+        let place_result = self.perform_place(field, sense, position);
         if let StepperResult::Ok(_) = place_result {
             if let StepperInstruction::Terminate = callback(
                 &self.state().previous_step_displacement,
@@ -187,17 +212,16 @@ trait RKFStepper3 {
         place_result
     }
 
-    fn step_with_callback<F>(
+    fn step_with_callback<S>(
         &mut self,
-        field: &VectorField3<F>,
-        interpolator: &dyn Interpolator3<F>,
+        field: &S,
         sense: SteppingSense,
         callback: &mut SteppingCallback,
     ) -> StepperResult<()>
     where
-        F: BFloat,
+        S: SteppingField3,
     {
-        let step_result = self.perform_step(field, interpolator, sense);
+        let step_result = self.perform_step(field, sense);
         if let StepperResult::Ok(_) = step_result {
             let effective_direction =
                 &self.state().previous_step_displacement / self.state().previous_step_length;
@@ -213,35 +237,33 @@ trait RKFStepper3 {
         step_result
     }
 
-    fn step_with_callback_dense_output<F>(
+    fn step_with_callback_dense_output<S>(
         &mut self,
-        field: &VectorField3<F>,
-        interpolator: &dyn Interpolator3<F>,
+        field: &S,
         sense: SteppingSense,
         callback: &mut SteppingCallback,
     ) -> StepperResult<()>
     where
-        F: BFloat,
+        S: SteppingField3,
     {
-        let step_result = self.perform_step(field, interpolator, sense);
+        let step_result = self.perform_step(field, sense);
         if let StepperResult::Ok(_) = step_result {
-            self.compute_dense_output(field.grid(), callback)
+            self.compute_dense_output(field, callback)
         } else {
             step_result
         }
     }
 
-    fn perform_place<F>(
+    fn perform_place<S>(
         &mut self,
-        field: &VectorField3<F>,
-        interpolator: &dyn Interpolator3<F>,
+        field: &S,
         sense: SteppingSense,
         position: &Point3<ftr>,
     ) -> StepperResult<()>
     where
-        F: BFloat,
+        S: SteppingField3,
     {
-        match Self::compute_direction(field, interpolator, sense, position) {
+        match Self::compute_direction(field, sense, position) {
             StepperResult::Ok(ComputedDirection3::Standard(direction)) => {
                 self.reset_state(position, &direction)
             }
@@ -254,27 +276,22 @@ trait RKFStepper3 {
         StepperResult::Ok(())
     }
 
-    fn perform_step<F>(
-        &mut self,
-        field: &VectorField3<F>,
-        interpolator: &dyn Interpolator3<F>,
-        sense: SteppingSense,
-    ) -> StepperResult<()>
+    fn perform_step<S>(&mut self, field: &S, sense: SteppingSense) -> StepperResult<()>
     where
-        F: BFloat,
+        S: SteppingField3,
     {
-        let grid = field.grid();
+        let grid_extents = field.extents();
         let mut attempts = 0;
 
         while attempts < self.state().config.max_step_attempts {
-            let step_attempt = match self.attempt_step(field, interpolator, sense) {
+            let step_attempt = match self.attempt_step(field, sense) {
                 StepperResult::Ok(step_attempt) => step_attempt,
                 StepperResult::Stopped(cause) => return StepperResult::Stopped(cause),
             };
 
             attempts += 1;
 
-            match self.compute_error(grid, &step_attempt) {
+            match self.compute_error(&grid_extents, &step_attempt) {
                 StepError::Acceptable(new_error) => {
                     let mut new_step_length = self.compute_step_length_accepted(new_error);
 
@@ -305,16 +322,15 @@ trait RKFStepper3 {
         }
     }
 
-    fn compute_direction<F>(
-        field: &VectorField3<F>,
-        interpolator: &dyn Interpolator3<F>,
+    fn compute_direction<S>(
+        field: &S,
         sense: SteppingSense,
         position: &Point3<ftr>,
     ) -> StepperResult<ComputedDirection3>
     where
-        F: BFloat,
+        S: SteppingField3,
     {
-        match interpolator.interp_vector_field(field, &Point3::from(position)) {
+        match field.vector(position) {
             GridPointQuery3::Inside(field_vector) => {
                 if field_vector.is_zero() {
                     StepperResult::Stopped(StoppingCause::Null)
@@ -329,7 +345,7 @@ trait RKFStepper3 {
                     StepperResult::Stopped(StoppingCause::Null)
                 } else {
                     StepperResult::Ok(ComputedDirection3::WithWrappedPosition((
-                        Point3::from(&wrapped_position),
+                        wrapped_position,
                         Self::compute_direction_from_field_vector(field_vector, sense),
                     )))
                 }
@@ -338,14 +354,11 @@ trait RKFStepper3 {
         }
     }
 
-    fn compute_direction_from_field_vector<F>(
-        field_vector: Vec3<F>,
+    fn compute_direction_from_field_vector(
+        field_vector: Vec3<ftr>,
         sense: SteppingSense,
-    ) -> Vec3<ftr>
-    where
-        F: BFloat,
-    {
-        let mut direction = Vec3::from(&field_vector);
+    ) -> Vec3<ftr> {
+        let mut direction = field_vector;
         direction.normalize();
         if sense == SteppingSense::Opposite {
             direction.reverse();
@@ -353,11 +366,9 @@ trait RKFStepper3 {
         direction
     }
 
-    fn compute_error(&self, grid: &FieldGrid3, attempt: &StepAttempt3) -> StepError {
+    fn compute_error(&self, grid_extents: &Vec3<ftr>, attempt: &StepAttempt3) -> StepError {
         let state = self.state();
         let error_deltas = self.compute_error_deltas(attempt);
-
-        let grid_extents: Vec3<ftr> = Vec3::from(grid.extents());
         let errors = Vec3::new(
             error_deltas[X]
                 / (state.config.absolute_tolerance
@@ -440,11 +451,14 @@ trait RKFStepper3 {
         state.error = new_error;
     }
 
-    fn compute_dense_output(
+    fn compute_dense_output<S>(
         &mut self,
-        grid: &FieldGrid3,
+        field: &S,
         callback: &mut SteppingCallback,
-    ) -> StepperResult<()> {
+    ) -> StepperResult<()>
+    where
+        S: SteppingField3,
+    {
         #![allow(clippy::float_cmp)] // Allows the float comparison with zero
         let state = self.state();
         let previous_distance = state.distance - state.previous_step_length;
@@ -461,11 +475,10 @@ trait RKFStepper3 {
                 let fraction =
                     (next_output_distance - previous_distance) / state.previous_step_length;
                 let unwrapped_output_position = self.interpolate_dense_position(&coefs, fraction);
-                let output_position =
-                    match grid.wrap_point(&Point3::from(&unwrapped_output_position)) {
-                        Some(ref position) => Point3::from(position),
-                        None => return StepperResult::Stopped(StoppingCause::OutOfBounds),
-                    };
+                let output_position = match field.wrap_point(&unwrapped_output_position) {
+                    Some(position) => position,
+                    None => return StepperResult::Stopped(StoppingCause::OutOfBounds),
+                };
                 let dense_step_displacement =
                     &unwrapped_output_position - &previous_unwrapped_output_position;
                 previous_unwrapped_output_position = unwrapped_output_position;
@@ -494,6 +507,164 @@ trait RKFStepper3 {
     }
 }
 
+impl<'a, F: BFloat> InterpolatedVectorField3<'a, F> {
+    fn new(field: &'a VectorField3<F>, interpolator: &'a dyn Interpolator3<F>) -> Self {
+        Self {
+            field,
+            interpolator,
+        }
+    }
+}
+
+impl<'a, F: BFloat> SteppingField3 for InterpolatedVectorField3<'a, F> {
+    fn vector(&self, position: &Point3<ftr>) -> GridPointQuery3<ftr, Vec3<ftr>> {
+        match self
+            .interpolator
+            .interp_vector_field(self.field, &Point3::from(position))
+        {
+            GridPointQuery3::Inside(field_vector) => {
+                GridPointQuery3::Inside(Vec3::from(&field_vector))
+            }
+            GridPointQuery3::MovedInside((field_vector, wrapped_position)) => {
+                GridPointQuery3::MovedInside((
+                    Vec3::from(&field_vector),
+                    Point3::from(&wrapped_position),
+                ))
+            }
+            GridPointQuery3::Outside => GridPointQuery3::Outside,
+        }
+    }
+
+    fn extents(&self) -> Vec3<ftr> {
+        Vec3::from(self.field.grid().extents())
+    }
+
+    fn wrap_point(&self, position: &Point3<ftr>) -> Option<Point3<ftr>> {
+        self.field
+            .grid()
+            .wrap_point(&Point3::from(position))
+            .map(|position| Point3::from(&position))
+    }
+}
+
+impl<'a, F: BFloat> InterpolatedVectorField2<'a, F> {
+    fn new(field: &'a VectorField2<F>, interpolator: &'a dyn Interpolator2<F>) -> Self {
+        Self {
+            field,
+            interpolator,
+        }
+    }
+}
+
+impl<'a, F: BFloat> SteppingField3 for InterpolatedVectorField2<'a, F> {
+    fn vector(&self, position: &Point3<ftr>) -> GridPointQuery3<ftr, Vec3<ftr>> {
+        match self
+            .interpolator
+            .interp_vector_field(self.field, &project_point(position))
+        {
+            GridPointQuery2::Inside(field_vector) => {
+                GridPointQuery3::Inside(embed_vector(&field_vector))
+            }
+            GridPointQuery2::MovedInside((field_vector, wrapped_position)) => {
+                GridPointQuery3::MovedInside((
+                    embed_vector(&field_vector),
+                    embed_point(&wrapped_position),
+                ))
+            }
+            GridPointQuery2::Outside => GridPointQuery3::Outside,
+        }
+    }
+
+    fn extents(&self) -> Vec3<ftr> {
+        embed_vector(self.field.grid().extents())
+    }
+
+    fn wrap_point(&self, position: &Point3<ftr>) -> Option<Point3<ftr>> {
+        self.field
+            .grid()
+            .wrap_point(&project_point(position))
+            .map(|position| embed_point(&position))
+    }
+}
+
+/// Embeds the given 2D point in the xy-plane of 3D space.
+fn embed_point(point: &Point2<ftr>) -> Point3<ftr> {
+    Point3::new(point[Dim2::X], point[Dim2::Y], 0.0)
+}
+
+/// Embeds the given 2D vector in the xy-plane of 3D space.
+fn embed_vector(vector: &Vec2<ftr>) -> Vec3<ftr> {
+    Vec3::new(vector[Dim2::X], vector[Dim2::Y], 0.0)
+}
+
+/// Projects the given 3D point onto the xy-plane.
+fn project_point(point: &Point3<ftr>) -> Point2<ftr> {
+    Point2::new(point[X], point[Y])
+}
+
+/// Projects the given 3D vector onto the xy-plane.
+fn project_vector(vector: &Vec3<ftr>) -> Vec2<ftr> {
+    Vec2::new(vector[X], vector[Y])
+}
+
+/// Places the given RKF stepper inside a 2D vector field.
+fn place_in_plane<St, F>(
+    stepper: &mut St,
+    field: &VectorField2<F>,
+    interpolator: &dyn Interpolator2<F>,
+    sense: SteppingSense,
+    position: &Point2<ftr>,
+    callback: &mut SteppingCallback2,
+) -> StepperResult<()>
+where
+    St: RKFStepper3,
+    F: BFloat,
+{
+    stepper.place_with_callback(
+        &InterpolatedVectorField2::new(field, interpolator),
+        sense,
+        &embed_point(position),
+        &mut |displacement, direction, position, distance| {
+            callback(
+                &project_vector(displacement),
+                &project_vector(direction),
+                &project_point(position),
+                distance,
+            )
+        },
+    )
+}
+
+/// Performs a step with the given RKF stepper in a 2D vector field.
+fn step_in_plane<St, F>(
+    stepper: &mut St,
+    field: &VectorField2<F>,
+    interpolator: &dyn Interpolator2<F>,
+    sense: SteppingSense,
+    callback: &mut SteppingCallback2,
+    dense_output: bool,
+) -> StepperResult<()>
+where
+    St: RKFStepper3,
+    F: BFloat,
+{
+    let field = InterpolatedVectorField2::new(field, interpolator);
+    let mut callback =
+        |displacement: &Vec3<ftr>, direction: &Vec3<ftr>, position: &Point3<ftr>, distance: ftr| {
+            callback(
+                &project_vector(displacement),
+                &project_vector(direction),
+                &project_point(position),
+                distance,
+            )
+        };
+    if dense_output {
+        stepper.step_with_callback_dense_output(&field, sense, &mut callback)
+    } else {
+        stepper.step_with_callback(&field, sense, &mut callback)
+    }
+}
+
 impl RKFStepperConfig {
     pub const DEFAULT_DENSE_STEP_LENGTH: ftr = 1e-2;
     pub const DEFAULT_MAX_STEP_ATTEMPTS: u32 = 16;
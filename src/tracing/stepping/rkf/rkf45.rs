@@ -3,14 +3,17 @@
 //! estimation through an embedded fourth-order step.
 
 use super::{
-    super::{DynStepper3, Stepper3, StepperResult, SteppingCallback, SteppingSense},
-    ComputedDirection3, PIControlParams, RKFStepper3, RKFStepperConfig, RKFStepperState3,
-    StepAttempt3,
+    super::{
+        DynStepper2, DynStepper3, Stepper2, Stepper3, StepperResult, SteppingCallback,
+        SteppingCallback2, SteppingSense,
+    },
+    place_in_plane, project_point, step_in_plane, ComputedDirection3, InterpolatedVectorField3,
+    PIControlParams, RKFStepper3, RKFStepperConfig, RKFStepperState3, StepAttempt3, SteppingField3,
 };
 use crate::{
-    field::VectorField3,
-    geometry::{Point3, Vec3},
-    interpolation::Interpolator3,
+    field::{VectorField2, VectorField3},
+    geometry::{Point2, Point3, Vec3},
+    interpolation::{Interpolator2, Interpolator3},
     num::BFloat,
     tracing::ftr,
 };
@@ -19,6 +22,10 @@ use crate::{
 #[derive(Clone, Debug)]
 pub struct RKF45Stepper3(RKFStepperState3);
 
+/// A stepper using the fifth order Runge–Kutta–Fehlberg method in 2D vector fields.
+#[derive(Clone, Debug)]
+pub struct RKF45Stepper2(RKF45Stepper3);
+
 impl RKF45Stepper3 {
     const ORDER: u8 = 5;
     const N_INTERMEDIATE_STEPS: usize = 5;
@@ -116,41 +123,30 @@ impl RKFStepper3 for RKF45Stepper3 {
         &mut self.0
     }
 
-    fn attempt_step<F>(
-        &self,
-        field: &VectorField3<F>,
-        interpolator: &dyn Interpolator3<F>,
-        sense: SteppingSense,
-    ) -> StepperResult<StepAttempt3>
+    fn attempt_step<S>(&self, field: &S, sense: SteppingSense) -> StepperResult<StepAttempt3>
     where
-        F: BFloat,
+        S: SteppingField3,
     {
         let state = self.state();
 
         let mut next_position =
             &state.position + &state.direction * (Self::A21 * state.step_length);
 
-        let intermediate_direction_1 =
-            match Self::compute_direction(field, interpolator, sense, &next_position) {
-                StepperResult::Ok(ComputedDirection3::Standard(direction)) => direction,
-                StepperResult::Ok(ComputedDirection3::WithWrappedPosition((_, direction))) => {
-                    direction
-                }
-                StepperResult::Stopped(cause) => return StepperResult::Stopped(cause),
-            };
+        let intermediate_direction_1 = match Self::compute_direction(field, sense, &next_position) {
+            StepperResult::Ok(ComputedDirection3::Standard(direction)) => direction,
+            StepperResult::Ok(ComputedDirection3::WithWrappedPosition((_, direction))) => direction,
+            StepperResult::Stopped(cause) => return StepperResult::Stopped(cause),
+        };
 
         next_position = &state.position
             + (&state.direction * Self::A31 + &intermediate_direction_1 * Self::A32)
                 * state.step_length;
 
-        let intermediate_direction_2 =
-            match Self::compute_direction(field, interpolator, sense, &next_position) {
-                StepperResult::Ok(ComputedDirection3::Standard(direction)) => direction,
-                StepperResult::Ok(ComputedDirection3::WithWrappedPosition((_, direction))) => {
-                    direction
-                }
-                StepperResult::Stopped(cause) => return StepperResult::Stopped(cause),
-            };
+        let intermediate_direction_2 = match Self::compute_direction(field, sense, &next_position) {
+            StepperResult::Ok(ComputedDirection3::Standard(direction)) => direction,
+            StepperResult::Ok(ComputedDirection3::WithWrappedPosition((_, direction))) => direction,
+            StepperResult::Stopped(cause) => return StepperResult::Stopped(cause),
+        };
 
         next_position = &state.position
             + (&state.direction * Self::A41
@@ -158,14 +154,11 @@ impl RKFStepper3 for RKF45Stepper3 {
                 + &intermediate_direction_2 * Self::A43)
                 * state.step_length;
 
-        let intermediate_direction_3 =
-            match Self::compute_direction(field, interpolator, sense, &next_position) {
-                StepperResult::Ok(ComputedDirection3::Standard(direction)) => direction,
-                StepperResult::Ok(ComputedDirection3::WithWrappedPosition((_, direction))) => {
-                    direction
-                }
-                StepperResult::Stopped(cause) => return StepperResult::Stopped(cause),
-            };
+        let intermediate_direction_3 = match Self::compute_direction(field, sense, &next_position) {
+            StepperResult::Ok(ComputedDirection3::Standard(direction)) => direction,
+            StepperResult::Ok(ComputedDirection3::WithWrappedPosition((_, direction))) => direction,
+            StepperResult::Stopped(cause) => return StepperResult::Stopped(cause),
+        };
 
         next_position = &state.position
             + (&state.direction * Self::A51
@@ -174,14 +167,11 @@ impl RKFStepper3 for RKF45Stepper3 {
                 + &intermediate_direction_3 * Self::A54)
                 * state.step_length;
 
-        let intermediate_direction_4 =
-            match Self::compute_direction(field, interpolator, sense, &next_position) {
-                StepperResult::Ok(ComputedDirection3::Standard(direction)) => direction,
-                StepperResult::Ok(ComputedDirection3::WithWrappedPosition((_, direction))) => {
-                    direction
-                }
-                StepperResult::Stopped(cause) => return StepperResult::Stopped(cause),
-            };
+        let intermediate_direction_4 = match Self::compute_direction(field, sense, &next_position) {
+            StepperResult::Ok(ComputedDirection3::Standard(direction)) => direction,
+            StepperResult::Ok(ComputedDirection3::WithWrappedPosition((_, direction))) => direction,
+            StepperResult::Stopped(cause) => return StepperResult::Stopped(cause),
+        };
 
         next_position = &state.position
             + (&state.direction * Self::A61
@@ -191,14 +181,11 @@ impl RKFStepper3 for RKF45Stepper3 {
                 + &intermediate_direction_4 * Self::A65)
                 * state.step_length;
 
-        let intermediate_direction_5 =
-            match Self::compute_direction(field, interpolator, sense, &next_position) {
-                StepperResult::Ok(ComputedDirection3::Standard(direction)) => direction,
-                StepperResult::Ok(ComputedDirection3::WithWrappedPosition((_, direction))) => {
-                    direction
-                }
-                StepperResult::Stopped(cause) => return StepperResult::Stopped(cause),
-            };
+        let intermediate_direction_5 = match Self::compute_direction(field, sense, &next_position) {
+            StepperResult::Ok(ComputedDirection3::Standard(direction)) => direction,
+            StepperResult::Ok(ComputedDirection3::WithWrappedPosition((_, direction))) => direction,
+            StepperResult::Stopped(cause) => return StepperResult::Stopped(cause),
+        };
 
         let step_displacement = (&state.direction * Self::A71
             + &intermediate_direction_2 * Self::A73
@@ -211,19 +198,18 @@ impl RKFStepper3 for RKF45Stepper3 {
 
         let mut step_wrapped = false;
 
-        let next_direction =
-            match Self::compute_direction(field, interpolator, sense, &next_position) {
-                StepperResult::Ok(ComputedDirection3::Standard(direction)) => direction,
-                StepperResult::Ok(ComputedDirection3::WithWrappedPosition((
-                    wrapped_position,
-                    direction,
-                ))) => {
-                    step_wrapped = true;
-                    next_position = wrapped_position;
-                    direction
-                }
-                StepperResult::Stopped(cause) => return StepperResult::Stopped(cause),
-            };
+        let next_direction = match Self::compute_direction(field, sense, &next_position) {
+            StepperResult::Ok(ComputedDirection3::Standard(direction)) => direction,
+            StepperResult::Ok(ComputedDirection3::WithWrappedPosition((
+                wrapped_position,
+                direction,
+            ))) => {
+                step_wrapped = true;
+                next_position = wrapped_position;
+                direction
+            }
+            StepperResult::Stopped(cause) => return StepperResult::Stopped(cause),
+        };
 
         StepperResult::Ok(StepAttempt3 {
             step_displacement,
@@ -289,7 +275,12 @@ impl<F> Stepper3<F> for RKF45Stepper3 {
     where
         F: BFloat,
     {
-        self.place_with_callback(field, interpolator, sense, position, callback)
+        self.place_with_callback(
+            &InterpolatedVectorField3::new(field, interpolator),
+            sense,
+            position,
+            callback,
+        )
     }
 
     fn step(
@@ -302,7 +293,11 @@ impl<F> Stepper3<F> for RKF45Stepper3 {
     where
         F: BFloat,
     {
-        self.step_with_callback(field, interpolator, sense, callback)
+        self.step_with_callback(
+            &InterpolatedVectorField3::new(field, interpolator),
+            sense,
+            callback,
+        )
     }
 
     fn step_dense_output(
@@ -315,7 +310,11 @@ impl<F> Stepper3<F> for RKF45Stepper3 {
     where
         F: BFloat,
     {
-        self.step_with_callback_dense_output(field, interpolator, sense, callback)
+        self.step_with_callback_dense_output(
+            &InterpolatedVectorField3::new(field, interpolator),
+            sense,
+            callback,
+        )
     }
 
     fn position(&self) -> &Point3<ftr> {
@@ -330,3 +329,64 @@ impl<F> Stepper3<F> for RKF45Stepper3 {
         Box::new(self.clone())
     }
 }
+
+impl RKF45Stepper2 {
+    /// Creates a new 2D RKF45 stepper with the given configuration.
+    pub fn new(config: RKFStepperConfig) -> Self {
+        Self(RKF45Stepper3::new(config))
+    }
+}
+
+impl<F> Stepper2<F> for RKF45Stepper2 {
+    fn place(
+        &mut self,
+        field: &VectorField2<F>,
+        interpolator: &dyn Interpolator2<F>,
+        sense: SteppingSense,
+        position: &Point2<ftr>,
+        callback: &mut SteppingCallback2,
+    ) -> StepperResult<()>
+    where
+        F: BFloat,
+    {
+        place_in_plane(&mut self.0, field, interpolator, sense, position, callback)
+    }
+
+    fn step(
+        &mut self,
+        field: &VectorField2<F>,
+        interpolator: &dyn Interpolator2<F>,
+        sense: SteppingSense,
+        callback: &mut SteppingCallback2,
+    ) -> StepperResult<()>
+    where
+        F: BFloat,
+    {
+        step_in_plane(&mut self.0, field, interpolator, sense, callback, false)
+    }
+
+    fn step_dense_output(
+        &mut self,
+        field: &VectorField2<F>,
+        interpolator: &dyn Interpolator2<F>,
+        sense: SteppingSense,
+        callback: &mut SteppingCallback2,
+    ) -> StepperResult<()>
+    where
+        F: BFloat,
+    {
+        step_in_plane(&mut self.0, field, interpolator, sense, callback, true)
+    }
+
+    fn position(&self) -> Point2<ftr> {
+        project_point(&self.0.state().position)
+    }
+
+    fn distance(&self) -> ftr {
+        self.0.state().distance
+    }
+
+    fn heap_clone(&self) -> DynStepper2<F> {
+        Box::new(self.clone())
+    }
+}
@@ -283,6 +283,32 @@ pub fn write_snapshot_with_computed_variable<P: AsRef<Path>>(
     );
 }
 
+pub fn write_snapshot_with_computed_variables<P: AsRef<Path>>(
+    grid: Arc<FieldGrid3>,
+    variables: Vec<(&str, FieldValueComputer<fdt>)>,
+    output_path: P,
+) {
+    let metadata = Box::new(OutputSnapshotMetadata::new());
+
+    let mut generator = Box::new(variables.into_iter().fold(
+        CustomScalarFieldGenerator3::new(grid, Verbosity::Quiet),
+        |generator, (variable_name, variable_computer)| {
+            generator.with_variable(variable_name.to_string(), variable_computer)
+        },
+    ));
+
+    exit_on_error!(
+        native::write_new_snapshot(
+            &*metadata,
+            &mut *generator,
+            output_path.as_ref(),
+            &IOContext::new(),
+            &Verbosity::Quiet,
+        ),
+        "Error: {}"
+    );
+}
+
 #[derive(Debug, Clone)]
 pub struct Test {
     output_dir: PathBuf,
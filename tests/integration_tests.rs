@@ -22,6 +22,15 @@ use common::run;
 #[cfg(feature = "for-testing")]
 use approx::RelativeEq;
 
+#[cfg(all(feature = "cli", feature = "corks", feature = "json"))]
+use backstaff::{
+    field::FieldGrid3,
+    geometry::{In3D, Vec3},
+    grid::regular::RegularGrid3,
+};
+#[cfg(all(feature = "cli", feature = "corks", feature = "json"))]
+use std::{fs, io::Write, sync::Arc};
+
 const MINIMAL_NATIVE_MESH: &str = "minimal.mesh";
 const MINIMAL_NATIVE_SNAP: &str = "minimal_001.idl";
 const MINIMAL_NETCDF_SNAP: &str = "minimal_001.nc";
@@ -181,6 +190,54 @@ fn extract_with_too_large_subgrid_preserves_input_snapshot() {
     common::assert_snapshot_files_equal(input_snapshot, output_snapshot, fdt::default_max_relative());
 });
 
+#[cfg(all(feature = "cli", feature = "corks", feature = "json"))]
+def_test!(
+IN[]
+OUT[snapshot_1="corks_001.idl", snapshot_2="corks_002.idl", seeds="seeds.txt", corks="corks.json"]
+fn corks_are_advected_across_snapshot_range() {
+    let grid = Arc::new(FieldGrid3::from(RegularGrid3::from_bounds(
+        In3D::same(8),
+        Vec3::new(0.0, 0.0, 0.0),
+        Vec3::new(1.0, 1.0, 1.0),
+        In3D::new(true, true, false),
+    )));
+    for snapshot in [snapshot_1, snapshot_2] {
+        common::write_snapshot_with_computed_variables(
+            grid.clone(),
+            vec![
+                ("r", field_value_computer!(constant = 2.0; (fdt))),
+                ("px", field_value_computer!(constant = 0.2; (fdt))),
+                ("py", field_value_computer!(constant = 0.0; (fdt))),
+                ("pz", field_value_computer!(constant = 0.0; (fdt))),
+                ("e", field_value_computer!(constant = 1.0; (fdt))),
+            ],
+            snapshot,
+        );
+        let mut params = fs::OpenOptions::new().append(true).open(snapshot).unwrap();
+        writeln!(params, "dtsnap = 2.0").unwrap();
+    }
+    fs::write(seeds, "0.25, 0.5, 0.5\n").unwrap();
+
+    run(["snapshot",
+         snapshot_1,
+         "--snap-range=1,2",
+         "corks",
+         corks,
+         "manual_seeder",
+         &format!("--input-file={}", seeds),
+    ]);
+
+    // The corks must be advected from the first snapshot rather than being
+    // reinitialized for the second
+    let output: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(corks).unwrap()).unwrap();
+    let positions = output["corks"][0]["positions"].as_array().unwrap();
+    assert_eq!(positions.len(), 2);
+    let x = |position: &serde_json::Value| position[0].as_f64().unwrap();
+    assert!((x(&positions[0]) - 0.25).abs() < 1e-6);
+    assert!((x(&positions[1]) - 0.45).abs() < 1e-6);
+});
+
 macro_rules! define_test_for_each_resampling_method {
     ($test_macro:ident) => {
         #[cfg(all(feature = "cli", feature = "for-testing"))]